@@ -0,0 +1,61 @@
+//! Generates the default configuration as a constant from `extra/config.toml`. This way, the
+//! default configuration does not have to be parsed when lemurs runs, and a default configuration
+//! that cannot be parsed fails the build instead.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use toml::Value;
+
+const DEFAULT_CONFIG_PATH: &str = "extra/config.toml";
+
+/// Write `value` as a `DefaultValue` expression to `code`
+fn write_value(code: &mut String, value: &Value) -> std::fmt::Result {
+    match value {
+        Value::Boolean(value) => write!(code, "DefaultValue::Boolean({value})"),
+        Value::Integer(value) => write!(code, "DefaultValue::Integer({value})"),
+        Value::Float(value) if value.is_finite() => {
+            write!(code, "DefaultValue::Float({value:?})")
+        }
+        Value::Float(value) => panic!("The default configuration has the float {value}"),
+        Value::String(value) => write!(code, "DefaultValue::String({value:?})"),
+        Value::Datetime(value) => panic!("The default configuration has the datetime {value}"),
+        Value::Array(values) => {
+            code.push_str("DefaultValue::Array(&[");
+            for value in values {
+                write_value(code, value)?;
+                code.push_str(", ");
+            }
+            code.push_str("])");
+            Ok(())
+        }
+        Value::Table(table) => {
+            code.push_str("DefaultValue::Table(&[");
+            for (key, value) in table {
+                write!(code, "({key:?}, ")?;
+                write_value(code, value)?;
+                code.push_str("), ");
+            }
+            code.push_str("])");
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={DEFAULT_CONFIG_PATH}");
+
+    let contents = fs::read_to_string(DEFAULT_CONFIG_PATH)
+        .unwrap_or_else(|err| panic!("Failed to read '{DEFAULT_CONFIG_PATH}'. Reason: {err}"));
+    let value = toml::from_str::<Value>(&contents)
+        .unwrap_or_else(|err| panic!("Failed to parse '{DEFAULT_CONFIG_PATH}'. Reason: {err}"));
+
+    let mut code = String::new();
+    write_value(&mut code, &value).expect("Writing to a string does not fail");
+
+    let out_dir = env::var("OUT_DIR").expect("Cargo sets OUT_DIR for build scripts");
+    fs::write(Path::new(&out_dir).join("default_config.rs"), code)
+        .expect("Failed to write the generated default configuration");
+}
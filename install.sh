@@ -73,6 +73,8 @@ $ROOT_CMD systemctl disable display-manager.service
 echo 'Setting up lemurs service'
 $ROOT_CMD cp -f extra/lemurs.service /usr/lib/systemd/system/lemurs.service
 if [ $? -ne 0 ]; then exit 1; fi
+$ROOT_CMD cp -f extra/lemurs@.service /usr/lib/systemd/system/lemurs@.service
+if [ $? -ne 0 ]; then exit 1; fi
 
 # Enable lemurs
 echo 'Enable the lemurs service'
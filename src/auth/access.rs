@@ -0,0 +1,294 @@
+//! Windows of time in which users are allowed to log in, e.g. the opening hours of a school lab.
+//!
+//! Unlike `pam_time`, these are checked before PAM is asked to authenticate the user, so that the
+//! user can be told when logins resume.
+
+use log::{error, info, warn};
+
+use crate::auth::policy::group_names;
+use crate::auth::AuthenticationError;
+use crate::config::{AccessConfig, AccessRule};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// The names of the days in the order of `tm_wday`
+const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const DAY_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+/// Parse a time of day in the `HH:MM` format to the minutes since midnight. `24:00` is allowed to
+/// end a window at midnight.
+fn parse_time_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    if hours > 24 || minutes >= 60 {
+        return None;
+    }
+
+    let time = hours * 60 + minutes;
+    (time <= MINUTES_PER_DAY).then_some(time)
+}
+
+/// A window in which logins are allowed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessWindow {
+    /// Whether the window starts on a day, by `tm_wday`
+    days: [bool; 7],
+    /// The minutes since midnight. A window with an end before its start ends on the next day.
+    start: u32,
+    end: u32,
+}
+
+impl AccessWindow {
+    /// The window of `rule`, or a description of what is wrong with it
+    pub fn parse(rule: &AccessRule) -> Result<Self, String> {
+        let mut days = [rule.days.is_empty(); 7];
+        for day in &rule.days {
+            let Some(index) = DAYS.iter().position(|name| day.eq_ignore_ascii_case(name)) else {
+                return Err(format!("'{day}' is not a day. Expected 'mon' to 'sun'"));
+            };
+            days[index] = true;
+        }
+
+        let Some((start, end)) = rule.hours.split_once('-') else {
+            return Err(format!(
+                "'{}' is not a range of hours. Expected 'HH:MM-HH:MM'",
+                rule.hours
+            ));
+        };
+        let (Some(start), Some(end)) = (parse_time_of_day(start), parse_time_of_day(end)) else {
+            return Err(format!(
+                "'{}' has an invalid time. Expected 'HH:MM-HH:MM'",
+                rule.hours
+            ));
+        };
+        if start == end || start == MINUTES_PER_DAY {
+            return Err(format!("'{}' is an empty range of hours", rule.hours));
+        }
+
+        Ok(Self { days, start, end })
+    }
+
+    fn contains(&self, weekday: usize, minutes: u32) -> bool {
+        if self.start < self.end {
+            return self.days[weekday] && (self.start..self.end).contains(&minutes);
+        }
+
+        // The window wraps around midnight
+        let yesterday = (weekday + 6) % 7;
+        (self.days[weekday] && minutes >= self.start)
+            || (self.days[yesterday] && minutes < self.end)
+    }
+
+    /// The minutes until the window starts next
+    fn minutes_until_start(&self, weekday: usize, minutes: u32) -> Option<u32> {
+        (0..=7)
+            .filter(|offset| self.days[(weekday + offset) % 7])
+            .map(|offset| offset as u32 * MINUTES_PER_DAY + self.start)
+            .find(|start| *start > minutes)
+            .map(|start| start - minutes)
+    }
+}
+
+/// The current local day of the week and the minutes since midnight
+fn local_time() -> Option<(usize, u32)> {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm = std::mem::MaybeUninit::<libc::tm>::uninit();
+
+    if unsafe { libc::localtime_r(&now, tm.as_mut_ptr()) }.is_null() {
+        return None;
+    }
+
+    let tm = unsafe { tm.assume_init() };
+    Some((tm.tm_wday as usize, (tm.tm_hour * 60 + tm.tm_min) as u32))
+}
+
+/// Describe when logins resume, e.g. "tomorrow at 07:00 (in 9h 30m)"
+fn describe_resume(weekday: usize, minutes: u32, minutes_until: u32) -> String {
+    let resume = minutes + minutes_until;
+    let days = resume / MINUTES_PER_DAY;
+    let time = resume % MINUTES_PER_DAY;
+    let time = format!("{:02}:{:02}", time / 60, time % 60);
+
+    let when = match days {
+        0 => format!("at {time}"),
+        1 => format!("tomorrow at {time}"),
+        _ => format!("on {} at {time}", DAY_NAMES[(weekday + days as usize) % 7]),
+    };
+
+    match (minutes_until / 60, minutes_until % 60) {
+        (hours, _) if hours >= 24 => when,
+        (0, minutes) => format!("{when} (in {minutes}m)"),
+        (hours, 0) => format!("{when} (in {hours}h)"),
+        (hours, minutes) => format!("{when} (in {hours}h {minutes}m)"),
+    }
+}
+
+/// Check whether `username` is allowed to log in at this time. Users without any rule that applies
+/// to them can always log in. Users to whom an invalid rule applies cannot log in, so that a typo
+/// does not lift the restriction.
+pub fn check_access_hours(
+    username: &str,
+    config: &AccessConfig,
+) -> Result<(), AuthenticationError> {
+    if config.rules.0.is_empty() {
+        return Ok(());
+    }
+
+    let groups = uzers::get_user_by_name(username)
+        .map(|user| {
+            let all_gids = user.groups().map_or_else(Vec::default, |groups| {
+                groups.into_iter().map(|group| group.gid()).collect()
+            });
+            group_names(user.primary_group_id(), &all_gids)
+        })
+        .unwrap_or_default();
+
+    let windows = config
+        .rules
+        .0
+        .iter()
+        .filter(|rule| {
+            (rule.users.is_empty() && rule.groups.is_empty())
+                || rule.users.iter().any(|user| user == username)
+                || rule.groups.iter().any(|group| groups.contains(group))
+        })
+        .map(AccessWindow::parse)
+        .collect::<Result<Vec<_>, _>>();
+
+    let windows = match windows {
+        Ok(windows) => windows,
+        Err(err) => {
+            error!(
+                "Denied login for '{username}' because of an invalid access rule. Reason: {err}"
+            );
+            return Err(AuthenticationError::OutsideAccessHours(None));
+        }
+    };
+
+    if windows.is_empty() {
+        return Ok(());
+    }
+
+    let Some((weekday, minutes)) = local_time() else {
+        warn!("Failed to get the local time. Not checking the access hours");
+        return Ok(());
+    };
+
+    if windows
+        .iter()
+        .any(|window| window.contains(weekday, minutes))
+    {
+        return Ok(());
+    }
+
+    info!("Denied login for '{username}' because it is outside of its access hours");
+
+    let resume = windows
+        .iter()
+        .filter_map(|window| window.minutes_until_start(weekday, minutes))
+        .min()
+        .map(|minutes_until| describe_resume(weekday, minutes, minutes_until));
+
+    Err(AuthenticationError::OutsideAccessHours(resume))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::AccessRuleVec;
+
+    fn window(days: &[&str], hours: &str) -> AccessWindow {
+        AccessWindow::parse(&AccessRule {
+            users: Vec::new(),
+            groups: Vec::new(),
+            days: days.iter().map(|day| day.to_string()).collect(),
+            hours: hours.to_string(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn access_windows() {
+        const MON: usize = 1;
+        const FRI: usize = 5;
+        const SAT: usize = 6;
+
+        let school = window(&["mon", "tue", "wed", "thu", "fri"], "07:00-18:00");
+        assert!(school.contains(MON, 7 * 60));
+        assert!(!school.contains(MON, 18 * 60));
+        assert!(!school.contains(SAT, 12 * 60));
+        assert_eq!(school.minutes_until_start(MON, 6 * 60 + 30), Some(30));
+        assert_eq!(
+            school.minutes_until_start(FRI, 19 * 60),
+            Some(2 * MINUTES_PER_DAY + 12 * 60)
+        );
+
+        let night = window(&["fri"], "22:00-02:00");
+        assert!(night.contains(FRI, 23 * 60));
+        assert!(night.contains(SAT, 60));
+        assert!(!night.contains(SAT, 23 * 60));
+
+        assert!(window(&[], "00:00-24:00").contains(SAT, 23 * 60 + 59));
+        assert!(AccessWindow::parse(&AccessRule {
+            hours: "07:00-07:00".to_string(),
+            ..AccessRule::default()
+        })
+        .is_err());
+        assert!(AccessWindow::parse(&AccessRule {
+            hours: "99999999:00-08:00".to_string(),
+            ..AccessRule::default()
+        })
+        .is_err());
+        assert_eq!(parse_time_of_day("71582789:20"), None);
+    }
+
+    #[test]
+    fn invalid_rule_denies() {
+        let config = AccessConfig {
+            rules: AccessRuleVec(vec![
+                AccessRule::default(),
+                AccessRule {
+                    hours: "07:00-25:00".to_string(),
+                    ..AccessRule::default()
+                },
+            ]),
+        };
+        assert!(matches!(
+            check_access_hours("lemurs-test-user", &config),
+            Err(AuthenticationError::OutsideAccessHours(None))
+        ));
+
+        // An invalid rule for other users does not apply
+        let config = AccessConfig {
+            rules: AccessRuleVec(vec![AccessRule {
+                users: vec!["someone-else".to_string()],
+                hours: "07:00-25:00".to_string(),
+                ..AccessRule::default()
+            }]),
+        };
+        assert!(check_access_hours("lemurs-test-user", &config).is_ok());
+    }
+
+    #[test]
+    fn resume_description() {
+        assert_eq!(describe_resume(1, 6 * 60 + 30, 30), "at 07:00 (in 30m)");
+        assert_eq!(
+            describe_resume(1, 18 * 60, 13 * 60),
+            "tomorrow at 07:00 (in 13h)"
+        );
+        assert_eq!(
+            describe_resume(5, 19 * 60, 2 * MINUTES_PER_DAY + 12 * 60),
+            "on Monday at 07:00"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+//! Resource limits of the PAM session.
+//!
+//! `pam_limits` sets the limits from `/etc/security/limits.conf` on the process that opens the
+//! PAM session, which is lemurs itself. The session inherits them from lemurs. The limits are
+//! compared against the configuration files to catch a PAM stack without `pam_limits`, and they
+//! are restored once the session is closed, so that the next session does not inherit them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+const LIMITS_CONF_PATH: &str = "/etc/security/limits.conf";
+const LIMITS_D_PATH: &str = "/etc/security/limits.d";
+
+#[cfg(target_env = "gnu")]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+type Resource = libc::c_int;
+
+/// The resources that are checked, with the factor to convert from the unit of `limits.conf`
+const RESOURCES: &[(&str, Resource, libc::rlim_t)] = &[
+    ("core", libc::RLIMIT_CORE, 1024),
+    ("data", libc::RLIMIT_DATA, 1024),
+    ("fsize", libc::RLIMIT_FSIZE, 1024),
+    ("memlock", libc::RLIMIT_MEMLOCK, 1024),
+    ("nofile", libc::RLIMIT_NOFILE, 1),
+    ("stack", libc::RLIMIT_STACK, 1024),
+    ("nproc", libc::RLIMIT_NPROC, 1),
+    ("as", libc::RLIMIT_AS, 1024),
+];
+
+fn get_rlimit(resource: Resource) -> Option<libc::rlimit> {
+    let mut rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    (unsafe { libc::getrlimit(resource, &mut rlimit) } == 0).then_some(rlimit)
+}
+
+/// The limits of the lemurs process before the PAM session was opened. They are restored when
+/// this is dropped.
+pub struct LimitsSnapshot(Vec<(Resource, libc::rlimit)>);
+
+impl LimitsSnapshot {
+    pub fn take() -> Self {
+        Self(
+            RESOURCES
+                .iter()
+                .filter_map(|(_, resource, _)| Some((*resource, get_rlimit(*resource)?)))
+                .collect(),
+        )
+    }
+}
+
+impl Drop for LimitsSnapshot {
+    fn drop(&mut self) {
+        for (resource, rlimit) in &self.0 {
+            if unsafe { libc::setrlimit(*resource, rlimit) } != 0 {
+                warn!(
+                    "Failed to restore resource limit {resource}. Reason: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
+}
+
+/// Which of the limits an entry sets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LimitType {
+    Soft,
+    Hard,
+    Both,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LimitEntry {
+    domain: String,
+    limit_type: LimitType,
+    item: String,
+    /// The value in the unit of `limits.conf`, or `RLIM_INFINITY` for no limit
+    value: libc::rlim_t,
+}
+
+/// Parse the entries of a `limits.conf` file. Entries that cannot be checked are skipped.
+fn parse_limits_conf(content: &str) -> Vec<LimitEntry> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let domain = fields.next()?;
+            let limit_type = match fields.next()? {
+                "soft" => LimitType::Soft,
+                "hard" => LimitType::Hard,
+                "-" => LimitType::Both,
+                _ => return None,
+            };
+            let item = fields.next()?;
+            let value = match fields.next()? {
+                "unlimited" | "infinity" | "-1" => libc::RLIM_INFINITY,
+                value => value.parse().ok()?,
+            };
+
+            Some(LimitEntry {
+                domain: domain.to_string(),
+                limit_type,
+                item: item.to_string(),
+                value,
+            })
+        })
+        .collect()
+}
+
+/// How specific the domain of an entry is for the user. `None` if it does not apply.
+fn domain_specificity(domain: &str, username: &str, groups: &[String]) -> Option<u8> {
+    if domain == username {
+        Some(3)
+    } else if domain
+        .strip_prefix('@')
+        .is_some_and(|group| groups.iter().any(|g| g == group))
+    {
+        Some(2)
+    } else if domain == "*" {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// The soft and hard limit for `item` that `pam_limits` should set for the user. A more specific
+/// domain takes precedence. For the same domain, the last entry wins.
+fn expected_limit(
+    entries: &[LimitEntry],
+    item: &str,
+    username: &str,
+    groups: &[String],
+) -> (Option<libc::rlim_t>, Option<libc::rlim_t>) {
+    let mut soft = None;
+    let mut hard = None;
+
+    for entry in entries.iter().filter(|entry| entry.item == item) {
+        let Some(specificity) = domain_specificity(&entry.domain, username, groups) else {
+            continue;
+        };
+
+        let set = |limit: &mut Option<(u8, libc::rlim_t)>| {
+            if limit.map_or(true, |(current, _)| specificity >= current) {
+                *limit = Some((specificity, entry.value));
+            }
+        };
+
+        if matches!(entry.limit_type, LimitType::Soft | LimitType::Both) {
+            set(&mut soft);
+        }
+        if matches!(entry.limit_type, LimitType::Hard | LimitType::Both) {
+            set(&mut hard);
+        }
+    }
+
+    (soft.map(|(_, value)| value), hard.map(|(_, value)| value))
+}
+
+fn limits_conf_paths() -> Vec<PathBuf> {
+    let mut paths = fs::read_dir(LIMITS_D_PATH)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "conf"))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    paths.sort();
+
+    // The files are handled as if they were concatenated after `limits.conf`
+    paths.insert(0, PathBuf::from(LIMITS_CONF_PATH));
+    paths
+}
+
+fn read_limits_conf(path: &Path) -> Vec<LimitEntry> {
+    match fs::read_to_string(path) {
+        Ok(content) => parse_limits_conf(&content),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            warn!("Failed to read '{}'. Reason: {err}", path.display());
+            Vec::new()
+        }
+    }
+}
+
+/// Compare the limits of the lemurs process with what `limits.conf` sets for the user and log the
+/// discrepancies
+pub fn verify_limits(username: &str, groups: &[String]) {
+    let entries = limits_conf_paths()
+        .iter()
+        .flat_map(|path| read_limits_conf(path))
+        .collect::<Vec<_>>();
+
+    if entries.is_empty() {
+        return;
+    }
+
+    let mut matches = true;
+    for (item, resource, factor) in RESOURCES {
+        let Some(rlimit) = get_rlimit(*resource) else {
+            continue;
+        };
+
+        let (soft, hard) = expected_limit(&entries, item, username, groups);
+        for (kind, expected, actual) in [
+            ("soft", soft, rlimit.rlim_cur),
+            ("hard", hard, rlimit.rlim_max),
+        ] {
+            let Some(expected) = expected else {
+                continue;
+            };
+            let expected = match expected {
+                libc::RLIM_INFINITY => libc::RLIM_INFINITY,
+                value => value.saturating_mul(*factor),
+            };
+
+            if expected != actual {
+                matches = false;
+                warn!("The {kind} limit of '{item}' is {actual}, but limits.conf sets it to {expected} for '{username}'");
+            }
+        }
+    }
+
+    if matches {
+        info!("Resource limits match limits.conf");
+    } else {
+        warn!("Resource limits differ from limits.conf. Is `pam_limits` part of the session stack of the PAM service?");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_conf() {
+        let entries = parse_limits_conf(
+            "# Comment\n\
+             *        soft  core    0\n\
+             *        hard  nofile  4096 # inline\n\
+             @audio   -     memlock unlimited\n\
+             @audio   -     nofile  8192\n\
+             alice    soft  nofile  2048\n\
+             bob      soft  nproc   100\n\
+             invalid\n",
+        );
+        assert_eq!(entries.len(), 6);
+
+        let groups = vec!["audio".to_string()];
+        assert_eq!(
+            expected_limit(&entries, "core", "alice", &groups),
+            (Some(0), None)
+        );
+        assert_eq!(
+            expected_limit(&entries, "nofile", "alice", &groups),
+            (Some(2048), Some(8192))
+        );
+        assert_eq!(
+            expected_limit(&entries, "nofile", "alice", &[]),
+            (Some(2048), Some(4096))
+        );
+        assert_eq!(
+            expected_limit(&entries, "memlock", "alice", &groups),
+            (Some(libc::RLIM_INFINITY), Some(libc::RLIM_INFINITY))
+        );
+        assert_eq!(
+            expected_limit(&entries, "nproc", "alice", &groups),
+            (None, None)
+        );
+    }
+}
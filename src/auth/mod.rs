@@ -1,11 +1,24 @@
+pub mod access;
+mod limits;
+mod offline;
 mod pam;
+mod policy;
 pub mod utmpx;
 
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
 use ::pam::{Authenticator, PasswordConv};
-use log::info;
+use log::{error, info};
+use once_cell::sync::Lazy;
 
+use crate::auth::access::check_access_hours;
+use crate::auth::limits::LimitsSnapshot;
 use crate::auth::pam::open_session;
 pub use crate::auth::pam::AuthenticationError;
+use crate::config::Config;
+use crate::error::ErrorCode;
+use crate::network::{is_local_user, NetworkStatus};
 
 pub struct AuthUserInfo<'a> {
     // This is used to keep the user session. If the struct is dropped then the user session is
@@ -13,6 +26,11 @@ pub struct AuthUserInfo<'a> {
     #[allow(dead_code)]
     authenticator: Authenticator<'a, PasswordConv>,
 
+    // The limits of lemurs are restored after the user session is closed, as this is dropped
+    // after the authenticator.
+    #[allow(dead_code)]
+    limits: LimitsSnapshot,
+
     pub username: String,
     pub uid: libc::uid_t,
     pub primary_gid: libc::gid_t,
@@ -21,18 +39,80 @@ pub struct AuthUserInfo<'a> {
     pub shell: String,
 }
 
+/// The moment of the last authentication attempt for any user
+static LAST_ATTEMPT: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+/// Delay the current authentication attempt if it comes in too quickly after the previous one
+fn throttle_attempt(min_interval: Duration) {
+    let mut last_attempt = match LAST_ATTEMPT.lock() {
+        Ok(guard) => guard,
+        Err(err) => {
+            error!("Lock failed. Reason: {}", err);
+            return;
+        }
+    };
+
+    if let Some(remaining) = last_attempt
+        .and_then(|last_attempt| min_interval.checked_sub(last_attempt.elapsed()))
+        .filter(|remaining| !remaining.is_zero())
+    {
+        info!(
+            "Delaying authentication attempt by {}ms",
+            remaining.as_millis()
+        );
+        std::thread::sleep(remaining);
+    }
+
+    *last_attempt = Some(Instant::now());
+}
+
 pub fn try_auth<'a>(
     username: &str,
     password: &str,
-    pam_service: &str,
+    config: &Config,
 ) -> Result<AuthUserInfo<'a>, AuthenticationError> {
     info!("Login attempt for '{username}'");
 
-    open_session(username, password, pam_service).map_err(|err| {
+    if config.auth.min_attempt_interval_ms > 0 {
+        throttle_attempt(Duration::from_millis(
+            config.auth.min_attempt_interval_ms.into(),
+        ));
+    }
+
+    check_access_hours(username, &config.access).map_err(|err| {
+        info!(
+            "Authentication refused for '{}'. Reason: {}",
+            username,
+            err.with_code()
+        );
+        err
+    })?;
+
+    let offline_login = &config.offline_login;
+    let is_network_account = || offline_login.enabled && !is_local_user(username);
+
+    match open_session(username, password, &config.pam_service, &config.auth) {
+        Ok(user_info) => {
+            if is_network_account() {
+                offline::remember(username, password, offline_login);
+            }
+            Ok(user_info)
+        }
+        Err(AuthenticationError::AccountValidation)
+            if is_network_account()
+                && NetworkStatus::current() != NetworkStatus::Connected
+                && offline::verify(username, password, offline_login) =>
+        {
+            info!("Opening an offline session for '{username}'");
+            open_session(username, password, &offline_login.pam_service, &config.auth)
+        }
+        Err(err) => Err(err),
+    }
+    .map_err(|err| {
         info!(
             "Authentication failed for '{}'. Reason: {}",
             username,
-            err.to_string()
+            err.with_code()
         );
         err
     })
@@ -0,0 +1,322 @@
+//! Logins of network accounts while the machine is offline, for when `pam_sss` or a similar module
+//! does not cache credentials itself.
+//!
+//! After a successful login of a network account, a salted SHA-512 `crypt(3)` hash of the password
+//! is stored in a file that only root can read. Without a network connection, a password that
+//! matches the hash allows a limited amount of logins until the hash expires. The session is then
+//! opened through a separate PAM service, as the regular service cannot verify the password.
+//!
+//! The hashes are made with `crypt(3)` of libcrypt, which is only loaded once offline logins are
+//! used, so that lemurs does not depend on it otherwise.
+
+use std::ffi::{CStr, CString, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::os::raw::{c_char, c_void};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use rand::Rng;
+
+use crate::config::OfflineLoginConfig;
+
+const FILE_MODE: u32 = 0o600;
+const DIRECTORY_MODE: u32 = 0o700;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The characters of a `crypt(3)` salt
+const SALT_CHARS: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const SALT_LENGTH: usize = 16;
+
+/// The names under which libcrypt is installed, as a nul-terminated string
+const LIBCRYPT_NAMES: [&[u8]; 3] = [b"libcrypt.so.1\0", b"libcrypt.so.2\0", b"libcrypt.so\0"];
+
+type CryptFn = unsafe extern "C" fn(key: *const c_char, setting: *const c_char) -> *mut c_char;
+
+/// `crypt` of libcrypt, which is loaded the first time that it is used
+static CRYPT: Lazy<Option<CryptFn>> = Lazy::new(|| {
+    for name in LIBCRYPT_NAMES {
+        let handle =
+            unsafe { libc::dlopen(name.as_ptr().cast(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            continue;
+        }
+
+        let symbol = unsafe { libc::dlsym(handle, b"crypt\0".as_ptr().cast()) };
+        if !symbol.is_null() {
+            return Some(unsafe { std::mem::transmute::<*mut c_void, CryptFn>(symbol) });
+        }
+    }
+
+    warn!("Failed to load libcrypt. Offline logins are not available");
+    None
+});
+
+/// `crypt` returns a static buffer
+static CRYPT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Hash `password` with the algorithm and the salt of `setting`
+fn hash_password(password: &str, setting: &str) -> Option<String> {
+    let crypt = (*CRYPT)?;
+    let key = CString::new(password).ok()?;
+    let setting = CString::new(setting).ok()?;
+
+    let _guard = CRYPT_LOCK.lock().ok()?;
+    let hash = unsafe { crypt(key.as_ptr(), setting.as_ptr()) };
+    if hash.is_null() {
+        return None;
+    }
+
+    let hash = unsafe { CStr::from_ptr(hash) }.to_str().ok()?.to_string();
+
+    // Some implementations signal a failure with a hash that starts with '*'
+    (!hash.starts_with('*')).then_some(hash)
+}
+
+/// The setting for a SHA-512 hash with a new random salt
+fn new_setting() -> String {
+    let mut rng = rand::thread_rng();
+    let salt = (0..SALT_LENGTH)
+        .map(|_| char::from(SALT_CHARS[rng.gen_range(0..SALT_CHARS.len())]))
+        .collect::<String>();
+
+    format!("$6${salt}$")
+}
+
+/// Compare without stopping at the first difference, so that the time does not tell how much of the
+/// hash matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn now_as_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// The hash of a user. Every entry is a line in the form `USERNAME:HASH:STORED_AT:REMAINING`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    username: String,
+    hash: String,
+    /// The seconds since the epoch at which the hash was stored
+    stored_at: u64,
+    /// The amount of offline logins that are left
+    remaining: u16,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split(':');
+        let entry = Self {
+            username: fields.next()?.to_string(),
+            hash: fields.next()?.to_string(),
+            stored_at: fields.next()?.parse().ok()?,
+            remaining: fields.next()?.parse().ok()?,
+        };
+
+        fields.next().is_none().then_some(entry)
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}:{}:{}:{}\n",
+            self.username, self.hash, self.stored_at, self.remaining
+        )
+    }
+
+    fn is_expired(&self, now: u64, config: &OfflineLoginConfig) -> bool {
+        now.saturating_sub(self.stored_at) >= u64::from(config.expiry_days) * SECONDS_PER_DAY
+    }
+}
+
+fn read_entries(path: &Path) -> Vec<Entry> {
+    match fs::read_to_string(path) {
+        Ok(content) => content.lines().filter_map(Entry::parse).collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            warn!("Failed to read the offline login hashes. Reason: {err}");
+            Vec::new()
+        }
+    }
+}
+
+/// Serialize the changes to the file at `path` between instances of lemurs on different ttys. The
+/// lock is a file next to it, with `.lock` appended, and is released when the returned file is
+/// dropped.
+fn lock_entries(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(DIRECTORY_MODE)
+            .create(parent)?;
+    }
+
+    let mut lock_path = OsString::from(path);
+    lock_path.push(".lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .mode(FILE_MODE)
+        .open(PathBuf::from(lock_path))?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(file)
+}
+
+/// Replace the file at `path` with root-only permissions, so that a crash cannot leave it half
+/// written. The file has to be locked with [`lock_entries`].
+fn write_entries(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    let temporary_path = path.with_extension("new");
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(FILE_MODE)
+        .open(&temporary_path)?;
+
+    let content = entries.iter().map(Entry::to_line).collect::<String>();
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+
+    fs::rename(&temporary_path, path)
+}
+
+/// Store the hash of `password` after a successful login of the network account `username`
+pub fn remember(username: &str, password: &str, config: &OfflineLoginConfig) {
+    // The username is a field in a line of the file
+    if username.contains([':', '\n']) || password.is_empty() {
+        return;
+    }
+
+    let Some(hash) = hash_password(password, &new_setting()) else {
+        warn!("Failed to hash the password for offline logins");
+        return;
+    };
+
+    let path = Path::new(&config.cache_path);
+    let now = now_as_secs();
+
+    let _lock = match lock_entries(path) {
+        Ok(lock) => lock,
+        Err(err) => {
+            warn!("Failed to lock the offline login hashes. Reason: {err}");
+            return;
+        }
+    };
+
+    let mut entries = read_entries(path);
+    entries.retain(|entry| entry.username != username && !entry.is_expired(now, config));
+    entries.push(Entry {
+        username: username.to_string(),
+        hash,
+        stored_at: now,
+        remaining: config.max_logins,
+    });
+
+    match write_entries(path, &entries) {
+        Ok(()) => info!("Stored the password hash of '{username}' for offline logins"),
+        Err(err) => warn!("Failed to store the password hash for offline logins. Reason: {err}"),
+    }
+}
+
+/// Check `password` against the stored hash of `username`. Every successful check uses up one of
+/// the offline logins of the user.
+pub fn verify(username: &str, password: &str, config: &OfflineLoginConfig) -> bool {
+    let path = Path::new(&config.cache_path);
+    let now = now_as_secs();
+
+    // The count of the logins is read and written back under the lock, so that another instance
+    // cannot use the same login
+    let _lock = match lock_entries(path) {
+        Ok(lock) => lock,
+        Err(err) => {
+            warn!("Failed to lock the offline login hashes. Reason: {err}");
+            return false;
+        }
+    };
+
+    let mut entries = read_entries(path);
+    let Some(index) = entries.iter().position(|entry| entry.username == username) else {
+        info!("No offline login is available for '{username}'");
+        return false;
+    };
+
+    let entry = &mut entries[index];
+    if entry.is_expired(now, config) || entry.remaining == 0 {
+        info!("The offline logins of '{username}' have expired or are used up");
+        return false;
+    }
+
+    let matches = hash_password(password, &entry.hash)
+        .is_some_and(|hash| constant_time_eq(hash.as_bytes(), entry.hash.as_bytes()));
+    if !matches {
+        info!("The password of '{username}' does not match its offline login hash");
+        return false;
+    }
+
+    entry.remaining -= 1;
+    info!(
+        "Allowing an offline login for '{username}'. {} left",
+        entry.remaining
+    );
+
+    if let Err(err) = write_entries(path, &entries) {
+        // Without storing the count, the logins would not run out
+        warn!("Failed to update the offline logins. Reason: {err}");
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_roundtrip() {
+        let entry = Entry {
+            username: "lemur".to_string(),
+            hash: "$6$abcdefgh$hash".to_string(),
+            stored_at: 42,
+            remaining: 3,
+        };
+
+        assert_eq!(Entry::parse(entry.to_line().trim_end()), Some(entry));
+        assert_eq!(Entry::parse("lemur:$6$abcdefgh$hash:42"), None);
+        assert_eq!(Entry::parse("lemur:$6$abcdefgh$hash:42:3:extra"), None);
+    }
+
+    #[test]
+    fn logins_run_out() {
+        if CRYPT.is_none() {
+            return;
+        }
+
+        let directory = std::env::temp_dir().join(format!("lemurs-offline-{}", std::process::id()));
+        let mut config = crate::config::Config::default().offline_login;
+        config.cache_path = directory.join("hashes").to_string_lossy().into_owned();
+        config.max_logins = 2;
+
+        remember("lemur", "hunter2", &config);
+        let results = [
+            verify("lemur", "wrong", &config),
+            verify("lemur", "hunter2", &config),
+            verify("lemur", "hunter2", &config),
+            verify("lemur", "hunter2", &config),
+        ];
+        let _ = fs::remove_dir_all(&directory);
+        assert_eq!(results, [false, true, true, false]);
+    }
+}
@@ -1,39 +1,96 @@
 use log::info;
 
 use pam::Authenticator;
+use thiserror::Error;
 use uzers::os::unix::UserExt;
 
+use crate::auth::limits::{verify_limits, LimitsSnapshot};
+use crate::auth::policy::{check_login_policy, group_names};
 use crate::auth::AuthUserInfo;
+use crate::config::AuthConfig;
+use crate::error::ErrorCode;
 
 /// All the different errors that can occur during PAM opening an authenticated session
-#[derive(Clone)]
+#[derive(Debug, Clone, Error)]
 pub enum AuthenticationError {
+    #[error("Failed to create authenticator with PAM service '{0}'")]
     PamService(String),
+    #[error("Invalid login credentials")]
     AccountValidation,
+    #[error("User home directory path contains invalid UTF-8")]
     HomeDirInvalidUtf8,
+    #[error("User shell path contains invalid UTF-8")]
     ShellInvalidUtf8,
+    #[error(
+        "Login creditionals are valid, but username is not found. This should not be possible :("
+    )]
     UsernameNotFound,
+    #[error("Failed to open a PAM session")]
     SessionOpen,
+    #[error("Account has expired")]
+    AccountExpired,
+    #[error("Password has expired and needs to be changed")]
+    PasswordExpired,
+    #[error("Access to the account was denied")]
+    AccessDenied,
+    #[error("Account is locked because of too many failed attempts")]
+    AccountLocked,
+    #[error("User is not allowed to log in because of its group membership")]
+    GroupDenied,
+    #[error("Logging in as root is not allowed")]
+    RootDenied,
+    /// When logins resume, if they do
+    #[error("Logging in is not allowed at this time")]
+    OutsideAccessHours(Option<String>),
 }
 
-impl ToString for AuthenticationError {
-    fn to_string(&self) -> String {
+impl ErrorCode for AuthenticationError {
+    fn code(&self) -> &'static str {
         match self {
-            AuthenticationError::PamService(service) => format!("Failed to create authenticator with PAM service '{service}'"),
-            AuthenticationError::AccountValidation => "Invalid login credentials".to_string(),
-            AuthenticationError::HomeDirInvalidUtf8 => "User home directory path contains invalid UTF-8".to_string(),
-            AuthenticationError::ShellInvalidUtf8 => "User shell path contains invalid UTF-8".to_string(),
-            AuthenticationError::UsernameNotFound => "Login creditionals are valid, but username is not found. This should not be possible :(".to_string(),
-            AuthenticationError::SessionOpen => "Failed to open a PAM session".to_string(),
+            Self::PamService(_) => "AUTH-01",
+            Self::AccountValidation => "AUTH-02",
+            Self::HomeDirInvalidUtf8 => "AUTH-03",
+            Self::ShellInvalidUtf8 => "AUTH-04",
+            Self::UsernameNotFound => "AUTH-05",
+            Self::SessionOpen => "AUTH-06",
+            Self::AccountExpired => "AUTH-07",
+            Self::PasswordExpired => "AUTH-08",
+            Self::AccessDenied => "AUTH-09",
+            Self::AccountLocked => "AUTH-10",
+            Self::GroupDenied => "AUTH-11",
+            Self::RootDenied => "AUTH-12",
+            Self::OutsideAccessHours(_) => "AUTH-13",
         }
     }
 }
 
+/// Map a PAM error from authentication or account management to an `AuthenticationError`
+///
+/// The `pam` crate does not expose the return code of a failed call, so it is recovered from the
+/// debug representation of the error.
+fn authentication_error_from_pam(err: &pam::PamError) -> AuthenticationError {
+    let err = format!("{err:?}");
+
+    if err.contains("ACCT_EXPIRED") {
+        AuthenticationError::AccountExpired
+    } else if err.contains("NEW_AUTHTOK_REQD") || err.contains("AUTHTOK_EXPIRED") {
+        AuthenticationError::PasswordExpired
+    } else if err.contains("PERM_DENIED") {
+        // This is returned by, for example, `pam_access` and `pam_time`
+        AuthenticationError::AccessDenied
+    } else if err.contains("MAXTRIES") {
+        AuthenticationError::AccountLocked
+    } else {
+        AuthenticationError::AccountValidation
+    }
+}
+
 /// Open a PAM authenticated session
 pub fn open_session<'a>(
     username: &str,
     password: &str,
     pam_service: &str,
+    policy: &AuthConfig,
 ) -> Result<AuthUserInfo<'a>, AuthenticationError> {
     info!("Started opening session");
 
@@ -52,7 +109,7 @@ pub fn open_session<'a>(
     // Validate the account
     authenticator
         .authenticate()
-        .map_err(|_| AuthenticationError::AccountValidation)?;
+        .map_err(|err| authentication_error_from_pam(&err))?;
 
     info!("Validated account");
 
@@ -74,15 +131,25 @@ pub fn open_session<'a>(
         .ok_or(AuthenticationError::ShellInvalidUtf8)?
         .to_string();
 
+    check_login_policy(username, uid, primary_gid, &all_gids, policy)?;
+
+    // `pam_limits` changes the limits of lemurs itself
+    let limits = LimitsSnapshot::take();
+
     authenticator
         .open_session()
         .map_err(|_| AuthenticationError::SessionOpen)?;
 
     info!("Opened session");
 
+    if policy.verify_limits {
+        verify_limits(username, &group_names(primary_gid, &all_gids));
+    }
+
     // NOTE: Logout happens automatically here with `drop` of authenticator
     Ok(AuthUserInfo {
         authenticator,
+        limits,
 
         username: username.to_string(),
         uid,
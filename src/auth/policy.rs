@@ -0,0 +1,61 @@
+//! Policies on which users are allowed to log in through lemurs.
+//!
+//! These are checked after the user has been authenticated, but before the PAM session is opened.
+
+use log::{info, warn};
+
+use crate::auth::AuthenticationError;
+use crate::config::AuthConfig;
+
+pub(super) fn group_names(primary_gid: libc::gid_t, all_gids: &[libc::gid_t]) -> Vec<String> {
+    std::iter::once(&primary_gid)
+        .chain(all_gids)
+        .filter_map(|gid| {
+            let group = uzers::get_group_by_gid(*gid);
+            if group.is_none() {
+                warn!("Failed to find the name of group '{gid}'");
+            }
+            group
+        })
+        .filter_map(|group| group.name().to_str().map(str::to_string))
+        .collect()
+}
+
+/// Check whether the user is allowed to log in according to the configured policies
+pub fn check_login_policy(
+    username: &str,
+    uid: libc::uid_t,
+    primary_gid: libc::gid_t,
+    all_gids: &[libc::gid_t],
+    policy: &AuthConfig,
+) -> Result<(), AuthenticationError> {
+    if uid == 0 && !policy.allow_root {
+        info!("Denied login for '{username}' because root logins are not allowed");
+        return Err(AuthenticationError::RootDenied);
+    }
+
+    if policy.allow_groups.is_empty() && policy.deny_groups.is_empty() {
+        return Ok(());
+    }
+
+    let groups = group_names(primary_gid, all_gids);
+
+    if let Some(group) = groups
+        .iter()
+        .find(|group| policy.deny_groups.contains(group))
+    {
+        info!("Denied login for '{username}' because it is a member of the denied group '{group}'");
+        return Err(AuthenticationError::GroupDenied);
+    }
+
+    if !policy.allow_groups.is_empty()
+        && !groups
+            .iter()
+            .any(|group| policy.allow_groups.contains(group))
+    {
+        info!("Denied login for '{username}' because it is not a member of any allowed group");
+        return Err(AuthenticationError::GroupDenied);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! Placement of lemurs and its sessions in the cgroup (v2) hierarchy of systemd.
+//!
+//! With logind (`pam_systemd` or `pam_elogind`), opening the PAM session places the calling
+//! process, which is lemurs itself, in `session-N.scope` within `user-UID.slice`. The session
+//! inherits this, but lemurs has to move back to its own cgroup once the session has ended.
+//!
+//! Without logind, nothing else manages a cgroup for the session. A cgroup is then created for it
+//! below the cgroup of lemurs itself, as the rest of the hierarchy belongs to the service manager.
+//! With systemd, this needs `Delegate=yes` in the unit of lemurs.
+
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::{info, warn};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const PROC_SELF_CGROUP: &str = "/proc/self/cgroup";
+
+/// The cgroup of the lemurs process, relative to the root of the hierarchy
+pub fn current() -> Option<PathBuf> {
+    let content = fs::read_to_string(PROC_SELF_CGROUP).ok()?;
+
+    // Only the unified hierarchy has an entry with the id 0 and no controllers
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| PathBuf::from(path.trim_start_matches('/')))
+}
+
+/// Move the lemurs process into `cgroup`, relative to the root of the hierarchy
+pub fn move_self_to(cgroup: &Path) -> io::Result<()> {
+    fs::write(
+        Path::new(CGROUP_ROOT).join(cgroup).join("cgroup.procs"),
+        std::process::id().to_string(),
+    )
+}
+
+/// The processes in `cgroup`, relative to the root of the hierarchy, and in the cgroups below it
+pub fn processes(cgroup: &Path) -> io::Result<Vec<u32>> {
+    fn collect(path: &Path, pids: &mut Vec<u32>) -> io::Result<()> {
+        let procs = fs::read_to_string(path.join("cgroup.procs"))?;
+        pids.extend(
+            procs
+                .lines()
+                .filter_map(|line| line.trim().parse::<u32>().ok()),
+        );
+
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                collect(&entry.path(), pids)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    let mut pids = Vec::new();
+    collect(&Path::new(CGROUP_ROOT).join(cgroup), &mut pids)?;
+    Ok(pids)
+}
+
+/// The cgroup that lemurs was in before a session was started. Lemurs moves back into it when
+/// this is dropped.
+pub struct GreeterCgroup(PathBuf);
+
+impl GreeterCgroup {
+    pub fn remember() -> Option<Self> {
+        current().map(Self)
+    }
+}
+
+impl Drop for GreeterCgroup {
+    fn drop(&mut self) {
+        let Self(cgroup) = self;
+
+        if current().as_ref() == Some(cgroup) {
+            return;
+        }
+
+        info!("Moving lemurs back into cgroup '{}'", cgroup.display());
+        if let Err(err) = move_self_to(cgroup) {
+            warn!(
+                "Failed to move lemurs back into cgroup '{}'. Reason: {err}",
+                cgroup.display()
+            );
+        }
+    }
+}
+
+/// A cgroup for a session that was not registered with logind. The cgroup is removed when it is
+/// dropped.
+pub struct SessionScope {
+    /// Relative to the root of the hierarchy
+    cgroup: PathBuf,
+    path: PathBuf,
+}
+
+impl SessionScope {
+    /// Create the cgroup for a session on `tty` below the cgroup of lemurs
+    pub fn create(tty: u8) -> io::Result<Self> {
+        let Some(own_cgroup) = current() else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "lemurs is not in the unified (v2) cgroup hierarchy",
+            ));
+        };
+
+        let cgroup = own_cgroup.join(format!("session-tty{tty}"));
+        let path = Path::new(CGROUP_ROOT).join(&cgroup);
+
+        fs::create_dir_all(&path)?;
+        info!("Created cgroup '{}' for the session", path.display());
+
+        Ok(Self { cgroup, path })
+    }
+
+    /// The cgroup relative to the root of the hierarchy
+    pub fn cgroup(&self) -> &Path {
+        &self.cgroup
+    }
+
+    /// Move the process that is spawned from `command` into the scope. This has to be added
+    /// before the permissions of the command are lowered to the user.
+    pub fn add_to_command(&self, command: &mut Command) {
+        let procs = self.path.join("cgroup.procs");
+        let Ok(procs) = CString::new(procs.as_os_str().as_bytes()) else {
+            return;
+        };
+
+        unsafe {
+            command.pre_exec(move || {
+                // Only async-signal-safe functions can be used after forking. Writing 0 moves the
+                // process that writes.
+                let fd = libc::open(procs.as_ptr(), libc::O_WRONLY);
+                if fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+                libc::close(fd);
+
+                if written < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+    }
+}
+
+impl Drop for SessionScope {
+    fn drop(&mut self) {
+        // This fails if processes of the session are still running in the scope
+        if let Err(err) = fs::remove_dir(&self.path) {
+            warn!(
+                "Failed to remove cgroup '{}'. Reason: {err}",
+                self.path.display()
+            );
+        }
+    }
+}
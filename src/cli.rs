@@ -1,8 +1,11 @@
 use std::env::args;
-use std::error::Error;
-use std::fmt::Display;
 use std::path::PathBuf;
 
+use thiserror::Error;
+
+use crate::error::ErrorCode;
+use crate::ui::screenshot::Format;
+
 pub fn usage() {
     print!(
         r###"Lemurs {}
@@ -16,13 +19,25 @@ OPTIONS:
     -v, --variables <FILE> A file to replace the set variables
     -h, --help             Print help information
         --no-log
+        --json             Print errors as JSON objects with their error code
         --preview
+        --size <WxH>       With `--preview` or `render`, render to a fixed size (e.g. 80x24)
+        --format <FORMAT>  With `render`, the output format: ansi (default), html or svg
+        --windowed         Run inside of an existing (minimal) compositor instead of on a TTY
+        --force            Clear inherited session variables instead of refusing to start
         --tty <N>          Override the configured TTY number
+        --explain <NAME>   With `envs`, print how an environment would be started
+        --user <USERNAME>  The user for `--explain` (defaults to the cached username)
     -V, --version          Print version information
 
 SUBCOMMANDS:
     cache
-    envs
+    envs     List the available environments
+    users    List the users that are shown in the UI
+    render   Print the login screen without a terminal (80x24 unless `--size` is given)
+    test-session <NAME>
+             Run an environment as the current user within the current graphical
+             session. X11 environments are run in Xephyr
     help     Print this message or the help of the given subcommand(s)
 "###,
         env!("CARGO_PKG_VERSION"),
@@ -32,53 +47,82 @@ SUBCOMMANDS:
 
 pub struct Cli {
     pub preview: bool,
+    pub windowed: bool,
+    pub size: Option<(u16, u16)>,
+    pub format: Option<Format>,
+    pub force: bool,
     pub no_log: bool,
+    pub json: bool,
     pub tty: Option<u8>,
     pub config: Option<PathBuf>,
     pub variables: Option<PathBuf>,
+    pub explain: Option<String>,
+    pub user: Option<String>,
     pub command: Option<Commands>,
 }
 
 pub enum Commands {
     Envs,
+    TestSession(String),
+    Users,
     Cache,
+    Render,
     Help,
     Version,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum CliError {
+    #[error("Missing an argument for the given flag '{0}'")]
     MissingArgument(&'static str),
+    #[error("Given an invalid TTY number (only 1-12 are allowed)")]
     InvalidTTY,
+    #[error("Given an invalid flag or command '{0}'")]
     InvalidArgument(String),
+    #[error("Given an invalid size (expected WIDTHxHEIGHT, e.g. 80x24)")]
+    InvalidSize,
+    #[error("The `--size` flag can only be used together with `--preview` or `render`")]
+    SizeWithoutPreview,
+    #[error("Given an invalid format '{0}' (expected ansi, html or svg)")]
+    InvalidFormat(String),
+    #[error("The `--format` flag can only be used together with `render`")]
+    FormatWithoutRender,
 }
 
-impl Display for CliError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ErrorCode for CliError {
+    fn code(&self) -> &'static str {
         match self {
-            CliError::MissingArgument(flag) => {
-                write!(f, "Missing an argument for the given flag '{flag}'")
-            }
-            CliError::InvalidTTY => {
-                write!(f, "Given an invalid TTY number (only 1-12 are allowed)")
-            }
-            CliError::InvalidArgument(arg) => {
-                write!(f, "Given an invalid flag or command '{arg}'")
-            }
+            Self::MissingArgument(_) => "CLI-01",
+            Self::InvalidTTY => "CLI-02",
+            Self::InvalidArgument(_) => "CLI-03",
+            Self::InvalidSize => "CLI-04",
+            Self::SizeWithoutPreview => "CLI-05",
+            Self::InvalidFormat(_) => "CLI-06",
+            Self::FormatWithoutRender => "CLI-07",
         }
     }
 }
 
-impl Error for CliError {}
+/// Whether errors should be printed as JSON. This is also known when the arguments cannot be parsed.
+pub fn json_requested() -> bool {
+    args().skip(1).any(|arg| arg.trim() == "--json")
+}
 
 impl Cli {
     pub fn parse() -> Result<Self, CliError> {
         let mut cli = Cli {
             preview: false,
+            windowed: false,
+            size: None,
+            format: None,
+            force: false,
             no_log: false,
+            json: false,
             tty: None,
             config: None,
             variables: None,
+            explain: None,
+            user: None,
             command: None,
         };
 
@@ -86,12 +130,23 @@ impl Cli {
         while let Some((i, arg)) = args.next() {
             match (i, arg.trim()) {
                 (0, "envs") => cli.command = Some(Commands::Envs),
+                (0, "users") => cli.command = Some(Commands::Users),
                 (0, "cache") => cli.command = Some(Commands::Cache),
+                (0, "render") => cli.command = Some(Commands::Render),
+                (0, "test-session") => {
+                    let (_, arg) = args
+                        .next()
+                        .ok_or(CliError::MissingArgument("test-session"))?;
+                    cli.command = Some(Commands::TestSession(arg));
+                }
                 (0, "help") | (_, "--help") | (_, "-h") => cli.command = Some(Commands::Help),
                 (_, "--version") | (_, "-V") => cli.command = Some(Commands::Version),
 
                 (_, "--preview") => cli.preview = true,
+                (_, "--windowed") => cli.windowed = true,
+                (_, "--force") => cli.force = true,
                 (_, "--no-log") => cli.no_log = true,
+                (_, "--json") => cli.json = true,
                 (_, "--tty") => {
                     let (_, arg) = args.next().ok_or(CliError::MissingArgument("tty"))?;
                     let arg = arg.parse().map_err(|_| CliError::InvalidTTY)?;
@@ -102,6 +157,31 @@ impl Cli {
 
                     cli.tty = Some(arg);
                 }
+                (_, "--size") => {
+                    let (_, arg) = args.next().ok_or(CliError::MissingArgument("size"))?;
+                    let (width, height) = arg.split_once('x').ok_or(CliError::InvalidSize)?;
+                    let width = width.parse().map_err(|_| CliError::InvalidSize)?;
+                    let height = height.parse().map_err(|_| CliError::InvalidSize)?;
+
+                    if width == 0 || height == 0 {
+                        return Err(CliError::InvalidSize);
+                    }
+
+                    cli.size = Some((width, height));
+                }
+                (_, "--format") => {
+                    let (_, arg) = args.next().ok_or(CliError::MissingArgument("format"))?;
+                    let format = Format::from_name(&arg).ok_or(CliError::InvalidFormat(arg))?;
+                    cli.format = Some(format);
+                }
+                (_, "--explain") => {
+                    let (_, arg) = args.next().ok_or(CliError::MissingArgument("explain"))?;
+                    cli.explain = Some(arg);
+                }
+                (_, "--user") => {
+                    let (_, arg) = args.next().ok_or(CliError::MissingArgument("user"))?;
+                    cli.user = Some(arg);
+                }
                 (_, "--config") | (_, "-c") => {
                     let (_, arg) = args.next().ok_or(CliError::MissingArgument("config"))?;
                     let arg = PathBuf::from(arg);
@@ -116,6 +196,14 @@ impl Cli {
             }
         }
 
+        let render = matches!(cli.command, Some(Commands::Render));
+        if cli.size.is_some() && !cli.preview && !render {
+            return Err(CliError::SizeWithoutPreview);
+        }
+        if cli.format.is_some() && !render {
+            return Err(CliError::FormatWithoutRender);
+        }
+
         Ok(cli)
     }
 }
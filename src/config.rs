@@ -1,6 +1,9 @@
+use crossterm::cursor::SetCursorStyle;
 use crossterm::event::KeyCode;
-use log::error;
+use log::{error, warn};
+use once_cell::sync::Lazy;
 use serde::{de::Error, Deserialize};
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::fs::File;
 use std::io::Read;
@@ -10,6 +13,8 @@ use toml::Value;
 
 use ratatui::style::{Color, Modifier};
 
+use crate::default_config::{self, DefaultValue};
+
 #[derive(Debug)]
 pub struct VarError {
     variable: String,
@@ -28,6 +33,15 @@ impl Display for VarError {
     }
 }
 
+/// Get a background color. An empty string leaves the background unchanged.
+pub fn get_bg_color(color: &str) -> Option<Color> {
+    if color.is_empty() {
+        return None;
+    }
+
+    Some(get_color(color))
+}
+
 pub fn get_color(color: &str) -> Color {
     if let Some(color) = str_to_color(color) {
         color
@@ -110,6 +124,29 @@ pub fn get_modifiers(modifiers: &str) -> Vec<Modifier> {
     ms
 }
 
+/// Parse an octal umask, such as "022"
+pub fn get_umask(umask: &str) -> Option<libc::mode_t> {
+    libc::mode_t::from_str_radix(umask.trim(), 8)
+        .ok()
+        .filter(|umask| *umask <= 0o777)
+}
+
+/// Parse a resource limit into its soft and hard limit. The limit is either a single value for
+/// both, or "soft:hard". A value is a number or "unlimited".
+pub fn get_rlimit(limit: &str) -> Option<(libc::rlim_t, libc::rlim_t)> {
+    let parse = |value: &str| match value.trim() {
+        "unlimited" | "infinity" => Some(libc::RLIM_INFINITY),
+        value => value.parse().ok(),
+    };
+
+    let (soft, hard) = match limit.split_once(':') {
+        Some((soft, hard)) => (parse(soft)?, parse(hard)?),
+        None => (parse(limit)?, parse(limit)?),
+    };
+
+    (soft <= hard).then_some((soft, hard))
+}
+
 pub fn get_function_key(key: &str) -> Option<KeyCode> {
     Some(match key.trim() {
         "F1" => KeyCode::F(1),
@@ -213,9 +250,13 @@ toml_config_struct! { Config, PartialConfig, RoughConfig,
 
     main_log_path => String,
     client_log_path => String,
+    client_log_append => bool,
+    client_log_user_owned => bool,
     cache_path => String,
 
     do_log => bool,
+    clear_inherited_session => bool,
+    strict_config => bool,
 
     pam_service => String,
     system_shell => String,
@@ -223,16 +264,255 @@ toml_config_struct! { Config, PartialConfig, RoughConfig,
     shell_login_flag => ShellLoginFlag,
 
     focus_behaviour => FocusBehaviour,
+    focus_on_typing => bool,
+    field_order => Vec<String>,
+
+    cache => CacheConfig [PartialCacheConfig, RoughCacheConfig],
+    failed_attempts => FailedAttemptsConfig [PartialFailedAttemptsConfig, RoughFailedAttemptsConfig],
+    metrics => MetricsConfig [PartialMetricsConfig, RoughMetricsConfig],
+    console_font => ConsoleFontConfig [PartialConsoleFontConfig, RoughConsoleFontConfig],
+    greeter_env => StringMap [PartialStringMap, RoughStringMap],
+
+    session_hooks => SessionHookVec [PartialSessionHookVec, RoughSessionHookVec],
+    command_widgets => CommandWidgetVec [PartialCommandWidgetVec, RoughCommandWidgetVec],
+    event_scripts => EventScriptsConfig [PartialEventScriptsConfig, RoughEventScriptsConfig],
+    notifications => NotificationsConfig [PartialNotificationsConfig, RoughNotificationsConfig],
+    network => NetworkConfig [PartialNetworkConfig, RoughNetworkConfig],
+    session => SessionConfig [PartialSessionConfig, RoughSessionConfig],
+    hidpi => HiDpiConfig [PartialHiDpiConfig, RoughHiDpiConfig],
+    display_layout => DisplayLayoutConfig [PartialDisplayLayoutConfig, RoughDisplayLayoutConfig],
+    recovery_shell => RecoveryShellConfig [PartialRecoveryShellConfig, RoughRecoveryShellConfig],
+    duress => DuressConfig [PartialDuressConfig, RoughDuressConfig],
+    touch => TouchConfig [PartialTouchConfig, RoughTouchConfig],
+    priority => PriorityConfig [PartialPriorityConfig, RoughPriorityConfig],
+    auth => AuthConfig [PartialAuthConfig, RoughAuthConfig],
+    access => AccessConfig [PartialAccessConfig, RoughAccessConfig],
+    offline_login => OfflineLoginConfig [PartialOfflineLoginConfig, RoughOfflineLoginConfig],
+    users => UsersConfig [PartialUsersConfig, RoughUsersConfig],
 
     background => BackgroundConfig [PartialBackgroundConfig, RoughBackgroundConfig],
+    theme => ThemeConfig [PartialThemeConfig, RoughThemeConfig],
+    layout => LayoutConfig [PartialLayoutConfig, RoughLayoutConfig],
 
     power_controls => PowerControlConfig [PartialPowerControlConfig, RoughPowerControlConfig],
+    status_message => StatusMessageConfig [PartialStatusMessageConfig, RoughStatusMessageConfig],
+    cursor => CursorConfig [PartialCursorConfig, RoughCursorConfig],
     environment_switcher => SwitcherConfig [PartialSwitcherConfig, RoughSwitcherConfig],
     username_field => UsernameFieldConfig [PartialUsernameFieldConfig, RoughUsernameFieldConfig],
     password_field => PasswordFieldConfig [PartialPasswordFieldConfig, RoughPasswordFieldConfig],
+    domain_field => DomainFieldConfig [PartialDomainFieldConfig, RoughDomainFieldConfig],
 
     x11 => X11Config [PartialX11Config, RoughX11Config],
     wayland => WaylandConfig [PartialWaylandConfig, RoughWaylandConfig],
+    tty_session => TtySessionConfig [PartialTtySessionConfig, RoughTtySessionConfig],
+}
+
+toml_config_struct! { CacheConfig, PartialCacheConfig, RoughCacheConfig,
+    enabled => bool,
+}
+
+toml_config_struct! { SessionConfig, PartialSessionConfig, RoughSessionConfig,
+    use_login_shell => bool,
+    desktop_names => StringMap [PartialStringMap, RoughStringMap],
+
+    show_summary => bool,
+    summary_timeout_secs => u16,
+    manage_cgroups => bool,
+    umask => String,
+    core_limit => String,
+    nofile_limit => String,
+    remove_env => Vec<String>,
+    user_env_file => String,
+    load_locale => bool,
+
+    hang_check => String,
+    hang_check_interval_secs => u16,
+    hang_timeout_secs => u16,
+
+    max_duration_mins => u32,
+    timeout_signal => String,
+    timeout_warning_mins => u32,
+    timeout_warning_message => String,
+    timeout_warning_cmd => String,
+}
+
+toml_config_struct! { HiDpiConfig, PartialHiDpiConfig, RoughHiDpiConfig,
+    enabled => bool,
+
+    toggle_key => String,
+    toggle_hint => String,
+    toggle_hint_color => String,
+    toggle_hint_modifiers => String,
+
+    gdk_scale => u8,
+    gdk_dpi_scale => String,
+    qt_scale_factor => String,
+}
+
+toml_config_struct! { DisplayLayoutConfig, PartialDisplayLayoutConfig, RoughDisplayLayoutConfig,
+    default => String,
+
+    toggle_key => String,
+    toggle_hint => String,
+    toggle_hint_color => String,
+    toggle_hint_modifiers => String,
+
+    layouts => DisplayLayoutVec [PartialDisplayLayoutVec, RoughDisplayLayoutVec],
+}
+
+impl DisplayLayoutConfig {
+    /// The layout with the name `name`
+    pub fn layout(&self, name: &str) -> Option<&DisplayLayout> {
+        self.layouts.0.iter().find(|layout| layout.name == name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct DisplayLayoutVec(pub Vec<DisplayLayout>);
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct PartialDisplayLayoutVec(pub Vec<PartialDisplayLayout>);
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+struct RoughDisplayLayoutVec(pub Vec<RoughDisplayLayout>);
+
+toml_config_struct! { DisplayLayout, PartialDisplayLayout, RoughDisplayLayout,
+    name => String,
+    x11_cmd => String,
+    wayland_cmd => String,
+}
+
+impl Default for DisplayLayout {
+    fn default() -> Self {
+        DisplayLayout {
+            name: "".to_string(),
+            x11_cmd: "".to_string(),
+            wayland_cmd: "".to_string(),
+        }
+    }
+}
+
+toml_config_struct! { EventScriptsConfig, PartialEventScriptsConfig, RoughEventScriptsConfig,
+    path => String,
+    timeout_secs => u32,
+}
+
+toml_config_struct! { NotificationsConfig, PartialNotificationsConfig, RoughNotificationsConfig,
+    socket_path => String,
+    default_timeout_secs => u32,
+}
+
+toml_config_struct! { DuressConfig, PartialDuressConfig, RoughDuressConfig,
+    sequence => Vec<String>,
+    command => String,
+}
+
+toml_config_struct! { TouchConfig, PartialTouchConfig, RoughTouchConfig,
+    enabled => bool,
+    device => String,
+
+    pin_pad => bool,
+    pin_pad_color => String,
+    pin_pad_modifiers => String,
+}
+
+toml_config_struct! { NetworkConfig, PartialNetworkConfig, RoughNetworkConfig,
+    show_indicator => bool,
+    check_interval_secs => u16,
+
+    connected_hint => String,
+    no_address_hint => String,
+    offline_hint => String,
+    hint_color => String,
+    offline_hint_color => String,
+    hint_modifiers => String,
+
+    warn_offline => bool,
+}
+
+toml_config_struct! { RecoveryShellConfig, PartialRecoveryShellConfig, RoughRecoveryShellConfig,
+    key => String,
+    hint => String,
+    hint_color => String,
+    hint_modifiers => String,
+}
+
+toml_config_struct! { OfflineLoginConfig, PartialOfflineLoginConfig, RoughOfflineLoginConfig,
+    enabled => bool,
+    cache_path => String,
+    pam_service => String,
+    max_logins => u16,
+    expiry_days => u16,
+}
+
+toml_config_struct! { AccessConfig, PartialAccessConfig, RoughAccessConfig,
+    rules => AccessRuleVec [PartialAccessRuleVec, RoughAccessRuleVec],
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct AccessRuleVec(pub Vec<AccessRule>);
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct PartialAccessRuleVec(pub Vec<PartialAccessRule>);
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+struct RoughAccessRuleVec(pub Vec<RoughAccessRule>);
+
+toml_config_struct! { AccessRule, PartialAccessRule, RoughAccessRule,
+    users => Vec<String>,
+    groups => Vec<String>,
+    days => Vec<String>,
+    hours => String,
+}
+
+impl Default for AccessRule {
+    fn default() -> Self {
+        AccessRule {
+            users: Vec::new(),
+            groups: Vec::new(),
+            days: Vec::new(),
+            hours: "00:00-24:00".to_string(),
+        }
+    }
+}
+
+toml_config_struct! { AuthConfig, PartialAuthConfig, RoughAuthConfig,
+    allow_root => bool,
+    min_attempt_interval_ms => u16,
+    allow_groups => Vec<String>,
+    deny_groups => Vec<String>,
+    verify_limits => bool,
+}
+
+toml_config_struct! { UsersConfig, PartialUsersConfig, RoughUsersConfig,
+    min_uid => u32,
+    max_uid => u32,
+    hide => Vec<String>,
+    shells => Vec<String>,
+}
+
+toml_config_struct! { FailedAttemptsConfig, PartialFailedAttemptsConfig, RoughFailedAttemptsConfig,
+    show_counter => bool,
+    lockout_threshold => u16,
+    lockout_secs => u16,
+}
+
+toml_config_struct! { ConsoleFontConfig, PartialConsoleFontConfig, RoughConsoleFontConfig,
+    font => String,
+    setfont_path => String,
+}
+
+toml_config_struct! { MetricsConfig, PartialMetricsConfig, RoughMetricsConfig,
+    enabled => bool,
+    textfile_path => String,
 }
 
 toml_config_struct! { BackgroundStyleConfig, PartialBackgroundStyleConfig, RoughBackgroundStyleConfig,
@@ -246,8 +526,47 @@ toml_config_struct! { BackgroundConfig, PartialBackgroundConfig, RoughBackground
     style => BackgroundStyleConfig [PartialBackgroundStyleConfig, RoughBackgroundStyleConfig],
 }
 
+toml_config_struct! { LayoutConfig, PartialLayoutConfig, RoughLayoutConfig,
+    max_width => u16,
+    horizontal_position => u8,
+    vertical_position => u8,
+    vertical_offset => u16,
+}
+
+toml_config_struct! { ThemeConfig, PartialThemeConfig, RoughThemeConfig,
+    enabled => bool,
+    day_start => String,
+    night_start => String,
+    day => ThemePalette [PartialThemePalette, RoughThemePalette],
+    night => ThemePalette [PartialThemePalette, RoughThemePalette],
+}
+
+toml_config_struct! { ThemePalette, PartialThemePalette, RoughThemePalette,
+    background => String,
+    foreground => String,
+    accent => String,
+    muted => String,
+}
+
+toml_config_struct! { StatusMessageConfig, PartialStatusMessageConfig, RoughStatusMessageConfig,
+    visible => bool,
+    bg_color => String,
+
+    info_color => String,
+    info_modifiers => String,
+    info_prefix => String,
+
+    error_color => String,
+    error_modifiers => String,
+    error_prefix => String,
+
+    colors => StringMap [PartialStringMap, RoughStringMap],
+}
+
 toml_config_struct! { PowerControlConfig, PartialPowerControlConfig, RoughPowerControlConfig,
+    visible => bool,
     hint_margin => u16,
+    bg_color => String,
     base_entries => PowerControlVec [PartialPowerControlVec, RoughPowerControlVec],
     entries => PowerControlVec [PartialPowerControlVec, RoughPowerControlVec],
 }
@@ -265,6 +584,138 @@ pub struct PartialPowerControlVec(pub Vec<PartialPowerControl>);
 #[repr(transparent)]
 struct RoughPowerControlVec(pub Vec<RoughPowerControl>);
 
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct CommandSessionVec(pub Vec<CommandSession>);
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct PartialCommandSessionVec(pub Vec<PartialCommandSession>);
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+struct RoughCommandSessionVec(pub Vec<RoughCommandSession>);
+
+toml_config_struct! { CommandSession, PartialCommandSession, RoughCommandSession,
+    name => String,
+    cmd => String,
+}
+
+impl Default for CommandSession {
+    fn default() -> Self {
+        CommandSession {
+            name: "".to_string(),
+            cmd: "".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct SystemdSessionVec(pub Vec<SystemdSession>);
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct PartialSystemdSessionVec(pub Vec<PartialSystemdSession>);
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+struct RoughSystemdSessionVec(pub Vec<RoughSystemdSession>);
+
+toml_config_struct! { SystemdSession, PartialSystemdSession, RoughSystemdSession,
+    name => String,
+    target => String,
+}
+
+impl Default for SystemdSession {
+    fn default() -> Self {
+        SystemdSession {
+            name: "".to_string(),
+            target: "".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct SessionHookVec(pub Vec<SessionHook>);
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct PartialSessionHookVec(pub Vec<PartialSessionHook>);
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+struct RoughSessionHookVec(pub Vec<RoughSessionHook>);
+
+toml_config_struct! { SessionHook, PartialSessionHook, RoughSessionHook,
+    name => String,
+    pre_exec => String,
+    post_exec => String,
+}
+
+impl Default for SessionHook {
+    fn default() -> Self {
+        SessionHook {
+            name: "".to_string(),
+            pre_exec: "".to_string(),
+            post_exec: "".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct CommandWidgetVec(pub Vec<CommandWidget>);
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct PartialCommandWidgetVec(pub Vec<PartialCommandWidget>);
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+struct RoughCommandWidgetVec(pub Vec<RoughCommandWidget>);
+
+toml_config_struct! { CommandWidget, PartialCommandWidget, RoughCommandWidget,
+    cmd => String,
+    interval_secs => u32,
+    timeout_secs => u32,
+    position => CommandWidgetPosition,
+    color => String,
+    modifiers => String,
+}
+
+impl Default for CommandWidget {
+    fn default() -> Self {
+        CommandWidget {
+            cmd: "".to_string(),
+            interval_secs: 60,
+            timeout_secs: 10,
+            position: CommandWidgetPosition::TopRight,
+            color: "dark gray".to_string(),
+            modifiers: "".to_string(),
+        }
+    }
+}
+
+/// A table from names to strings, such as a table of environment variables
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct StringMap(pub BTreeMap<String, String>);
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct PartialStringMap(pub BTreeMap<String, String>);
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+struct RoughStringMap(pub BTreeMap<String, PossibleVariable<String>>);
+
 toml_config_struct! { PowerControl, PartialPowerControl, RoughPowerControl,
     hint => String,
     hint_color => String,
@@ -291,9 +742,17 @@ toml_config_struct! { SwitcherConfig, PartialSwitcherConfig, RoughSwitcherConfig
     toggle_hint_color => String,
     toggle_hint_modifiers => String,
 
+    show_comments => bool,
+    locale => String,
+
     include_tty_shell => bool,
+    command_sessions => CommandSessionVec [PartialCommandSessionVec, RoughCommandSessionVec],
+    systemd_sessions => SystemdSessionVec [PartialSystemdSessionVec, RoughSystemdSessionVec],
+    hidden => Vec<String>,
 
     remember => bool,
+    default => String,
+    fallback => String,
 
     show_movers => bool,
     mover_color => String,
@@ -313,6 +772,9 @@ toml_config_struct! { SwitcherConfig, PartialSwitcherConfig, RoughSwitcherConfig
     selected_modifiers => String,
     selected_modifiers_focused => String,
 
+    selected_bg_color => String,
+    selected_bg_color_focused => String,
+
     show_neighbours => bool,
     neighbour_color => String,
     neighbour_color_focused => String,
@@ -326,6 +788,8 @@ toml_config_struct! { SwitcherConfig, PartialSwitcherConfig, RoughSwitcherConfig
 
     no_envs_text => String,
 
+    loading_text => String,
+
     no_envs_color => String,
     no_envs_color_focused => String,
 
@@ -336,6 +800,7 @@ toml_config_struct! { SwitcherConfig, PartialSwitcherConfig, RoughSwitcherConfig
 toml_config_struct! { InputFieldStyle, PartialInputFieldStyle, RoughInputFieldStyle,
     show_title => bool,
     title => String,
+    placeholder => String,
 
     show_border => bool,
 
@@ -345,20 +810,34 @@ toml_config_struct! { InputFieldStyle, PartialInputFieldStyle, RoughInputFieldSt
     content_color => String,
     content_color_focused => String,
 
+    content_modifiers => String,
+    content_modifiers_focused => String,
+
     border_color => String,
     border_color_focused => String,
 
+    bg_color => String,
+    bg_color_focused => String,
+
     use_max_width => bool,
     max_width => u16,
 }
 
 toml_config_struct! { UsernameFieldConfig, PartialUsernameFieldConfig, RoughUsernameFieldConfig,
+    visible => bool,
     remember => bool,
     style => InputFieldStyle [PartialInputFieldStyle, RoughInputFieldStyle],
 }
 
 toml_config_struct! { PasswordFieldConfig, PartialPasswordFieldConfig, RoughPasswordFieldConfig,
     content_replacement_character => char,
+    allow_empty => bool,
+    style => InputFieldStyle [PartialInputFieldStyle, RoughInputFieldStyle],
+}
+
+toml_config_struct! { DomainFieldConfig, PartialDomainFieldConfig, RoughDomainFieldConfig,
+    enabled => bool,
+    username_format => String,
     style => InputFieldStyle [PartialInputFieldStyle, RoughInputFieldStyle],
 }
 
@@ -371,15 +850,77 @@ toml_config_struct! { X11Config, PartialX11Config, RoughX11Config,
 
     xserver_path => String,
     xauth_path => String,
+    xauthority_path => String,
+
+    server_vt => u8,
+    retain_server => bool,
+
+    xephyr_path => String,
+    xephyr_args => String,
 
     scripts_path => String,
     xsetup_path => String,
     xsessions_path => String,
+
+    env => StringMap [PartialStringMap, RoughStringMap],
 }
 
 toml_config_struct! { WaylandConfig, PartialWaylandConfig, RoughWaylandConfig,
     scripts_path => String,
     wayland_sessions_path => String,
+
+    systemd_unit => bool,
+
+    env => StringMap [PartialStringMap, RoughStringMap],
+}
+
+toml_config_struct! { TtySessionConfig, PartialTtySessionConfig, RoughTtySessionConfig,
+    multiplexer => String,
+    multiplexer_title => String,
+    multiplexer_session => String,
+    multiplexer_users => MultiplexerUserVec [PartialMultiplexerUserVec, RoughMultiplexerUserVec],
+
+    env => StringMap [PartialStringMap, RoughStringMap],
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct MultiplexerUserVec(pub Vec<MultiplexerUser>);
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct PartialMultiplexerUserVec(pub Vec<PartialMultiplexerUser>);
+#[derive(Clone, Debug, Deserialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+struct RoughMultiplexerUserVec(pub Vec<RoughMultiplexerUser>);
+
+toml_config_struct! { MultiplexerUser, PartialMultiplexerUser, RoughMultiplexerUser,
+    user => String,
+    session => String,
+}
+
+impl Default for MultiplexerUser {
+    fn default() -> Self {
+        MultiplexerUser {
+            user: "".to_string(),
+            session: "".to_string(),
+        }
+    }
+}
+
+toml_config_struct! { PriorityConfig, PartialPriorityConfig, RoughPriorityConfig,
+    greeter_oom_score_adj => i16,
+    session_oom_score_adj => i16,
+    session_nice => i8,
+    session_ionice_class => String,
+    session_ionice_level => u8,
+}
+
+toml_config_struct! { CursorConfig, PartialCursorConfig, RoughCursorConfig,
+    shape => CursorShape,
+    blinking => bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -396,6 +937,52 @@ pub enum FocusBehaviour {
     Password,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum CursorShape {
+    #[serde(rename = "default")]
+    Default,
+    #[serde(rename = "block")]
+    Block,
+    #[serde(rename = "bar")]
+    Bar,
+    #[serde(rename = "underline")]
+    Underline,
+    #[serde(rename = "hidden")]
+    Hidden,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum CommandWidgetPosition {
+    #[serde(rename = "top-left")]
+    TopLeft,
+    #[serde(rename = "top-right")]
+    TopRight,
+    #[serde(rename = "bottom-left")]
+    BottomLeft,
+    #[serde(rename = "bottom-right")]
+    BottomRight,
+}
+
+impl CursorConfig {
+    /// The cursor style to set on the terminal. `None` means that the terminal's own cursor style
+    /// is kept.
+    pub fn cursor_style(&self) -> Option<SetCursorStyle> {
+        Some(match (self.shape, self.blinking) {
+            (CursorShape::Default | CursorShape::Hidden, _) => return None,
+            (CursorShape::Block, false) => SetCursorStyle::SteadyBlock,
+            (CursorShape::Block, true) => SetCursorStyle::BlinkingBlock,
+            (CursorShape::Bar, false) => SetCursorStyle::SteadyBar,
+            (CursorShape::Bar, true) => SetCursorStyle::BlinkingBar,
+            (CursorShape::Underline, false) => SetCursorStyle::SteadyUnderScore,
+            (CursorShape::Underline, true) => SetCursorStyle::BlinkingUnderScore,
+        })
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.shape != CursorShape::Hidden
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub enum ShellLoginFlag {
     #[serde(rename = "none")]
@@ -419,9 +1006,10 @@ impl<'de> Deserialize<'de> for SwitcherVisibility {
     where
         D: serde::Deserializer<'de>,
     {
-        let s: &str = Deserialize::deserialize(deserializer)?;
+        // This is not a borrowed `&str`, since that cannot be deserialized from a `toml::Value`
+        let s: String = Deserialize::deserialize(deserializer)?;
 
-        Ok(match s {
+        Ok(match s.as_str() {
             "visible" => Self::Visible,
             "hidden" => Self::Hidden,
             key => {
@@ -437,12 +1025,18 @@ impl<'de> Deserialize<'de> for SwitcherVisibility {
     }
 }
 
+/// The default configuration. It is only deserialized once, the first time it is used. The
+/// `default_config_is_valid` test makes sure that this succeeds.
+static DEFAULT_CONFIG: Lazy<Config> = Lazy::new(|| {
+    Config::deserialize(default_config::DEFAULT_CONFIG).unwrap_or_else(|e| {
+        eprintln!("Default configuration cannot be properly loaded: {e}");
+        process::exit(1);
+    })
+});
+
 impl Default for Config {
     fn default() -> Config {
-        toml::from_str(include_str!("../extra/config.toml")).unwrap_or_else(|e| {
-            eprintln!("Default configuration file cannot be properly parsed: {e}");
-            process::exit(1);
-        })
+        DEFAULT_CONFIG.clone()
     }
 }
 
@@ -467,13 +1061,86 @@ impl PartialConfig {
     }
 }
 
-impl Variables {
-    /// Facilitates the loading of the entire configuration
-    pub fn from_file(path: &Path) -> Result<Variables, Box<dyn std::error::Error>> {
-        let mut file = File::open(path)?;
-        let mut contents = String::new();
+/// The prefix of the environment variables that override configuration options
+const ENV_OVERRIDE_PREFIX: &str = "LEMURS_";
 
-        file.read_to_string(&mut contents)?;
+/// Interpret the value of an environment variable override. Options that are strings by default
+/// are always taken literally. Other values are parsed as TOML (e.g. `4`, `true` or `["a", "b"]`)
+/// and taken literally if that fails.
+fn env_override_value(default: DefaultValue, value: &str) -> Value {
+    if default.is_str() {
+        return Value::String(value.to_string());
+    }
+
+    toml::from_str::<toml::value::Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+impl PartialConfig {
+    /// Load the configuration options that are overridden with environment variables. The
+    /// variables are named `LEMURS_` followed by the path of the option in upper case, where
+    /// nested keys are separated with a double underscore (e.g. `LEMURS_TTY=4` or
+    /// `LEMURS_X11__XSERVER_PATH=/usr/libexec/Xorg`). Variables that do not name an option are
+    /// ignored. Returns `None` if there are no overrides.
+    pub fn from_env_vars(
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Option<PartialConfig>, Box<dyn std::error::Error>> {
+        let defaults = default_config::DEFAULT_CONFIG;
+
+        let mut overrides = toml::value::Table::new();
+        for (key, value) in vars {
+            let Some(path) = key.strip_prefix(ENV_OVERRIDE_PREFIX) else {
+                continue;
+            };
+
+            let path = path.to_lowercase();
+            let path = path.split("__").collect::<Vec<_>>();
+            let Some((last, parents)) = path.split_last() else {
+                continue;
+            };
+
+            // Only options can be overridden. Other variables with the prefix, such as the ones that
+            // lemurs sets for sessions (e.g. `LEMURS_DISPLAY_LAYOUT`), are left alone.
+            let default = path
+                .iter()
+                .try_fold(defaults, |value, key| value.get(key))
+                .filter(|default| !default.is_table());
+            let Some(default) = default else {
+                warn!("Ignoring the environment variable '{key}', as it is not a configuration option");
+                continue;
+            };
+
+            let mut table = &mut overrides;
+            for parent in parents {
+                table = match table
+                    .entry(parent.to_string())
+                    .or_insert(Value::Table(toml::value::Table::new()))
+                {
+                    Value::Table(table) => table,
+                    _ => return Err(format!("'{key}' overrides a nested key of a value").into()),
+                };
+            }
+
+            table.insert(last.to_string(), env_override_value(default, &value));
+        }
+
+        if overrides.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Value::Table(overrides).try_into()?))
+    }
+}
+
+impl Variables {
+    /// Facilitates the loading of the entire configuration
+    pub fn from_file(path: &Path) -> Result<Variables, Box<dyn std::error::Error>> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+
+        file.read_to_string(&mut contents)?;
 
         Ok(toml::from_str(&contents)?)
     }
@@ -588,6 +1255,228 @@ impl RoughPowerControlVec {
     }
 }
 
+impl CommandSessionVec {
+    pub fn merge_in_partial(&mut self, partial: PartialCommandSessionVec) {
+        *self = CommandSessionVec(
+            partial
+                .0
+                .into_iter()
+                .map(|partial_elem| {
+                    let mut elem = CommandSession::default();
+                    elem.merge_in_partial(partial_elem);
+                    elem
+                })
+                .collect::<Vec<CommandSession>>(),
+        );
+    }
+}
+
+impl RoughCommandSessionVec {
+    pub fn into_partial(
+        self,
+        variables: &Variables,
+    ) -> Result<PartialCommandSessionVec, VariableInsertionError> {
+        self.0
+            .into_iter()
+            .map(|rough_elem| rough_elem.into_partial(variables))
+            .collect::<Result<Vec<PartialCommandSession>, VariableInsertionError>>()
+            .map(PartialCommandSessionVec)
+    }
+}
+
+impl SystemdSessionVec {
+    pub fn merge_in_partial(&mut self, partial: PartialSystemdSessionVec) {
+        *self = SystemdSessionVec(
+            partial
+                .0
+                .into_iter()
+                .map(|partial_elem| {
+                    let mut elem = SystemdSession::default();
+                    elem.merge_in_partial(partial_elem);
+                    elem
+                })
+                .collect::<Vec<SystemdSession>>(),
+        );
+    }
+}
+
+impl RoughSystemdSessionVec {
+    pub fn into_partial(
+        self,
+        variables: &Variables,
+    ) -> Result<PartialSystemdSessionVec, VariableInsertionError> {
+        self.0
+            .into_iter()
+            .map(|rough_elem| rough_elem.into_partial(variables))
+            .collect::<Result<Vec<PartialSystemdSession>, VariableInsertionError>>()
+            .map(PartialSystemdSessionVec)
+    }
+}
+
+impl MultiplexerUserVec {
+    pub fn merge_in_partial(&mut self, partial: PartialMultiplexerUserVec) {
+        *self = MultiplexerUserVec(
+            partial
+                .0
+                .into_iter()
+                .map(|partial_elem| {
+                    let mut elem = MultiplexerUser::default();
+                    elem.merge_in_partial(partial_elem);
+                    elem
+                })
+                .collect::<Vec<MultiplexerUser>>(),
+        );
+    }
+}
+
+impl RoughMultiplexerUserVec {
+    pub fn into_partial(
+        self,
+        variables: &Variables,
+    ) -> Result<PartialMultiplexerUserVec, VariableInsertionError> {
+        self.0
+            .into_iter()
+            .map(|rough_elem| rough_elem.into_partial(variables))
+            .collect::<Result<Vec<PartialMultiplexerUser>, VariableInsertionError>>()
+            .map(PartialMultiplexerUserVec)
+    }
+}
+
+impl SessionHookVec {
+    pub fn merge_in_partial(&mut self, partial: PartialSessionHookVec) {
+        *self = SessionHookVec(
+            partial
+                .0
+                .into_iter()
+                .map(|partial_elem| {
+                    let mut elem = SessionHook::default();
+                    elem.merge_in_partial(partial_elem);
+                    elem
+                })
+                .collect::<Vec<SessionHook>>(),
+        );
+    }
+}
+
+impl RoughSessionHookVec {
+    pub fn into_partial(
+        self,
+        variables: &Variables,
+    ) -> Result<PartialSessionHookVec, VariableInsertionError> {
+        self.0
+            .into_iter()
+            .map(|rough_elem| rough_elem.into_partial(variables))
+            .collect::<Result<Vec<PartialSessionHook>, VariableInsertionError>>()
+            .map(PartialSessionHookVec)
+    }
+}
+
+impl AccessRuleVec {
+    pub fn merge_in_partial(&mut self, partial: PartialAccessRuleVec) {
+        *self = AccessRuleVec(
+            partial
+                .0
+                .into_iter()
+                .map(|partial_elem| {
+                    let mut elem = AccessRule::default();
+                    elem.merge_in_partial(partial_elem);
+                    elem
+                })
+                .collect::<Vec<AccessRule>>(),
+        );
+    }
+}
+
+impl RoughAccessRuleVec {
+    pub fn into_partial(
+        self,
+        variables: &Variables,
+    ) -> Result<PartialAccessRuleVec, VariableInsertionError> {
+        self.0
+            .into_iter()
+            .map(|rough_elem| rough_elem.into_partial(variables))
+            .collect::<Result<Vec<PartialAccessRule>, VariableInsertionError>>()
+            .map(PartialAccessRuleVec)
+    }
+}
+
+impl DisplayLayoutVec {
+    pub fn merge_in_partial(&mut self, partial: PartialDisplayLayoutVec) {
+        *self = DisplayLayoutVec(
+            partial
+                .0
+                .into_iter()
+                .map(|partial_elem| {
+                    let mut elem = DisplayLayout::default();
+                    elem.merge_in_partial(partial_elem);
+                    elem
+                })
+                .collect::<Vec<DisplayLayout>>(),
+        );
+    }
+}
+
+impl RoughDisplayLayoutVec {
+    pub fn into_partial(
+        self,
+        variables: &Variables,
+    ) -> Result<PartialDisplayLayoutVec, VariableInsertionError> {
+        self.0
+            .into_iter()
+            .map(|rough_elem| rough_elem.into_partial(variables))
+            .collect::<Result<Vec<PartialDisplayLayout>, VariableInsertionError>>()
+            .map(PartialDisplayLayoutVec)
+    }
+}
+
+impl CommandWidgetVec {
+    pub fn merge_in_partial(&mut self, partial: PartialCommandWidgetVec) {
+        *self = CommandWidgetVec(
+            partial
+                .0
+                .into_iter()
+                .map(|partial_elem| {
+                    let mut elem = CommandWidget::default();
+                    elem.merge_in_partial(partial_elem);
+                    elem
+                })
+                .collect::<Vec<CommandWidget>>(),
+        );
+    }
+}
+
+impl RoughCommandWidgetVec {
+    pub fn into_partial(
+        self,
+        variables: &Variables,
+    ) -> Result<PartialCommandWidgetVec, VariableInsertionError> {
+        self.0
+            .into_iter()
+            .map(|rough_elem| rough_elem.into_partial(variables))
+            .collect::<Result<Vec<PartialCommandWidget>, VariableInsertionError>>()
+            .map(PartialCommandWidgetVec)
+    }
+}
+
+impl StringMap {
+    pub fn merge_in_partial(&mut self, partial: PartialStringMap) {
+        *self = StringMap(partial.0);
+    }
+}
+
+impl RoughStringMap {
+    pub fn into_partial(
+        self,
+        variables: &Variables,
+    ) -> Result<PartialStringMap, VariableInsertionError> {
+        self.0
+            .into_iter()
+            .map(|(key, value)| Ok((key, String::insert(value, variables)?)))
+            .collect::<Result<BTreeMap<String, String>, VariableInsertionError>>()
+            .map(PartialStringMap)
+    }
+}
+
 impl std::error::Error for VariableInsertionError {}
 
 macro_rules! non_string_var_insert {
@@ -652,11 +1541,17 @@ macro_rules! non_string_var_insert {
 
 non_string_var_insert! {
     bool ["boolean"],
+    i8 ["signed 8-bit integer"],
+    i16 ["signed 16-bit integer"],
     u8 ["unsigned 8-bit integer"],
     u16 ["unsigned 16-bit integer"],
+    u32 ["unsigned 32-bit integer"],
     char ["character"],
+    Vec<String> ["list of strings"],
     ShellLoginFlag ["shell login flag"],
     FocusBehaviour ["focus behavior"],
+    CursorShape ["cursor shape"],
+    CommandWidgetPosition ["command widget position"],
     SwitcherVisibility ["switcher visibility"],
 }
 
@@ -762,9 +1657,471 @@ impl<'a> Iterator for VariableIterator<'a> {
     }
 }
 
+impl Config {
+    /// Check the options whose values are only interpreted at runtime, such as colors and keys.
+    /// Returns a description of every problem that was found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let mut check_color = |option: &str, color: &str| {
+            if str_to_color(color).is_none() {
+                problems.push(format!("'{option}' has the unknown color '{color}'"));
+            }
+        };
+
+        let background = &self.background.style;
+        check_color("background.style.color", &background.color);
+        check_color("background.style.border_color", &background.border_color);
+
+        for (name, palette) in [("day", &self.theme.day), ("night", &self.theme.night)] {
+            for (option, color) in [
+                ("background", &palette.background),
+                ("foreground", &palette.foreground),
+                ("accent", &palette.accent),
+                ("muted", &palette.muted),
+            ] {
+                // An empty palette color keeps the color of the widget
+                if !color.is_empty() {
+                    check_color(&format!("theme.{name}.{option}"), color);
+                }
+            }
+        }
+
+        let power_controls = self
+            .power_controls
+            .base_entries
+            .0
+            .iter()
+            .map(|entry| ("power_controls.base_entries", entry))
+            .chain(
+                self.power_controls
+                    .entries
+                    .0
+                    .iter()
+                    .map(|entry| ("power_controls.entries", entry)),
+            );
+        for (option, entry) in power_controls {
+            check_color(&format!("{option}.hint_color"), &entry.hint_color);
+        }
+
+        check_color("hidpi.toggle_hint_color", &self.hidpi.toggle_hint_color);
+        check_color("network.hint_color", &self.network.hint_color);
+        check_color(
+            "network.offline_hint_color",
+            &self.network.offline_hint_color,
+        );
+        check_color(
+            "display_layout.toggle_hint_color",
+            &self.display_layout.toggle_hint_color,
+        );
+        for widget in &self.command_widgets.0 {
+            check_color("command_widgets.color", &widget.color);
+        }
+        check_color("recovery_shell.hint_color", &self.recovery_shell.hint_color);
+        check_color("touch.pin_pad_color", &self.touch.pin_pad_color);
+
+        let status_message = &self.status_message;
+        check_color("status_message.info_color", &status_message.info_color);
+        check_color("status_message.error_color", &status_message.error_color);
+        for (message, color) in &status_message.colors.0 {
+            check_color(&format!("status_message.colors.{message}"), color);
+        }
+
+        let switcher = &self.environment_switcher;
+        for (option, color) in [
+            ("toggle_hint_color", &switcher.toggle_hint_color),
+            ("mover_color", &switcher.mover_color),
+            ("mover_color_focused", &switcher.mover_color_focused),
+            ("selected_color", &switcher.selected_color),
+            ("selected_color_focused", &switcher.selected_color_focused),
+            ("neighbour_color", &switcher.neighbour_color),
+            ("neighbour_color_focused", &switcher.neighbour_color_focused),
+            ("no_envs_color", &switcher.no_envs_color),
+            ("no_envs_color_focused", &switcher.no_envs_color_focused),
+        ] {
+            check_color(&format!("environment_switcher.{option}"), color);
+        }
+
+        for (field, style) in [
+            ("username_field", &self.username_field.style),
+            ("password_field", &self.password_field.style),
+            ("domain_field", &self.domain_field.style),
+        ] {
+            for (option, color) in [
+                ("title_color", &style.title_color),
+                ("title_color_focused", &style.title_color_focused),
+                ("content_color", &style.content_color),
+                ("content_color_focused", &style.content_color_focused),
+                ("border_color", &style.border_color),
+                ("border_color_focused", &style.border_color_focused),
+            ] {
+                check_color(&format!("{field}.style.{option}"), color);
+            }
+        }
+
+        let mut check_bg_color = |option: &str, color: &str| {
+            if !color.is_empty() && str_to_color(color).is_none() {
+                problems.push(format!("'{option}' has the unknown color '{color}'"));
+            }
+        };
+
+        check_bg_color("status_message.bg_color", &self.status_message.bg_color);
+        check_bg_color("power_controls.bg_color", &self.power_controls.bg_color);
+        check_bg_color(
+            "environment_switcher.selected_bg_color",
+            &switcher.selected_bg_color,
+        );
+        check_bg_color(
+            "environment_switcher.selected_bg_color_focused",
+            &switcher.selected_bg_color_focused,
+        );
+        for (field, style) in [
+            ("username_field", &self.username_field.style),
+            ("password_field", &self.password_field.style),
+            ("domain_field", &self.domain_field.style),
+        ] {
+            check_bg_color(&format!("{field}.style.bg_color"), &style.bg_color);
+            check_bg_color(
+                &format!("{field}.style.bg_color_focused"),
+                &style.bg_color_focused,
+            );
+        }
+
+        let mut check_modifiers = |option: &str, modifiers: &str| {
+            for modifier in modifiers.split(',').filter(|m| !m.trim().is_empty()) {
+                if get_modifier(modifier).is_none() {
+                    problems.push(format!(
+                        "'{option}' has the unknown modifier '{}'",
+                        modifier.trim()
+                    ));
+                }
+            }
+        };
+
+        for entry in self
+            .power_controls
+            .base_entries
+            .0
+            .iter()
+            .chain(self.power_controls.entries.0.iter())
+        {
+            check_modifiers("power_controls.hint_modifiers", &entry.hint_modifiers);
+        }
+
+        check_modifiers(
+            "hidpi.toggle_hint_modifiers",
+            &self.hidpi.toggle_hint_modifiers,
+        );
+        check_modifiers("network.hint_modifiers", &self.network.hint_modifiers);
+        check_modifiers(
+            "display_layout.toggle_hint_modifiers",
+            &self.display_layout.toggle_hint_modifiers,
+        );
+        for widget in &self.command_widgets.0 {
+            check_modifiers("command_widgets.modifiers", &widget.modifiers);
+        }
+        check_modifiers(
+            "recovery_shell.hint_modifiers",
+            &self.recovery_shell.hint_modifiers,
+        );
+        check_modifiers("touch.pin_pad_modifiers", &self.touch.pin_pad_modifiers);
+        check_modifiers(
+            "status_message.info_modifiers",
+            &self.status_message.info_modifiers,
+        );
+        check_modifiers(
+            "status_message.error_modifiers",
+            &self.status_message.error_modifiers,
+        );
+
+        for (option, modifiers) in [
+            ("toggle_hint_modifiers", &switcher.toggle_hint_modifiers),
+            ("mover_modifiers", &switcher.mover_modifiers),
+            ("mover_modifiers_focused", &switcher.mover_modifiers_focused),
+            ("selected_modifiers", &switcher.selected_modifiers),
+            (
+                "selected_modifiers_focused",
+                &switcher.selected_modifiers_focused,
+            ),
+            ("neighbour_modifiers", &switcher.neighbour_modifiers),
+            (
+                "neighbour_modifiers_focused",
+                &switcher.neighbour_modifiers_focused,
+            ),
+            ("no_envs_modifiers", &switcher.no_envs_modifiers),
+            (
+                "no_envs_modifiers_focused",
+                &switcher.no_envs_modifiers_focused,
+            ),
+        ] {
+            check_modifiers(&format!("environment_switcher.{option}"), modifiers);
+        }
+
+        for (field, style) in [
+            ("username_field", &self.username_field.style),
+            ("password_field", &self.password_field.style),
+            ("domain_field", &self.domain_field.style),
+        ] {
+            check_modifiers(
+                &format!("{field}.style.content_modifiers"),
+                &style.content_modifiers,
+            );
+            check_modifiers(
+                &format!("{field}.style.content_modifiers_focused"),
+                &style.content_modifiers_focused,
+            );
+        }
+
+        for entry in self
+            .power_controls
+            .base_entries
+            .0
+            .iter()
+            .chain(self.power_controls.entries.0.iter())
+        {
+            if get_function_key(&entry.key).is_none() {
+                problems.push(format!(
+                    "The power control '{}' has the key '{}'. Only F1-F12 are allowed",
+                    entry.hint, entry.key
+                ));
+            }
+        }
+
+        if !self.hidpi.toggle_key.is_empty() && get_function_key(&self.hidpi.toggle_key).is_none() {
+            problems.push(format!(
+                "'hidpi.toggle_key' has the key '{}'. Only F1-F12 are allowed",
+                self.hidpi.toggle_key
+            ));
+        }
+
+        let display_layout = &self.display_layout;
+        if !display_layout.toggle_key.is_empty()
+            && get_function_key(&display_layout.toggle_key).is_none()
+        {
+            problems.push(format!(
+                "'display_layout.toggle_key' has the key '{}'. Only F1-F12 are allowed",
+                display_layout.toggle_key
+            ));
+        }
+        if !display_layout.default.is_empty()
+            && display_layout.layout(&display_layout.default).is_none()
+        {
+            problems.push(format!(
+                "'display_layout.default' is '{}', but there is no layout with that name",
+                display_layout.default
+            ));
+        }
+
+        if self.offline_login.enabled
+            && (self.offline_login.max_logins == 0 || self.offline_login.expiry_days == 0)
+        {
+            problems.push(
+                "'offline_login.max_logins' and 'offline_login.expiry_days' cannot be 0"
+                    .to_string(),
+            );
+        }
+
+        for rule in &self.access.rules.0 {
+            if let Err(err) = crate::auth::access::AccessWindow::parse(rule) {
+                problems.push(format!("'access.rules' has an invalid rule. {err}"));
+            }
+        }
+
+        for key in &self.duress.sequence {
+            if crate::ui::duress::parse_key(key).is_none() {
+                problems.push(format!(
+                    "'duress.sequence' has the key '{key}'. Only F1-F12 and ctrl+a-z are allowed"
+                ));
+            }
+        }
+
+        if !self.recovery_shell.key.is_empty()
+            && get_function_key(&self.recovery_shell.key).is_none()
+        {
+            problems.push(format!(
+                "'recovery_shell.key' has the key '{}'. Only F1-F12 are allowed",
+                self.recovery_shell.key
+            ));
+        }
+
+        for widget in &self.command_widgets.0 {
+            if widget.interval_secs == 0 {
+                problems.push(format!(
+                    "The command widget '{}' has an 'interval_secs' of 0",
+                    widget.cmd
+                ));
+            }
+        }
+
+        for name in &self.field_order {
+            if !matches!(name.trim(), "username" | "password" | "domain") {
+                problems.push(format!("'field_order' contains the unknown field '{name}'"));
+            }
+        }
+
+        for (option, position) in [
+            (
+                "layout.horizontal_position",
+                self.layout.horizontal_position,
+            ),
+            ("layout.vertical_position", self.layout.vertical_position),
+        ] {
+            if position > 100 {
+                problems.push(format!(
+                    "'{option}' is {position}, but it is a percentage from 0 to 100"
+                ));
+            }
+        }
+
+        let session = &self.session;
+        if !session.umask.is_empty() && get_umask(&session.umask).is_none() {
+            problems.push(format!(
+                "'session.umask' is '{}', but it should be an octal mode such as \"022\"",
+                session.umask
+            ));
+        }
+        for (option, limit) in [
+            ("session.core_limit", &session.core_limit),
+            ("session.nofile_limit", &session.nofile_limit),
+        ] {
+            if !limit.is_empty() && get_rlimit(limit).is_none() {
+                problems.push(format!(
+                    "'{option}' is '{limit}', but it should be a limit or \"soft:hard\" limits"
+                ));
+            }
+        }
+
+        if crate::post_login::session_timer::signal_from_name(&session.timeout_signal).is_none() {
+            problems.push(format!(
+                "'session.timeout_signal' has the unknown signal '{}'",
+                session.timeout_signal
+            ));
+        }
+
+        if !session.hang_check.is_empty()
+            && crate::post_login::watchdog::HangCheck::from_name(&session.hang_check).is_none()
+        {
+            problems.push(format!(
+                "'session.hang_check' has the unknown check '{}'",
+                session.hang_check
+            ));
+        }
+
+        let tty_session = &self.tty_session;
+        if !tty_session.multiplexer.is_empty()
+            && crate::post_login::multiplexer::Multiplexer::from_name(&tty_session.multiplexer)
+                .is_none()
+        {
+            problems.push(format!(
+                "'tty_session.multiplexer' is '{}', but it can only be 'tmux' or 'screen'",
+                tty_session.multiplexer
+            ));
+        }
+        if !tty_session.multiplexer.is_empty() && tty_session.multiplexer_session.is_empty() {
+            problems.push(String::from(
+                "'tty_session.multiplexer_session' cannot be empty",
+            ));
+        }
+
+        if self.x11.retain_server && (self.x11.server_vt == 0 || self.x11.server_vt == self.tty) {
+            problems.push(String::from(
+                "'x11.retain_server' needs 'x11.server_vt' to be set to another tty than lemurs",
+            ));
+        }
+
+        let priority = &self.priority;
+        for (option, oom_score_adj) in [
+            (
+                "priority.greeter_oom_score_adj",
+                priority.greeter_oom_score_adj,
+            ),
+            (
+                "priority.session_oom_score_adj",
+                priority.session_oom_score_adj,
+            ),
+        ] {
+            if !(-1000..=1000).contains(&oom_score_adj) {
+                problems.push(format!(
+                    "'{option}' is {oom_score_adj}, but it ranges from -1000 to 1000"
+                ));
+            }
+        }
+
+        if !(-20..=19).contains(&priority.session_nice) {
+            problems.push(format!(
+                "'priority.session_nice' is {}, but it ranges from -20 to 19",
+                priority.session_nice
+            ));
+        }
+
+        if !priority.session_ionice_class.is_empty()
+            && crate::priority::ionice_class(&priority.session_ionice_class).is_none()
+        {
+            problems.push(format!(
+                "'priority.session_ionice_class' has the unknown class '{}'",
+                priority.session_ionice_class
+            ));
+        }
+
+        if priority.session_ionice_level > 7 {
+            problems.push(format!(
+                "'priority.session_ionice_level' is {}, but it ranges from 0 to 7",
+                priority.session_ionice_level
+            ));
+        }
+
+        problems
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::VariableIterator;
+    use super::{Config, PartialConfig, VariableIterator};
+
+    #[test]
+    fn env_var_overrides() {
+        let vars = [
+            ("LEMURS_TTY", "4"),
+            ("LEMURS_X11__XSERVER_PATH", "/usr/libexec/Xorg"),
+            ("LEMURS_HIDPI__QT_SCALE_FACTOR", "2"),
+            ("LEMURS_USERNAME_FIELD__STYLE__SHOW_TITLE", "false"),
+            ("LEMURS_TEST_USER", "lemur"),
+            ("PATH", "/usr/bin"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+
+        let mut config = Config::default();
+        let partial = PartialConfig::from_env_vars(vars).unwrap().unwrap();
+        config.merge_in_partial(partial);
+
+        assert_eq!(config.tty, 4);
+        assert_eq!(config.x11.xserver_path, "/usr/libexec/Xorg");
+        assert_eq!(config.hidpi.qt_scale_factor, "2");
+        assert!(!config.username_field.style.show_title);
+
+        let vars = [
+            ("PATH", "/usr/bin"),
+            ("LEMURS_TEST_USER", "lemur"),
+            ("LEMURS_DISPLAY_LAYOUT", "wlr-randr --output HDMI-A-1 --off"),
+            ("LEMURS_X11__XSERVER", "Xorg"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+        assert!(PartialConfig::from_env_vars(vars).unwrap().is_none());
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(Config::deserialize(super::default_config::DEFAULT_CONFIG).is_ok());
+        assert_eq!(Config::default().validate(), Vec::<String>::new());
+
+        let mut config = Config::default();
+        config.background.style.color = "not a color".to_string();
+        config.environment_switcher.mover_modifiers = "bold, sparkly".to_string();
+        config.hidpi.toggle_key = "F13".to_string();
+        assert_eq!(config.validate().len(), 3);
+    }
 
     #[test]
     fn test_variable_iterator() {
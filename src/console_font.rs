@@ -0,0 +1,106 @@
+//! Changing the font of the Linux console while lemurs is shown.
+//!
+//! The original font is saved to `/run/lemurs`, which only root can write to, so that the backup
+//! cannot be replaced or redirected by other users.
+
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use log::{error, info, warn};
+
+use crate::config::ConsoleFontConfig;
+
+const BACKUP_DIR: &str = "/run/lemurs";
+
+/// Restores the original console font when dropped
+pub struct ConsoleFontGuard {
+    setfont_path: String,
+    backup_path: PathBuf,
+}
+
+fn run_setfont(setfont_path: &str, args: &[&str]) -> bool {
+    match Command::new(setfont_path)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+    {
+        Ok(status) if status.success() => true,
+        Ok(status) => {
+            warn!("'{setfont_path}' exited with {status}");
+            false
+        }
+        Err(err) => {
+            error!("Failed to run '{setfont_path}'. Reason: {err}");
+            false
+        }
+    }
+}
+
+/// Create the directory for the backup, or check that the existing one can only be written by root
+fn create_backup_dir() -> io::Result<()> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o755)
+        .create(BACKUP_DIR)?;
+
+    let metadata = fs::symlink_metadata(BACKUP_DIR)?;
+    if !metadata.is_dir() || metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "it is not a directory that only root can write to",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Set the configured console font, if there is one
+pub fn set_console_font(config: &ConsoleFontConfig) -> Option<ConsoleFontGuard> {
+    if config.font.is_empty() {
+        return None;
+    }
+
+    if let Err(err) = create_backup_dir() {
+        warn!("Not changing the console font, as '{BACKUP_DIR}' cannot be used. Reason: {err}");
+        return None;
+    }
+
+    let backup_path =
+        Path::new(BACKUP_DIR).join(format!("console-font-{}.psf", std::process::id()));
+    let Some(backup_path_str) = backup_path.to_str() else {
+        warn!("Console font backup path is not valid UTF-8. Not changing the console font");
+        return None;
+    };
+
+    // Without a backup of the original font, it cannot be restored afterwards.
+    if !run_setfont(&config.setfont_path, &["-O", backup_path_str]) {
+        warn!("Failed to save the current console font. Not changing the console font");
+        return None;
+    }
+
+    info!("Setting console font to '{}'", config.font);
+    if !run_setfont(&config.setfont_path, &[&config.font]) {
+        warn!("Failed to set console font to '{}'", config.font);
+    }
+
+    Some(ConsoleFontGuard {
+        setfont_path: config.setfont_path.clone(),
+        backup_path,
+    })
+}
+
+impl Drop for ConsoleFontGuard {
+    fn drop(&mut self) {
+        info!("Restoring the original console font");
+
+        if let Some(backup_path) = self.backup_path.to_str() {
+            run_setfont(&self.setfont_path, &[backup_path]);
+        }
+
+        let _ = fs::remove_file(&self.backup_path);
+    }
+}
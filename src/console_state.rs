@@ -0,0 +1,122 @@
+//! Saving and restoring the state of the Linux console around a session.
+//!
+//! An environment that crashes (e.g. the X server) can leave the console in a state where the
+//! keyboard or the display does not work anymore. Therefore, the state of the console is saved
+//! before an environment is started and restored once lemurs gets back the console.
+
+#[cfg(not(target_env = "musl"))]
+pub type RequestType = libc::c_ulong;
+#[cfg(target_env = "musl")]
+pub type RequestType = libc::c_int;
+
+use std::io::{self, Write};
+
+use libc::{c_char, c_int, c_short};
+use log::{info, warn};
+
+// Request Numbers to get and set the display mode (text or graphics)
+const KDGETMODE: RequestType = 0x4B3B;
+const KDSETMODE: RequestType = 0x4B3A;
+
+// Request Numbers to get and set the keyboard mode
+const KDGKBMODE: RequestType = 0x4B44;
+const KDSKBMODE: RequestType = 0x4B45;
+
+// Request Numbers to get and set the VT switching mode
+const VT_GETMODE: RequestType = 0x5601;
+pub const VT_SETMODE: RequestType = 0x5602;
+
+/// Reset the colors and attributes and show the cursor
+const RESET_SEQUENCE: &[u8] = b"\x1b[0m\x1b[?25h";
+
+/// The `vt_mode` struct from `linux/vt.h`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VtMode {
+    pub mode: c_char,
+    pub waitv: c_char,
+    pub relsig: c_short,
+    pub acqsig: c_short,
+    pub frsig: c_short,
+}
+
+pub struct ConsoleState {
+    fd: c_int,
+    display_mode: Option<c_int>,
+    keyboard_mode: Option<c_int>,
+    vt_mode: Option<VtMode>,
+    termios: Option<libc::termios>,
+}
+
+impl ConsoleState {
+    /// Save the current state of the console lemurs is running on
+    pub fn snapshot() -> Self {
+        let fd = libc::STDIN_FILENO;
+
+        let mut display_mode: c_int = 0;
+        let display_mode =
+            (unsafe { libc::ioctl(fd, KDGETMODE, &mut display_mode) } == 0).then_some(display_mode);
+
+        let mut keyboard_mode: c_int = 0;
+        let keyboard_mode = (unsafe { libc::ioctl(fd, KDGKBMODE, &mut keyboard_mode) } == 0)
+            .then_some(keyboard_mode);
+
+        let mut vt_mode = VtMode::default();
+        let vt_mode =
+            (unsafe { libc::ioctl(fd, VT_GETMODE, &mut vt_mode) } == 0).then_some(vt_mode);
+
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        let termios = (unsafe { libc::tcgetattr(fd, &mut termios) } == 0).then_some(termios);
+
+        if display_mode.is_none() || keyboard_mode.is_none() || vt_mode.is_none() {
+            info!("Not running on a virtual console. Only saving the terminal attributes");
+        } else {
+            info!("Saved the console state");
+        }
+
+        Self {
+            fd,
+            display_mode,
+            keyboard_mode,
+            vt_mode,
+            termios,
+        }
+    }
+
+    /// Restore the console to the saved state
+    pub fn restore(&self) {
+        info!("Restoring the console state");
+
+        if let Some(display_mode) = self.display_mode {
+            if unsafe { libc::ioctl(self.fd, KDSETMODE, display_mode) } != 0 {
+                warn!("Failed to restore the console display mode");
+            }
+        }
+
+        if let Some(keyboard_mode) = self.keyboard_mode {
+            if unsafe { libc::ioctl(self.fd, KDSKBMODE, keyboard_mode) } != 0 {
+                warn!("Failed to restore the console keyboard mode");
+            }
+        }
+
+        if let Some(vt_mode) = self.vt_mode {
+            if unsafe { libc::ioctl(self.fd, VT_SETMODE, &vt_mode) } != 0 {
+                warn!("Failed to restore the console VT mode");
+            }
+        }
+
+        if let Some(termios) = self.termios {
+            if unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &termios) } != 0 {
+                warn!("Failed to restore the terminal attributes");
+            }
+        }
+
+        let mut stdout = io::stdout();
+        if let Err(err) = stdout
+            .write_all(RESET_SEQUENCE)
+            .and_then(|_| stdout.flush())
+        {
+            warn!("Failed to reset the cursor and colors. Reason: {err}");
+        }
+    }
+}
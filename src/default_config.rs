@@ -0,0 +1,109 @@
+//! The default configuration as a constant.
+//!
+//! The build script turns `extra/config.toml` into a [`DefaultValue`], so the default
+//! configuration does not have to be parsed when lemurs runs. It is deserialized into a
+//! [`Config`](crate::config::Config) like any other configuration.
+
+use serde::de::value::{BorrowedStrDeserializer, Error, MapDeserializer, SeqDeserializer};
+use serde::de::{Deserializer, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+/// The default configuration, as generated from `extra/config.toml` by the build script
+pub const DEFAULT_CONFIG: DefaultValue = include!(concat!(env!("OUT_DIR"), "/default_config.rs"));
+
+/// A value of the default configuration
+#[derive(Debug, Clone, Copy)]
+pub enum DefaultValue {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(&'static str),
+    Array(&'static [DefaultValue]),
+    Table(&'static [(&'static str, DefaultValue)]),
+}
+
+impl DefaultValue {
+    /// The value of `key` if this is a table that contains it
+    pub fn get(self, key: &str) -> Option<DefaultValue> {
+        let Self::Table(entries) = self else {
+            return None;
+        };
+
+        entries
+            .iter()
+            .find(|(entry_key, _)| *entry_key == key)
+            .map(|(_, value)| *value)
+    }
+
+    pub fn is_str(self) -> bool {
+        matches!(self, Self::String(_))
+    }
+
+    pub fn is_table(self) -> bool {
+        matches!(self, Self::Table(_))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for DefaultValue {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for DefaultValue {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Self::Boolean(value) => visitor.visit_bool(value),
+            Self::Integer(value) => visitor.visit_i64(value),
+            Self::Float(value) => visitor.visit_f64(value),
+            Self::String(value) => visitor.visit_borrowed_str(value),
+            Self::Array(values) => {
+                let mut values = SeqDeserializer::<_, Error>::new(values.iter().copied());
+                let value = visitor.visit_seq(&mut values)?;
+                values.end()?;
+                Ok(value)
+            }
+            Self::Table(entries) => {
+                let mut entries = MapDeserializer::<_, Error>::new(entries.iter().copied());
+                let value = visitor.visit_map(&mut entries)?;
+                entries.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            // Enums are unit variants that are written as their name
+            Self::String(value) => BorrowedStrDeserializer::<Error>::new(value)
+                .deserialize_enum(name, variants, visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
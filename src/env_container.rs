@@ -9,7 +9,7 @@ use log::{error, info};
 pub struct EnvironmentContainer {
     snapshot: HashMap<String, String>,
     snapshot_pwd: String,
-    owned: HashMap<&'static str, String>,
+    owned: HashMap<String, String>,
 }
 
 impl EnvironmentContainer {
@@ -30,10 +30,11 @@ impl EnvironmentContainer {
     }
 
     /// Set an environment variable and own the value
-    pub fn set(&mut self, key: &'static str, value: impl Into<String>) {
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let key = key.into();
         let value = value.into();
 
-        env::set_var(key, &value);
+        env::set_var(&key, &value);
         info!("Set environment variable '{}' to '{}'", key, value);
 
         self.owned.insert(key, value);
@@ -43,26 +44,26 @@ impl EnvironmentContainer {
     ///
     /// If the variable was already set, then the [`EnvironmentContainer`] considers the value as
     /// one of its own.
-    pub fn set_or_own(&mut self, key: &'static str, value: impl Into<String>) {
+    pub fn set_or_own(&mut self, key: &str, value: impl Into<String>) {
         if let Ok(value) = env::var(key) {
             info!(
                 "Skipped setting environment variable '{}'. It was already set to '{}'",
                 key, value
             );
-            self.owned.insert(key, value);
+            self.owned.insert(key.to_string(), value);
         } else {
             self.set(key, value)
         }
     }
 
-    pub fn remove_var(&mut self, key: &'static str) {
+    pub fn remove_var(&mut self, key: &str) {
         if env::var(key).is_ok() {
             info!("Preemptively removed environment variable '{key}'",);
             env::remove_var(key);
         }
     }
 
-    /// Sets the working directory
+    /// Sets the working directory. The original one is restored when the container is dropped.
     pub fn set_current_dir(&mut self, value: impl Into<String>) {
         let value = value.into();
 
@@ -71,7 +72,6 @@ impl EnvironmentContainer {
         } else {
             error!("Failed to change the working directory to {}", value);
         }
-        self.snapshot_pwd = value;
     }
 }
 
@@ -0,0 +1,69 @@
+//! Shared functionality for the errors of lemurs.
+//!
+//! Every error that can be shown to a user has a stable error code. These codes are shown in the
+//! logs and in the UI, so that a user can look up what went wrong without having to match on the
+//! error message.
+
+/// An error with a stable error code
+pub trait ErrorCode: std::error::Error {
+    /// The stable code of the error (e.g. `AUTH-02`)
+    fn code(&self) -> &'static str;
+
+    /// The error message prefixed with its error code (e.g. `[AUTH-02] Invalid login credentials`)
+    fn with_code(&self) -> String {
+        format!("[{}] {self}", self.code())
+    }
+
+    /// The error as a JSON object with its code and message, for the `--json` flag (e.g.
+    /// `{"code":"AUTH-02","message":"Invalid login credentials"}`)
+    fn to_json(&self) -> String {
+        format!(
+            r#"{{"code":"{}","message":"{}"}}"#,
+            escape_json(self.code()),
+            escape_json(&self.to_string())
+        )
+    }
+}
+
+/// Escape `s` for the inside of a JSON string
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("Cannot open \"{0}\"\n")]
+    struct TestError(&'static str);
+
+    impl ErrorCode for TestError {
+        fn code(&self) -> &'static str {
+            "TEST-01"
+        }
+    }
+
+    #[test]
+    fn json_is_escaped() {
+        assert_eq!(
+            TestError("a\\b").to_json(),
+            r#"{"code":"TEST-01","message":"Cannot open \"a\\b\"\n"}"#
+        );
+        assert_eq!(escape_json("\u{1b}[0m"), "\\u001b[0m");
+    }
+}
@@ -1,19 +1,56 @@
 use log::{info, warn};
-use std::fs::{read_to_string, write};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, read_to_string, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::time::SystemTime;
 
 use crate::config::Config;
 
 const USERNAME_LENGTH_LIMIT: usize = 32;
 
-// Saved in the /var/cache/lemurs file as
-// ```
-// ENVIRONMENT\n
-// USERNAME
-// ```
-#[derive(Debug, Clone)]
+/// The cache may contain usernames, so it should only be readable and writable by root.
+const CACHE_FILE_MODE: u32 = 0o600;
+
+/// The version of the cache file format that is written by this version of lemurs.
+///
+/// Version 0 is the legacy format that is saved as
+/// ```text
+/// ENVIRONMENT\n
+/// USERNAME
+/// ```
+const CACHE_VERSION: u32 = 1;
+
+/// Saved in the /var/cache/lemurs file as a TOML file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CachedInfo {
+    version: u32,
     environment: Option<String>,
     username: Option<String>,
+    /// Seconds since the UNIX epoch of the last successful login
+    last_login: Option<u64>,
+    /// Amount of failed login attempts since the last successful login
+    #[serde(default)]
+    failed_attempts: u32,
+    /// Seconds since the UNIX epoch of the last failed login attempt
+    last_failed_attempt: Option<u64>,
+    /// Information that is remembered per user
+    #[serde(default)]
+    users: BTreeMap<String, CachedUserInfo>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachedUserInfo {
+    environment: Option<String>,
+    /// Seconds since the UNIX epoch of the last successful login
+    last_login: Option<u64>,
+    /// Whether the user enabled HiDPI scaling
+    hidpi: Option<bool>,
+    /// The name of the display layout that the user chose
+    display_layout: Option<String>,
 }
 
 fn verify_username(username: &str) -> bool {
@@ -39,6 +76,14 @@ fn verify_username(username: &str) -> bool {
     true
 }
 
+/// The current time in seconds since the UNIX epoch
+pub fn now_as_secs() -> Option<u64> {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_secs())
+}
+
 impl CachedInfo {
     pub fn environment(&self) -> Option<&str> {
         self.environment.as_deref()
@@ -47,64 +92,254 @@ impl CachedInfo {
     pub fn username(&self) -> Option<&str> {
         self.username.as_deref()
     }
+
+    pub fn last_login(&self) -> Option<u64> {
+        self.last_login
+    }
+
+    pub fn failed_attempts(&self) -> u32 {
+        self.failed_attempts
+    }
+
+    pub fn last_failed_attempt(&self) -> Option<u64> {
+        self.last_failed_attempt
+    }
+
+    pub fn user(&self, username: &str) -> Option<&CachedUserInfo> {
+        self.users.get(username)
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = (&str, &CachedUserInfo)> {
+        self.users.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Parse the legacy (version 0) two-line cache format
+    fn from_legacy(cached: &str) -> Self {
+        let mut lines = cached.trim().lines();
+
+        let environment = lines.next().filter(|s| !s.is_empty());
+        let username = lines.next().filter(|s| !s.is_empty());
+
+        CachedInfo {
+            version: CACHE_VERSION,
+            environment: environment.map(str::to_string),
+            username: username.map(str::to_string),
+            ..CachedInfo::default()
+        }
+    }
+
+    /// Parse the cache file content, migrating from older formats where needed
+    fn parse(cached: &str) -> Self {
+        match toml::from_str::<CachedInfo>(cached) {
+            Ok(cached_info) if cached_info.version > CACHE_VERSION => {
+                warn!(
+                    "Cache file has version {} which is newer than the supported version {}. Ignoring it.",
+                    cached_info.version, CACHE_VERSION
+                );
+                CachedInfo::default()
+            }
+            Ok(cached_info) => cached_info,
+            Err(_) => {
+                info!("Cache file is not in the current format. Migrating from the legacy format.");
+                Self::from_legacy(cached)
+            }
+        }
+    }
+
+    /// Remove all usernames that do not pass the username verification
+    fn sanitize(mut self) -> Self {
+        if let Some(username) = self.username.as_deref() {
+            // Username length check
+            if username.len() > USERNAME_LENGTH_LIMIT {
+                warn!("Cached username is too long and is therefore not loaded.");
+                self.username = None;
+
+            // Username validity check (through regex)
+            } else if !verify_username(username) {
+                warn!("Cached username is not a valid username and is therefore not loaded.");
+                self.username = None;
+            }
+        }
+
+        self.users.retain(|username, _| {
+            let is_valid = verify_username(username);
+            if !is_valid {
+                warn!("Ignoring invalid username in the per-user cache.");
+            }
+            is_valid
+        });
+
+        self
+    }
 }
 
-pub fn get_cached_information(config: &Config) -> CachedInfo {
+impl CachedUserInfo {
+    pub fn environment(&self) -> Option<&str> {
+        self.environment.as_deref()
+    }
+
+    pub fn last_login(&self) -> Option<u64> {
+        self.last_login
+    }
+
+    pub fn hidpi(&self) -> Option<bool> {
+        self.hidpi
+    }
+
+    pub fn display_layout(&self) -> Option<&str> {
+        self.display_layout.as_deref()
+    }
+}
+
+/// Serialize the access to the cache between instances of lemurs on different ttys. The lock is
+/// released when the returned file is dropped.
+fn lock_cache(cache_path: &str, operation: libc::c_int) -> Option<File> {
+    // Special files, such as `/dev/null`, do not need a lock next to them
+    if fs::metadata(cache_path).is_ok_and(|metadata| !metadata.is_file()) {
+        return None;
+    }
+
+    let lock_path = format!("{cache_path}.lock");
+    let file = match OpenOptions::new()
+        .create(true)
+        .write(true)
+        .mode(CACHE_FILE_MODE)
+        .open(&lock_path)
+    {
+        Ok(file) => file,
+        Err(err) => {
+            info!("Not locking the cache with '{lock_path}'. Reason: {err}");
+            return None;
+        }
+    };
+
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } != 0 {
+        warn!(
+            "Failed to lock the cache. Reason: {}",
+            io::Error::last_os_error()
+        );
+        return None;
+    }
+
+    Some(file)
+}
+
+fn read_cache(config: &Config) -> CachedInfo {
     let cache_path = &config.cache_path;
 
+    if !config.cache.enabled {
+        info!("Cache is disabled. Not reading from '{cache_path}'");
+        return CachedInfo::default();
+    }
+
     info!("Attempting to get a cached information from '{cache_path}'",);
 
     match read_to_string(cache_path) {
-        Ok(cached) => {
-            // Remove any line feeds
-            let cached = cached.trim().to_string();
+        Ok(cached) => CachedInfo::parse(&cached).sanitize(),
+        Err(err) => {
+            warn!("Unable to read cache file. Reason: '{}'", err);
+            CachedInfo::default()
+        }
+    }
+}
 
-            let mut lines = cached.lines();
+pub fn get_cached_information(config: &Config) -> CachedInfo {
+    let lock = config
+        .cache
+        .enabled
+        .then(|| lock_cache(&config.cache_path, libc::LOCK_SH))
+        .flatten();
+    let cached_info = read_cache(config);
+    drop(lock);
+
+    info!(
+        "Read cache file and found environment '{}' and username '{}'",
+        cached_info.environment().unwrap_or("None"),
+        cached_info.username().unwrap_or("None")
+    );
 
-            let cached_environment = lines.next();
-            let cached_username = lines.next();
+    cached_info
+}
 
+/// Write the contents of the cache file with root-only permissions and flush it to disk
+fn write_cache_file(cache_path: &Path, content: &str) -> io::Result<()> {
+    if let Some(parent) = cache_path.parent() {
+        if !parent.as_os_str().is_empty() && !parent.exists() {
             info!(
-                "Read cache file and found environment '{}' and username '{}'",
-                cached_environment.unwrap_or("None"),
-                cached_username.unwrap_or("None")
+                "Creating parent directory '{}' for cache file",
+                parent.display()
             );
+            fs::create_dir_all(parent)?;
+        }
+    }
 
-            let cached_username = if let Some(cached_username) = cached_username {
-                // Username length check
-                if cached_username.len() > USERNAME_LENGTH_LIMIT {
-                    warn!("Cached username is too long and is therefore not loaded.");
-                    None
-
-                // Username validity check (through regex)
-                } else if !verify_username(cached_username) {
-                    warn!("Cached username is not a valid username and is therefore not loaded.");
-                    None
-                } else {
-                    Some(cached_username)
-                }
-            } else {
-                cached_username
-            };
-
-            CachedInfo {
-                environment: cached_environment.map(|x| x.to_string()),
-                username: cached_username.map(|x| x.to_string()),
-            }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(CACHE_FILE_MODE)
+        .open(cache_path)?;
+
+    // The mode is only applied on creation. Make sure an existing cache file is also tightened,
+    // but leave special files such as `/dev/null` alone.
+    let metadata = file.metadata()?;
+    if metadata.is_file() {
+        if metadata.permissions().mode() & 0o777 != CACHE_FILE_MODE {
+            info!("Restricting permissions of the cache file");
+            file.set_permissions(fs::Permissions::from_mode(CACHE_FILE_MODE))?;
+        }
+
+        if uzers::get_current_uid() == 0 {
+            nix::unistd::chown(
+                cache_path,
+                Some(nix::unistd::Uid::from_raw(0)),
+                Some(nix::unistd::Gid::from_raw(0)),
+            )
+            .map_err(io::Error::from)?;
+        }
+    }
+
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+fn write_cache(cached_info: &CachedInfo, config: &Config) {
+    let cache_path = &config.cache_path;
+
+    let cache_file_content = match toml::to_string(cached_info) {
+        Ok(content) => content,
+        Err(err) => {
+            warn!("Failed to serialize cache. Reason: '{err}'");
+            return;
         }
+    };
+
+    match write_cache_file(Path::new(cache_path), &cache_file_content) {
         Err(err) => {
-            warn!("Unable to read cache file. Reason: '{}'", err);
-            CachedInfo {
-                environment: None,
-                username: None,
-            }
+            warn!("Failed to write cache file. Reason: '{err}'");
+        }
+        _ => {
+            info!("Successfully wrote cache file");
         }
     }
 }
 
-pub fn set_cache(environment: Option<&str>, username: Option<&str>, config: &Config) {
+pub fn set_cache(
+    environment: Option<&str>,
+    username: Option<&str>,
+    hidpi: Option<bool>,
+    display_layout: Option<&str>,
+    config: &Config,
+) {
     let cache_path = &config.cache_path;
 
+    if !config.cache.enabled {
+        info!("Cache is disabled. Not writing to '{cache_path}'");
+        return;
+    }
+
     info!("Attempting to set cache: {cache_path}");
 
     let username = if let Some(username) = username {
@@ -125,18 +360,116 @@ pub fn set_cache(environment: Option<&str>, username: Option<&str>, config: &Con
         None
     };
 
-    let cache_file_content = format!(
-        "{}\n{}\n",
-        environment.unwrap_or_default(),
-        username.unwrap_or_default()
-    );
+    let _lock = lock_cache(cache_path, libc::LOCK_EX);
+    let mut cached_info = read_cache(config);
+    let now = now_as_secs();
 
-    match write(cache_path, cache_file_content) {
-        Err(err) => {
-            warn!("Failed to set username to cache file. Reason: '{err}'");
+    cached_info.version = CACHE_VERSION;
+    cached_info.environment = environment.map(str::to_string);
+    cached_info.username = username.map(str::to_string);
+    cached_info.last_login = now;
+
+    if let Some(username) = username {
+        let user_info = cached_info.users.entry(username.to_string()).or_default();
+
+        if environment.is_some() {
+            user_info.environment = environment.map(str::to_string);
         }
-        _ => {
-            info!("Successfully set username in cache file");
+        if hidpi.is_some() {
+            user_info.hidpi = hidpi;
+        }
+        if display_layout.is_some() {
+            user_info.display_layout = display_layout.map(str::to_string);
         }
+        user_info.last_login = now;
+    }
+
+    write_cache(&cached_info, config);
+}
+
+/// Store the amount of failed login attempts since the last successful login
+pub fn set_failed_attempts(failed_attempts: u32, config: &Config) {
+    if !config.cache.enabled {
+        return;
+    }
+
+    info!("Storing {failed_attempts} failed login attempt(s) in cache");
+
+    let _lock = lock_cache(&config.cache_path, libc::LOCK_EX);
+    let mut cached_info = read_cache(config);
+
+    cached_info.version = CACHE_VERSION;
+    cached_info.failed_attempts = failed_attempts;
+    cached_info.last_failed_attempt = if failed_attempts == 0 {
+        None
+    } else {
+        now_as_secs()
+    };
+
+    write_cache(&cached_info, config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_migration() {
+        let cached_info = CachedInfo::parse("i3\nlemur\n");
+        assert_eq!(cached_info.version, CACHE_VERSION);
+        assert_eq!(cached_info.environment(), Some("i3"));
+        assert_eq!(cached_info.username(), Some("lemur"));
+
+        let cached_info = CachedInfo::parse("\n\n");
+        assert_eq!(cached_info.environment(), None);
+        assert_eq!(cached_info.username(), None);
+    }
+
+    #[test]
+    fn toml_roundtrip() {
+        let mut cached_info = CachedInfo {
+            version: CACHE_VERSION,
+            environment: Some("sway".to_string()),
+            username: Some("lemur".to_string()),
+            last_login: Some(42),
+            ..CachedInfo::default()
+        };
+        cached_info.users.insert(
+            "lemur".to_string(),
+            CachedUserInfo {
+                environment: Some("sway".to_string()),
+                last_login: Some(42),
+                hidpi: Some(true),
+                display_layout: Some("Docked".to_string()),
+            },
+        );
+
+        let content = toml::to_string(&cached_info).unwrap();
+        let parsed = CachedInfo::parse(&content);
+
+        assert_eq!(parsed.environment(), Some("sway"));
+        assert_eq!(parsed.username(), Some("lemur"));
+        assert_eq!(parsed.last_login(), Some(42));
+        assert_eq!(
+            parsed.user("lemur").and_then(CachedUserInfo::environment),
+            Some("sway")
+        );
+        assert_eq!(
+            parsed.user("lemur").and_then(CachedUserInfo::hidpi),
+            Some(true)
+        );
+        assert_eq!(
+            parsed
+                .user("lemur")
+                .and_then(CachedUserInfo::display_layout),
+            Some("Docked")
+        );
+    }
+
+    #[test]
+    fn invalid_usernames_are_dropped() {
+        let cached_info = CachedInfo::parse("i3\n0invalid\n").sanitize();
+        assert_eq!(cached_info.username(), None);
+        assert_eq!(cached_info.environment(), Some("i3"));
     }
 }
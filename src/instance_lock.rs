@@ -0,0 +1,86 @@
+//! Locking of the tty of lemurs, so that two instances do not fight over the same VT.
+//!
+//! The lock is an `flock` on a file in `/run/lemurs`. The kernel releases it when the instance
+//! exits, also when it crashes, so a lock file that is left behind does not block the next
+//! instance. The file contains the pid of the instance that holds the lock.
+//!
+//! Instances in windowed mode do not switch to their tty, so they do not take the lock.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+use log::info;
+use thiserror::Error;
+
+use crate::error::ErrorCode;
+
+const LOCK_DIR: &str = "/run/lemurs";
+
+#[derive(Debug, Error)]
+pub enum InstanceLockError {
+    #[error("Lemurs is already running on tty {tty}{}", .pid.map(|pid| format!(" with pid {pid}")).unwrap_or_default())]
+    AlreadyRunning { tty: u8, pid: Option<u32> },
+    #[error("Failed to lock tty {tty}. Reason: {err}")]
+    Io { tty: u8, err: io::Error },
+}
+
+impl ErrorCode for InstanceLockError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::AlreadyRunning { .. } => "LOCK-01",
+            Self::Io { .. } => "LOCK-02",
+        }
+    }
+}
+
+/// The lock on the tty of this instance. It is released when this is dropped.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    pub fn acquire(tty: u8) -> Result<Self, InstanceLockError> {
+        let io_error = |err| InstanceLockError::Io { tty, err };
+
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(LOCK_DIR)
+            .map_err(io_error)?;
+
+        let path = PathBuf::from(LOCK_DIR).join(format!("tty{tty}.lock"));
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .mode(0o644)
+            .open(&path)
+            .map_err(io_error)?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::WouldBlock {
+                return Err(io_error(err));
+            }
+
+            let mut content = String::new();
+            let pid = file
+                .read_to_string(&mut content)
+                .ok()
+                .and_then(|_| content.trim().parse().ok());
+            return Err(InstanceLockError::AlreadyRunning { tty, pid });
+        }
+
+        // The pid of a previous instance is replaced
+        file.set_len(0).map_err(io_error)?;
+        file.rewind().map_err(io_error)?;
+        writeln!(file, "{}", std::process::id()).map_err(io_error)?;
+
+        info!("Locked tty {tty} with '{}'", path.display());
+
+        Ok(Self { _file: file })
+    }
+}
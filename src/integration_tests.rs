@@ -0,0 +1,277 @@
+//! End-to-end tests of the login flow.
+//!
+//! These tests run the complete `start_session` path, so they need to be run as root with a
+//! permissive PAM service installed. They are ignored by default and can be run with
+//!
+//! ```sh
+//! sudo install -m 644 extra/lemurs-test.pam /etc/pam.d/lemurs-test
+//! sudo -E cargo test -- --ignored --test-threads=1
+//! ```
+//!
+//! The X11 test additionally needs `Xvfb` and `xauth` to be installed. The user that is logged in
+//! can be set with the `LEMURS_TEST_USER` environment variable and defaults to `root`.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use crate::config::Config;
+use crate::post_login::{ExecHooks, PostLoginEnvironment, SessionDesktop};
+use crate::{start_session, Hooks};
+
+const TEST_PAM_SERVICE: &str = "lemurs-test";
+const TEST_TTY: u8 = 9;
+const TEST_DISPLAY: &str = ":99";
+
+/// `start_session` modifies the process environment, so only one session can run at a time.
+static SESSION_LOCK: Mutex<()> = Mutex::new(());
+
+fn test_user() -> String {
+    env::var("LEMURS_TEST_USER").unwrap_or_else(|_| "root".to_string())
+}
+
+fn test_config() -> Config {
+    assert_eq!(
+        uzers::get_current_uid(),
+        0,
+        "The integration tests need to be run as root"
+    );
+
+    let mut config = Config::default();
+
+    config.pam_service = TEST_PAM_SERVICE.to_string();
+    config.tty = TEST_TTY;
+    config.do_log = false;
+    config.cache.enabled = false;
+    config.auth.allow_root = true;
+
+    config
+}
+
+/// A temporary directory that is writable by the test user and removed when dropped
+struct TestDir(PathBuf);
+
+impl TestDir {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("lemurs-test-{}-{name}", std::process::id()));
+
+        fs::create_dir_all(&path).expect("Failed to create test directory");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o777))
+            .expect("Failed to set permissions of test directory");
+
+        Self(path)
+    }
+
+    fn file(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn write_script(path: &Path, content: &str) {
+    fs::write(path, content).expect("Failed to write script");
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+        .expect("Failed to make script executable");
+}
+
+/// A script that dumps the environment it was started in to `env_dump`
+fn env_dump_script(env_dump: &Path) -> String {
+    format!("#!/bin/sh\nenv > '{}'\n", env_dump.display())
+}
+
+fn read_env_dump(env_dump: &Path) -> HashMap<String, String> {
+    fs::read_to_string(env_dump)
+        .expect("Session did not write its environment")
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[cfg(target_env = "gnu")]
+fn utmpx_entry_is_active(username: &str, tty: u8) -> bool {
+    fn c_str(chars: &[libc::c_char]) -> String {
+        chars
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u8 as char)
+            .collect()
+    }
+
+    let line = format!("tty{tty}");
+    let mut is_active = false;
+
+    unsafe {
+        libc::setutxent();
+
+        loop {
+            let entry = libc::getutxent();
+            if entry.is_null() {
+                break;
+            }
+
+            let entry = &*entry;
+            if entry.ut_type == libc::USER_PROCESS
+                && c_str(&entry.ut_line) == line
+                && c_str(&entry.ut_user) == username
+            {
+                is_active = true;
+                break;
+            }
+        }
+
+        libc::endutxent();
+    }
+
+    is_active
+}
+
+#[cfg(not(target_env = "gnu"))]
+fn utmpx_entry_is_active(_username: &str, _tty: u8) -> bool {
+    false
+}
+
+/// Run a session and return the environment of the session and whether the UTMPX entry was active
+/// while waiting for the session.
+fn run_session(
+    username: &str,
+    post_login_env: &PostLoginEnvironment,
+    config: &Config,
+    env_dump: &Path,
+) -> (HashMap<String, String>, bool) {
+    let utmpx_was_active = Cell::new(false);
+    let pre_wait = || utmpx_was_active.set(utmpx_entry_is_active(username, TEST_TTY));
+
+    let hooks = Hooks {
+        pre_validate: None,
+        pre_auth: None,
+        pre_environment: None,
+        pre_wait: Some(&pre_wait),
+        pre_return: None,
+    };
+
+    let env_before = env::vars().collect::<HashMap<_, _>>();
+    let dir_before = env::current_dir().ok();
+
+    start_session(username, "", post_login_env, &hooks, config)
+        .unwrap_or_else(|err| panic!("Failed to start session: {err}"));
+
+    // Lemurs should be back in the state it was in before the session
+    assert_eq!(env::vars().collect::<HashMap<_, _>>(), env_before);
+    assert_eq!(env::current_dir().ok(), dir_before);
+    assert!(!utmpx_entry_is_active(username, TEST_TTY));
+
+    (read_env_dump(env_dump), utmpx_was_active.get())
+}
+
+fn assert_user_environment(session_env: &HashMap<String, String>, username: &str) {
+    let user = uzers::get_user_by_name(username).expect("Test user does not exist");
+    let get = |key: &str| session_env.get(key).map(String::as_str);
+
+    assert_eq!(get("USER"), Some(username));
+    assert_eq!(get("LOGNAME"), Some(username));
+    assert_eq!(get("HOME"), user.home_dir().to_str());
+    assert_eq!(get("XDG_SESSION_CLASS"), Some("user"));
+    assert_eq!(get("XDG_SEAT"), Some("seat0"));
+    assert_eq!(get("XDG_VTNR"), Some(TEST_TTY.to_string().as_str()));
+    assert_eq!(
+        get("XDG_RUNTIME_DIR"),
+        Some(format!("/run/user/{}", user.uid()).as_str())
+    );
+}
+
+#[test]
+#[ignore = "needs root and the lemurs-test PAM service"]
+fn wayland_session() {
+    let _guard = SESSION_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let config = test_config();
+    let username = test_user();
+
+    let dir = TestDir::new("wayland");
+    let env_dump = dir.file("env");
+    let compositor = dir.file("compositor.sh");
+    write_script(&compositor, &env_dump_script(&env_dump));
+
+    let post_login_env = PostLoginEnvironment::Wayland {
+        script_path: compositor.display().to_string(),
+        desktop: SessionDesktop {
+            session: "test-compositor".to_string(),
+            current_desktop: Some("Test:wlroots".to_string()),
+        },
+        hooks: ExecHooks::default(),
+    };
+
+    let (session_env, utmpx_was_active) =
+        run_session(&username, &post_login_env, &config, &env_dump);
+
+    assert_user_environment(&session_env, &username);
+    assert_eq!(
+        session_env.get("XDG_SESSION_TYPE").map(String::as_str),
+        Some("wayland")
+    );
+    assert_eq!(
+        session_env.get("DESKTOP_SESSION").map(String::as_str),
+        Some("test-compositor")
+    );
+    assert_eq!(
+        session_env.get("XDG_CURRENT_DESKTOP").map(String::as_str),
+        Some("Test:wlroots")
+    );
+    assert_eq!(utmpx_was_active, cfg!(target_env = "gnu"));
+}
+
+#[test]
+#[ignore = "needs root, the lemurs-test PAM service and Xvfb"]
+fn x11_session() {
+    let _guard = SESSION_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let mut config = test_config();
+    let username = test_user();
+
+    let dir = TestDir::new("x11");
+    let env_dump = dir.file("env");
+    let xinitrc = dir.file("xinitrc.sh");
+    let xserver = dir.file("xserver.sh");
+    write_script(&xinitrc, &env_dump_script(&env_dump));
+    // Xvfb does not know about virtual terminals, so the `vtXX` argument is dropped.
+    write_script(&xserver, "#!/bin/sh\nexec Xvfb \"$1\" -nolisten tcp\n");
+
+    config.x11.x11_display = TEST_DISPLAY.to_string();
+    config.x11.xserver_path = xserver.display().to_string();
+    config.x11.xsetup_path = concat!(env!("CARGO_MANIFEST_DIR"), "/extra/xsetup.sh").to_string();
+
+    let post_login_env = PostLoginEnvironment::X {
+        xinitrc_path: xinitrc.display().to_string(),
+        desktop: SessionDesktop::default(),
+        hooks: ExecHooks::default(),
+    };
+
+    let (session_env, utmpx_was_active) =
+        run_session(&username, &post_login_env, &config, &env_dump);
+
+    assert_user_environment(&session_env, &username);
+    assert_eq!(
+        session_env.get("XDG_SESSION_TYPE").map(String::as_str),
+        Some("x11")
+    );
+    assert_eq!(
+        session_env.get("DISPLAY").map(String::as_str),
+        Some(TEST_DISPLAY)
+    );
+    assert!(session_env.contains_key("XAUTHORITY"));
+    assert_eq!(utmpx_was_active, cfg!(target_env = "gnu"));
+
+    // The X server should have been shut down with the session
+    let display_number = TEST_DISPLAY.trim_start_matches(':');
+    assert!(!Path::new(&format!("/tmp/.X{display_number}-lock")).exists());
+}
@@ -1,38 +1,66 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io;
+use std::time::Instant;
 use std::{error::Error, path::Path};
 
 use crossterm::{
+    cursor::SetCursorStyle,
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use log::{error, info, warn};
+use once_cell::sync::OnceCell;
 use ratatui::backend::CrosstermBackend;
-use ratatui::Terminal;
+use ratatui::layout::Rect;
+use ratatui::{Terminal, TerminalOptions, Viewport};
 
 mod auth;
+mod cgroup;
 mod chvt;
 mod cli;
 mod config;
+mod console_font;
+mod console_state;
+mod default_config;
 mod env_container;
+mod error;
 mod info_caching;
+mod instance_lock;
+#[cfg(test)]
+mod integration_tests;
+mod metrics;
+mod network;
+mod notifications;
 mod post_login;
+mod priority;
+mod provisioning;
 mod ui;
+mod users;
+mod vt_control;
 
 use auth::try_auth;
-use config::Config;
-use post_login::{EnvironmentStartError, PostLoginEnvironment};
+use config::{Config, CursorConfig, StringMap};
+use instance_lock::InstanceLock;
+use post_login::session_timer::SessionTimer;
+use post_login::watchdog::Watchdog;
+use post_login::{EnvironmentStartError, PostLoginEnvironment, SessionSummary};
+use ui::config_error::ConfigErrorChoice;
 
 use crate::{
     auth::utmpx::add_utmpx_entry,
     cli::{Cli, Commands},
+    console_state::ConsoleState,
 };
 
 use self::{
     auth::AuthenticationError,
+    cgroup::{GreeterCgroup, SessionScope},
     env_container::EnvironmentContainer,
+    error::ErrorCode,
     post_login::env_variables::{
-        remove_xdg, set_basic_variables, set_display, set_seat_vars, set_session_params,
+        remove_xdg, set_basic_variables, set_display, set_display_layout_vars, set_hidpi_vars,
+        set_locale_vars, set_seat_vars, set_session_params, set_session_type_vars,
         set_session_vars, set_xdg_common_paths,
     },
 };
@@ -41,11 +69,20 @@ const DEFAULT_VARIABLES_PATH: &str = "/etc/lemurs/variables.toml";
 const DEFAULT_CONFIG_PATH: &str = "/etc/lemurs/config.toml";
 const PREVIEW_LOG_PATH: &str = "lemurs.log";
 
+/// The values that the variables of `greeter_env` had before they were set for lemurs itself. The
+/// sessions get these values back.
+static GREETER_ENV_ORIGINALS: OnceCell<HashMap<String, Option<String>>> = OnceCell::new();
+
+/// Merge the configuration file, the variables file and the overrides from environment variables
+/// into `config`. If a file that was given explicitly cannot be loaded, the rest is still merged
+/// in and the reason is returned.
 fn merge_in_configuration(
     config: &mut Config,
     config_path: Option<&Path>,
     variables_path: Option<&Path>,
-) {
+) -> Result<(), String> {
+    let mut load_error = None;
+
     let load_variables_path = variables_path.unwrap_or_else(|| Path::new(DEFAULT_VARIABLES_PATH));
 
     let variables = match config::Variables::from_file(load_variables_path) {
@@ -58,15 +95,14 @@ fn merge_in_configuration(
             Some(variables)
         }
         Err(err) => {
-            // If we have given it a specific config path, it should crash if this file cannot be
-            // loaded. If it is the default config location just put a warning in the logs.
+            // If we have given it a specific path, it is an error if this file cannot be loaded.
+            // If it is the default location just put a warning in the logs.
             if let Some(variables_path) = variables_path {
-                eprintln!(
+                load_error = Some(format!(
                     "The variables file '{}' cannot be loaded.\nReason: {}",
                     variables_path.display(),
                     err
-                );
-                std::process::exit(1);
+                ));
             } else {
                 info!(
                     "No variables file loaded from the default location ({}). Reason: {}",
@@ -89,15 +125,14 @@ fn merge_in_configuration(
             config.merge_in_partial(partial_config)
         }
         Err(err) => {
-            // If we have given it a specific config path, it should crash if this file cannot be
-            // loaded. If it is the default config location just put a warning in the logs.
+            // If we have given it a specific path, it is an error if this file cannot be loaded.
+            // If it is the default location just put a warning in the logs.
             if let Some(config_path) = config_path {
-                eprintln!(
+                load_error.get_or_insert(format!(
                     "The config file '{}' cannot be loaded.\nReason: {}",
                     config_path.display(),
                     err
-                );
-                std::process::exit(1);
+                ));
             } else {
                 warn!(
                     "No configuration file loaded from the expected location ({}). Reason: {}",
@@ -106,6 +141,80 @@ fn merge_in_configuration(
             }
         }
     }
+
+    // Variables that are not valid UTF-8 cannot be configuration overrides
+    let env_vars = std::env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)));
+    match config::PartialConfig::from_env_vars(env_vars) {
+        Ok(Some(partial_config)) => {
+            info!("Applying configuration overrides from environment variables");
+            config.merge_in_partial(partial_config)
+        }
+        Ok(None) => {}
+        Err(err) => {
+            load_error.get_or_insert(format!(
+                "The configuration overrides from environment variables cannot be loaded.\nReason: {err}"
+            ));
+        }
+    }
+
+    match load_error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Show why the configuration cannot be loaded until it loads after a retry, or the user chooses
+/// to continue with the default configuration
+fn recover_from_config_error(cli: &Cli, mut err: String) -> io::Result<Config> {
+    error!("{err}");
+
+    // The screen has to be visible on boot, before lemurs switched to its configured tty
+    if !cli.preview && !cli.windowed {
+        if let Some(tty) = own_tty() {
+            if let Err(err) = unsafe { chvt::chvt(tty.into()) } {
+                error!("Failed to switch to tty {tty}. Reason: {err}");
+            }
+        }
+    }
+
+    let mut terminal = tui_enable(cli.size, &Config::default().cursor)?;
+    let config = loop {
+        match ui::config_error::show_config_error(&mut terminal, &err)? {
+            ConfigErrorChoice::Retry => {
+                let mut config = Config::default();
+                match merge_in_configuration(
+                    &mut config,
+                    cli.config.as_deref(),
+                    cli.variables.as_deref(),
+                ) {
+                    Ok(()) => {
+                        info!("Loaded the configuration after a retry");
+                        break config;
+                    }
+                    Err(new_err) => {
+                        error!("{new_err}");
+                        err = new_err;
+                    }
+                }
+            }
+            // The configuration is not loaded again, since the parts that did load would otherwise
+            // be merged into the defaults
+            ConfigErrorChoice::Defaults => {
+                warn!("Continuing with the default configuration");
+                break Config::default();
+            }
+        }
+    };
+    tui_disable(terminal)?;
+
+    Ok(config)
+}
+
+/// The number of the tty that lemurs is running on, if it is a virtual console
+fn own_tty() -> Option<u8> {
+    let path = std::fs::read_link("/proc/self/fd/0").ok()?;
+    path.to_str()?.strip_prefix("/dev/tty")?.parse().ok()
 }
 
 pub fn initialize_panic_handler() {
@@ -133,21 +242,52 @@ fn setup_logger(log_path: &str) {
 
 fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse().unwrap_or_else(|err| {
-        eprintln!("{err}\n");
-        cli::usage();
+        if cli::json_requested() {
+            eprintln!("{}", err.to_json());
+        } else {
+            eprintln!("{}\n", err.with_code());
+            cli::usage();
+        }
         std::process::exit(2);
     });
 
     let mut config = Config::default();
-    merge_in_configuration(&mut config, cli.config.as_deref(), cli.variables.as_deref());
+    let config_error =
+        merge_in_configuration(&mut config, cli.config.as_deref(), cli.variables.as_deref()).err();
 
     if let Some(cmd) = cli.command {
+        if let Some(err) = config_error {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+
         match cmd {
             Commands::Envs => {
+                if let Some(env_name) = cli.explain.as_deref() {
+                    explain_environment(env_name, cli.user.as_deref(), &config).unwrap_or_else(
+                        |err| {
+                            eprintln!("{err}");
+                            std::process::exit(1);
+                        },
+                    );
+                    return Ok(());
+                }
+
                 let envs = post_login::get_envs(&config);
 
-                for (env_name, _) in envs.into_iter() {
-                    println!("{env_name}");
+                for entry in envs.into_iter() {
+                    println!("{}", entry.name);
+                }
+            }
+            Commands::TestSession(env_name) => {
+                test_session(&env_name, &config).unwrap_or_else(|err| {
+                    eprintln!("{err}");
+                    std::process::exit(1);
+                });
+            }
+            Commands::Users => {
+                for username in users::get_users(&config.users) {
+                    println!("{username}");
                 }
             }
             Commands::Cache => {
@@ -163,6 +303,27 @@ fn main() -> Result<(), Box<dyn Error>> {
 
                 println!("environment: '{environment}'");
                 println!("username: '{username}'");
+
+                if let Some(last_login) = cached_info.last_login() {
+                    println!("last login: {last_login}");
+                }
+
+                for (username, user_info) in cached_info.users() {
+                    println!("\nuser '{username}':");
+                    println!(
+                        "  environment: '{}'",
+                        user_info.environment().unwrap_or("No cached value")
+                    );
+                    if let Some(last_login) = user_info.last_login() {
+                        println!("  last login: {last_login}");
+                    }
+                }
+            }
+            Commands::Render => {
+                let (width, height) = cli.size.unwrap_or((80, 24));
+                let buffer = ui::headless::render(config, width, height)?;
+                let format = cli.format.unwrap_or(ui::screenshot::Format::Ansi);
+                print!("{}", format.render(&buffer));
             }
             Commands::Help => {
                 cli::usage();
@@ -183,15 +344,22 @@ fn main() -> Result<(), Box<dyn Error>> {
             &config.main_log_path
         });
         info!("Main lemurs logger is running");
-    } else {
+    }
+
+    if let Some(err) = config_error {
+        config = recover_from_config_error(&cli, err)?;
+    }
+
+    if cli.no_log {
         config.do_log = false;
     }
 
-    if !cli.preview {
-        if std::env::var("XDG_SESSION_TYPE").is_ok() {
-            eprintln!("Lemurs cannot be ran without `--preview` within an existing session. Namely, `XDG_SESSION_TYPE` is set.");
-            error!("Lemurs cannot be started when within an existing session. Namely, `XDG_SESSION_TYPE` is set.");
-            std::process::exit(1);
+    check_config(&config);
+
+    let _instance_lock = if !cli.preview {
+        // In windowed mode, lemurs runs inside of a (minimal) compositor which has its own session.
+        if !cli.windowed {
+            check_inherited_session(cli.force || config.clear_inherited_session);
         }
 
         let uid = uzers::get_current_uid();
@@ -204,21 +372,67 @@ fn main() -> Result<(), Box<dyn Error>> {
         if let Some(tty) = cli.tty {
             info!("Overwritten the tty to '{tty}' with the --tty flag");
             config.tty = tty;
+        } else if let Some(tty) = own_tty() {
+            // Lemurs draws on the tty it is started on, so that is the tty to switch to
+            if tty != config.tty {
+                info!(
+                    "Running on tty {tty}. Using it instead of the configured tty {}",
+                    config.tty
+                );
+                config.tty = tty;
+            }
         }
 
-        // Switch to the proper tty
-        info!("Switching to tty {}", config.tty);
-
-        unsafe { chvt::chvt(config.tty.into()) }.unwrap_or_else(|err| {
-            error!("Failed to switch tty {}. Reason: {err}", config.tty);
+        // In windowed mode, lemurs does not take over the tty, so it does not lock it either
+        let instance_lock = (!cli.windowed).then(|| {
+            InstanceLock::acquire(config.tty).unwrap_or_else(|err| {
+                if cli.json {
+                    eprintln!("{}", err.to_json());
+                } else {
+                    eprintln!("{}", err.with_code());
+                }
+                error!("{}", err.with_code());
+                std::process::exit(1);
+            })
         });
-    }
+
+        if cli.windowed {
+            info!(
+                "Running in windowed mode. Not switching to tty {}",
+                config.tty
+            );
+        } else {
+            // Switch to the proper tty
+            info!("Switching to tty {}", config.tty);
+
+            unsafe { chvt::chvt(config.tty.into()) }.unwrap_or_else(|err| {
+                error!("Failed to switch tty {}. Reason: {err}", config.tty);
+            });
+        }
+
+        priority::set_greeter_oom_score_adj(&config.priority);
+
+        instance_lock
+    } else {
+        None
+    };
 
     initialize_panic_handler();
 
+    metrics::init(&config);
+
+    // The console font is only relevant when lemurs is running on the Linux console itself.
+    let _console_font = if cli.preview || cli.windowed {
+        None
+    } else {
+        console_font::set_console_font(&config.console_font)
+    };
+
+    let _greeter_env = set_greeter_environment(&config.greeter_env);
+
     // Start application
-    let mut terminal = tui_enable()?;
-    let login_form = ui::LoginForm::new(config, cli.preview);
+    let mut terminal = tui_enable(cli.size, &config.cursor)?;
+    let login_form = ui::LoginForm::new(config, cli.preview).windowed(cli.windowed);
     login_form.run(&mut terminal)?;
     tui_disable(terminal)?;
 
@@ -227,12 +441,99 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-pub fn tui_enable() -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
+/// Report the problems in the configuration. With `strict_config`, lemurs exits if there are any.
+fn check_config(config: &Config) {
+    let problems = config.validate();
+
+    if problems.is_empty() {
+        return;
+    }
+
+    if config.strict_config {
+        eprintln!("The configuration contains {} problem(s):", problems.len());
+        for problem in &problems {
+            eprintln!("  - {problem}");
+            error!("Configuration problem: {problem}");
+        }
+        std::process::exit(1);
+    }
+
+    for problem in &problems {
+        warn!("Configuration problem: {problem}");
+    }
+}
+
+/// The variables that indicate that lemurs is started from within an existing session. The first
+/// variable blocks startup, the others are cleared together with it.
+const INHERITED_SESSION_VARIABLES: [&str; 7] = [
+    "XDG_SESSION_TYPE",
+    "XDG_SESSION_ID",
+    "XDG_SESSION_CLASS",
+    "XDG_SEAT",
+    "XDG_VTNR",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+];
+
+/// Refuse to start when lemurs inherited the environment of an existing session, or clear the
+/// inherited variables if `clear` is set.
+fn check_inherited_session(clear: bool) {
+    let Ok(session_type) = std::env::var(INHERITED_SESSION_VARIABLES[0]) else {
+        return;
+    };
+
+    let inherited = INHERITED_SESSION_VARIABLES
+        .iter()
+        .filter_map(|&key| {
+            std::env::var(key)
+                .ok()
+                .map(|value| format!("{key}='{value}'"))
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if clear {
+        warn!("Clearing inherited session variables: {inherited}");
+
+        for key in INHERITED_SESSION_VARIABLES {
+            std::env::remove_var(key);
+        }
+
+        return;
+    }
+
+    eprintln!("Lemurs cannot be ran without `--preview` within an existing session. Namely, `XDG_SESSION_TYPE` is set to '{session_type}'.");
+    eprintln!("Inherited session variables: {inherited}");
+    eprintln!("This usually means lemurs was started from a unit that leaks its environment. Use `--force` or the `clear_inherited_session` option to clear these variables and start anyway.");
+    error!("Lemurs cannot be started when within an existing session. Namely, `XDG_SESSION_TYPE` is set to '{session_type}'. Inherited session variables: {inherited}");
+    std::process::exit(1);
+}
+
+/// Start the TUI. If a `size` is given, the UI is rendered to a fixed size in the top-left corner
+/// instead of to the whole terminal.
+pub fn tui_enable(
+    size: Option<(u16, u16)>,
+    cursor: &CursorConfig,
+) -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen)?;
+    if let Some(cursor_style) = cursor.cursor_style() {
+        execute!(stdout, cursor_style)?;
+    }
     let backend = CrosstermBackend::new(stdout);
-    let terminal = Terminal::new(backend)?;
+    let terminal = match size {
+        Some((width, height)) => {
+            info!("Rendering to a fixed size of {width}x{height}");
+            Terminal::with_options(
+                backend,
+                TerminalOptions {
+                    viewport: Viewport::Fixed(Rect::new(0, 0, width, height)),
+                },
+            )?
+        }
+        None => Terminal::new(backend)?,
+    };
 
     info!("UI booted up");
 
@@ -241,7 +542,11 @@ pub fn tui_enable() -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
 
 pub fn tui_disable(mut terminal: Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        SetCursorStyle::DefaultUserShape
+    )?;
     terminal.show_cursor()?;
 
     info!("Reset terminal environment");
@@ -257,21 +562,231 @@ struct Hooks<'a> {
     pre_return: Option<&'a dyn Fn()>,
 }
 
+#[derive(Debug, thiserror::Error)]
 pub enum StartSessionError {
-    AuthenticationError(AuthenticationError),
-    EnvironmentStartError(EnvironmentStartError),
+    #[error(transparent)]
+    AuthenticationError(#[from] AuthenticationError),
+    #[error(transparent)]
+    EnvironmentStartError(#[from] EnvironmentStartError),
 }
 
-impl From<EnvironmentStartError> for StartSessionError {
-    fn from(value: EnvironmentStartError) -> Self {
-        Self::EnvironmentStartError(value)
+impl ErrorCode for StartSessionError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::AuthenticationError(err) => err.code(),
+            Self::EnvironmentStartError(err) => err.code(),
+        }
     }
 }
 
-impl From<AuthenticationError> for StartSessionError {
-    fn from(value: AuthenticationError) -> Self {
-        Self::AuthenticationError(value)
+/// Set the environment variables that do not depend on the user that logs in
+///
+/// These are set before the PAM session is opened, so that logind registers the session with the
+/// right type, class, seat and VT.
+fn set_session_environment(
+    process_env: &mut EnvironmentContainer,
+    post_login_env: &PostLoginEnvironment,
+    config: &Config,
+) {
+    remove_xdg(process_env);
+    // With an automatic display, it is only known once the X server is running
+    if matches!(post_login_env, PostLoginEnvironment::X { .. })
+        && !post_login::x::is_auto_display(config)
+    {
+        set_display(&config.x11.x11_display, process_env);
+    }
+    set_session_params(process_env, post_login_env);
+
+    // An X session is on the tty of the X server
+    let vt = match post_login_env {
+        PostLoginEnvironment::X { .. } => post_login::x::server_vt(config),
+        _ => config.tty,
+    };
+    set_seat_vars(process_env, vt);
+
+    // The greeter environment is only meant for lemurs itself
+    if let Some(originals) = GREETER_ENV_ORIGINALS.get() {
+        for (key, original) in originals {
+            match original {
+                Some(value) => process_env.set(key, value),
+                None => process_env.remove_var(key),
+            }
+        }
+    }
+
+    for pattern in &config.session.remove_env {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => {
+                let keys = std::env::vars_os()
+                    .filter_map(|(key, _)| key.into_string().ok())
+                    .filter(|key| key.starts_with(prefix))
+                    .collect::<Vec<_>>();
+
+                for key in keys {
+                    process_env.remove_var(&key);
+                }
+            }
+            None => process_env.remove_var(pattern),
+        }
+    }
+}
+
+/// Set the environment variables for the lemurs process itself. These are reverted when the
+/// returned container is dropped.
+fn set_greeter_environment(greeter_env: &StringMap) -> EnvironmentContainer {
+    let mut process_env = EnvironmentContainer::take_snapshot();
+
+    let originals = greeter_env
+        .0
+        .keys()
+        .map(|key| (key.clone(), std::env::var(key).ok()))
+        .collect();
+    if GREETER_ENV_ORIGINALS.set(originals).is_err() {
+        warn!("The greeter environment was already set. Not recording its original values");
+    }
+
+    for (key, value) in &greeter_env.0 {
+        process_env.set(key, value);
     }
+
+    process_env
+}
+
+/// Set the environment variables for the user that logs in
+fn set_user_environment(
+    process_env: &mut EnvironmentContainer,
+    uid: u32,
+    username: &str,
+    homedir: &str,
+    shell: &str,
+) {
+    set_session_vars(process_env, uid);
+    set_basic_variables(process_env, username, homedir, shell);
+    set_xdg_common_paths(process_env, homedir);
+}
+
+/// Print what would be executed to start `env_name` for `username`, without spawning anything
+fn explain_environment(
+    env_name: &str,
+    username: Option<&str>,
+    config: &Config,
+) -> Result<(), String> {
+    use uzers::os::unix::UserExt;
+
+    let envs = post_login::get_envs(config);
+    let Some(post_login_env) = envs
+        .iter()
+        .find(|entry| entry.name == env_name)
+        .map(|entry| &entry.post_login_env)
+    else {
+        return Err(format!("No environment with the name '{env_name}' found"));
+    };
+
+    let cached_info = info_caching::get_cached_information(config);
+    let Some(username) = username.or(cached_info.username()) else {
+        return Err("No user given with `--user` and no cached username found".to_string());
+    };
+
+    let user = uzers::get_user_by_name(username)
+        .ok_or_else(|| format!("User '{username}' does not exist"))?;
+    let homedir = user
+        .home_dir()
+        .to_str()
+        .ok_or("User home directory path contains invalid UTF-8")?
+        .to_string();
+    let shell = user
+        .shell()
+        .to_str()
+        .ok_or("User shell path contains invalid UTF-8")?
+        .to_string();
+    let groups = user.groups().map_or_else(Vec::default, |groups| {
+        groups.iter().map(|group| group.gid().to_string()).collect()
+    });
+
+    println!("environment: '{env_name}' ({post_login_env:?})");
+    println!(
+        "user: '{username}' (uid: {}, gid: {}, groups: {})",
+        user.uid(),
+        user.primary_group_id(),
+        groups.join(", ")
+    );
+
+    println!("\ncommands:");
+    for (name, command) in post_login_env.explain_commands(&shell, config) {
+        println!("  {name}: {command}");
+    }
+
+    // Apply the environment in the same way as `start_session` does. The original environment is
+    // restored when `process_env` is dropped.
+    let mut process_env = EnvironmentContainer::take_snapshot();
+    set_session_environment(&mut process_env, post_login_env, config);
+    set_user_environment(&mut process_env, user.uid(), username, &homedir, &shell);
+    if config.session.load_locale {
+        set_locale_vars(&mut process_env, user.uid(), &homedir);
+    }
+    set_hidpi_vars(&mut process_env, post_login_env, &config.hidpi);
+    set_display_layout_vars(&mut process_env, post_login_env, &config.display_layout);
+    set_session_type_vars(&mut process_env, post_login_env, config);
+    let variables = std::env::vars().collect::<std::collections::BTreeMap<_, _>>();
+    drop(process_env);
+
+    println!("\nenvironment variables:");
+    for (key, value) in variables {
+        println!("  {key}={value}");
+    }
+    if matches!(post_login_env, PostLoginEnvironment::X { .. }) {
+        println!("  XAUTHORITY is set once the X server has been started");
+    }
+
+    let user_variables =
+        post_login::user_env::read_user_env(user.uid(), &homedir, &config.session.user_env_file);
+    if !user_variables.is_empty() {
+        println!("\nuser environment variables (client only):");
+        for (key, value) in user_variables {
+            println!("  {key}={value}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Run `env_name` as the current user within the current graphical session, so that session
+/// scripts can be tried without logging out
+fn test_session(env_name: &str, config: &Config) -> Result<(), String> {
+    use uzers::os::unix::UserExt;
+
+    let envs = post_login::get_envs(config);
+    let Some(post_login_env) = envs
+        .iter()
+        .find(|entry| entry.name == env_name)
+        .map(|entry| &entry.post_login_env)
+    else {
+        return Err(format!("No environment with the name '{env_name}' found"));
+    };
+
+    let user = uzers::get_user_by_uid(uzers::get_current_uid())
+        .ok_or("The current user does not exist")?;
+    let shell = user
+        .shell()
+        .to_str()
+        .ok_or("User shell path contains invalid UTF-8")?
+        .to_string();
+
+    // Only the variables that describe the environment are set. The rest is inherited from the
+    // current session.
+    let mut process_env = EnvironmentContainer::take_snapshot();
+    set_session_params(&mut process_env, post_login_env);
+    set_hidpi_vars(&mut process_env, post_login_env, &config.hidpi);
+    set_display_layout_vars(&mut process_env, post_login_env, &config.display_layout);
+    set_session_type_vars(&mut process_env, post_login_env, config);
+
+    println!("Starting '{env_name}'");
+    let exit_status = post_login_env.run_nested(&shell, &mut process_env, config)?;
+    drop(process_env);
+
+    println!("'{env_name}' exited with {exit_status}");
+
+    Ok(())
 }
 
 fn start_session(
@@ -280,7 +795,7 @@ fn start_session(
     post_login_env: &PostLoginEnvironment,
     hooks: &Hooks<'_>,
     config: &Config,
-) -> Result<(), StartSessionError> {
+) -> Result<SessionSummary, StartSessionError> {
     info!(
         "Starting new session for '{}' in environment '{:?}'",
         username, post_login_env
@@ -290,40 +805,77 @@ fn start_session(
         pre_validate_hook();
     }
 
+    let session_start_time = Instant::now();
+
     let mut process_env = EnvironmentContainer::take_snapshot();
 
     if let Some(pre_auth_hook) = hooks.pre_auth {
         pre_auth_hook();
     }
 
-    if matches!(post_login_env, PostLoginEnvironment::X { .. }) {
-        set_display(&config.x11.x11_display, &mut process_env);
-    }
-    set_session_params(&mut process_env, post_login_env);
-    remove_xdg(&mut process_env);
-
-    let auth_session = try_auth(username, password, &config.pam_service)?;
+    set_session_environment(&mut process_env, post_login_env, config);
+
+    // Opening the PAM session can move lemurs into the scope of the session
+    let _greeter_cgroup = config
+        .session
+        .manage_cgroups
+        .then(GreeterCgroup::remember)
+        .flatten();
+
+    let auth_session = try_auth(username, password, config).map_err(|err| {
+        metrics::record_login_failure();
+        err
+    })?;
+
+    // Without logind, the session is not placed in a scope when the PAM session is opened
+    let session_scope =
+        if config.session.manage_cgroups && std::env::var_os("XDG_SESSION_ID").is_none() {
+            match SessionScope::create(config.tty) {
+                Ok(scope) => Some(scope),
+                Err(err) => {
+                    warn!("Failed to create a cgroup for the session. Reason: {err}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
     if let Some(pre_environment_hook) = hooks.pre_environment {
         pre_environment_hook();
     }
 
     let tty = config.tty;
-    let uid = auth_session.uid;
-    let homedir = &auth_session.home_dir;
-    let shell = &auth_session.shell;
 
-    set_seat_vars(&mut process_env, tty);
-    set_session_vars(&mut process_env, uid);
-    set_basic_variables(&mut process_env, username, homedir, shell);
-    set_xdg_common_paths(&mut process_env, homedir);
+    set_user_environment(
+        &mut process_env,
+        auth_session.uid,
+        username,
+        &auth_session.home_dir,
+        &auth_session.shell,
+    );
+    if config.session.load_locale {
+        set_locale_vars(&mut process_env, auth_session.uid, &auth_session.home_dir);
+    }
+    set_hidpi_vars(&mut process_env, post_login_env, &config.hidpi);
+    set_display_layout_vars(&mut process_env, post_login_env, &config.display_layout);
+    set_session_type_vars(&mut process_env, post_login_env, config);
+
+    let console_state = ConsoleState::snapshot();
 
-    let spawned_environment = post_login_env.spawn(&auth_session, &mut process_env, config)?;
+    let mut spawned_environment = post_login_env.spawn(
+        &auth_session,
+        &mut process_env,
+        session_scope.as_ref(),
+        config,
+    )?;
 
     let pid = spawned_environment.pid();
+    let spawn_time = Instant::now();
+
+    metrics::record_login_success(session_start_time.elapsed());
 
     let utmpx_session = add_utmpx_entry(username, tty, pid);
-    drop(process_env);
 
     info!("Waiting for environment to terminate");
 
@@ -331,7 +883,29 @@ fn start_session(
         pre_wait_hook();
     }
 
-    spawned_environment.wait();
+    // With logind, lemurs is in the scope of the session, which is the cgroup of the session
+    let session_cgroup = match &session_scope {
+        Some(scope) => Some(scope.cgroup().to_path_buf()),
+        None => cgroup::current().filter(|cgroup| cgroup.extension() == Some("scope".as_ref())),
+    };
+    let session_timer =
+        SessionTimer::new(&spawned_environment, session_cgroup, &auth_session, config)
+            .map(SessionTimer::start);
+
+    if let Some(watchdog) = Watchdog::new(&spawned_environment, config) {
+        watchdog.supervise(&mut spawned_environment, &console_state);
+    }
+
+    let exit_status = spawned_environment.wait();
+    drop(session_timer);
+    let duration = spawn_time.elapsed();
+
+    console_state.restore();
+
+    // The process environment is kept until here, so that the post-exec command runs with the
+    // same environment as the session.
+    post_login_env.run_post_exec(&auth_session, config);
+    drop(process_env);
 
     info!("Environment terminated. Returning to Lemurs...");
 
@@ -339,8 +913,17 @@ fn start_session(
         pre_return_hook();
     }
 
+    let log_path = config
+        .do_log
+        .then(|| post_login::client_log_path(&auth_session, config));
+
     drop(utmpx_session);
+    drop(session_scope);
     drop(auth_session);
 
-    Ok(())
+    Ok(SessionSummary {
+        exit_status,
+        duration,
+        log_path,
+    })
 }
@@ -0,0 +1,156 @@
+//! This module implements an opt-in Prometheus textfile exporter.
+//!
+//! The metrics are written to a file that can be picked up by the node exporter's textfile
+//! collector. The file is rewritten atomically every time one of the metrics changes.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use once_cell::sync::Lazy;
+
+use crate::config::Config;
+use crate::info_caching::now_as_secs;
+
+static METRICS: Lazy<Mutex<Option<Metrics>>> = Lazy::new(|| Mutex::new(None));
+
+struct Metrics {
+    textfile_path: PathBuf,
+
+    /// Seconds since the UNIX epoch at which the greeter was started
+    start_time: u64,
+
+    login_successes: u64,
+    login_failures: u64,
+
+    last_session_start_latency: Option<Duration>,
+    last_xserver_start_time: Option<Duration>,
+}
+
+impl Metrics {
+    fn render(&self) -> String {
+        let mut out = String::new();
+
+        macro_rules! metric {
+            ($name:literal, $kind:literal, $help:literal, $value:expr) => {
+                let _ = writeln!(out, "# HELP {} {}", $name, $help);
+                let _ = writeln!(out, "# TYPE {} {}", $name, $kind);
+                let _ = writeln!(out, "{} {}", $name, $value);
+            };
+        }
+
+        metric!(
+            "lemurs_start_time_seconds",
+            "gauge",
+            "Start time of the greeter since the UNIX epoch in seconds.",
+            self.start_time
+        );
+        metric!(
+            "lemurs_login_successes_total",
+            "counter",
+            "Amount of successful logins since the greeter started.",
+            self.login_successes
+        );
+        metric!(
+            "lemurs_login_failures_total",
+            "counter",
+            "Amount of failed logins since the greeter started.",
+            self.login_failures
+        );
+
+        if let Some(latency) = self.last_session_start_latency {
+            metric!(
+                "lemurs_session_start_latency_seconds",
+                "gauge",
+                "Time between submitting the login form and the session being spawned.",
+                latency.as_secs_f64()
+            );
+        }
+
+        if let Some(start_time) = self.last_xserver_start_time {
+            metric!(
+                "lemurs_xserver_start_duration_seconds",
+                "gauge",
+                "Time it took the X server to signal that it accepts connections.",
+                start_time.as_secs_f64()
+            );
+        }
+
+        out
+    }
+
+    /// Atomically write the metrics to the textfile
+    fn write(&self) {
+        let mut tmp_path = self.textfile_path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let result = fs::write(&tmp_path, self.render())
+            .and_then(|_| fs::rename(&tmp_path, &self.textfile_path));
+
+        if let Err(err) = result {
+            warn!(
+                "Failed to write metrics to '{}'. Reason: {err}",
+                self.textfile_path.display()
+            );
+        }
+    }
+}
+
+fn with_metrics(f: impl FnOnce(&mut Metrics)) {
+    let mut guard = match METRICS.lock() {
+        Ok(guard) => guard,
+        Err(err) => {
+            error!("Lock failed. Reason: {}", err);
+            return;
+        }
+    };
+
+    if let Some(metrics) = guard.as_mut() {
+        f(metrics);
+        metrics.write();
+    }
+}
+
+/// Start exporting metrics if it is enabled in the configuration
+pub fn init(config: &Config) {
+    if !config.metrics.enabled {
+        return;
+    }
+
+    info!("Exporting metrics to '{}'", config.metrics.textfile_path);
+
+    let metrics = Metrics {
+        textfile_path: PathBuf::from(&config.metrics.textfile_path),
+        start_time: now_as_secs().unwrap_or_default(),
+        login_successes: 0,
+        login_failures: 0,
+        last_session_start_latency: None,
+        last_xserver_start_time: None,
+    };
+
+    metrics.write();
+
+    match METRICS.lock() {
+        Ok(mut guard) => *guard = Some(metrics),
+        Err(err) => error!("Lock failed. Reason: {}", err),
+    }
+}
+
+pub fn record_login_success(session_start_latency: Duration) {
+    with_metrics(|metrics| {
+        metrics.login_successes += 1;
+        metrics.last_session_start_latency = Some(session_start_latency);
+    });
+}
+
+pub fn record_login_failure() {
+    with_metrics(|metrics| metrics.login_failures += 1);
+}
+
+pub fn record_xserver_start_time(start_time: Duration) {
+    with_metrics(|metrics| metrics.last_xserver_start_time = Some(start_time));
+}
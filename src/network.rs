@@ -0,0 +1,117 @@
+//! The state of the network connection of the machine. Users from a network directory, such as
+//! LDAP or Active Directory, usually cannot log in without one.
+
+use std::fs;
+
+/// The route flag for routes that are in use
+const RTF_UP: u32 = 0x1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkStatus {
+    /// No network interface has a link
+    Offline,
+    /// An interface has a link, but there is no default route, e.g. because DHCP has not finished
+    NoAddress,
+    Connected,
+}
+
+impl NetworkStatus {
+    /// The status of the network interfaces of the machine
+    pub fn current() -> Self {
+        let has_default_route = fs::read_to_string("/proc/net/route")
+            .is_ok_and(|routes| has_default_ipv4_route(&routes))
+            || fs::read_to_string("/proc/net/ipv6_route")
+                .is_ok_and(|routes| has_default_ipv6_route(&routes));
+        if has_default_route {
+            return Self::Connected;
+        }
+
+        if has_link() {
+            Self::NoAddress
+        } else {
+            Self::Offline
+        }
+    }
+}
+
+/// Whether a physical network interface has a link
+fn has_link() -> bool {
+    let Ok(entries) = fs::read_dir("/sys/class/net") else {
+        return false;
+    };
+
+    entries.filter_map(Result::ok).any(|entry| {
+        let path = entry.path();
+
+        // Virtual interfaces, such as the loopback and bridges, have no device
+        path.join("device").exists()
+            && fs::read_to_string(path.join("carrier")).is_ok_and(|carrier| carrier.trim() == "1")
+    })
+}
+
+/// Whether `/proc/net/route` has a default route that is in use
+fn has_default_ipv4_route(routes: &str) -> bool {
+    routes.lines().skip(1).any(|route| {
+        let fields = route.split_whitespace().collect::<Vec<_>>();
+        matches!(
+            fields[..],
+            [iface, "00000000", _, flags, ..]
+                if iface != "lo"
+                    && u32::from_str_radix(flags, 16).is_ok_and(|flags| flags & RTF_UP != 0)
+        )
+    })
+}
+
+/// Whether `/proc/net/ipv6_route` has a default route that is in use
+fn has_default_ipv6_route(routes: &str) -> bool {
+    routes.lines().any(|route| {
+        let fields = route.split_whitespace().collect::<Vec<_>>();
+        matches!(
+            fields[..],
+            [destination, "00", _, _, _, _, _, _, flags, iface]
+                if iface != "lo"
+                    && destination.chars().all(|c| c == '0')
+                    && u32::from_str_radix(flags, 16).is_ok_and(|flags| flags & RTF_UP != 0)
+        )
+    })
+}
+
+/// Whether `username` is an account in `/etc/passwd`, instead of an account from a network
+/// directory
+pub fn is_local_user(username: &str) -> bool {
+    fs::read_to_string("/etc/passwd").is_ok_and(|passwd| {
+        passwd
+            .lines()
+            .any(|line| line.split(':').next() == Some(username))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_routes() {
+        let ipv4 = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+wlan0\t0001A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+";
+        assert!(has_default_ipv4_route(ipv4));
+        assert!(!has_default_ipv4_route(
+            "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t0001A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0
+"
+        ));
+
+        let ipv6 = "\
+00000000000000000000000000000000 00 00000000000000000000000000000000 00 00000000000000000000000000000000 ffffffff 00000001 00000000 00200200       lo
+fe800000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001    wlan0
+";
+        assert!(!has_default_ipv6_route(ipv6));
+        assert!(has_default_ipv6_route(
+            "00000000000000000000000000000000 00 00000000000000000000000000000000 00 fe80000000000000022618fffe123456 00000400 00000001 00000000 00450003    wlan0"
+        ));
+    }
+}
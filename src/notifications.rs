@@ -0,0 +1,246 @@
+//! A unix socket through which other programs can show a short message on the login screen, e.g.
+//! `echo "info 30 Backup finished" | nc -U /run/lemurs/notify.sock`.
+//!
+//! Every line that is written to the socket is a notification in the form
+//! `[SEVERITY [TIMEOUT_SECS]] MESSAGE`. The severity is "info", "warning" or "error". A timeout of
+//! 0 keeps the message until it is replaced.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+
+use log::{info, warn};
+
+/// The longest message that is shown. Longer messages are cut off.
+const MAX_MESSAGE_LENGTH: usize = 256;
+
+/// The most bytes that are read from a single connection
+const MAX_CONNECTION_BYTES: u64 = 4096;
+
+/// How long a connection can take to send its notifications
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "info" => Some(Self::Info),
+            "warning" => Some(Self::Warning),
+            "error" => Some(Self::Error),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// Tells apart notifications with the same message
+    pub id: u64,
+    pub severity: Severity,
+    /// How long the message is shown. `None` keeps it until it is replaced.
+    pub timeout: Option<Duration>,
+    pub message: String,
+}
+
+impl Notification {
+    /// Parse a line that was written to the socket. Returns `None` for an empty message.
+    fn parse(line: &str, id: u64, default_timeout: Duration) -> Option<Self> {
+        let mut rest = line.trim();
+
+        let mut severity = Severity::Info;
+        let mut timeout = default_timeout;
+
+        if let Some((word, after)) = rest.split_once(char::is_whitespace) {
+            if let Some(parsed) = Severity::from_name(word) {
+                severity = parsed;
+                rest = after.trim_start();
+
+                if let Some((word, after)) = rest.split_once(char::is_whitespace) {
+                    if let Ok(secs) = word.parse() {
+                        timeout = Duration::from_secs(secs);
+                        rest = after.trim_start();
+                    }
+                }
+            }
+        }
+
+        let message = rest
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(MAX_MESSAGE_LENGTH)
+            .collect::<String>();
+        if message.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            id,
+            severity,
+            timeout: (!timeout.is_zero()).then_some(timeout),
+            message,
+        })
+    }
+}
+
+/// Send a notification to the socket at `path`, in the same way as other programs do. `None`
+/// keeps the message until it is replaced.
+pub fn send(
+    path: &Path,
+    severity: Severity,
+    timeout: Option<Duration>,
+    message: &str,
+) -> io::Result<()> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+
+    // A timeout of 0 keeps the message, so shorter timeouts are rounded up
+    let timeout = timeout.map_or(0, |timeout| timeout.as_secs().max(1));
+    let message = message.replace(['\r', '\n'], " ");
+    writeln!(stream, "{} {timeout} {message}", severity.name())
+}
+
+/// Listen on the socket at `path` in the background. `on_notification` is called for every
+/// notification that is received. Only the user of lemurs can write to the socket.
+pub fn listen(
+    path: &Path,
+    default_timeout: Duration,
+    on_notification: impl Fn(Notification) + Send + 'static,
+) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(parent)?;
+    }
+
+    // A socket that is left behind by an instance that crashed is replaced
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another instance is listening on the socket",
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+    info!("Listening for notifications on '{}'", path.display());
+
+    std::thread::spawn(move || {
+        let mut id = 0;
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Failed to accept a notification connection. Reason: {err}");
+                    continue;
+                }
+            };
+
+            // A client that does not close the connection cannot block the others
+            if let Err(err) = stream.set_read_timeout(Some(CONNECTION_TIMEOUT)) {
+                warn!("Failed to set the timeout of a notification connection. Reason: {err}");
+                continue;
+            }
+
+            let reader = BufReader::new(stream.take(MAX_CONNECTION_BYTES));
+            for line in reader.lines().map_while(Result::ok) {
+                id += 1;
+                if let Some(notification) = Notification::parse(&line, id, default_timeout) {
+                    info!(
+                        "Received a notification: [{:?}] {}",
+                        notification.severity, notification.message
+                    );
+                    on_notification(notification);
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_to_listener() {
+        let path = std::env::temp_dir().join(format!("lemurs-notify-{}.sock", std::process::id()));
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        listen(&path, Duration::from_secs(10), move |notification| {
+            let _ = sender.send(notification);
+        })
+        .unwrap();
+        send(
+            &path,
+            Severity::Warning,
+            Some(Duration::from_secs(300)),
+            "Session ends\nsoon",
+        )
+        .unwrap();
+
+        let notification = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(notification.severity, Severity::Warning);
+        assert_eq!(notification.timeout, Some(Duration::from_secs(300)));
+        assert_eq!(notification.message, "Session ends soon");
+    }
+
+    #[test]
+    fn parse_notification() {
+        let default_timeout = Duration::from_secs(10);
+        let parse = |line| Notification::parse(line, 1, default_timeout);
+
+        let notification = parse("warning 30 Updates pending reboot").unwrap();
+        assert_eq!(notification.severity, Severity::Warning);
+        assert_eq!(notification.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(notification.message, "Updates pending reboot");
+
+        let notification = parse("error 0 Backup failed").unwrap();
+        assert_eq!(notification.severity, Severity::Error);
+        assert_eq!(notification.timeout, None);
+
+        let notification = parse("info 2 disks are full").unwrap();
+        assert_eq!(notification.timeout, Some(Duration::from_secs(2)));
+        assert_eq!(notification.message, "disks are full");
+
+        let notification = parse("Backup finished\r").unwrap();
+        assert_eq!(notification.severity, Severity::Info);
+        assert_eq!(notification.timeout, Some(default_timeout));
+        assert_eq!(notification.message, "Backup finished");
+
+        assert_eq!(parse("  "), None);
+        assert_eq!(
+            parse("error"),
+            Some(Notification {
+                id: 1,
+                severity: Severity::Info,
+                timeout: Some(default_timeout),
+                message: "error".to_string(),
+            })
+        );
+    }
+}
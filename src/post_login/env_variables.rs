@@ -1,9 +1,20 @@
-use log::info;
+use std::env;
+use std::fs;
+use std::path::Path;
 
+use log::{info, warn};
+
+use crate::config::{Config, DisplayLayoutConfig, HiDpiConfig};
 use crate::env_container::EnvironmentContainer;
 
+use super::user_env::{parse, read_user_file};
 use super::PostLoginEnvironment;
 
+/// The locale of the system, as set by `localectl`
+const SYSTEM_LOCALE_PATH: &str = "/etc/locale.conf";
+/// The locale of the user, relative to the home directory
+const USER_LOCALE_PATH: &str = ".config/locale.conf";
+
 pub fn set_display(display: &str, process_env: &mut EnvironmentContainer) {
     info!("Setting Display");
 
@@ -15,6 +26,7 @@ pub fn remove_xdg(process_env: &mut EnvironmentContainer) {
 
     process_env.remove_var("XDG_SESSION_CLASS");
     process_env.remove_var("XDG_CURRENT_DESKTOP");
+    process_env.remove_var("DESKTOP_SESSION");
     process_env.remove_var("XDG_SESSION_DESKTOP");
 
     process_env.remove_var("XDG_SEAT");
@@ -37,12 +49,90 @@ pub fn set_session_params(
 ) {
     info!("Setting XDG Session Parameters");
 
+    // The sessions of lemurs are always user sessions. The greeter and lock screen classes are
+    // for the login screen itself.
     process_env.set("XDG_SESSION_CLASS", "user");
     process_env.set("XDG_SESSION_TYPE", post_login_env.to_xdg_type());
 
-    // TODO: Implement
-    // process_env.set("XDG_CURRENT_DESKTOP", post_login_env.to_xdg_desktop());
-    // process_env.set("XDG_SESSION_DESKTOP", post_login_env.to_xdg_desktop());
+    if let Some(desktop) = post_login_env.desktop() {
+        process_env.set("DESKTOP_SESSION", &desktop.session);
+        process_env.set("XDG_SESSION_DESKTOP", &desktop.session);
+
+        if let Some(current_desktop) = &desktop.current_desktop {
+            process_env.set("XDG_CURRENT_DESKTOP", current_desktop);
+        }
+    }
+}
+
+/// Set the environment variables that are configured for the type of the environment (i.e.
+/// `[x11.env]`, `[wayland.env]` or `[tty_session.env]`)
+pub fn set_session_type_vars(
+    process_env: &mut EnvironmentContainer,
+    post_login_env: &PostLoginEnvironment,
+    config: &Config,
+) {
+    let env = match post_login_env {
+        PostLoginEnvironment::X { .. } => &config.x11.env,
+        PostLoginEnvironment::Wayland { .. } | PostLoginEnvironment::Systemd { .. } => {
+            &config.wayland.env
+        }
+        PostLoginEnvironment::Shell { .. } => &config.tty_session.env,
+    };
+
+    if env.0.is_empty() {
+        return;
+    }
+
+    info!(
+        "Setting configured {} Environment Variables",
+        post_login_env.to_xdg_type()
+    );
+
+    for (key, value) in &env.0 {
+        process_env.set(key, value);
+    }
+}
+
+/// Set the scaling variables for HiDPI screens, if the scaling is enabled
+pub fn set_hidpi_vars(
+    process_env: &mut EnvironmentContainer,
+    post_login_env: &PostLoginEnvironment,
+    config: &HiDpiConfig,
+) {
+    if !config.enabled || post_login_env.desktop().is_none() {
+        return;
+    }
+
+    info!("Setting HiDPI Scaling Variables");
+
+    process_env.set("GDK_SCALE", config.gdk_scale.to_string());
+    process_env.set("GDK_DPI_SCALE", &config.gdk_dpi_scale);
+    process_env.set("QT_SCALE_FACTOR", &config.qt_scale_factor);
+}
+
+/// Pass the command of the chosen display layout to Wayland sessions, which apply it themselves
+pub fn set_display_layout_vars(
+    process_env: &mut EnvironmentContainer,
+    post_login_env: &PostLoginEnvironment,
+    config: &DisplayLayoutConfig,
+) {
+    if !matches!(
+        post_login_env,
+        PostLoginEnvironment::Wayland { .. } | PostLoginEnvironment::Systemd { .. }
+    ) {
+        return;
+    }
+
+    let Some(layout) = config.layout(&config.default) else {
+        return;
+    };
+    if layout.wayland_cmd.is_empty() {
+        return;
+    }
+
+    info!("Setting Display Layout Variable for '{}'", layout.name);
+
+    process_env.set("LEMURS_DISPLAY_LAYOUT", &layout.wayland_cmd);
 }
 
 pub fn set_seat_vars(process_env: &mut EnvironmentContainer, tty: u8) {
@@ -58,7 +148,16 @@ pub fn set_session_vars(process_env: &mut EnvironmentContainer, uid: u32) {
     info!("Setting XDG Session Variables");
 
     process_env.set_or_own("XDG_RUNTIME_DIR", &format!("/run/user/{uid}"));
-    process_env.set_or_own("XDG_SESSION_ID", "1");
+
+    // The session id is given by logind (through `pam_systemd` or `pam_elogind`) when the PAM
+    // session is opened. There is no sensible value to make up if it was not given.
+    match env::var("XDG_SESSION_ID") {
+        Ok(session_id) => {
+            info!("Using session id '{session_id}' from logind");
+            process_env.set("XDG_SESSION_ID", session_id);
+        }
+        Err(_) => warn!("No session id was given by PAM. Is `pam_systemd` or `pam_elogind` used?"),
+    }
 }
 
 /// Set all the environment variables
@@ -93,3 +192,39 @@ pub fn set_xdg_common_paths(process_env: &mut EnvironmentContainer, homedir: &st
     process_env.set_or_own("XDG_DATA_DIRS", "/usr/local/share:/usr/share");
     process_env.set_or_own("XDG_CONFIG_DIRS", "/etc/xdg");
 }
+
+fn is_locale_variable(key: &str) -> bool {
+    key == "LANG" || key == "LANGUAGE" || key.starts_with("LC_")
+}
+
+/// Set the locale variables that are not set yet (e.g. by `pam_env`) from the `locale.conf` of the
+/// user or, if the user has none, from the one of the system. Otherwise, sessions come up with the
+/// POSIX locale on distributions that do not set the locale through PAM.
+pub fn set_locale_vars(process_env: &mut EnvironmentContainer, uid: u32, homedir: &str) {
+    let (path, content) = match read_user_file(uid, homedir, USER_LOCALE_PATH) {
+        Some(user_locale) => user_locale,
+        None => match fs::read_to_string(SYSTEM_LOCALE_PATH) {
+            Ok(content) => (Path::new(SYSTEM_LOCALE_PATH).to_path_buf(), content),
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to read '{SYSTEM_LOCALE_PATH}'. Reason: {err}");
+                }
+                return;
+            }
+        },
+    };
+
+    info!("Setting Locale Variables from '{}'", path.display());
+
+    for (key, value) in parse(&content, &path) {
+        // A locale with a `/` is a path to locale files, which should not come from a user
+        if !is_locale_variable(&key) || value.contains('/') {
+            warn!("Ignoring '{key}={value}' in '{}'", path.display());
+            continue;
+        }
+
+        if env::var_os(&key).is_none() {
+            process_env.set(&key, value);
+        }
+    }
+}
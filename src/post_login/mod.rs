@@ -1,78 +1,456 @@
 use log::{error, info, warn};
-use std::error::Error;
-use std::fmt::Display;
 use std::fs;
 use std::path::Path;
 
+use std::io;
 use std::os::unix::process::CommandExt;
-use std::process::{Child, Command, Stdio};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::auth::AuthUserInfo;
-use crate::config::{Config, ShellLoginFlag};
+use crate::cgroup::SessionScope;
+use crate::config::{get_rlimit, get_umask, Config, ShellLoginFlag};
 use crate::env_container::EnvironmentContainer;
-use crate::post_login::x::setup_x;
+use crate::error::ErrorCode;
+use crate::post_login::x::{can_retain_server, retain_server, setup_x, xserver_command};
+use crate::priority;
 
 use nix::unistd::{Gid, Uid};
+use thiserror::Error;
 
-use self::wait_with_log::LemursChild;
+use self::wait_with_log::{LemursChild, LogTarget};
 use self::x::XSetupError;
 
 pub(crate) mod env_variables;
+pub mod multiplexer;
+pub mod session_timer;
+pub mod user_env;
 mod wait_with_log;
-mod x;
+pub mod watchdog;
+pub(crate) mod x;
+
+/// Commands that are run as the user before and after an environment
+#[derive(Debug, Clone, Default)]
+pub struct ExecHooks {
+    pub pre_exec: Option<String>,
+    pub post_exec: Option<String>,
+}
+
+/// The desktop that is started by a graphical environment
+#[derive(Debug, Clone, Default)]
+pub struct SessionDesktop {
+    /// The value of `DESKTOP_SESSION`, i.e. the basename of the desktop entry or script
+    pub session: String,
+    /// The value of `XDG_CURRENT_DESKTOP` (e.g. `KDE` or `sway:wlroots`)
+    pub current_desktop: Option<String>,
+}
 
 #[derive(Debug, Clone)]
 pub enum PostLoginEnvironment {
-    X { xinitrc_path: String },
-    Wayland { script_path: String },
-    Shell,
+    X {
+        xinitrc_path: String,
+        desktop: SessionDesktop,
+        hooks: ExecHooks,
+    },
+    Wayland {
+        script_path: String,
+        desktop: SessionDesktop,
+        hooks: ExecHooks,
+    },
+    Shell {
+        command: Option<String>,
+        hooks: ExecHooks,
+    },
+    /// A target of the systemd user manager (e.g. `sway-session.target`) that starts the session
+    /// itself
+    Systemd {
+        target: String,
+        desktop: SessionDesktop,
+        hooks: ExecHooks,
+    },
+}
+
+/// An environment that can be selected in the environment switcher
+#[derive(Debug, Clone)]
+pub struct EnvironmentEntry {
+    pub name: String,
+    /// A description of the environment (e.g. the `Comment` of a desktop entry)
+    pub comment: Option<String>,
+    /// Whether the environment is only shown when its exact name is typed in the switcher
+    pub hidden: bool,
+    pub post_login_env: PostLoginEnvironment,
+}
+
+impl EnvironmentEntry {
+    pub fn new(name: impl Into<String>, post_login_env: PostLoginEnvironment) -> Self {
+        Self {
+            name: name.into(),
+            comment: None,
+            hidden: false,
+            post_login_env,
+        }
+    }
+}
+
+/// The parts of a desktop entry that are used by lemurs
+struct DesktopEntry {
+    name: String,
+    exec: String,
+    desktop: SessionDesktop,
+    comment: Option<String>,
+    hidden: bool,
+    hooks: ExecHooks,
 }
 
 impl PostLoginEnvironment {
+    pub fn hooks(&self) -> &ExecHooks {
+        match self {
+            Self::X { hooks, .. }
+            | Self::Wayland { hooks, .. }
+            | Self::Shell { hooks, .. }
+            | Self::Systemd { hooks, .. } => hooks,
+        }
+    }
+
+    fn hooks_mut(&mut self) -> &mut ExecHooks {
+        match self {
+            Self::X { hooks, .. }
+            | Self::Wayland { hooks, .. }
+            | Self::Shell { hooks, .. }
+            | Self::Systemd { hooks, .. } => hooks,
+        }
+    }
+
     pub fn to_xdg_type(&self) -> &'static str {
         match self {
-            Self::Shell => "tty",
+            Self::Shell { .. } => "tty",
             Self::X { .. } => "x11",
-            Self::Wayland { .. } => "wayland",
+            Self::Wayland { .. } | Self::Systemd { .. } => "wayland",
+        }
+    }
+
+    /// The desktop that is started. TTY sessions have no desktop.
+    pub fn desktop(&self) -> Option<&SessionDesktop> {
+        match self {
+            Self::X { desktop, .. }
+            | Self::Wayland { desktop, .. }
+            | Self::Systemd { desktop, .. } => Some(desktop),
+            Self::Shell { .. } => None,
+        }
+    }
+
+    fn desktop_mut(&mut self) -> Option<&mut SessionDesktop> {
+        match self {
+            Self::X { desktop, .. }
+            | Self::Wayland { desktop, .. }
+            | Self::Systemd { desktop, .. } => Some(desktop),
+            Self::Shell { .. } => None,
+        }
+    }
+
+    /// The command that is passed to the system shell to start the environment
+    fn client_command(&self, user_shell: &str, config: &Config) -> String {
+        match self {
+            Self::X { xinitrc_path, .. } => {
+                format!("{} {}", &config.x11.xsetup_path, xinitrc_path)
+            }
+            Self::Wayland { script_path, .. } if config.wayland.systemd_unit => {
+                systemd_unit_command(script_path, config)
+            }
+            Self::Wayland { script_path, .. } => script_path.clone(),
+            Self::Shell { command: None, .. } => user_shell.to_string(),
+            Self::Shell {
+                command: Some(command),
+                ..
+            } => format!("{user_shell} -c {}", shell_quote(command)),
+            Self::Systemd { target, .. } => systemd_target_command(target),
         }
     }
 
-    // pub fn to_xdg_desktop(&self) -> &str {
-    //     // TODO: Implement properly
-    //     ""
-    // }
+    /// Describe the commands that would be executed to start the environment, without actually
+    /// executing them
+    pub fn explain_commands(
+        &self,
+        user_shell: &str,
+        config: &Config,
+    ) -> Vec<(&'static str, String)> {
+        let mut commands = Vec::new();
+
+        if matches!(self, Self::X { .. }) {
+            let vtnr = x::server_vt(config).to_string();
+            let display = if x::is_auto_display(config) {
+                "-displayfd <fd>"
+            } else {
+                &config.x11.x11_display
+            };
+            commands.push((
+                "X server",
+                format!(
+                    "{} -c {}",
+                    config.system_shell,
+                    shell_quote(&xserver_command(display, &vtnr, config))
+                ),
+            ));
+        }
+
+        if let Some(pre_exec) = &self.hooks().pre_exec {
+            commands.push((
+                "Pre-exec",
+                format!("{} -c {}", config.system_shell, shell_quote(pre_exec)),
+            ));
+        }
+
+        let mut client = client_shell(user_shell, config).to_string();
+        if let Some(shell_login_flag) = shell_login_flag(config) {
+            client.push(' ');
+            client.push_str(shell_login_flag);
+        }
+        client.push_str(" -c ");
+        client.push_str(&shell_quote(&self.client_command(user_shell, config)));
+        commands.push(("Client", client));
+
+        if let Some(post_exec) = &self.hooks().post_exec {
+            commands.push((
+                "Post-exec",
+                format!("{} -c {}", config.system_shell, shell_quote(post_exec)),
+            ));
+        }
+
+        commands
+    }
+}
+
+/// Quote a string so that it is passed as a single argument by a POSIX shell
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// The variables of the session that are imported into the systemd user manager. Only these are
+/// imported, since the user manager outlives the session and shares its environment with all
+/// units of the user.
+const SYSTEMD_SESSION_VARIABLES: &[&str] = &[
+    "DESKTOP_SESSION",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "XAUTHORITY",
+    "XDG_CURRENT_DESKTOP",
+    "XDG_SEAT",
+    "XDG_SESSION_CLASS",
+    "XDG_SESSION_DESKTOP",
+    "XDG_SESSION_ID",
+    "XDG_SESSION_TYPE",
+    "XDG_VTNR",
+    "GDK_SCALE",
+    "GDK_DPI_SCALE",
+    "QT_SCALE_FACTOR",
+    "LEMURS_DISPLAY_LAYOUT",
+];
+
+/// The command that runs `start` in the background with the variables of the session imported
+/// into the systemd user manager, and unsets them again once it has exited. `stop` is run when the
+/// command is asked to exit, e.g. by the session time limit, and can refer to the process of
+/// `start` as `$pid`.
+fn systemd_user_command(start: &str, stop: &str) -> String {
+    let variables = SYSTEMD_SESSION_VARIABLES.join(" ");
+    format!(
+        "systemctl --user import-environment {variables}; \
+         {start} & pid=$!; \
+         trap \"{stop}\" HUP INT TERM; \
+         wait $pid; status=$?; \
+         while kill -0 $pid 2>/dev/null; do wait $pid; status=$?; done; \
+         systemctl --user unset-environment {variables}; \
+         exit $status"
+    )
+}
+
+/// The command that runs `command` in a transient scope of the systemd user manager. The scope
+/// keeps the environment and the output of the command, unlike a service.
+fn systemd_unit_command(command: &str, config: &Config) -> String {
+    systemd_user_command(
+        &format!(
+            "systemd-run --user --scope --collect --quiet --unit=lemurs-wayland-tty{} -- {} -c {}",
+            config.tty,
+            config.system_shell,
+            shell_quote(command)
+        ),
+        "kill -TERM $pid",
+    )
+}
+
+/// The command that starts `target` in the systemd user manager and waits for it to stop. The
+/// target is stopped when the command is asked to exit, e.g. by the session time limit, since it
+/// would otherwise outlive the session.
+fn systemd_target_command(target: &str) -> String {
+    let target = shell_quote(target);
+    systemd_user_command(
+        &format!("systemctl --user start --wait {target}"),
+        &format!("systemctl --user stop {target}"),
+    )
+}
+
+/// Whether `shell` cannot be used interactively, such as `nologin`, `false` or a missing shell
+pub fn is_non_interactive_shell(shell: &str) -> bool {
+    let path = Path::new(shell);
+
+    matches!(
+        path.file_name().and_then(|name| name.to_str()),
+        Some("nologin" | "false")
+    ) || !path.is_file()
+}
+
+/// The shell that is used to start the client of the environment
+fn client_shell<'a>(user_shell: &'a str, config: &'a Config) -> &'a str {
+    if !config.session.use_login_shell {
+        return &config.system_shell;
+    }
+
+    if is_non_interactive_shell(user_shell) {
+        warn!(
+            "Login shell '{user_shell}' is not an interactive shell. Falling back to '{}'",
+            config.system_shell
+        );
+        return &config.system_shell;
+    }
+
+    user_shell
+}
+
+fn shell_login_flag(config: &Config) -> Option<&'static str> {
+    match config.shell_login_flag {
+        ShellLoginFlag::None => None,
+        ShellLoginFlag::Short => Some("-l"),
+        ShellLoginFlag::Long => Some("--login"),
+    }
+}
+
+impl PostLoginEnvironment {
+    /// Run the environment as the current user within the current session. X11 environments are
+    /// run within Xephyr and Wayland compositors are expected to run nested when they are started
+    /// within another graphical session.
+    pub fn run_nested(
+        &self,
+        user_shell: &str,
+        process_env: &mut EnvironmentContainer,
+        config: &Config,
+    ) -> Result<ExitStatus, String> {
+        if let Self::Systemd { target, .. } = self {
+            return Err(format!(
+                "The systemd target '{target}' cannot be tested, since it would run in the user manager of the current session"
+            ));
+        }
+
+        let in_graphical_session =
+            std::env::var_os("WAYLAND_DISPLAY").is_some() || std::env::var_os("DISPLAY").is_some();
+        if !matches!(self, Self::Shell { .. }) && !in_graphical_session {
+            return Err(String::from(
+                "A graphical environment can only be tested from within a graphical session",
+            ));
+        }
+
+        let mut xephyr = match self {
+            Self::X { .. } => {
+                let (xephyr, display) = x::start_xephyr(config)?;
+                process_env.set("DISPLAY", display);
+                process_env.remove_var("WAYLAND_DISPLAY");
+                Some(xephyr)
+            }
+            _ => None,
+        };
+
+        let mut client = Command::new(client_shell(user_shell, config));
+        if let Some(shell_login_flag) = shell_login_flag(config) {
+            client.arg(shell_login_flag);
+        }
+        client.arg("-c");
+        client.arg(self.client_command(user_shell, config));
+
+        let exit_status = client
+            .status()
+            .map_err(|err| format!("Failed to start the environment. Reason: {err}"));
+
+        if let Some(xephyr) = &mut xephyr {
+            let _ = xephyr.kill();
+            let _ = xephyr.wait();
+        }
+
+        exit_status
+    }
 }
 
+/// Information on how an environment ended
 #[derive(Debug, Clone)]
+pub struct SessionSummary {
+    /// The exit status of the client, if it could be retrieved
+    pub exit_status: Option<ExitStatus>,
+    pub duration: Duration,
+    /// Where the output of the client was logged to
+    pub log_path: Option<String>,
+}
+
+/// The path of the client log of the user. '%user%', '%uid%' and '%home%' are replaced.
+pub fn client_log_path(user_info: &AuthUserInfo<'_>, config: &Config) -> String {
+    config
+        .client_log_path
+        .replace("%user%", &user_info.username)
+        .replace("%uid%", &user_info.uid.to_string())
+        .replace("%home%", &user_info.home_dir)
+}
+
+#[derive(Debug, Clone, Error)]
 pub enum EnvironmentStartError {
+    #[error("Failed to start Wayland compositor")]
     WaylandStart,
-    XSetup(XSetupError),
+    #[error("Failed to setup X11 server. Reason: '{0}'")]
+    XSetup(#[from] XSetupError),
+    #[error("Failed to start X11 client")]
     XStartEnv,
+    #[error("Failed to start TTY")]
     TTYStart,
+    #[error("Pre-exec command failed")]
+    PreExec,
+    #[error("User has no interactive shell")]
+    NoInteractiveShell,
+    #[error("'{0}' not found in PATH")]
+    MissingExecutable(String),
 }
 
-impl Display for EnvironmentStartError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ErrorCode for EnvironmentStartError {
+    fn code(&self) -> &'static str {
         match self {
-            Self::WaylandStart => f.write_str("Failed to start Wayland compositor"),
-            Self::XSetup(err) => write!(f, "Failed to setup X11 server. Reason: '{err}'"),
-            Self::XStartEnv => f.write_str("Failed to start X11 client"),
-            Self::TTYStart => f.write_str("Failed to start TTY"),
+            Self::WaylandStart => "ENV-01",
+            Self::XSetup(err) => err.code(),
+            Self::XStartEnv => "ENV-02",
+            Self::TTYStart => "ENV-03",
+            Self::PreExec => "ENV-04",
+            Self::NoInteractiveShell => "ENV-05",
+            Self::MissingExecutable(_) => "ENV-06",
         }
     }
 }
 
-impl Error for EnvironmentStartError {}
-impl From<XSetupError> for EnvironmentStartError {
-    fn from(value: XSetupError) -> Self {
-        Self::XSetup(value)
-    }
-}
-
 fn lower_command_permissions_to_user(
     mut command: Command,
     user_info: &AuthUserInfo<'_>,
+    config: &Config,
 ) -> Command {
+    let session = &config.session;
+    let umask = get_umask(&session.umask);
+    let limits = [
+        (libc::RLIMIT_CORE, &session.core_limit),
+        (libc::RLIMIT_NOFILE, &session.nofile_limit),
+    ]
+    .into_iter()
+    .filter(|(_, limit)| !limit.is_empty())
+    .filter_map(|(resource, limit)| match get_rlimit(limit) {
+        Some(limit) => Some((resource, limit)),
+        None => {
+            warn!("Invalid resource limit '{limit}'. Ignoring it");
+            None
+        }
+    })
+    .collect::<Vec<_>>();
+
     let uid = user_info.uid;
     let gid = user_info.primary_gid;
     let groups = user_info
@@ -84,6 +462,21 @@ fn lower_command_permissions_to_user(
 
     unsafe {
         command.pre_exec(move || {
+            // The limits are set whilst still running as root, so that hard limits can be raised
+            for (resource, (soft, hard)) in &limits {
+                let rlimit = libc::rlimit {
+                    rlim_cur: *soft,
+                    rlim_max: *hard,
+                };
+                if libc::setrlimit(*resource, &rlimit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+
+            if let Some(umask) = umask {
+                libc::umask(umask);
+            }
+
             // NOTE: The order here is very vital, otherwise permission errors occur
             // This is basically a copy of how the nightly standard library does it.
             nix::unistd::setgroups(&groups)
@@ -96,10 +489,18 @@ fn lower_command_permissions_to_user(
     command
 }
 
+/// How often [`SpawnedEnvironment::wait_timeout`] checks whether the client has exited
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 pub enum SpawnedEnvironment {
     X11 {
         server: LemursChild,
         client: LemursChild,
+        display: String,
+        /// The user of the session, for whom the server can be kept running
+        uid: libc::uid_t,
+        /// The VT to switch back to if the server is kept running after the session
+        return_vt: Option<u8>,
     },
     Wayland(LemursChild),
     Tty(Child),
@@ -113,21 +514,97 @@ impl SpawnedEnvironment {
         }
     }
 
-    pub fn wait(self) {
+    /// The processes that lemurs started for the environment
+    pub fn pids(&self) -> Vec<u32> {
+        match self {
+            Self::X11 { server, client, .. } => vec![client.id(), server.id()],
+            _ => vec![self.pid()],
+        }
+    }
+
+    /// Check whether the client has exited, without blocking. Once it has, [`Self::wait`] shuts
+    /// down the rest of the environment without blocking on the client.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        match self {
+            Self::X11 { client, .. } | Self::Wayland(client) => client.try_wait(),
+            Self::Tty(client) => client.try_wait(),
+        }
+    }
+
+    /// Wait for the client to exit for at most `timeout`. Returns `None` if it is still running.
+    pub fn wait_timeout(&mut self, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+        let start_time = Instant::now();
+
+        loop {
+            if let Some(exit_status) = self.try_wait()? {
+                return Ok(Some(exit_status));
+            }
+
+            let elapsed = start_time.elapsed();
+            if elapsed >= timeout {
+                return Ok(None);
+            }
+
+            thread::sleep(WAIT_POLL_INTERVAL.min(timeout - elapsed));
+        }
+    }
+
+    /// Ask the client to exit and kill it if it is still running after `grace`. The rest of the
+    /// environment is shut down by [`Self::wait`].
+    pub fn terminate(&mut self, grace: Duration) -> io::Result<()> {
+        info!("Terminating the environment");
+
+        if unsafe { libc::kill(self.pid() as libc::pid_t, libc::SIGTERM) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if self.wait_timeout(grace)?.is_some() {
+            return Ok(());
+        }
+
+        warn!(
+            "Client is still running {}ms after it was asked to exit. Killing it",
+            grace.as_millis()
+        );
+        match self {
+            Self::X11 { client, .. } | Self::Wayland(client) => client.kill(),
+            Self::Tty(client) => client.kill(),
+        }
+    }
+
+    /// Wait for the environment to exit and return the exit status of the client
+    pub fn wait(self) -> Option<ExitStatus> {
         info!("Waiting for client to exit");
 
         match self {
             Self::X11 {
                 mut client,
                 mut server,
+                display,
+                uid,
+                return_vt,
             } => {
-                match client.wait() {
-                    Ok(exit_code) => info!("Client exited with exit code `{exit_code}`"),
+                let exit_status = match client.wait() {
+                    Ok(exit_code) => {
+                        info!("Client exited with exit code `{exit_code}`");
+                        Some(exit_code)
+                    }
                     Err(err) => {
                         error!("Failed to wait for client. Reason: {err}");
+                        None
                     }
                 };
 
+                if let Some(vt) = return_vt {
+                    retain_server(server, display, uid);
+
+                    if let Err(err) = unsafe { crate::chvt::chvt(vt.into()) } {
+                        error!("Failed to switch back to tty {vt}. Reason: {err}");
+                    }
+
+                    return exit_status;
+                }
+
                 info!("Telling X server to shut down");
                 match server.send_sigterm() {
                     Ok(_) => {}
@@ -139,53 +616,224 @@ impl SpawnedEnvironment {
                     Ok(_) => {}
                     Err(err) => error!("Failed to wait for X11. Reason: {err}"),
                 }
+
+                exit_status
             }
             Self::Wayland(mut client) => match client.wait() {
-                Ok(exit_code) => info!("Client exited with exit code `{exit_code}`"),
-                Err(err) => error!("Failed to wait for client. Reason: {err}"),
+                Ok(exit_code) => {
+                    info!("Client exited with exit code `{exit_code}`");
+                    Some(exit_code)
+                }
+                Err(err) => {
+                    error!("Failed to wait for client. Reason: {err}");
+                    None
+                }
             },
             Self::Tty(mut client) => match client.wait() {
-                Ok(exit_code) => info!("Client exited with exit code `{exit_code}`"),
-                Err(err) => error!("Failed to wait for client. Reason: {err}"),
+                Ok(exit_code) => {
+                    info!("Client exited with exit code `{exit_code}`");
+                    Some(exit_code)
+                }
+                Err(err) => {
+                    error!("Failed to wait for client. Reason: {err}");
+                    None
+                }
             },
         }
     }
 }
 
+/// Whether the file at `path` can be executed by the user
+fn is_executable_by_user(path: &Path, user_info: &AuthUserInfo<'_>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(metadata) = fs::metadata(path) else {
+        return false;
+    };
+
+    if !metadata.is_file() {
+        return false;
+    }
+
+    let mode = metadata.mode();
+    let mask = if user_info.uid == 0 {
+        0o111
+    } else if metadata.uid() == user_info.uid {
+        0o100
+    } else if user_info.all_gids.contains(&metadata.gid()) {
+        0o010
+    } else {
+        0o001
+    };
+
+    mode & mask != 0
+}
+
+/// Whether `program` can be executed by the user. A program without a `/` is searched for in the
+/// `PATH` of the session, as it is before the client reads any profile.
+fn is_program_available(program: &str, user_info: &AuthUserInfo<'_>) -> bool {
+    if program.contains('/') {
+        return is_executable_by_user(Path::new(program), user_info);
+    }
+
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+
+    std::env::split_paths(&path).any(|dir| is_executable_by_user(&dir.join(program), user_info))
+}
+
+/// Run a pre-exec or post-exec command as the user and log its output
+fn run_exec_hook(
+    kind: &str,
+    command: &str,
+    user_info: &AuthUserInfo<'_>,
+    config: &Config,
+) -> Result<(), ()> {
+    info!("Running {kind} command '{command}'");
+
+    // The hooks are part of the session, so they get its priorities
+    let mut hook = Command::new(&config.system_shell);
+    priority::add_to_command(&mut hook, &config.priority);
+
+    let output = lower_command_permissions_to_user(hook, user_info, config)
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| {
+            error!("Failed to start {kind} command. Reason: {err}");
+        })?;
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        info!("{kind} stdout: {line}");
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        warn!("{kind} stderr: {line}");
+    }
+
+    if !output.status.success() {
+        error!("{kind} command exited with {}", output.status);
+        return Err(());
+    }
+
+    Ok(())
+}
+
 impl PostLoginEnvironment {
+    /// The program that is started by the environment, i.e. the first word of its exec line. TTY
+    /// sessions run their command in the user's shell, so they have no such program.
+    fn session_program(&self) -> Option<&str> {
+        match self {
+            Self::X {
+                xinitrc_path: exec, ..
+            }
+            | Self::Wayland {
+                script_path: exec, ..
+            } => exec.split_whitespace().next(),
+            Self::Shell { .. } | Self::Systemd { .. } => None,
+        }
+    }
+
+    fn run_pre_exec(
+        &self,
+        user_info: &AuthUserInfo<'_>,
+        config: &Config,
+    ) -> Result<(), EnvironmentStartError> {
+        match &self.hooks().pre_exec {
+            Some(pre_exec) => run_exec_hook("pre-exec", pre_exec, user_info, config)
+                .map_err(|_| EnvironmentStartError::PreExec),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the post-exec command of the environment, if there is one. This should be called after
+    /// the environment has terminated.
+    pub fn run_post_exec(&self, user_info: &AuthUserInfo<'_>, config: &Config) {
+        if let Some(post_exec) = &self.hooks().post_exec {
+            // The session has already ended, so there is nothing to propagate the failure to.
+            let _ = run_exec_hook("post-exec", post_exec, user_info, config);
+        }
+    }
+
     pub fn spawn(
         &self,
         user_info: &AuthUserInfo<'_>,
         process_env: &mut EnvironmentContainer,
+        scope: Option<&SessionScope>,
         config: &Config,
     ) -> Result<SpawnedEnvironment, EnvironmentStartError> {
-        let shell_login_flag = match config.shell_login_flag {
-            ShellLoginFlag::None => None,
-            ShellLoginFlag::Short => Some("-l"),
-            ShellLoginFlag::Long => Some("--login"),
-        };
+        // Fail early with a specific error, instead of letting the session exit right away. A login
+        // shell reads the profile of the user, which can add to the `PATH`, so then a program that
+        // is not found here might still be found by the client.
+        if let Some(program) = self.session_program() {
+            if !is_program_available(program, user_info) {
+                if !program.contains('/') && shell_login_flag(config).is_some() {
+                    warn!("Session program '{program}' is not in the PATH of lemurs. The profile of the user might add it");
+                } else {
+                    error!("Session program '{program}' cannot be found or executed by the user");
+                    return Err(EnvironmentStartError::MissingExecutable(
+                        program.to_string(),
+                    ));
+                }
+            }
+        }
 
-        let mut client =
-            lower_command_permissions_to_user(Command::new(&config.system_shell), user_info);
+        let mut client = Command::new(client_shell(&user_info.shell, config));
+
+        // The session is moved into its scope and given its priorities before it runs as the user
+        if let Some(scope) = scope {
+            scope.add_to_command(&mut client);
+        }
+        priority::add_to_command(&mut client, &config.priority);
 
-        let log_path = config.do_log.then_some(Path::new(&config.client_log_path));
+        let mut client = lower_command_permissions_to_user(client, user_info, config);
+        client.envs(user_env::read_user_env(
+            user_info.uid,
+            &user_info.home_dir,
+            &config.session.user_env_file,
+        ));
 
-        if let Some(shell_login_flag) = shell_login_flag {
+        let log_path = client_log_path(user_info, config);
+        let mut log = LogTarget::new(Path::new(&log_path)).append(config.client_log_append);
+        if config.client_log_user_owned {
+            log = log.owned_by(user_info.uid, user_info.primary_gid);
+        }
+        let log = config.do_log.then_some(log);
+
+        if let Some(shell_login_flag) = shell_login_flag(config) {
             client.arg(shell_login_flag);
         }
 
         client.arg("-c");
+        client.arg(self.client_command(&user_info.shell, config));
 
         match self {
-            PostLoginEnvironment::X { xinitrc_path } => {
+            PostLoginEnvironment::X { .. } => {
                 info!("Starting X11 session");
 
-                let server = setup_x(process_env, user_info, config)
+                let (mut server, display) = setup_x(process_env, user_info, config)
                     .map_err(EnvironmentStartError::XSetup)?;
 
-                client.arg(format!("{} {}", &config.x11.xsetup_path, xinitrc_path));
+                // The display layout is applied before anything else runs on the display. A
+                // layout that fails to apply leaves the screens as they are.
+                if let Some(layout) = config.display_layout.layout(&config.display_layout.default) {
+                    if !layout.x11_cmd.is_empty() {
+                        let _ = run_exec_hook("display layout", &layout.x11_cmd, user_info, config);
+                    }
+                }
+
+                // The pre-exec command is run once the X server is running, so that it can
+                // interact with the display.
+                if let Err(err) = self.run_pre_exec(user_info, config) {
+                    if let Err(err) = server.send_sigterm() {
+                        error!("Failed to terminate X11. Reason: {err}");
+                    }
+                    let _ = server.wait();
+                    return Err(err);
+                }
 
-                let client = match LemursChild::spawn(client, log_path) {
+                let client = match LemursChild::spawn(client, log) {
                     Ok(child) => child,
                     Err(err) => {
                         error!("Failed to start X11 environment. Reason '{}'", err);
@@ -193,14 +841,25 @@ impl PostLoginEnvironment {
                     }
                 };
 
-                Ok(SpawnedEnvironment::X11 { server, client })
+                Ok(SpawnedEnvironment::X11 {
+                    server,
+                    client,
+                    display,
+                    uid: user_info.uid,
+                    return_vt: can_retain_server(config).then_some(config.tty),
+                })
             }
-            PostLoginEnvironment::Wayland { script_path } => {
-                info!("Starting Wayland session");
+            PostLoginEnvironment::Wayland { .. } | PostLoginEnvironment::Systemd { .. } => {
+                match self {
+                    PostLoginEnvironment::Systemd { target, .. } => {
+                        info!("Starting systemd target '{target}'")
+                    }
+                    _ => info!("Starting Wayland session"),
+                }
 
-                client.arg(script_path);
+                self.run_pre_exec(user_info, config)?;
 
-                let child = match LemursChild::spawn(client, log_path) {
+                let child = match LemursChild::spawn(client, log) {
                     Ok(child) => child,
                     Err(err) => {
                         error!("Failed to start Wayland Compositor. Reason '{err}'");
@@ -210,12 +869,23 @@ impl PostLoginEnvironment {
 
                 Ok(SpawnedEnvironment::Wayland(child))
             }
-            PostLoginEnvironment::Shell => {
-                info!("Starting TTY shell");
+            PostLoginEnvironment::Shell { command, .. } => {
+                match command {
+                    Some(command) => info!("Starting TTY command session '{command}'"),
+                    None => info!("Starting TTY shell"),
+                }
+
+                if is_non_interactive_shell(&user_info.shell) {
+                    error!(
+                        "User shell '{}' is not an interactive shell. Not starting TTY session",
+                        user_info.shell
+                    );
+                    return Err(EnvironmentStartError::NoInteractiveShell);
+                }
+
+                self.run_pre_exec(user_info, config)?;
 
-                let shell = &user_info.shell;
                 let child = match client
-                    .arg(shell)
                     .stdout(Stdio::inherit())
                     .stderr(Stdio::inherit())
                     .stdin(Stdio::inherit())
@@ -234,7 +904,49 @@ impl PostLoginEnvironment {
     }
 }
 
-fn parse_desktop_entry(path: &Path, _: &Config) -> Result<(String, String), String> {
+/// The locale that is used for the `Name` and `Comment` of desktop entries
+fn desktop_entry_locale(config: &Config) -> Option<String> {
+    if !config.environment_switcher.locale.is_empty() {
+        return Some(config.environment_switcher.locale.clone());
+    }
+
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|locale| !locale.is_empty())
+        .filter(|locale| locale != "C" && locale != "POSIX")
+}
+
+/// The localized variants of a desktop entry key from the most to the least specific, as described
+/// in the Desktop Entry Specification. The locale has the form `lang_COUNTRY.ENCODING@MODIFIER`,
+/// where everything except `lang` is optional.
+fn localized_keys(key: &str, locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split_once('.').map_or(locale, |(locale, _)| locale);
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+
+    let mut keys = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        keys.push(format!("{key}[{lang}_{country}@{modifier}]"));
+    }
+    if let Some(country) = country {
+        keys.push(format!("{key}[{lang}_{country}]"));
+    }
+    if let Some(modifier) = modifier {
+        keys.push(format!("{key}[{lang}@{modifier}]"));
+    }
+    keys.push(format!("{key}[{lang}]"));
+
+    keys
+}
+
+fn parse_desktop_entry(path: &Path, config: &Config) -> Result<DesktopEntry, String> {
     let content = match fs::read_to_string(path) {
         Ok(content) => content,
         Err(err) => {
@@ -270,26 +982,66 @@ fn parse_desktop_entry(path: &Path, _: &Config) -> Result<(String, String), Stri
         }
     };
 
-    let name = match desktop_entry.get("Name") {
-        Some(name) => match name.value().as_string() {
-            Ok(v) => v,
+    let get_string = |key: &str| match desktop_entry.get(key) {
+        Some(value) => match value.value().as_string() {
+            Ok(v) => Some(v.to_string()),
             Err(err) => {
                 warn!(
-                    "Cannot use 'Name' in '{}' because it does not contain a string. Reason: {err}",
+                    "Cannot use '{key}' in '{}' because it does not contain a string. Reason: {err}",
                     path.display()
                 );
-
-                exec
+                None
             }
         },
-        None => exec,
+        None => None,
     };
 
-    Ok((name.to_string(), exec.to_string()))
+    let locale = desktop_entry_locale(config);
+    let get_localized = |key: &str| {
+        locale
+            .as_deref()
+            .map_or_else(Vec::new, |locale| localized_keys(key, locale))
+            .iter()
+            .find_map(|localized_key| get_string(localized_key))
+            .or_else(|| get_string(key))
+    };
+
+    let name = get_localized("Name").unwrap_or_else(|| exec.to_string());
+
+    // `DesktopNames` is a list separated by `;`, whereas `XDG_CURRENT_DESKTOP` uses `:`
+    let desktop = SessionDesktop {
+        session: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        current_desktop: get_string("DesktopNames")
+            .map(|names| {
+                names
+                    .split(';')
+                    .filter(|name| !name.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(":")
+            })
+            .filter(|names| !names.is_empty()),
+    };
+
+    let hooks = ExecHooks {
+        pre_exec: get_string("X-Lemurs-PreExec"),
+        post_exec: get_string("X-Lemurs-PostExec"),
+    };
+
+    Ok(DesktopEntry {
+        name,
+        exec: exec.to_string(),
+        desktop,
+        comment: get_localized("Comment"),
+        hidden: get_string("X-Lemurs-Hidden").is_some_and(|hidden| hidden == "true"),
+        hooks,
+    })
 }
 
-pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
-    // NOTE: Maybe we can do something smart with `with_capacity` here.
+/// Get the environments from the X11 desktop entries
+fn get_xsessions_envs(config: &Config) -> Vec<EnvironmentEntry> {
     let mut envs = Vec::new();
 
     match fs::read_dir(&config.x11.xsessions_path) {
@@ -302,9 +1054,18 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
                 let path = path.path();
 
                 match parse_desktop_entry(&path, config) {
-                    Ok((name, exec)) => {
-                        info!("Added environment '{name}' from xsessions");
-                        envs.push((name, PostLoginEnvironment::X { xinitrc_path: exec }));
+                    Ok(entry) => {
+                        info!("Added environment '{}' from xsessions", entry.name);
+                        envs.push(EnvironmentEntry {
+                            name: entry.name,
+                            comment: entry.comment,
+                            hidden: entry.hidden,
+                            post_login_env: PostLoginEnvironment::X {
+                                xinitrc_path: entry.exec,
+                                desktop: entry.desktop,
+                                hooks: entry.hooks,
+                            },
+                        });
                     }
                     Err(err) => warn!("Skipping '{}', because {err}", path.display()),
                 }
@@ -315,6 +1076,13 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
         }
     }
 
+    envs
+}
+
+/// Get the environments from the Wayland desktop entries
+fn get_wayland_sessions_envs(config: &Config) -> Vec<EnvironmentEntry> {
+    let mut envs = Vec::new();
+
     match fs::read_dir(&config.wayland.wayland_sessions_path) {
         Ok(paths) => {
             for path in paths {
@@ -325,9 +1093,18 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
                 let path = path.path();
 
                 match parse_desktop_entry(&path, config) {
-                    Ok((name, exec)) => {
-                        info!("Added environment '{name}' from wayland sessions");
-                        envs.push((name, PostLoginEnvironment::Wayland { script_path: exec }))
+                    Ok(entry) => {
+                        info!("Added environment '{}' from wayland sessions", entry.name);
+                        envs.push(EnvironmentEntry {
+                            name: entry.name,
+                            comment: entry.comment,
+                            hidden: entry.hidden,
+                            post_login_env: PostLoginEnvironment::Wayland {
+                                script_path: entry.exec,
+                                desktop: entry.desktop,
+                                hooks: entry.hooks,
+                            },
+                        })
                     }
                     Err(err) => warn!("Skipping '{}', because {err}", path.display()),
                 }
@@ -338,6 +1115,13 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
         }
     }
 
+    envs
+}
+
+/// Get the environments from the lemurs X11 scripts
+fn get_x11_script_envs(config: &Config) -> Vec<EnvironmentEntry> {
+    let mut envs = Vec::new();
+
     match fs::read_dir(&config.x11.scripts_path) {
         Ok(paths) => {
             for path in paths {
@@ -356,8 +1140,8 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
                         }
 
                         info!("Added environment '{file_name}' from lemurs x11 scripts");
-                        envs.push((
-                            file_name,
+                        envs.push(EnvironmentEntry::new(
+                            file_name.clone(),
                             PostLoginEnvironment::X {
                                 xinitrc_path: match path.path().to_str() {
                                     Some(p) => p.to_string(),
@@ -368,6 +1152,11 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
                                         continue;
                                     }
                                 },
+                                desktop: SessionDesktop {
+                                    session: file_name,
+                                    current_desktop: None,
+                                },
+                                hooks: ExecHooks::default(),
                             },
                         ));
                     } else {
@@ -386,6 +1175,13 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
         }
     }
 
+    envs
+}
+
+/// Get the environments from the lemurs Wayland scripts
+fn get_wayland_script_envs(config: &Config) -> Vec<EnvironmentEntry> {
+    let mut envs = Vec::new();
+
     match fs::read_dir(&config.wayland.scripts_path) {
         Ok(paths) => {
             for path in paths {
@@ -405,8 +1201,8 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
                         }
 
                         info!("Added environment '{file_name}' from lemurs wayland scripts");
-                        envs.push((
-                            file_name,
+                        envs.push(EnvironmentEntry::new(
+                            file_name.clone(),
                             PostLoginEnvironment::Wayland {
                                 script_path: match path.path().to_str() {
                                     Some(p) => p.to_string(),
@@ -417,6 +1213,11 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
                                         continue;
                                     }
                                 },
+                                desktop: SessionDesktop {
+                                    session: file_name,
+                                    current_desktop: None,
+                                },
+                                hooks: ExecHooks::default(),
                             },
                         ));
                     } else {
@@ -435,13 +1236,231 @@ pub fn get_envs(config: &Config) -> Vec<(String, PostLoginEnvironment)> {
         }
     }
 
+    envs
+}
+
+pub fn get_envs(config: &Config) -> Vec<EnvironmentEntry> {
+    // The different directories are scanned in parallel, since reading them may be slow.
+    let mut envs = std::thread::scope(|s| {
+        let handles = [
+            s.spawn(|| get_xsessions_envs(config)),
+            s.spawn(|| get_wayland_sessions_envs(config)),
+            s.spawn(|| get_x11_script_envs(config)),
+            s.spawn(|| get_wayland_script_envs(config)),
+        ];
+
+        handles
+            .into_iter()
+            .flat_map(|handle| {
+                handle.join().unwrap_or_else(|_| {
+                    error!("Thread scanning for environments panicked");
+                    Vec::new()
+                })
+            })
+            .collect::<Vec<_>>()
+    });
+
     if envs.is_empty() || config.environment_switcher.include_tty_shell {
         if envs.is_empty() {
             info!("Added TTY SHELL because no other environments were found");
         }
 
-        envs.push(("TTYSHELL".to_string(), PostLoginEnvironment::Shell));
+        envs.push(EnvironmentEntry::new(
+            "TTYSHELL",
+            PostLoginEnvironment::Shell {
+                command: None,
+                hooks: ExecHooks::default(),
+            },
+        ));
+    }
+
+    for command_session in &config.environment_switcher.command_sessions.0 {
+        if command_session.name.is_empty() || command_session.cmd.is_empty() {
+            warn!("Skipping command session without a name or command");
+            continue;
+        }
+
+        envs.push(EnvironmentEntry::new(
+            command_session.name.clone(),
+            PostLoginEnvironment::Shell {
+                command: Some(command_session.cmd.clone()),
+                hooks: ExecHooks::default(),
+            },
+        ));
+    }
+
+    if let Some((title, command)) = multiplexer::multiplexer_session(&config.tty_session) {
+        envs.push(EnvironmentEntry::new(
+            title,
+            PostLoginEnvironment::Shell {
+                command: Some(command),
+                hooks: ExecHooks::default(),
+            },
+        ));
+    }
+
+    for systemd_session in &config.environment_switcher.systemd_sessions.0 {
+        if systemd_session.name.is_empty() || systemd_session.target.is_empty() {
+            warn!("Skipping systemd session without a name or target");
+            continue;
+        }
+
+        let target = &systemd_session.target;
+        envs.push(EnvironmentEntry::new(
+            systemd_session.name.clone(),
+            PostLoginEnvironment::Systemd {
+                target: target.clone(),
+                desktop: SessionDesktop {
+                    session: target.strip_suffix(".target").unwrap_or(target).to_string(),
+                    current_desktop: None,
+                },
+                hooks: ExecHooks::default(),
+            },
+        ));
+    }
+
+    for name in &config.environment_switcher.hidden {
+        let mut found = false;
+
+        for entry in envs.iter_mut().filter(|entry| &entry.name == name) {
+            entry.hidden = true;
+            found = true;
+        }
+
+        if !found {
+            warn!(
+                "Environment '{name}' is configured to be hidden, but no such environment exists"
+            );
+        }
+    }
+
+    for (name, current_desktop) in &config.session.desktop_names.0 {
+        let mut found = false;
+
+        for desktop in envs
+            .iter_mut()
+            .filter(|entry| &entry.name == name)
+            .filter_map(|entry| entry.post_login_env.desktop_mut())
+        {
+            desktop.current_desktop = Some(current_desktop.clone());
+            found = true;
+        }
+
+        if !found {
+            warn!("A desktop name is configured for '{name}', but no such graphical environment exists");
+        }
+    }
+
+    // Hooks from the configuration take precedence over hooks from the desktop entries
+    for session_hook in &config.session_hooks.0 {
+        let mut found = false;
+
+        for entry in envs
+            .iter_mut()
+            .filter(|entry| entry.name == session_hook.name)
+        {
+            let hooks = entry.post_login_env.hooks_mut();
+
+            if !session_hook.pre_exec.is_empty() {
+                hooks.pre_exec = Some(session_hook.pre_exec.clone());
+            }
+            if !session_hook.post_exec.is_empty() {
+                hooks.post_exec = Some(session_hook.post_exec.clone());
+            }
+
+            found = true;
+        }
+
+        if !found {
+            warn!(
+                "Session hooks are configured for '{}', but no such environment exists",
+                session_hook.name
+            );
+        }
     }
 
     envs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawned_environment_terminate() {
+        let child = Command::new("sleep").arg("60").spawn().unwrap();
+        let mut environment = SpawnedEnvironment::Tty(child);
+
+        assert!(environment
+            .wait_timeout(Duration::from_millis(100))
+            .unwrap()
+            .is_none());
+
+        environment.terminate(Duration::from_secs(5)).unwrap();
+        assert!(environment.try_wait().unwrap().is_some());
+
+        let exit_status = environment.wait();
+        assert!(exit_status.is_some_and(|status| !status.success()));
+    }
+
+    /// Whether `command` is valid for a POSIX shell
+    fn is_valid_shell(command: &str) -> bool {
+        Command::new("sh")
+            .arg("-n")
+            .arg("-c")
+            .arg(command)
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    #[test]
+    fn systemd_unit_command_imports_session_variables() {
+        let config = Config::default();
+        let command = systemd_unit_command("/etc/lemurs/wayland/it's sway", &config);
+
+        assert!(is_valid_shell(&command));
+        assert!(command.contains(&format!(
+            "systemd-run --user --scope --collect --quiet --unit=lemurs-wayland-tty{} -- {} -c '/etc/lemurs/wayland/it'\\''s sway'",
+            config.tty, config.system_shell
+        )));
+
+        let variables = SYSTEMD_SESSION_VARIABLES.join(" ");
+        assert!(command.starts_with(&format!("systemctl --user import-environment {variables};")));
+        assert!(command.contains(&format!("systemctl --user unset-environment {variables};")));
+        assert!(!command.contains("env |"));
+    }
+
+    #[test]
+    fn systemd_target_command_imports_session_variables() {
+        let command = systemd_target_command("sway-session.target");
+
+        assert!(is_valid_shell(&command));
+        assert!(command.contains("systemctl --user start --wait 'sway-session.target' & pid=$!;"));
+        assert!(
+            command.contains("trap \"systemctl --user stop 'sway-session.target'\" HUP INT TERM;")
+        );
+
+        let variables = SYSTEMD_SESSION_VARIABLES.join(" ");
+        assert!(command.starts_with(&format!("systemctl --user import-environment {variables};")));
+        assert!(command.contains(&format!("systemctl --user unset-environment {variables};")));
+        assert!(!command.contains("env |"));
+    }
+
+    #[test]
+    fn desktop_entry_localized_keys() {
+        assert_eq!(
+            localized_keys("Name", "sr_YU.UTF-8@Latn"),
+            [
+                "Name[sr_YU@Latn]",
+                "Name[sr_YU]",
+                "Name[sr@Latn]",
+                "Name[sr]"
+            ]
+        );
+        assert_eq!(
+            localized_keys("Comment", "de_DE.UTF-8"),
+            ["Comment[de_DE]", "Comment[de]"]
+        );
+        assert_eq!(localized_keys("Name", "nl"), ["Name[nl]"]);
+    }
+}
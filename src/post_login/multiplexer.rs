@@ -0,0 +1,108 @@
+//! A TTY session that attaches to a named tmux or screen session, or creates it when it does not
+//! exist yet, so that a workspace on a server persists between logins
+
+use super::shell_quote;
+use crate::config::TtySessionConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+impl Multiplexer {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "tmux" => Some(Self::Tmux),
+            "screen" => Some(Self::Screen),
+            _ => None,
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Self::Tmux => "tmux",
+            Self::Screen => "screen",
+        }
+    }
+
+    /// The arguments that attach to the session in `$session`, or create it
+    fn attach_args(self) -> &'static str {
+        match self {
+            Self::Tmux => "new-session -A -s \"$session\"",
+            Self::Screen => "-xRR -S \"$session\"",
+        }
+    }
+}
+
+/// A session name in which `%user%` is replaced by the user that logs in, quoted for a POSIX shell
+fn quote_session_name(name: &str) -> String {
+    name.split("%user%")
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join("\"$USER\"")
+}
+
+/// The title and the command of the multiplexer session, if one is configured. The command is run
+/// by `sh`, so that it does not depend on the shell of the user.
+pub fn multiplexer_session(config: &TtySessionConfig) -> Option<(String, String)> {
+    let multiplexer = Multiplexer::from_name(&config.multiplexer)?;
+
+    let mut script = String::from("case \"$USER\" in ");
+    for user in &config.multiplexer_users.0 {
+        script.push_str(&format!(
+            "{}) session={} ;; ",
+            shell_quote(&user.user),
+            quote_session_name(&user.session)
+        ));
+    }
+    script.push_str(&format!(
+        "*) session={} ;; esac; exec {} {}",
+        quote_session_name(&config.multiplexer_session),
+        multiplexer.program(),
+        multiplexer.attach_args()
+    ));
+
+    let title = if config.multiplexer_title.is_empty() {
+        multiplexer.program().to_string()
+    } else {
+        config.multiplexer_title.clone()
+    };
+
+    Some((title, format!("exec sh -c {}", shell_quote(&script))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::{Config, MultiplexerUser};
+
+    #[test]
+    fn multiplexer_command() {
+        let mut config = Config::default().tty_session;
+        config.multiplexer_session = "main-%user%".to_string();
+        config.multiplexer_users.0.push(MultiplexerUser {
+            user: "alice".to_string(),
+            session: "work".to_string(),
+        });
+        assert_eq!(multiplexer_session(&config), None);
+
+        config.multiplexer = "tmux".to_string();
+        let (title, command) = multiplexer_session(&config).unwrap();
+        assert_eq!(title, "tmux");
+
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command.replace("exec tmux", "echo"))
+            .env("USER", "bob")
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "new-session -A -s main-bob\n"
+        );
+
+        assert_eq!(quote_session_name("%user%"), "''\"$USER\"''");
+    }
+}
@@ -0,0 +1,315 @@
+//! Sessions that end after a maximum duration, e.g. on lab and exam machines.
+//!
+//! Some time before the session expires, the user is warned through the notification socket, on
+//! the tty and, in graphical sessions, with a command that runs in the session. Once it has
+//! expired, the configured signal is sent to the processes of the session and lemurs returns to
+//! the greeter when the session has exited.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::auth::AuthUserInfo;
+use crate::cgroup;
+use crate::config::Config;
+use crate::notifications::{self, Severity};
+
+use super::watchdog::{is_running, process_trees};
+use super::{lower_command_permissions_to_user, SpawnedEnvironment};
+
+/// How long the session gets to exit after the signal before it is killed
+const SIGNAL_GRACE: Duration = Duration::from_secs(10);
+
+/// The signal that is sent by its name in the configuration
+pub fn signal_from_name(name: &str) -> Option<libc::c_int> {
+    Some(match name.trim() {
+        "SIGTERM" => libc::SIGTERM,
+        "SIGHUP" => libc::SIGHUP,
+        "SIGINT" => libc::SIGINT,
+        "SIGUSR1" => libc::SIGUSR1,
+        "SIGUSR2" => libc::SIGUSR2,
+        "SIGKILL" => libc::SIGKILL,
+        _ => return None,
+    })
+}
+
+/// The environment variable with the warning message for the `session.timeout_warning_cmd`
+const WARNING_MESSAGE_VAR: &str = "LEMURS_TIMEOUT_MESSAGE";
+
+/// The warning that the session is about to end
+struct Warning {
+    message: String,
+    socket_path: Option<PathBuf>,
+    tty: u8,
+    /// The command that shows the warning in a graphical session
+    command: Option<Command>,
+}
+
+impl Warning {
+    /// The warning `minutes` before the end of the session, if there is one. Graphical sessions
+    /// are warned with the `session.timeout_warning_cmd` as well, which is run as root until its
+    /// permissions are lowered.
+    fn new(minutes: u32, is_graphical: bool, config: &Config) -> Option<Self> {
+        let session = &config.session;
+        if minutes == 0 || session.timeout_warning_message.is_empty() {
+            return None;
+        }
+
+        let message = session
+            .timeout_warning_message
+            .replace("%minutes%", &minutes.to_string());
+
+        // The command runs with the environment of the session, so that it reaches e.g. the
+        // notification daemon of the session
+        let command = (is_graphical && !session.timeout_warning_cmd.is_empty()).then(|| {
+            let mut command = Command::new(&config.system_shell);
+            command
+                .arg("-c")
+                .arg(
+                    session
+                        .timeout_warning_cmd
+                        .replace("%minutes%", &minutes.to_string()),
+                )
+                .env(WARNING_MESSAGE_VAR, &message)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+            command
+        });
+
+        let socket_path = &config.notifications.socket_path;
+        Some(Self {
+            message,
+            socket_path: (!socket_path.is_empty())
+                .then(|| PathBuf::from(socket_path.replace("%tty%", &config.tty.to_string()))),
+            tty: config.tty,
+            command,
+        })
+    }
+
+    /// Show the warning on the screen of lemurs and on its tty, which is also the tty of TTY
+    /// sessions. Graphical sessions do not show either, so there the warning command is run in the
+    /// session. The command is returned so that it can be reaped.
+    fn send(&mut self, timeout: Duration) -> Option<Child> {
+        if let Some(socket_path) = &self.socket_path {
+            if let Err(err) =
+                notifications::send(socket_path, Severity::Warning, Some(timeout), &self.message)
+            {
+                warn!(
+                    "Failed to send the timeout warning to '{}'. Reason: {err}",
+                    socket_path.display()
+                );
+            }
+        }
+
+        let tty_path = format!("/dev/tty{}", self.tty);
+        let written = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(&tty_path)
+            .and_then(|mut tty| write!(tty, "\r\n\x07{}\r\n", self.message));
+        if let Err(err) = written {
+            warn!("Failed to write the timeout warning to '{tty_path}'. Reason: {err}");
+        }
+
+        let command = self.command.as_mut()?;
+        command
+            .spawn()
+            .map_err(|err| warn!("Failed to run the timeout warning command. Reason: {err}"))
+            .ok()
+    }
+}
+
+/// Ends a session once it has run for `session.max_duration_mins`
+pub struct SessionTimer {
+    pid: u32,
+    cgroup: Option<PathBuf>,
+    max_duration: Duration,
+    signal: libc::c_int,
+    warning_before: Duration,
+    warning: Option<Warning>,
+}
+
+impl SessionTimer {
+    /// The timer for `environment`, if the session has a maximum duration. The processes of the
+    /// session are found through its `cgroup`, relative to the root of the hierarchy, so that
+    /// processes that left the process tree of the session are ended as well. Without a cgroup,
+    /// the process tree is used.
+    pub fn new(
+        environment: &SpawnedEnvironment,
+        cgroup: Option<PathBuf>,
+        user_info: &AuthUserInfo<'_>,
+        config: &Config,
+    ) -> Option<Self> {
+        let session = &config.session;
+        if session.max_duration_mins == 0 {
+            return None;
+        }
+
+        let signal = signal_from_name(&session.timeout_signal).unwrap_or_else(|| {
+            warn!(
+                "Unknown signal '{}'. Using SIGTERM instead",
+                session.timeout_signal
+            );
+            libc::SIGTERM
+        });
+
+        let minutes = session
+            .timeout_warning_mins
+            .min(session.max_duration_mins - 1);
+        let is_graphical = !matches!(environment, SpawnedEnvironment::Tty(_));
+        let mut warning = Warning::new(minutes, is_graphical, config);
+        if let Some(warning) = &mut warning {
+            warning.command = warning
+                .command
+                .take()
+                .map(|command| lower_command_permissions_to_user(command, user_info, config));
+        }
+
+        Some(Self {
+            pid: environment.pid(),
+            cgroup,
+            max_duration: Duration::from_secs(u64::from(session.max_duration_mins) * 60),
+            signal,
+            warning_before: Duration::from_secs(u64::from(minutes) * 60),
+            warning,
+        })
+    }
+
+    /// Run the timer in the background. The timer stops once the returned sender is dropped,
+    /// which should happen as soon as the session has exited.
+    pub fn start(self) -> Sender<()> {
+        info!(
+            "Ending the session after {} minutes",
+            self.max_duration.as_secs() / 60
+        );
+
+        let (stop, stopped) = mpsc::channel();
+        thread::spawn(move || self.run(stopped));
+        stop
+    }
+
+    /// The processes of the session, without lemurs itself. With logind, lemurs is in the scope of
+    /// the session as well.
+    fn processes(&self) -> Vec<u32> {
+        let processes = match &self.cgroup {
+            Some(cgroup) => cgroup::processes(cgroup).unwrap_or_else(|err| {
+                warn!(
+                    "Failed to read the processes of cgroup '{}'. Reason: {err}",
+                    cgroup.display()
+                );
+                process_trees(&[self.pid])
+            }),
+            None => process_trees(&[self.pid]),
+        };
+
+        let own_pid = std::process::id();
+        processes
+            .into_iter()
+            .filter(|pid| *pid != own_pid)
+            .collect()
+    }
+
+    fn run(mut self, stopped: Receiver<()>) {
+        // Whether the session is still running after `timeout`
+        let wait = |timeout| {
+            matches!(
+                stopped.recv_timeout(timeout),
+                Err(RecvTimeoutError::Timeout)
+            )
+        };
+
+        let mut remaining = self.max_duration;
+        let mut warning_command = None;
+        if let Some(warning) = &mut self.warning {
+            if !wait(self.max_duration - self.warning_before) {
+                return;
+            }
+            remaining = self.warning_before;
+
+            info!(
+                "Warning that the session ends in {} minutes",
+                self.warning_before.as_secs() / 60
+            );
+            warning_command = warning.send(self.warning_before);
+        }
+
+        let still_running = wait(remaining);
+
+        if let Some(child) = warning_command {
+            reap(child);
+        }
+        if !still_running {
+            return;
+        }
+
+        info!("The session has reached its maximum duration. Ending it");
+        let processes = self.processes();
+        for pid in &processes {
+            unsafe { libc::kill(*pid as libc::pid_t, self.signal) };
+        }
+
+        if !wait(SIGNAL_GRACE) {
+            return;
+        }
+
+        let remaining = processes
+            .into_iter()
+            .filter(|pid| is_running(*pid))
+            .collect::<Vec<_>>();
+        if !remaining.is_empty() {
+            warn!("Session did not exit in time. Killing it");
+            for pid in remaining {
+                unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+            }
+        }
+    }
+}
+
+/// Reap the warning command, so that it does not linger as a zombie. It is killed if it still runs.
+fn reap(mut child: Child) {
+    if !matches!(child.try_wait(), Ok(Some(_))) {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graphical_sessions_run_warning_command() {
+        let mut config = Config::default();
+        config.system_shell = "/bin/sh".to_string();
+        config.session.timeout_warning_message = "Ending in %minutes% minutes".to_string();
+        config.session.timeout_warning_cmd =
+            "echo \"%minutes%: $LEMURS_TIMEOUT_MESSAGE\"".to_string();
+
+        let warning = Warning::new(5, true, &config).unwrap();
+        let output = warning
+            .command
+            .unwrap()
+            .stdout(Stdio::piped())
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "5: Ending in 5 minutes\n"
+        );
+
+        // TTY sessions see the warning on the tty
+        let warning = Warning::new(5, false, &config).unwrap();
+        assert!(warning.command.is_none());
+        assert_eq!(warning.message, "Ending in 5 minutes");
+
+        assert!(Warning::new(0, true, &config).is_none());
+    }
+}
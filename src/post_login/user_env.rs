@@ -0,0 +1,182 @@
+//! Environment variables that users set for their own sessions in a `KEY=VALUE` file in their
+//! home directory.
+//!
+//! Files of the user are read by root, so they are only used when they are a regular file that is
+//! owned by the user and cannot be written by others. The variables are only given to the client
+//! of the session, as lemurs also starts processes as root (e.g. the X server).
+
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+/// Files larger than this are not read
+const MAX_FILE_SIZE: u64 = 64 * 1024;
+
+/// The variables that describe the session and the user, which lemurs sets itself
+const PROTECTED_VARIABLES: [&str; 13] = [
+    "USER",
+    "LOGNAME",
+    "HOME",
+    "SHELL",
+    "DISPLAY",
+    "XAUTHORITY",
+    "WAYLAND_DISPLAY",
+    "XDG_RUNTIME_DIR",
+    "XDG_SEAT",
+    "XDG_VTNR",
+    "XDG_SESSION_ID",
+    "XDG_SESSION_TYPE",
+    "XDG_SESSION_CLASS",
+];
+
+fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse the `KEY=VALUE` lines of `content`, which was read from `source`. Empty lines and lines
+/// that start with `#` are skipped, and a value can be quoted with `"` or `'`. Nothing is expanded.
+pub(super) fn parse(content: &str, source: &Path) -> Vec<(String, String)> {
+    let source = source.display();
+    let mut variables = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            warn!("Ignoring line {} of '{source}' without a '='", index + 1);
+            continue;
+        };
+
+        let key = key.trim();
+        if !is_valid_key(key) {
+            warn!("Ignoring the invalid variable name '{key}' in '{source}'");
+            continue;
+        }
+        if PROTECTED_VARIABLES.contains(&key) {
+            warn!("Ignoring '{key}' in '{source}', since it is set by lemurs");
+            continue;
+        }
+
+        let value = value.trim();
+        let value = ['"', '\'']
+            .into_iter()
+            .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+            .unwrap_or(value);
+        if value.contains('\0') {
+            warn!("Ignoring '{key}' in '{source}', since it contains a NUL byte");
+            continue;
+        }
+
+        variables.push((key.to_string(), value.to_string()));
+    }
+
+    variables
+}
+
+/// Read the file of the user with `uid` at `path`, which is relative to the home directory of the
+/// user. Returns the path and the content, or nothing if the file does not exist or cannot be used.
+pub(super) fn read_user_file(uid: u32, home_dir: &str, path: &str) -> Option<(PathBuf, String)> {
+    let path = Path::new(home_dir).join(path);
+
+    // The file is not followed if it is a symlink, and it is opened without blocking in case it is
+    // a FIFO
+    let file = match File::options()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(err) => {
+            warn!("Failed to open '{}'. Reason: {err}", path.display());
+            return None;
+        }
+    };
+
+    let metadata = match file.metadata() {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            warn!("Failed to read '{}'. Reason: {err}", path.display());
+            return None;
+        }
+    };
+
+    if !metadata.is_file() || metadata.uid() != uid || metadata.mode() & 0o022 != 0 {
+        warn!(
+            "Ignoring '{}', since it is not a regular file that only its user can write",
+            path.display()
+        );
+        return None;
+    }
+    if metadata.len() > MAX_FILE_SIZE {
+        warn!(
+            "Ignoring '{}', since it is larger than {MAX_FILE_SIZE} bytes",
+            path.display()
+        );
+        return None;
+    }
+
+    let mut content = String::new();
+    if let Err(err) = file.take(MAX_FILE_SIZE).read_to_string(&mut content) {
+        warn!("Failed to read '{}'. Reason: {err}", path.display());
+        return None;
+    }
+
+    Some((path, content))
+}
+
+/// Read the variables of the user with `uid` from `path`, which is relative to the home directory
+/// of the user. Returns nothing if `path` is empty or the file cannot be used.
+pub fn read_user_env(uid: u32, home_dir: &str, path: &str) -> Vec<(String, String)> {
+    if path.is_empty() {
+        return Vec::new();
+    }
+
+    let Some((path, content)) = read_user_file(uid, home_dir, path) else {
+        return Vec::new();
+    };
+
+    let variables = parse(&content, &path);
+    info!(
+        "Loaded {} variable(s) from the user environment file",
+        variables.len()
+    );
+
+    variables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_user_env() {
+        let variables = parse(
+            "# Comment\n\nEDITOR=vim\nexport GTK_THEME = \"Adwaita:dark\"\nQUOTED='$HOME'\n\
+             1INVALID=x\nHOME=/tmp\nNO_VALUE\nEMPTY=\n",
+            Path::new("env"),
+        );
+
+        assert_eq!(
+            variables,
+            [
+                ("EDITOR", "vim"),
+                ("GTK_THEME", "Adwaita:dark"),
+                ("QUOTED", "$HOME"),
+                ("EMPTY", ""),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+        );
+    }
+}
@@ -1,14 +1,17 @@
 //! This module implements a thread that ensures that the log files don't exceed a specific size.
+//! The thread can also pass the output on to a listener line by line as it is written.
 
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::io::{self, BufWriter};
-use std::path::Path;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{SyncSender, TrySendError};
 use std::thread::JoinHandle;
 
 use std::io::Read;
 
-use log::info;
+use log::{error, info, warn};
 use mio::unix::pipe::Receiver;
 use mio::{Events, Interest, Poll, Token, Waker};
 
@@ -48,15 +51,261 @@ impl<W: io::Write> io::Write for LimitSizeWriter<W> {
 }
 
 impl<W: io::Write> LimitSizeWriter<W> {
-    pub fn new(writer: W, size_limit: usize) -> Self {
+    /// `current_byte_count` is what was already written, such as the log of an earlier session
+    /// that is appended to
+    pub fn new(writer: W, current_byte_count: usize, size_limit: usize) -> Self {
         Self {
             writer: BufWriter::new(writer),
-            current_byte_count: 0,
+            current_byte_count: usize::min(current_byte_count, size_limit),
             size_limit,
         }
     }
 }
 
+/// Where the output of a process is logged to
+#[derive(Debug, Clone, Copy)]
+pub struct LogTarget<'a> {
+    path: &'a Path,
+    append: bool,
+    owner: Option<(libc::uid_t, libc::gid_t)>,
+}
+
+impl<'a> LogTarget<'a> {
+    pub fn new(path: &'a Path) -> Self {
+        Self {
+            path,
+            append: false,
+            owner: None,
+        }
+    }
+
+    /// Append to the file instead of truncating it. A file that exceeds the size limit is still
+    /// truncated.
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Give the file to a user, so that only they can read it
+    pub fn owned_by(mut self, uid: libc::uid_t, gid: libc::gid_t) -> Self {
+        self.owner = Some((uid, gid));
+        self
+    }
+
+    fn open(&self) -> io::Result<File> {
+        use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+
+        let mut file_options = OpenOptions::new();
+        file_options.create(true);
+        file_options.write(true);
+        file_options.append(self.append);
+        // A link is never followed, and the file is only truncated after it has been checked
+        file_options.custom_flags(libc::O_NOFOLLOW);
+
+        let Some((uid, gid)) = self.owner else {
+            // Root can write anywhere, so its logs are only written in directories that other
+            // users cannot change
+            let file = file_options.open(resolve_trusted_path(self.path)?)?;
+
+            let metadata = file.metadata()?;
+            if metadata.uid() != 0 && metadata.uid() != unsafe { libc::geteuid() } {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "Log file is owned by another user",
+                ));
+            }
+
+            // Devices such as `/dev/null` cannot be truncated
+            if metadata.is_file()
+                && (!self.append || metadata.len() >= LOG_WRITER_SIZE_LIMIT as u64)
+            {
+                file.set_len(0)?;
+            }
+            return Ok(file);
+        };
+
+        // The file can be in a directory of the user, such as their home directory. Therefore, it
+        // is opened as the user, so that it cannot lead to a file that only root can write to.
+        file_options.mode(0o600);
+        let file = open_as_user(&file_options, self.path, uid, gid)?;
+
+        let metadata = file.metadata()?;
+        if !metadata.is_file() || metadata.nlink() > 1 || metadata.uid() != uid {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Log file is not a regular file of the user",
+            ));
+        }
+
+        if unsafe { libc::fchown(file.as_raw_fd(), uid, gid) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+
+        if !self.append || metadata.len() >= LOG_WRITER_SIZE_LIMIT as u64 {
+            file.set_len(0)?;
+        }
+
+        Ok(file)
+    }
+}
+
+/// The path of a log file with its directory resolved. Every directory on the way has to be owned
+/// by root and not be writable by everyone, unless it is sticky like `/tmp`, so that the file
+/// cannot be redirected to another file by another user. Directories that are writable by their
+/// group, such as `/var/log` with the `syslog` group, are allowed, since the administrator chose
+/// that group.
+fn resolve_trusted_path(path: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    /// The sticky bit, with which only the owner of an entry can replace it
+    const STICKY: u32 = 0o1000;
+
+    let Some(file_name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Log path has no file name",
+        ));
+    };
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+
+    // The resolved directory has no links, so only its ancestors have to be checked
+    let directory = directory.canonicalize()?;
+    let euid = unsafe { libc::geteuid() };
+    for ancestor in directory.ancestors() {
+        let metadata = ancestor.metadata()?;
+        let mode = metadata.mode();
+
+        let is_trusted_owner = metadata.uid() == 0 || metadata.uid() == euid;
+        if !is_trusted_owner || (mode & 0o002 != 0 && mode & STICKY == 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "'{}' can be changed by users other than root and its group",
+                    ancestor.display()
+                ),
+            ));
+        }
+    }
+
+    Ok(directory.join(file_name))
+}
+
+/// Open `path` with the file system ids of the user, so that the permissions of the user apply
+/// instead of those of root
+fn open_as_user(
+    options: &OpenOptions,
+    path: &Path,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+) -> io::Result<File> {
+    // The ids are only changed for this thread. Setting them cannot fail with an error, so they
+    // are checked by setting them a second time, which returns the current ids.
+    let previous_gid = unsafe { libc::setfsgid(gid) } as libc::gid_t;
+    let previous_uid = unsafe { libc::setfsuid(uid) } as libc::uid_t;
+
+    let is_switched = unsafe { libc::setfsgid(gid) } as libc::gid_t == gid
+        && unsafe { libc::setfsuid(uid) } as libc::uid_t == uid;
+    let file = if is_switched {
+        options.open(path)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Failed to switch to the user to open the log file",
+        ))
+    };
+
+    unsafe {
+        libc::setfsuid(previous_uid);
+        libc::setfsgid(previous_gid);
+    }
+
+    file
+}
+
+/// Lines longer than this are split, so that a process without newlines does not fill the memory
+const MAX_LINE_LENGTH: usize = 4096;
+
+/// The output of a process that a line was written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// A line of output of a process, without the newline
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub line: String,
+}
+
+/// Receives the output of a process line by line as it is written. Lines are dropped when the
+/// receiving end does not keep up, so that the process never blocks on its output.
+pub type LogListener = SyncSender<LogLine>;
+
+/// Splits the output of one stream into lines for the listener
+struct LineForwarder {
+    stream: LogStream,
+    listener: Option<LogListener>,
+    partial: Vec<u8>,
+}
+
+impl LineForwarder {
+    fn new(stream: LogStream, listener: Option<LogListener>) -> Self {
+        Self {
+            stream,
+            listener,
+            partial: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, line: &[u8]) {
+        let Some(listener) = &self.listener else {
+            return;
+        };
+
+        let line = LogLine {
+            stream: self.stream,
+            line: String::from_utf8_lossy(line).into_owned(),
+        };
+
+        if let Err(TrySendError::Disconnected(_)) = listener.try_send(line) {
+            self.listener = None;
+            self.partial.clear();
+        }
+    }
+
+    fn forward(&mut self, buf: &[u8]) {
+        if self.listener.is_none() {
+            return;
+        }
+
+        self.partial.extend_from_slice(buf);
+
+        while let Some(position) = self.partial.iter().position(|&b| b == b'\n') {
+            let line = self.partial.drain(..=position).collect::<Vec<_>>();
+            self.send(&line[..position]);
+        }
+
+        if self.partial.len() >= MAX_LINE_LENGTH {
+            let line = std::mem::take(&mut self.partial);
+            self.send(&line);
+        }
+    }
+
+    /// Send the last line, which did not end with a newline
+    fn finish(&mut self) {
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.send(&line);
+        }
+    }
+}
+
 /// This is a wrapper of the rust std `Child` struct.
 ///
 /// This makes handling spawning, killing and waiting a lot easier to combine with the
@@ -72,8 +321,8 @@ pub struct LimitedOutputChild {
 }
 
 impl LemursChild {
-    pub fn spawn(mut command: Command, log_path: Option<&Path>) -> io::Result<Self> {
-        Ok(match log_path {
+    pub fn spawn(mut command: Command, log: Option<LogTarget>) -> io::Result<Self> {
+        Ok(match log {
             None => Self::NoLog(
                 command
                     .stdin(Stdio::null())
@@ -81,10 +330,23 @@ impl LemursChild {
                     .stderr(Stdio::null())
                     .spawn()?,
             ),
-            Some(log_path) => Self::Log(LimitedOutputChild::spawn(command, log_path)?),
+            Some(log) => Self::Log(LimitedOutputChild::spawn(command, Some(log), None)?),
         })
     }
 
+    /// Spawn `command` and send its output to `listener`, besides logging it to `log`
+    pub fn spawn_with_listener(
+        command: Command,
+        log: Option<LogTarget>,
+        listener: LogListener,
+    ) -> io::Result<Self> {
+        Ok(Self::Log(LimitedOutputChild::spawn(
+            command,
+            log,
+            Some(listener),
+        )?))
+    }
+
     pub fn wait(&mut self) -> io::Result<ExitStatus> {
         match self {
             Self::NoLog(process) => process.wait(),
@@ -120,8 +382,56 @@ impl LemursChild {
     }
 }
 
+/// The log file. Logging to the file stops when writing to it fails, e.g. because the disk is full,
+/// so that the output keeps being drained and the process never blocks on it.
+struct LogFile<W: io::Write>(Option<LimitSizeWriter<W>>);
+
+impl<W: io::Write> LogFile<W> {
+    fn write(&mut self, buf: &[u8]) {
+        let Some(writer) = &mut self.0 else {
+            return;
+        };
+
+        if let Err(err) = io::Write::write_all(writer, buf) {
+            warn!("Failed to write to the log file. Stopping logging to it. Reason: {err}");
+            self.0 = None;
+        }
+    }
+}
+
+/// Read everything that is currently available from `receiver`. Returns whether the write end is
+/// closed.
+fn forward_output(
+    receiver: &mut Receiver,
+    log_file: &mut LogFile<impl io::Write>,
+    lines: &mut LineForwarder,
+) -> io::Result<bool> {
+    let mut buf = [0u8; 2048];
+
+    // The pipes are registered edge-triggered, so they have to be read until they would block
+    loop {
+        match receiver.read(&mut buf) {
+            Ok(0) => {
+                lines.finish();
+                return Ok(true);
+            }
+            Ok(n) => {
+                log_file.write(&buf[..n]);
+                lines.forward(&buf[..n]);
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
 impl LimitedOutputChild {
-    pub fn spawn(mut command: Command, log_path: &Path) -> io::Result<Self> {
+    pub fn spawn(
+        mut command: Command,
+        log: Option<LogTarget>,
+        listener: Option<LogListener>,
+    ) -> io::Result<Self> {
         const STDOUT_PIPE_RECV: Token = Token(0);
         const STDERR_PIPE_RECV: Token = Token(1);
         const WAKER_TOKEN: Token = Token(2);
@@ -134,17 +444,21 @@ impl LimitedOutputChild {
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
 
-        use io::Write;
-
-        let mut file_options = OpenOptions::new();
-        file_options.create(true);
-        file_options.write(true);
-        file_options.truncate(true);
+        // The output is still drained when the log file cannot be opened, as a missing log should
+        // not prevent a session from starting
+        let file = log.and_then(|log| match log.open() {
+            Ok(file) => Some(file),
+            Err(err) => {
+                warn!(
+                    "Failed to open log file '{}'. Reason: {err}",
+                    log.path.display()
+                );
+                None
+            }
+        });
 
         let mut process = command.spawn()?;
 
-        let file = file_options.open(log_path)?;
-
         let Some(stdout) = process.stdout.take() else {
             return Err(io::Error::new(
                 io::ErrorKind::Other,
@@ -171,59 +485,66 @@ impl LimitedOutputChild {
 
         let waker = Waker::new(poll.registry(), WAKER_TOKEN)?;
 
-        let mut file_handle = LimitSizeWriter::new(file, LOG_WRITER_SIZE_LIMIT);
-
-        let join_handle = std::thread::spawn(move || loop {
-            poll.poll(&mut events, None)?;
-
-            fn forward_receiver_to_file(
-                receiver: &mut Receiver,
-                file_handle: &mut LimitSizeWriter<impl io::Write>,
-                is_read_closed: bool,
-            ) -> io::Result<()> {
-                let mut buf = [0u8; 2048];
-
-                loop {
-                    if is_read_closed {
-                        let mut v = Vec::new();
-                        receiver.read_to_end(&mut v)?;
-                        file_handle.write_all(&v)?;
-
-                        break;
-                    }
+        // The log of an earlier session that is appended to counts towards the size limit
+        let mut log_file = LogFile(file.map(|file| {
+            let current_len = file.metadata().map_or(0, |metadata| metadata.len());
+            LimitSizeWriter::new(file, current_len as usize, LOG_WRITER_SIZE_LIMIT)
+        }));
+        let mut stdout_lines = LineForwarder::new(LogStream::Stdout, listener.clone());
+        let mut stderr_lines = LineForwarder::new(LogStream::Stderr, listener);
+
+        let join_handle = std::thread::spawn(move || {
+            let mut stdout_closed = false;
+            let mut stderr_closed = false;
+
+            loop {
+                match poll.poll(&mut events, None) {
+                    Ok(()) => {}
+                    // Signals to lemurs, such as the one of the X server, interrupt the poll
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                }
 
-                    match receiver.read(&mut buf) {
-                        Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
-                            break;
+                let mut is_stopping = false;
+                for event in events.iter() {
+                    match event.token() {
+                        x if x == WAKER_TOKEN => is_stopping = true,
+                        x if x == STDOUT_PIPE_RECV => {
+                            stdout_closed |= forward_output(
+                                &mut stdout_receiver,
+                                &mut log_file,
+                                &mut stdout_lines,
+                            )?;
+                        }
+                        x if x == STDERR_PIPE_RECV => {
+                            stderr_closed |= forward_output(
+                                &mut stderr_receiver,
+                                &mut log_file,
+                                &mut stderr_lines,
+                            )?;
                         }
-                        Err(err) => return Err(err),
-                        Ok(n) => {
-                            file_handle.write_all(&buf[..n])?;
+                        _ => {
+                            return Err(io::Error::new(io::ErrorKind::Other, "Invalid event"));
                         }
                     }
                 }
 
-                Ok(())
-            }
-
-            for event in events.iter() {
-                match event.token() {
-                    x if x == WAKER_TOKEN => {
-                        return Ok(());
+                if is_stopping {
+                    // The process has exited, but its last output can still be in the pipes
+                    if !stdout_closed {
+                        forward_output(&mut stdout_receiver, &mut log_file, &mut stdout_lines)?;
+                        stdout_lines.finish();
                     }
-                    x if x == STDOUT_PIPE_RECV => forward_receiver_to_file(
-                        &mut stdout_receiver,
-                        &mut file_handle,
-                        event.is_read_closed(),
-                    )?,
-                    x if x == STDERR_PIPE_RECV => forward_receiver_to_file(
-                        &mut stderr_receiver,
-                        &mut file_handle,
-                        event.is_read_closed(),
-                    )?,
-                    _ => {
-                        return Err(io::Error::new(io::ErrorKind::Other, "Invalid event"));
+                    if !stderr_closed {
+                        forward_output(&mut stderr_receiver, &mut log_file, &mut stderr_lines)?;
+                        stderr_lines.finish();
                     }
+
+                    return Ok(());
+                }
+
+                if stdout_closed && stderr_closed {
+                    return Ok(());
                 }
             }
         });
@@ -234,24 +555,30 @@ impl LimitedOutputChild {
         })
     }
 
-    fn stop_logging(&mut self) -> io::Result<()> {
-        if let Some((waker, join_handle)) = self.log_thread.take() {
-            waker.wake()?;
-
-            info!("Joining with logging thread.");
+    fn stop_logging(&mut self) {
+        let Some((waker, join_handle)) = self.log_thread.take() else {
+            return;
+        };
 
-            join_handle
-                .join()
-                .expect("Failed to join with log thread")?;
+        // Without the wake up, the thread only stops once all the pipes are closed
+        if let Err(err) = waker.wake() {
+            warn!("Failed to stop the logging thread. Reason: {err}");
+            return;
         }
 
-        Ok(())
+        info!("Joining with logging thread.");
+
+        match join_handle.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => warn!("Logging thread failed. Reason: {err}"),
+            Err(_) => error!("Logging thread panicked"),
+        }
     }
 
     pub fn wait(&mut self) -> io::Result<ExitStatus> {
         let exit_status = self.process.wait()?;
 
-        self.stop_logging()?;
+        self.stop_logging();
 
         Ok(exit_status)
     }
@@ -262,7 +589,7 @@ impl LimitedOutputChild {
             Some(exit_status) => exit_status,
         };
 
-        self.stop_logging()?;
+        self.stop_logging();
 
         Ok(Some(exit_status))
     }
@@ -270,7 +597,7 @@ impl LimitedOutputChild {
     pub fn kill(&mut self) -> io::Result<()> {
         self.process.kill()?;
 
-        self.stop_logging()?;
+        self.stop_logging();
 
         Ok(())
     }
@@ -279,3 +606,127 @@ impl LimitedOutputChild {
         self.process.id()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(script);
+        command
+    }
+
+    #[test]
+    fn no_output_lost() {
+        let log_path = std::env::temp_dir().join(format!("lemurs-log-{}", std::process::id()));
+
+        // Both pipes are written faster than they are read
+        let mut child = LimitedOutputChild::spawn(
+            shell("head -c 1000000 /dev/zero; head -c 500000 /dev/zero >&2"),
+            Some(LogTarget::new(&log_path)),
+            None,
+        )
+        .unwrap();
+        assert!(child.wait().unwrap().success());
+
+        let logged = std::fs::metadata(&log_path).unwrap().len();
+        let _ = std::fs::remove_file(&log_path);
+        assert_eq!(logged, 1_500_000);
+    }
+
+    #[test]
+    fn lines_to_listener() {
+        let (listener, lines) = mpsc::sync_channel(16);
+
+        let mut child = LimitedOutputChild::spawn(
+            shell("printf 'a\\nb\\n'; printf c >&2"),
+            None,
+            Some(listener),
+        )
+        .unwrap();
+        assert!(child.wait().unwrap().success());
+
+        let lines = lines
+            .try_iter()
+            .map(|line| (line.stream, line.line))
+            .collect::<Vec<_>>();
+        assert!(lines.contains(&(LogStream::Stdout, "a".to_string())));
+        assert!(lines.contains(&(LogStream::Stdout, "b".to_string())));
+        assert!(lines.contains(&(LogStream::Stderr, "c".to_string())));
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn append_counts_towards_limit() {
+        let mut writer = LimitSizeWriter::new(Vec::new(), 6, 10);
+
+        io::Write::write_all(&mut writer, b"123456789").unwrap();
+        io::Write::flush(&mut writer).unwrap();
+        assert_eq!(writer.writer.get_ref(), b"1234");
+    }
+
+    #[test]
+    fn log_not_opened_in_shared_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = std::env::temp_dir().join(format!("lemurs-shared-{}", std::process::id()));
+        std::fs::create_dir(&directory).unwrap();
+        std::fs::set_permissions(&directory, std::fs::Permissions::from_mode(0o777)).unwrap();
+
+        let log_path = directory.join("client.log");
+        let result = LogTarget::new(&log_path).open();
+        let _ = std::fs::remove_dir_all(&directory);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn log_opened_in_group_writable_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = std::env::temp_dir().join(format!("lemurs-group-{}", std::process::id()));
+        std::fs::create_dir(&directory).unwrap();
+        std::fs::set_permissions(&directory, std::fs::Permissions::from_mode(0o775)).unwrap();
+
+        let log_path = directory.join("client.log");
+        let result = LogTarget::new(&log_path).open();
+        let _ = std::fs::remove_dir_all(&directory);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn user_log_does_not_follow_links() {
+        let directory = std::env::temp_dir().join(format!("lemurs-link-{}", std::process::id()));
+        std::fs::create_dir(&directory).unwrap();
+        let target = directory.join("target");
+        std::fs::write(&target, "unchanged").unwrap();
+        let log_path = directory.join("client.log");
+        std::os::unix::fs::symlink(&target, &log_path).unwrap();
+
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let result = LogTarget::new(&log_path).owned_by(uid, gid).open();
+        let content = std::fs::read_to_string(&target).unwrap();
+        let _ = std::fs::remove_dir_all(&directory);
+        assert!(result.is_err());
+        assert_eq!(content, "unchanged");
+    }
+
+    #[test]
+    fn full_disk_does_not_block() {
+        let full = Path::new("/dev/full");
+        if !full.exists() {
+            return;
+        }
+
+        // More than fits in the pipe, so the process blocks if the output is not drained
+        let mut child = LimitedOutputChild::spawn(
+            shell("head -c 1000000 /dev/zero"),
+            Some(LogTarget::new(full)),
+            None,
+        )
+        .unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,388 @@
+//! Detection of sessions that stopped responding.
+//!
+//! While lemurs waits for a graphical session, the session is checked periodically. When it has
+//! not responded for a while, the greeter VT is given back to the console and the user is asked
+//! whether the session should be logged out forcefully.
+
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+
+use crate::config::Config;
+use crate::console_state::ConsoleState;
+
+use super::SpawnedEnvironment;
+
+/// How long a single check can take before the session is considered unresponsive
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the session gets to exit after it was asked to before it is killed
+const FORCE_LOGOUT_GRACE: Duration = Duration::from_secs(5);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How the session is checked for being responsive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HangCheck {
+    /// The processes of the session are running and not stopped
+    Process,
+    /// The Wayland compositor of the session answers a roundtrip on its socket
+    WaylandSocket,
+    /// The X server answers `xset q`
+    XServer,
+}
+
+impl HangCheck {
+    /// The check by its name in the configuration
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.trim() {
+            "process" => Self::Process,
+            "wayland_socket" => Self::WaylandSocket,
+            "x_server" => Self::XServer,
+            _ => return None,
+        })
+    }
+
+    fn is_responsive(self, pids: &[u32]) -> bool {
+        match self {
+            Self::Process => pids.iter().all(|pid| {
+                process_stat(*pid).is_some_and(|(state, _)| !matches!(state, 'Z' | 'T' | 't'))
+            }),
+            Self::WaylandSocket => wayland_roundtrip(pids),
+            Self::XServer => xset_query(),
+        }
+    }
+}
+
+/// The state and the parent of a process
+fn process_stat(pid: u32) -> Option<(char, u32)> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+
+    // The name of the process can contain spaces and parentheses
+    let (_, fields) = stat.rsplit_once(')')?;
+    let mut fields = fields.split_whitespace();
+    let state = fields.next()?.chars().next()?;
+    let ppid = fields.next()?.parse().ok()?;
+
+    Some((state, ppid))
+}
+
+/// The processes in the trees that start at `pids`
+pub(super) fn process_trees(pids: &[u32]) -> Vec<u32> {
+    let parents = fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+                .filter_map(|pid| Some((pid, process_stat(pid)?.1)))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let mut tree = pids.to_vec();
+    let mut i = 0;
+    while i < tree.len() {
+        let parent = tree[i];
+        tree.extend(
+            parents
+                .iter()
+                .filter(|(pid, ppid)| *ppid == parent && !tree.contains(pid))
+                .map(|(pid, _)| *pid)
+                .collect::<Vec<_>>(),
+        );
+        i += 1;
+    }
+
+    tree
+}
+
+/// The socket inodes that are open in the process `pid`
+fn socket_inodes(pid: u32) -> Vec<u64> {
+    fs::read_dir(format!("/proc/{pid}/fd"))
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| {
+                    let target = fs::read_link(entry.path()).ok()?;
+                    let target = target.to_str()?;
+                    target
+                        .strip_prefix("socket:[")?
+                        .strip_suffix(']')?
+                        .parse()
+                        .ok()
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The Wayland socket of the session. This is `WAYLAND_DISPLAY` if it is set for the session, and
+/// otherwise the `wayland-*` socket in `XDG_RUNTIME_DIR` that a process of the session listens on.
+/// Sockets that were left behind by earlier compositors are not considered.
+fn session_wayland_socket(pids: &[u32]) -> Option<PathBuf> {
+    let runtime_dir = PathBuf::from(std::env::var("XDG_RUNTIME_DIR").ok()?);
+
+    if let Ok(display) = std::env::var("WAYLAND_DISPLAY") {
+        return Some(runtime_dir.join(display));
+    }
+
+    let inodes = process_trees(pids)
+        .into_iter()
+        .flat_map(socket_inodes)
+        .collect::<Vec<_>>();
+
+    // The columns are `Num RefCount Protocol Flags Type St Inode Path`. Listening sockets have the
+    // `__SO_ACCEPTCON` flag.
+    let unix_sockets = fs::read_to_string("/proc/net/unix").ok()?;
+    unix_sockets.lines().skip(1).find_map(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let (flags, inode, path) = (fields.get(3)?, fields.get(6)?, fields.get(7)?);
+
+        let is_listening = u32::from_str_radix(flags, 16).is_ok_and(|flags| flags & 0x10000 != 0);
+        let inode = inode.parse::<u64>().ok()?;
+        let path = PathBuf::from(path);
+        let is_wayland = path.parent() == Some(runtime_dir.as_path())
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("wayland-") && !name.ends_with(".lock"));
+
+        (is_listening && is_wayland && inodes.contains(&inode)).then_some(path)
+    })
+}
+
+/// Do a `wl_display.sync` roundtrip with the Wayland compositor of the session
+fn wayland_roundtrip(pids: &[u32]) -> bool {
+    let Some(socket) = session_wayland_socket(pids) else {
+        return false;
+    };
+
+    // The object id of `wl_display` is 1. The sync request has opcode 0 and creates the callback
+    // with id 2, which gets the `done` event once the compositor has handled the request. The
+    // second word holds the size of the message and the opcode.
+    let mut request = Vec::with_capacity(12);
+    request.extend_from_slice(&1u32.to_ne_bytes());
+    request.extend_from_slice(&(12u32 << 16).to_ne_bytes());
+    request.extend_from_slice(&2u32.to_ne_bytes());
+
+    let roundtrip = || -> io::Result<()> {
+        let mut stream = UnixStream::connect(&socket)?;
+        stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+        stream.write_all(&request)?;
+
+        let mut event_header = [0; 8];
+        stream.read_exact(&mut event_header)
+    };
+
+    roundtrip().is_ok()
+}
+
+/// Query the X server of the session with `xset q`
+fn xset_query() -> bool {
+    let mut xset = match Command::new("xset")
+        .arg("q")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(xset) => xset,
+        Err(err) => {
+            warn!("Failed to run `xset`. Reason: {err}");
+            return false;
+        }
+    };
+
+    let start_time = Instant::now();
+    while start_time.elapsed() < RESPONSE_TIMEOUT {
+        match xset.try_wait() {
+            Ok(Some(status)) => return status.success(),
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(_) => break,
+        }
+    }
+
+    let _ = xset.kill();
+    let _ = xset.wait();
+    false
+}
+
+fn switch_to_vt(vt: u8) {
+    if let Err(err) = unsafe { crate::chvt::chvt(vt.into()) } {
+        error!("Failed to switch to tty {vt}. Reason: {err}");
+    }
+}
+
+pub(super) fn is_running(pid: u32) -> bool {
+    process_stat(pid).is_some_and(|(state, _)| state != 'Z')
+}
+
+/// Checks a session while lemurs waits for it
+pub struct Watchdog {
+    check: HangCheck,
+    pids: Vec<u32>,
+    interval: Duration,
+    timeout: Duration,
+    tty: u8,
+    session_vt: u8,
+}
+
+impl Watchdog {
+    /// The watchdog for `environment`, if `session.hang_check` is set and applies to it
+    pub fn new(environment: &SpawnedEnvironment, config: &Config) -> Option<Self> {
+        let session = &config.session;
+        if session.hang_check.is_empty() {
+            return None;
+        }
+
+        let Some(check) = HangCheck::from_name(&session.hang_check) else {
+            warn!("Unknown hang check '{}'. Ignoring it", session.hang_check);
+            return None;
+        };
+
+        let session_vt = match (environment, check) {
+            (SpawnedEnvironment::Tty(_), _) => return None,
+            (SpawnedEnvironment::X11 { .. }, HangCheck::Process | HangCheck::XServer) => {
+                super::x::server_vt(config)
+            }
+            (SpawnedEnvironment::Wayland(_), HangCheck::Process | HangCheck::WaylandSocket) => {
+                config.tty
+            }
+            _ => {
+                warn!(
+                    "Hang check '{}' does not apply to the session. Not watching it",
+                    session.hang_check
+                );
+                return None;
+            }
+        };
+
+        Some(Self {
+            check,
+            pids: environment.pids(),
+            interval: Duration::from_secs(session.hang_check_interval_secs.max(1).into()),
+            timeout: Duration::from_secs(session.hang_timeout_secs.into()),
+            tty: config.tty,
+            session_vt,
+        })
+    }
+
+    /// Check `environment` until its client exits. Afterwards, it still has to be waited for.
+    pub fn supervise(&self, environment: &mut SpawnedEnvironment, console_state: &ConsoleState) {
+        info!("Watching the session with the {:?} check", self.check);
+
+        let mut last_response = Instant::now();
+        loop {
+            match environment.wait_timeout(self.interval) {
+                Ok(None) => {}
+                Ok(Some(_)) => return,
+                Err(err) => {
+                    error!("Failed to check on the session. Reason: {err}");
+                    return;
+                }
+            }
+
+            if self.check.is_responsive(&self.pids) {
+                last_response = Instant::now();
+                continue;
+            }
+
+            if last_response.elapsed() < self.timeout {
+                continue;
+            }
+
+            warn!(
+                "Session has not responded for {} seconds",
+                last_response.elapsed().as_secs()
+            );
+            if self.prompt_force_logout(environment, console_state) {
+                self.force_logout(environment);
+                return;
+            }
+
+            last_response = Instant::now();
+        }
+    }
+
+    /// Ask on the greeter VT whether the session should be logged out forcefully
+    fn prompt_force_logout(
+        &self,
+        environment: &mut SpawnedEnvironment,
+        console_state: &ConsoleState,
+    ) -> bool {
+        if self.session_vt != self.tty {
+            switch_to_vt(self.tty);
+        }
+
+        // This takes the console back from a session that is on the same VT
+        console_state.restore();
+
+        let mut stdout = io::stdout();
+        let _ = write!(
+            stdout,
+            "\nThe session appears to be hung. Force logout? [y/N] "
+        );
+        let _ = stdout.flush();
+
+        // Wait for an answer, unless the session exits in the meantime
+        let mut pollfd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let poll_timeout = POLL_INTERVAL.as_millis() as libc::c_int;
+        loop {
+            if !matches!(environment.try_wait(), Ok(None)) {
+                return false;
+            }
+
+            if (unsafe { libc::poll(&mut pollfd, 1, poll_timeout) }) > 0 {
+                break;
+            }
+        }
+
+        let mut answer = String::new();
+        if let Err(err) = io::stdin().lock().read_line(&mut answer) {
+            warn!("Failed to read the answer. Reason: {err}");
+            return false;
+        }
+
+        let force_logout = matches!(answer.trim(), "y" | "Y" | "yes");
+        if !force_logout && self.session_vt != self.tty {
+            switch_to_vt(self.session_vt);
+        }
+
+        force_logout
+    }
+
+    /// Terminate all processes of the session, and kill those that do not exit in time
+    fn force_logout(&self, environment: &mut SpawnedEnvironment) {
+        info!("Forcefully logging out the session");
+
+        let processes = process_trees(&self.pids);
+        for pid in &processes {
+            unsafe { libc::kill(*pid as libc::pid_t, libc::SIGTERM) };
+        }
+
+        if let Err(err) = environment.terminate(FORCE_LOGOUT_GRACE) {
+            warn!("Failed to terminate the session. Reason: {err}");
+        }
+
+        // The other processes, such as the X server, had as much time as the client
+        let remaining = processes
+            .into_iter()
+            .filter(|pid| is_running(*pid))
+            .collect::<Vec<_>>();
+        if !remaining.is_empty() {
+            warn!("Session did not exit in time. Killing it");
+            for pid in remaining {
+                unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+            }
+        }
+    }
+}
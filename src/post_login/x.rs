@@ -3,53 +3,156 @@ use rand::Rng;
 
 use once_cell::sync::Lazy;
 
+use std::collections::VecDeque;
 use std::env;
-use std::error::Error;
-use std::fmt::Display;
-use std::fs::remove_file;
+use std::fs::{self, remove_file, File};
+use std::io::{self, BufRead, BufReader};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::process::{Command, Stdio};
 use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
 use std::{thread, time};
 
 use std::path::{Path, PathBuf};
 
-use log::{error, info};
+use log::{error, info, warn};
+use thiserror::Error;
 
 use crate::auth::AuthUserInfo;
 use crate::config::Config;
 use crate::env_container::EnvironmentContainer;
-use crate::post_login::wait_with_log::LemursChild;
+use crate::error::ErrorCode;
+use crate::post_login::wait_with_log::{LemursChild, LogLine, LogStream, LogTarget};
+use crate::priority;
 
 const XSTART_CHECK_INTERVAL_MILLIS: u64 = 100;
 
-#[derive(Debug, Clone)]
+/// The number of lines of the output of the X server that are kept to find the cause of a failed
+/// start
+const XSERVER_OUTPUT_LINES: usize = 256;
+
+#[derive(Debug, Clone, Error)]
 pub enum XSetupError {
+    #[error("`DISPLAY` is not set")]
     DisplayEnvVar,
+    #[error("`XDG_VTNR` is not set")]
     VTNREnvVar,
+    #[error("Failed to fill `.Xauthority` file")]
     FillingXAuth,
+    #[error("Path that is given is not valid UTF8")]
     InvalidUTF8Path,
+    #[error("Failed to start X server binary")]
     XServerStart,
+    #[error("Timeout while waiting for X server to start")]
     XServerTimeout,
+    #[error("X server exited before it signaled to accept connections")]
     XServerPrematureExit,
+    #[error("X server found no usable screens")]
+    NoScreens,
+    #[error("X server cannot open the virtual console")]
+    VirtualConsole,
+    #[error("X server failed to load module `{0}`")]
+    MissingModule(String),
+    #[error("X server did not report its display")]
+    DisplayFd,
 }
 
-impl Display for XSetupError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ErrorCode for XSetupError {
+    fn code(&self) -> &'static str {
         match self {
-            Self::DisplayEnvVar => f.write_str("`DISPLAY` is not set"),
-            Self::VTNREnvVar => f.write_str("`XDG_VTNR` is not set"),
-            Self::FillingXAuth => f.write_str("Failed to fill `.Xauthority` file"),
-            Self::InvalidUTF8Path => f.write_str("Path that is given is not valid UTF8"),
-            Self::XServerStart => f.write_str("Failed to start X server binary"),
-            Self::XServerTimeout => f.write_str("Timeout while waiting for X server to start"),
-            Self::XServerPrematureExit => {
-                f.write_str("X server exited before it signaled to accept connections")
-            }
+            Self::DisplayEnvVar => "X11-01",
+            Self::VTNREnvVar => "X11-02",
+            Self::FillingXAuth => "X11-03",
+            Self::InvalidUTF8Path => "X11-04",
+            Self::XServerStart => "X11-05",
+            Self::XServerTimeout => "X11-06",
+            Self::XServerPrematureExit => "X11-07",
+            Self::NoScreens => "X11-08",
+            Self::VirtualConsole => "X11-09",
+            Self::MissingModule(_) => "X11-10",
+            Self::DisplayFd => "X11-11",
         }
     }
 }
 
-impl Error for XSetupError {}
+impl XSetupError {
+    /// A hint on how to resolve the error that can be shown to the user
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Self::NoScreens => Some(String::from(
+                "X server found no screens. Check the graphics driver",
+            )),
+            Self::VirtualConsole => Some(String::from(
+                "X server cannot open the virtual console. Check its permissions",
+            )),
+            Self::MissingModule(module) => Some(format!(
+                "X server module `{module}` is missing. Is its driver installed?"
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Find the cause of a failed X server start in its output. The most specific cause is returned,
+/// since a missing module is often only a warning.
+fn diagnose_xserver_output(output: &str) -> Option<XSetupError> {
+    let errors = output
+        .lines()
+        .filter(|line| line.contains("(EE)") || line.starts_with("Fatal server error"))
+        .collect::<Vec<_>>();
+
+    if errors.iter().any(|line| {
+        line.contains("Cannot open /dev/tty0") || line.contains("Cannot open virtual console")
+    }) {
+        return Some(XSetupError::VirtualConsole);
+    }
+
+    let missing_module = errors.iter().find_map(|line| {
+        let (_, module) = line.split_once("Failed to load module \"")?;
+        let (module, reason) = module.split_once('"')?;
+        reason
+            .contains("module does not exist")
+            .then(|| module.to_string())
+    });
+
+    if errors.iter().any(|line| line.contains("no screens found")) {
+        return Some(missing_module.map_or(XSetupError::NoScreens, XSetupError::MissingModule));
+    }
+
+    missing_module.map(XSetupError::MissingModule)
+}
+
+/// Keep the last lines of the output of the X server. It reports its errors on stderr.
+fn collect_xserver_output(output: &Receiver<LogLine>, lines: &mut VecDeque<String>) {
+    for line in output
+        .try_iter()
+        .filter(|line| line.stream == LogStream::Stderr)
+    {
+        if lines.len() == XSERVER_OUTPUT_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line.line);
+    }
+}
+
+/// Find the cause of a failed X server start in its output
+fn diagnose_xserver_failure(
+    output: &Receiver<LogLine>,
+    lines: &mut VecDeque<String>,
+) -> Option<XSetupError> {
+    collect_xserver_output(output, lines);
+
+    let output = lines
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let diagnosis = diagnose_xserver_output(&output)?;
+
+    error!("X server output shows: {diagnosis}");
+    Some(diagnosis)
+}
 
 fn mcookie() -> String {
     // TODO: Verify that this is actually safe. Maybe just use the mcookie binary?? Is that always
@@ -61,6 +164,91 @@ fn mcookie() -> String {
 
 static X_HAS_STARTED: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
 
+/// An X server that was kept running after a session
+struct RetainedServer {
+    server: LemursChild,
+    display: String,
+    /// The user of the session. Clients of the session can keep running after it, and the server
+    /// only resets once all of them have disconnected, so the server is only reused for this user.
+    uid: libc::uid_t,
+}
+
+/// The X server that was kept running after the previous session
+static RETAINED_SERVER: Lazy<Mutex<Option<RetainedServer>>> = Lazy::new(|| Mutex::new(None));
+
+/// The VT that the X server runs on
+pub fn server_vt(config: &Config) -> u8 {
+    if config.x11.server_vt == 0 {
+        config.tty
+    } else {
+        config.x11.server_vt
+    }
+}
+
+/// Whether the X server can be kept running after a session. Lemurs needs its own VT to show the
+/// form in the meantime.
+pub fn can_retain_server(config: &Config) -> bool {
+    config.x11.retain_server && server_vt(config) != config.tty
+}
+
+/// Keep the X server running for the next session of the user with `uid`. The server resets itself
+/// once the last client has disconnected.
+pub fn retain_server(mut server: LemursChild, display: String, uid: libc::uid_t) {
+    if let Ok(Some(status)) = server.try_wait() {
+        warn!("X server exited with status {status}. It cannot be retained");
+        return;
+    }
+
+    match RETAINED_SERVER.lock() {
+        Ok(mut retained) => {
+            info!("Keeping the X server running for the next session");
+            *retained = Some(RetainedServer {
+                server,
+                display,
+                uid,
+            });
+        }
+        Err(err) => {
+            error!("Lock failed. Reason: {err}");
+            if let Err(err) = server.send_sigterm() {
+                error!("Failed to terminate X11. Reason: {err}");
+            }
+        }
+    }
+}
+
+/// Take the X server that was kept running after the previous session, if it is still running and
+/// the previous session was of the user with `uid`. A server of another user is terminated, since
+/// clients of that user can still be connected to it.
+fn take_retained_server(uid: libc::uid_t) -> Option<(LemursChild, String)> {
+    let RetainedServer {
+        mut server,
+        display,
+        uid: retained_uid,
+    } = RETAINED_SERVER.lock().ok()?.take()?;
+
+    if retained_uid != uid {
+        info!("Terminating the retained X server, since it was used by another user");
+        if let Err(err) = server.send_sigterm() {
+            error!("Failed to terminate X11. Reason: {err}");
+        }
+        let _ = server.wait();
+        return None;
+    }
+
+    match server.try_wait() {
+        Ok(None) => Some((server, display)),
+        Ok(Some(status)) => {
+            warn!("Retained X server exited with status {status}. Starting a new one");
+            None
+        }
+        Err(err) => {
+            warn!("Failed to check on the retained X server. Reason: {err}");
+            None
+        }
+    }
+}
+
 #[allow(dead_code)]
 fn handle_sigusr1(_: i32) {
     X_HAS_STARTED.store(true, std::sync::atomic::Ordering::SeqCst);
@@ -70,41 +258,199 @@ fn handle_sigusr1(_: i32) {
     }
 }
 
-pub fn setup_x(
-    process_env: &mut EnvironmentContainer,
-    user_info: &AuthUserInfo,
-    config: &Config,
-) -> Result<LemursChild, XSetupError> {
-    use std::os::unix::process::CommandExt;
+/// Start Xephyr on a display that it picks itself. Returns the process and the display.
+pub fn start_xephyr(config: &Config) -> Result<(std::process::Child, String), String> {
+    let (pipe, write) = display_pipe()
+        .map_err(|err| format!("Failed to create a pipe for `-displayfd`. Reason: {err}"))?;
 
-    info!("Start setup of X server");
+    let mut command = Command::new(&config.system_shell);
+    let display_arg = inherit_display_fd(&mut command, &write);
 
-    let display_value = env::var("DISPLAY").map_err(|_| XSetupError::DisplayEnvVar)?;
-    let vtnr_value = env::var("XDG_VTNR").map_err(|_| XSetupError::VTNREnvVar)?;
+    let mut xephyr = command
+        .arg("-c")
+        .arg(format!(
+            "{} {display_arg} {}",
+            config.x11.xephyr_path, config.x11.xephyr_args
+        ))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("Failed to start Xephyr. Reason: {err}"))?;
+
+    // Close the write end, so that reading gives an EOF once Xephyr has exited
+    drop(write);
+
+    // Xephyr writes its display to the pipe once it accepts connections
+    let start_time = time::Instant::now();
+    while !is_readable(&pipe) {
+        if let Ok(Some(status)) = xephyr.try_wait() {
+            return Err(format!("Xephyr exited with {status}"));
+        }
+
+        if config.x11.xserver_timeout_secs != 0
+            && start_time.elapsed().as_secs() > config.x11.xserver_timeout_secs.into()
+        {
+            let _ = xephyr.kill();
+            return Err(String::from("Timeout while waiting for Xephyr to start"));
+        }
 
-    // Setup xauth
-    let xauth_dir = if let Ok(config_home) = env::var("XDG_CONFIG_HOME") {
-        PathBuf::from(config_home)
+        thread::sleep(time::Duration::from_millis(XSTART_CHECK_INTERVAL_MILLIS));
+    }
+
+    match read_display(pipe) {
+        Ok(display) => Ok((xephyr, display)),
+        Err(err) => {
+            let _ = xephyr.kill();
+            Err(err.to_string())
+        }
+    }
+}
+
+/// The path of the Xauthority file of the user for `display`
+fn xauthority_path(display: &str, user_info: &AuthUserInfo, config: &Config) -> PathBuf {
+    let template = &config.x11.xauthority_path;
+
+    if template.is_empty() {
+        let xauth_dir = if let Ok(config_home) = env::var("XDG_CONFIG_HOME") {
+            PathBuf::from(config_home)
+        } else {
+            PathBuf::from(user_info.home_dir.clone())
+        };
+
+        return xauth_dir.join(".Xauthority");
+    }
+
+    let runtime_dir =
+        env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| format!("/run/user/{}", user_info.uid));
+
+    PathBuf::from(
+        template
+            .replace("%home%", &user_info.home_dir)
+            .replace("%runtime_dir%", &runtime_dir)
+            .replace("%display%", display.trim_start_matches(':')),
+    )
+}
+
+/// The command that is given to the system shell to start the X server
+pub fn xserver_command(display: &str, vtnr: &str, config: &Config) -> String {
+    let doubledigit_vtnr = if vtnr.len() == 1 {
+        format!("0{vtnr}")
     } else {
-        PathBuf::from(user_info.home_dir.clone())
+        vtnr.to_string()
     };
-    let xauth_path = xauth_dir.join(".Xauthority");
+
+    format!(
+        "{} {display} vt{doubledigit_vtnr}",
+        &config.x11.xserver_path
+    )
+}
+
+/// Whether the X server picks its display itself and reports it through `-displayfd`
+pub fn is_auto_display(config: &Config) -> bool {
+    config.x11.x11_display == "auto"
+}
+
+/// Create a pipe for `-displayfd`. Both ends are closed on exec.
+fn display_pipe() -> io::Result<(File, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { (File::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+fn is_readable(file: &File) -> bool {
+    let mut pollfd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    (unsafe { libc::poll(&mut pollfd, 1, 0) }) > 0
+}
+
+/// Let the process that is spawned from `command` inherit the write end of the `-displayfd` pipe.
+/// Returns the argument for the X server.
+fn inherit_display_fd(command: &mut Command, write: &OwnedFd) -> String {
+    use std::os::unix::process::CommandExt;
+
+    let write_fd = write.as_raw_fd();
+
+    unsafe {
+        command.pre_exec(move || {
+            let flags = libc::fcntl(write_fd, libc::F_GETFD);
+            if flags < 0 || libc::fcntl(write_fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+
+    format!("-displayfd {write_fd}")
+}
+
+/// Read the display number that the X server wrote to the `-displayfd` pipe. Returns `None` if the
+/// pipe was closed without a display, which happens when the X server exits before it is ready.
+fn read_display(pipe: File) -> Result<Option<String>, XSetupError> {
+    let mut line = String::new();
+    let read = BufReader::new(pipe).read_line(&mut line).map_err(|err| {
+        error!("Failed to read the display from the X server. Reason: {err}");
+        XSetupError::DisplayFd
+    })?;
+    if read == 0 {
+        return Ok(None);
+    }
+
+    match line.trim().parse::<u32>() {
+        Ok(number) => Ok(Some(format!(":{number}"))),
+        Err(_) => {
+            error!("X server reported an invalid display '{}'", line.trim());
+            Err(XSetupError::DisplayFd)
+        }
+    }
+}
+
+/// Fill the Xauthority file of the user with a cookie for `display`
+fn fill_xauthority(
+    display: &str,
+    process_env: &mut EnvironmentContainer,
+    user_info: &AuthUserInfo,
+    config: &Config,
+) -> Result<(), XSetupError> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::process::CommandExt;
+
+    let xauth_path = xauthority_path(display, user_info, config);
 
     info!(
         "Filling `.Xauthority` file at `{xauth_path}`",
         xauth_path = xauth_path.display()
     );
 
-    // Make sure that we are generating a new file. This is necessary since sometimes, there may be
-    // a `root` permission `.Xauthority` file there.
-    let _ = remove_file(xauth_path.clone());
+    // Sometimes, there may be a `root` permission `.Xauthority` file there, which the user cannot
+    // write to. Any other file is kept, so that the entries for other displays are not lost.
+    if let Ok(metadata) = fs::metadata(&xauth_path) {
+        if metadata.uid() != user_info.uid {
+            warn!(
+                "Removing `{}`, since it is not owned by the user",
+                xauth_path.display()
+            );
+            let _ = remove_file(&xauth_path);
+        }
+    }
 
-    Command::new(&config.system_shell)
+    // `xauth add` replaces the existing entry for the display
+    let mut xauth = Command::new(&config.system_shell);
+    priority::reset_oom_score_adj(&mut xauth, &config.priority);
+    xauth
         .arg("-c")
         .arg(format!(
-            "{} add {} . {}",
+            "{} -q -f '{}' add {} . {}",
             &config.x11.xauth_path,
-            display_value,
+            xauth_path.display(),
+            display,
             mcookie()
         ))
         .uid(user_info.uid)
@@ -123,10 +469,20 @@ pub fn setup_x(
     let xauth_path = xauth_path.to_str().ok_or(XSetupError::InvalidUTF8Path)?;
     process_env.set("XAUTHORITY", xauth_path);
 
-    let doubledigit_vtnr = if vtnr_value.len() == 1 {
-        format!("0{vtnr_value}")
+    Ok(())
+}
+
+/// Start the X server and wait until it accepts connections. Returns the server and its display.
+fn start_xserver(vtnr: &str, config: &Config) -> Result<(LemursChild, String), XSetupError> {
+    let (display, pipe) = if is_auto_display(config) {
+        let (read, write) = display_pipe().map_err(|err| {
+            error!("Failed to create a pipe for `-displayfd`. Reason: {err}");
+            XSetupError::DisplayFd
+        })?;
+        (None, Some((read, write)))
     } else {
-        vtnr_value
+        let display = env::var("DISPLAY").map_err(|_| XSetupError::DisplayEnvVar)?;
+        (Some(display), None)
     };
 
     // Here we explicitely ignore the first USR defined signal. Xorg looks at whether this signal
@@ -141,19 +497,26 @@ pub fn setup_x(
 
     let mut child = Command::new(&config.system_shell);
 
-    let log_path = config
+    let log = config
         .do_log
-        .then_some(Path::new(&config.x11.xserver_log_path));
+        .then_some(LogTarget::new(Path::new(&config.x11.xserver_log_path)));
 
-    child.arg("-c").arg(format!(
-        "{} {display_value} vt{doubledigit_vtnr}",
-        &config.x11.xserver_path
-    ));
+    let display_arg = match (&display, &pipe) {
+        (Some(display), _) => display.clone(),
+        (None, Some((_, write))) => inherit_display_fd(&mut child, write),
+        (None, None) => unreachable!(),
+    };
 
-    let mut child = LemursChild::spawn(child, log_path).map_err(|err| {
-        error!("Failed to start X server. Reason: {}", err);
-        XSetupError::XServerStart
-    })?;
+    child
+        .arg("-c")
+        .arg(xserver_command(&display_arg, vtnr, config));
+    priority::reset_oom_score_adj(&mut child, &config.priority);
+
+    // The output is kept to find the cause if the X server fails to start
+    let (output_listener, output) = mpsc::sync_channel(XSERVER_OUTPUT_LINES);
+    let mut output_lines = VecDeque::new();
+
+    let spawned = LemursChild::spawn_with_listener(child, log, output_listener);
 
     // See note above
     unsafe {
@@ -161,8 +524,18 @@ pub fn setup_x(
         signal(SIGUSR1, handle_sigusr1 as usize);
     }
 
-    // Wait for XServer to boot-up
+    let mut child = spawned.map_err(|err| {
+        error!("Failed to start X server. Reason: {}", err);
+        XSetupError::XServerStart
+    })?;
+
+    // Close the write end, so that reading gives an EOF once the X server has exited
+    let pipe = pipe.map(|(read, _)| read);
+
+    // Wait for XServer to boot-up. The X server writes its display to the pipe once it accepts
+    // connections, which also works when a wrapper does not pass on the signal.
     let start_time = time::SystemTime::now();
+    let mut display_written = false;
     loop {
         if config.x11.xserver_timeout_secs == 0
             || start_time.elapsed().map_or(false, |t| {
@@ -177,21 +550,30 @@ pub fn setup_x(
             break;
         }
 
+        // The pipe also becomes readable when the X server exits, so that is checked first
         if let Some(status) = child.try_wait().unwrap_or(None) {
             error!("X server died before signaling it was ready to received connections. Status code: {status}.");
 
-            return Err(XSetupError::XServerPrematureExit);
+            return Err(diagnose_xserver_failure(&output, &mut output_lines)
+                .unwrap_or(XSetupError::XServerPrematureExit));
         }
 
+        if pipe.as_ref().is_some_and(is_readable) {
+            display_written = true;
+            break;
+        }
+
+        collect_xserver_output(&output, &mut output_lines);
         thread::sleep(time::Duration::from_millis(XSTART_CHECK_INTERVAL_MILLIS));
     }
 
     // If the value is still `false`, this means we have time-ed out and Xorg is not running.
-    if !X_HAS_STARTED.load(std::sync::atomic::Ordering::SeqCst) {
+    if !X_HAS_STARTED.load(std::sync::atomic::Ordering::SeqCst) && !display_written {
         child.kill().unwrap_or_else(|err| {
             error!("Failed to kill Xorg after it timed out. Reason: {err}");
         });
-        return Err(XSetupError::XServerTimeout);
+        return Err(diagnose_xserver_failure(&output, &mut output_lines)
+            .unwrap_or(XSetupError::XServerTimeout));
     }
 
     if let Ok(x_server_start_time) = start_time.elapsed() {
@@ -199,11 +581,151 @@ pub fn setup_x(
             "It took X server {start_ms}ms to start",
             start_ms = x_server_start_time.as_millis()
         );
+        crate::metrics::record_xserver_start_time(x_server_start_time);
     }
 
     X_HAS_STARTED.store(false, std::sync::atomic::Ordering::SeqCst);
 
+    let display = match (display, pipe) {
+        (Some(display), _) => display,
+        (None, Some(pipe)) => match read_display(pipe) {
+            Ok(Some(display)) => display,
+            result => {
+                if let Err(err) = child.send_sigterm() {
+                    error!("Failed to terminate X11. Reason: {err}");
+                }
+                let _ = child.wait();
+
+                return Err(match result {
+                    Err(err) => err,
+                    _ => {
+                        error!("X server exited before it wrote its display");
+                        diagnose_xserver_failure(&output, &mut output_lines)
+                            .unwrap_or(XSetupError::XServerPrematureExit)
+                    }
+                });
+            }
+        },
+        (None, None) => unreachable!(),
+    };
+
+    info!("X server is running on display {display}");
+
+    Ok((child, display))
+}
+
+/// Start the X server, or reuse a retained one, and give the user access to it. Returns the server
+/// and its display.
+pub fn setup_x(
+    process_env: &mut EnvironmentContainer,
+    user_info: &AuthUserInfo,
+    config: &Config,
+) -> Result<(LemursChild, String), XSetupError> {
+    info!("Start setup of X server");
+
+    let vtnr_value = env::var("XDG_VTNR").map_err(|_| XSetupError::VTNREnvVar)?;
+
+    let retained = can_retain_server(config)
+        .then(|| take_retained_server(user_info.uid))
+        .flatten();
+
+    let (mut server, display) = match retained {
+        Some((server, display)) => {
+            info!("Reusing the running X server");
+
+            let vt = server_vt(config);
+            if let Err(err) = unsafe { crate::chvt::chvt(vt.into()) } {
+                error!("Failed to switch to the X server on tty {vt}. Reason: {err}");
+            }
+
+            (server, display)
+        }
+        None => start_xserver(&vtnr_value, config)?,
+    };
+
+    process_env.set("DISPLAY", &display);
+
+    if let Err(err) = fill_xauthority(&display, process_env, user_info, config) {
+        if let Err(err) = server.send_sigterm() {
+            error!("Failed to terminate X11. Reason: {err}");
+        }
+        let _ = server.wait();
+        return Err(err);
+    }
+
     info!("X server is running");
 
-    Ok(child)
+    Ok((server, display))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xserver_output_diagnosis() {
+        let missing_driver = "\
+[    12.345] (EE) Failed to load module \"nvidia\" (module does not exist, 0)
+[    12.346] (EE) No devices detected.
+[    12.346] (EE)
+Fatal server error:
+[    12.346] (EE) no screens found(EE)
+";
+        assert!(matches!(
+            diagnose_xserver_output(missing_driver),
+            Some(XSetupError::MissingModule(module)) if module == "nvidia"
+        ));
+
+        let no_screens = "[    12.346] (EE) no screens found(EE) \n";
+        assert!(matches!(
+            diagnose_xserver_output(no_screens),
+            Some(XSetupError::NoScreens)
+        ));
+
+        let tty = "(EE) parse_vt_settings: Cannot open /dev/tty0 (Permission denied)\n";
+        assert!(matches!(
+            diagnose_xserver_output(tty),
+            Some(XSetupError::VirtualConsole)
+        ));
+
+        assert!(diagnose_xserver_output("(II) Loading /usr/lib/xorg/modules/libfb.so\n").is_none());
+    }
+
+    #[test]
+    fn display_read_from_pipe() {
+        let (read, write) = display_pipe().unwrap();
+        let mut write = File::from(write);
+        io::Write::write_all(&mut write, b"3\n").unwrap();
+        drop(write);
+        assert_eq!(read_display(read).unwrap().as_deref(), Some(":3"));
+
+        // The X server exited before it was ready
+        let (read, write) = display_pipe().unwrap();
+        drop(write);
+        assert!(is_readable(&read));
+        assert_eq!(read_display(read).unwrap(), None);
+    }
+
+    #[test]
+    fn retained_server_only_reused_by_same_user() {
+        let spawn_server = || {
+            let mut command = Command::new("/bin/sh");
+            command.arg("-c").arg("sleep 10");
+            LemursChild::spawn(command, None).unwrap()
+        };
+
+        retain_server(spawn_server(), ":5".to_string(), 1000);
+        let (mut server, display) = take_retained_server(1000).unwrap();
+        assert_eq!(display, ":5");
+        let _ = server.kill();
+        let _ = server.wait();
+
+        let server = spawn_server();
+        let pid = server.id() as libc::pid_t;
+        retain_server(server, ":5".to_string(), 1000);
+        assert!(take_retained_server(1001).is_none());
+        // The server was terminated and reaped
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+        assert!(take_retained_server(1000).is_none());
+    }
 }
@@ -0,0 +1,120 @@
+//! The OOM score adjustment, nice value and I/O priority of lemurs and the sessions it starts.
+//!
+//! Lemurs can be protected from the OOM killer, so that a runaway session does not take the login
+//! manager down with it. Sessions inherit this from lemurs, so it is reset for them.
+
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use log::{info, warn};
+
+use crate::config::PriorityConfig;
+
+const OOM_SCORE_ADJ_PATH: &str = "/proc/self/oom_score_adj";
+
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+/// The I/O scheduling class by its name in the configuration
+pub fn ionice_class(name: &str) -> Option<libc::c_int> {
+    Some(match name.trim() {
+        "realtime" => 1,
+        "best-effort" => 2,
+        "idle" => 3,
+        _ => return None,
+    })
+}
+
+/// Set the OOM score adjustment of lemurs itself
+pub fn set_greeter_oom_score_adj(config: &PriorityConfig) {
+    if config.greeter_oom_score_adj == 0 {
+        return;
+    }
+
+    info!(
+        "Setting the OOM score adjustment of lemurs to {}",
+        config.greeter_oom_score_adj
+    );
+    if let Err(err) = fs::write(OOM_SCORE_ADJ_PATH, config.greeter_oom_score_adj.to_string()) {
+        warn!("Failed to set the OOM score adjustment. Reason: {err}");
+    }
+}
+
+/// Give the process that is spawned from `command` the OOM score adjustment of sessions instead of
+/// the one it inherits from lemurs. Every process that is started for a session needs this, such
+/// as the X server and the hooks, as it is protected from the OOM killer like lemurs otherwise.
+pub fn reset_oom_score_adj(command: &mut Command, config: &PriorityConfig) {
+    // The adjustment is only changed if lemurs has one
+    if config.greeter_oom_score_adj == 0 && config.session_oom_score_adj == 0 {
+        return;
+    }
+
+    let value = config.session_oom_score_adj.to_string();
+    let Ok(path) = CString::new(OOM_SCORE_ADJ_PATH) else {
+        return;
+    };
+
+    unsafe {
+        command.pre_exec(move || {
+            // Only async-signal-safe functions can be used after forking
+            let fd = libc::open(path.as_ptr(), libc::O_WRONLY);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let written = libc::write(fd, value.as_ptr().cast(), value.len());
+            libc::close(fd);
+
+            if written < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        });
+    }
+}
+
+/// Apply the priorities for the session to the process that is spawned from `command`. This has
+/// to be added before the permissions of the command are lowered to the user, as lowering the
+/// nice value or the OOM score adjustment requires privileges.
+pub fn add_to_command(command: &mut Command, config: &PriorityConfig) {
+    reset_oom_score_adj(command, config);
+
+    let nice = config.session_nice;
+
+    let ioprio = if config.session_ionice_class.is_empty() {
+        None
+    } else {
+        match ionice_class(&config.session_ionice_class) {
+            Some(class) => {
+                Some((class << IOPRIO_CLASS_SHIFT) | libc::c_int::from(config.session_ionice_level))
+            }
+            None => {
+                warn!(
+                    "Unknown I/O scheduling class '{}'. Ignoring it",
+                    config.session_ionice_class
+                );
+                None
+            }
+        }
+    };
+
+    unsafe {
+        command.pre_exec(move || {
+            if nice != 0 && libc::setpriority(libc::PRIO_PROCESS, 0, nice.into()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            if let Some(ioprio) = ioprio {
+                if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,126 @@
+//! Initial values for the login form that are provided by the system instead of the cache.
+//!
+//! This is meant for machines that are imaged per user. The values are read from the systemd
+//! credentials of the service (`LoadCredential=lemurs.user:...`) or from the kernel command line
+//! (`lemurs.user=alice lemurs.session="Plasma (X11)"`). The credentials take precedence.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use log::{info, warn};
+
+const KERNEL_CMDLINE_PATH: &str = "/proc/cmdline";
+
+const USER_KEY: &str = "lemurs.user";
+const SESSION_KEY: &str = "lemurs.session";
+
+#[derive(Debug, Clone, Default)]
+pub struct Provisioned {
+    pub username: Option<String>,
+    pub session: Option<String>,
+}
+
+/// Split the kernel command line into its parameters. Double quotes can be used to include
+/// spaces in a parameter and are removed.
+fn split_kernel_cmdline(cmdline: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut param = String::new();
+    let mut in_quotes = false;
+
+    for c in cmdline.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !param.is_empty() {
+                    params.push(std::mem::take(&mut param));
+                }
+            }
+            c => param.push(c),
+        }
+    }
+
+    if !param.is_empty() {
+        params.push(param);
+    }
+
+    params
+}
+
+/// Get the value of `key` from the kernel command line. The last occurrence wins, as with other
+/// kernel parameters.
+fn kernel_cmdline_value(params: &[String], key: &str) -> Option<String> {
+    params
+        .iter()
+        .rev()
+        .filter_map(|param| param.split_once('='))
+        .find(|(param_key, _)| *param_key == key)
+        .map(|(_, value)| value.to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Read a systemd credential of the service
+fn credential(name: &str) -> Option<String> {
+    let directory = env::var_os("CREDENTIALS_DIRECTORY")?;
+
+    match fs::read_to_string(Path::new(&directory).join(name)) {
+        Ok(value) => Some(value.trim().to_string()).filter(|value| !value.is_empty()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => {
+            warn!("Failed to read credential '{name}'. Reason: {err}");
+            None
+        }
+    }
+}
+
+impl Provisioned {
+    /// Read the provisioned values from the credentials and the kernel command line
+    pub fn read() -> Self {
+        let params = fs::read_to_string(KERNEL_CMDLINE_PATH)
+            .map(|cmdline| split_kernel_cmdline(&cmdline))
+            .unwrap_or_default();
+
+        let provisioned = Self {
+            username: credential(USER_KEY).or_else(|| kernel_cmdline_value(&params, USER_KEY)),
+            session: credential(SESSION_KEY).or_else(|| kernel_cmdline_value(&params, SESSION_KEY)),
+        };
+
+        if let Some(username) = &provisioned.username {
+            info!("Found provisioned username '{username}'");
+        }
+        if let Some(session) = &provisioned.session {
+            info!("Found provisioned session '{session}'");
+        }
+
+        provisioned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_cmdline() {
+        let params = split_kernel_cmdline(
+            "root=/dev/sda1 quiet lemurs.user=alice lemurs.session=\"Plasma (X11)\"\n",
+        );
+
+        assert_eq!(
+            kernel_cmdline_value(&params, USER_KEY).as_deref(),
+            Some("alice")
+        );
+        assert_eq!(
+            kernel_cmdline_value(&params, SESSION_KEY).as_deref(),
+            Some("Plasma (X11)")
+        );
+        assert_eq!(kernel_cmdline_value(&params, "quiet"), None);
+
+        let params = split_kernel_cmdline("lemurs.user=alice lemurs.user=bob lemurs.session=");
+        assert_eq!(
+            kernel_cmdline_value(&params, USER_KEY).as_deref(),
+            Some("bob")
+        );
+        assert_eq!(kernel_cmdline_value(&params, SESSION_KEY), None);
+    }
+}
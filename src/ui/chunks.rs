@@ -3,45 +3,218 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     Frame,
 };
-use Constraint::{Length, Min};
+use Constraint::Length;
+
+use super::touch::PIN_PAD_HEIGHT;
+use crate::config::LayoutConfig;
+
+const HORIZONTAL_MARGIN: u16 = 2;
+const VERTICAL_MARGIN: u16 = 1;
+
+/// The narrowest form that is rendered with the full layout
+const MIN_FORM_WIDTH: u16 = 20;
+
+/// The parts of the form that are shown. Hidden parts do not take up any space.
+pub struct ChunksLayout {
+    pub key_menu: bool,
+    pub switcher: bool,
+    /// The switcher shows the comment of the selected environment below it
+    pub switcher_comment: bool,
+    pub field_count: usize,
+    pub status_message: bool,
+    pub pin_pad: bool,
+}
 
 pub struct Chunks {
-    pub key_menu: Rect,
-    pub switcher: Rect,
-    pub username_field: Rect,
-    pub password_field: Rect,
-    pub status_message: Rect,
+    pub key_menu: Option<Rect>,
+    pub switcher: Option<Rect>,
+    /// The input fields from top to bottom
+    pub fields: Vec<Rect>,
+    pub status_message: Option<Rect>,
+    /// The on-screen PIN pad below the form, which is left out in the compact layout
+    pub pin_pad: Option<Rect>,
+    /// The screen is too small for the full layout. The fields take a single line each and the
+    /// key menu and the switcher are left out.
+    pub compact: bool,
+}
+
+/// Get the `part` percent of `value`
+fn percent_of(value: u16, part: u8) -> u16 {
+    (u32::from(value) * u32::from(part.min(100)) / 100) as u16
 }
 
 impl Chunks {
-    pub fn new<B: Backend>(frame: &Frame<B>) -> Self {
-        let constraints = [
-            Length(1),
-            Length(1),
-            Length(2),
-            Length(1),
-            Length(2),
-            Length(3),
-            Length(2),
-            Length(3),
-            Length(2),
-            Length(1),
-            Min(0),
-        ];
+    pub fn new<B: Backend>(frame: &Frame<B>, layout: &ChunksLayout, config: &LayoutConfig) -> Self {
+        Self::from_size(frame.size(), layout, config)
+    }
+
+    fn from_size(size: Rect, layout: &ChunksLayout, config: &LayoutConfig) -> Self {
+        // The key menu always stays at the top of the screen
+        let key_menu = layout.key_menu.then(|| {
+            Rect::new(
+                size.x + HORIZONTAL_MARGIN,
+                size.y + VERTICAL_MARGIN,
+                size.width.saturating_sub(2 * HORIZONTAL_MARGIN),
+                1,
+            )
+            .intersection(size)
+        });
+
+        let mut constraints = Vec::new();
+        let mut add = |constraint| {
+            constraints.push(constraint);
+            constraints.len() - 1
+        };
+
+        add(Length(2));
+        // The comment of the selected environment takes a line of the space below the switcher
+        let switcher = layout.switcher.then(|| {
+            let comment_height = u16::from(layout.switcher_comment);
+            let index = add(Length(1 + comment_height));
+            add(Length(2 - comment_height));
+            index
+        });
+        let fields = (0..layout.field_count)
+            .map(|_| {
+                let index = add(Length(3));
+                add(Length(2));
+                index
+            })
+            .collect::<Vec<_>>();
+        let status_message = layout.status_message.then(|| add(Length(1)));
+        let pin_pad = layout.pin_pad.then(|| {
+            add(Length(1));
+            add(Length(PIN_PAD_HEIGHT))
+        });
+
+        let form_height = constraints
+            .iter()
+            .map(|constraint| match constraint {
+                Length(length) => *length,
+                _ => 0,
+            })
+            .sum::<u16>();
+
+        // The space that is available for the form below the key menu
+        let top = VERTICAL_MARGIN + if layout.key_menu { 2 } else { 0 };
+
+        let min_width = 2 * HORIZONTAL_MARGIN
+            + match config.max_width {
+                0 => MIN_FORM_WIDTH,
+                max_width => max_width.min(MIN_FORM_WIDTH),
+            };
+        let min_height = top + form_height + VERTICAL_MARGIN;
+        if size.width < min_width || size.height < min_height {
+            return Self::compact(size, layout);
+        }
+        let available = Rect::new(
+            size.x + HORIZONTAL_MARGIN,
+            size.y + top,
+            size.width.saturating_sub(2 * HORIZONTAL_MARGIN),
+            size.height.saturating_sub(top + VERTICAL_MARGIN),
+        )
+        .intersection(size);
+
+        let width = match config.max_width {
+            0 => available.width,
+            max_width => available.width.min(max_width),
+        };
+        let height = available.height.min(form_height);
+
+        let free_width = available.width - width;
+        let free_height = available.height - height;
+
+        let form = Rect::new(
+            available.x + percent_of(free_width, config.horizontal_position),
+            available.y
+                + percent_of(free_height, config.vertical_position)
+                    .saturating_add(config.vertical_offset)
+                    .min(free_height),
+            width,
+            height,
+        );
 
         let chunks = Layout::default()
             .direction(Direction::Vertical)
-            .horizontal_margin(2)
-            .vertical_margin(1)
             .constraints(constraints.as_ref())
-            .split(frame.size());
+            .split(form);
+
+        Self {
+            key_menu,
+            switcher: switcher.map(|index| chunks[index]),
+            fields: fields.into_iter().map(|index| chunks[index]).collect(),
+            status_message: status_message.map(|index| chunks[index]),
+            pin_pad: pin_pad.map(|index| chunks[index]),
+            compact: false,
+        }
+    }
+
+    /// One line for each field and the status message from the top of the screen. What does not
+    /// fit gets an empty area.
+    fn compact(size: Rect, layout: &ChunksLayout) -> Self {
+        let line = |index: usize| {
+            let y = size
+                .y
+                .saturating_add(u16::try_from(index).unwrap_or(u16::MAX));
+            Rect::new(size.x, y, size.width, 1).intersection(size)
+        };
 
         Self {
-            key_menu: chunks[0],
-            switcher: chunks[3],
-            username_field: chunks[5],
-            password_field: chunks[7],
-            status_message: chunks[9],
+            key_menu: None,
+            switcher: None,
+            fields: (0..layout.field_count).map(line).collect(),
+            status_message: layout.status_message.then(|| line(layout.field_count)),
+            pin_pad: None,
+            compact: true,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn compact_on_small_screens() {
+        let layout = ChunksLayout {
+            key_menu: true,
+            switcher: true,
+            switcher_comment: false,
+            field_count: 2,
+            status_message: true,
+            pin_pad: false,
+        };
+        let config = Config::default().layout;
+
+        let chunks = Chunks::from_size(Rect::new(0, 0, 80, 24), &layout, &config);
+        assert!(!chunks.compact);
+        assert_eq!(chunks.switcher.map(|area| area.height), Some(1));
+        assert_eq!(chunks.fields[0].height, 3);
+
+        let with_comment = ChunksLayout {
+            switcher_comment: true,
+            ..layout
+        };
+        let chunks_with_comment =
+            Chunks::from_size(Rect::new(0, 0, 80, 24), &with_comment, &config);
+        assert_eq!(
+            chunks_with_comment.switcher.map(|area| area.height),
+            Some(2)
+        );
+        assert_eq!(chunks_with_comment.fields, chunks.fields);
+
+        let chunks = Chunks::from_size(Rect::new(0, 0, 80, 8), &layout, &config);
+        assert!(chunks.compact);
+        assert!(chunks.key_menu.is_none() && chunks.switcher.is_none());
+        assert_eq!(
+            chunks.fields,
+            [Rect::new(0, 0, 80, 1), Rect::new(0, 1, 80, 1)]
+        );
+        assert_eq!(chunks.status_message, Some(Rect::new(0, 2, 80, 1)));
+
+        let chunks = Chunks::from_size(Rect::new(0, 0, 10, 2), &layout, &config);
+        assert!(chunks.compact);
+        assert_eq!(chunks.status_message.map(|area| area.area()), Some(0));
+    }
+}
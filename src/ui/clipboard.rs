@@ -0,0 +1,94 @@
+//! Access to the clipboard of the graphical session that the preview is running in.
+//!
+//! There is no clipboard on the Linux console, so this is only used with `--preview`.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use log::warn;
+
+/// The commands that print the clipboard, for Wayland and X11 respectively
+const PASTE_COMMANDS: [(&str, &str, &[&str]); 2] = [
+    ("WAYLAND_DISPLAY", "wl-paste", &["--no-newline"]),
+    ("DISPLAY", "xclip", &["-selection", "clipboard", "-out"]),
+];
+
+/// The text on the clipboard
+pub fn paste() -> Option<String> {
+    let Some((_, program, args)) = PASTE_COMMANDS
+        .iter()
+        .find(|(variable, _, _)| std::env::var_os(variable).is_some())
+    else {
+        warn!("Not running in a graphical session. There is no clipboard to paste from");
+        return None;
+    };
+
+    let output = match Command::new(program)
+        .args(*args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            warn!("Failed to run `{program}` to paste. Reason: {err}");
+            return None;
+        }
+    };
+
+    if !output.status.success() {
+        warn!("`{program}` failed to paste with {}", output.status);
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3F;
+                encoded.push(char::from(BASE64_ALPHABET[index as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Put `text` on the clipboard with an OSC 52 sequence. The terminal emulator handles the rest,
+/// so this also works without any clipboard tools installed.
+pub fn copy(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"color = \"red\"\n"), "Y29sb3IgPSAicmVkIgo=");
+    }
+}
@@ -0,0 +1,213 @@
+//! Running the commands of which the output is shown on the login screen.
+//!
+//! They run as the user of lemurs, which is usually root, so they are given an empty environment
+//! instead of the one of lemurs. They are killed when they take too long, since the screen would
+//! otherwise wait on them.
+
+use std::io::{self, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// The `PATH` that the commands are run with
+pub const COMMAND_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// The most output of a command that is kept. The rest is read and dropped.
+const MAX_OUTPUT_LEN: u64 = 65_536;
+
+/// How often is checked whether a command has exited
+const EXIT_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How a command ended
+#[derive(Debug)]
+pub enum CommandOutput {
+    Exited { status: ExitStatus, stdout: String },
+    TimedOut,
+}
+
+/// A command for `program` with an empty environment except for the `PATH`, which is started
+/// from the root directory
+pub fn clean_command(program: impl AsRef<std::ffi::OsStr>) -> Command {
+    let mut command = Command::new(program);
+    command
+        .env_clear()
+        .env("PATH", COMMAND_PATH)
+        .current_dir("/");
+    command
+}
+
+/// Run `command` and collect what it prints. The output is read while the command runs, so that
+/// it does not block on a full pipe. The command and the processes that it started are killed
+/// after `timeout`.
+pub fn run_with_timeout(mut command: Command, timeout: Duration) -> io::Result<CommandOutput> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+
+    let kill = |child: &mut std::process::Child| {
+        if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+            unsafe { libc::kill(-pid, libc::SIGKILL) };
+        }
+        let _ = child.wait();
+    };
+
+    let Some(mut stdout) = child.stdout.take() else {
+        kill(&mut child);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "Failed to grab stdout",
+        ));
+    };
+
+    // The output is read on another thread, since it only ends once every process that holds the
+    // pipe has exited
+    let (sender, receiver) = mpsc::sync_channel(1);
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = (&mut stdout).take(MAX_OUTPUT_LEN).read_to_end(&mut output);
+        let _ = io::copy(&mut stdout, &mut io::sink());
+        let _ = sender.send(String::from_utf8_lossy(&output).into_owned());
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if start.elapsed() < timeout => std::thread::sleep(EXIT_CHECK_INTERVAL),
+            Ok(None) => {
+                kill(&mut child);
+                return Ok(CommandOutput::TimedOut);
+            }
+            Err(err) => {
+                kill(&mut child);
+                return Err(err);
+            }
+        }
+    };
+
+    // A process that the command left running in the background can still hold the pipe
+    match receiver.recv_timeout(timeout.saturating_sub(start.elapsed())) {
+        Ok(stdout) => Ok(CommandOutput::Exited { status, stdout }),
+        Err(_) => {
+            kill(&mut child);
+            Ok(CommandOutput::TimedOut)
+        }
+    }
+}
+
+/// `line` without escape sequences, such as those of colors and cursor movements, and without
+/// other control characters
+pub fn strip_escape_sequences(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // Control sequences end with a character from '@' to '~'
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // Strings, such as the title of a terminal, end with BEL or with ESC and '\'
+                Some(']' | 'P' | 'X' | '^' | '_') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                // Sequences such as those of character sets end with a character from '0' to '~'
+                Some(' '..='/') => {
+                    for c in chars.by_ref() {
+                        if ('0'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // Other sequences consist of a single character after the escape
+                _ => {}
+            },
+            c if c.is_control() => {}
+            c => stripped.push(c),
+        }
+    }
+
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str) -> Command {
+        let mut command = clean_command("/bin/sh");
+        command.arg("-c").arg(script);
+        command
+    }
+
+    fn stdout(output: CommandOutput) -> String {
+        match output {
+            CommandOutput::Exited { stdout, .. } => stdout,
+            CommandOutput::TimedOut => panic!("The command timed out"),
+        }
+    }
+
+    #[test]
+    fn escape_sequences_stripped() {
+        assert_eq!(
+            strip_escape_sequences("\x1b[1;31m3\x1b[0m updates\x1b[K"),
+            "3 updates"
+        );
+        assert_eq!(
+            strip_escape_sequences("\x1b]0;title\x07VPN \x1b]8;;https://a\x1b\\up\x1b(B"),
+            "VPN up"
+        );
+        assert_eq!(strip_escape_sequences("a\tb\rc\x1b"), "abc");
+        assert_eq!(strip_escape_sequences("22 °C"), "22 °C");
+    }
+
+    #[test]
+    fn environment_cleared() {
+        std::env::set_var("LEMURS_COMMAND_OUTPUT_TEST", "secret");
+
+        let output = run_with_timeout(shell("env"), Duration::from_secs(5)).unwrap();
+        let output = stdout(output);
+        assert!(!output.contains("LEMURS_COMMAND_OUTPUT_TEST"));
+        assert!(output.contains(&format!("PATH={COMMAND_PATH}")));
+    }
+
+    #[test]
+    fn large_output_does_not_block() {
+        // More than fits in the pipe
+        let output =
+            run_with_timeout(shell("head -c 1000000 /dev/zero"), Duration::from_secs(5)).unwrap();
+        assert_eq!(stdout(output).len(), MAX_OUTPUT_LEN as usize);
+    }
+
+    #[test]
+    fn killed_after_timeout() {
+        let start = Instant::now();
+        let output = run_with_timeout(shell("sleep 10"), Duration::from_millis(200)).unwrap();
+        assert!(matches!(output, CommandOutput::TimedOut));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // A process in the background that holds the output open is killed as well
+        let start = Instant::now();
+        let output =
+            run_with_timeout(shell("echo a; sleep 10 &"), Duration::from_millis(200)).unwrap();
+        assert!(matches!(output, CommandOutput::TimedOut));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
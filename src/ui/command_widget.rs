@@ -0,0 +1,192 @@
+//! Widgets that show the output of a command on the login screen, e.g. the weather, the VPN
+//! status or the pending updates.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use log::{error, info, warn};
+use ratatui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    style::Style,
+    text::{Line, Span, Text},
+    widgets::Paragraph,
+    Frame,
+};
+
+use super::chunks::Chunks;
+use super::command_output::{
+    clean_command, run_with_timeout, strip_escape_sequences, CommandOutput,
+};
+use crate::config::{get_color, get_modifiers, CommandWidget, CommandWidgetPosition};
+
+const HORIZONTAL_MARGIN: u16 = 2;
+const VERTICAL_MARGIN: u16 = 1;
+
+/// The most lines of output that are shown for a single command
+const MAX_LINES: usize = 5;
+
+/// The lines of the output of `cmd`, without escape sequences such as those of colors. The
+/// command runs as the user of lemurs, which is usually root, with an empty environment except
+/// for the `PATH`. It is killed after `timeout`.
+fn run_command(shell: &str, cmd: &str, timeout: Duration) -> Option<Vec<String>> {
+    let mut command = clean_command(shell);
+    command.arg("-c").arg(cmd);
+
+    let (status, stdout) = match run_with_timeout(command, timeout) {
+        Ok(CommandOutput::Exited { status, stdout }) => (status, stdout),
+        Ok(CommandOutput::TimedOut) => {
+            warn!(
+                "Killed the command widget `{cmd}` after {} seconds",
+                timeout.as_secs()
+            );
+            return None;
+        }
+        Err(err) => {
+            warn!("Failed to run the command widget `{cmd}`. Reason: {err}");
+            return None;
+        }
+    };
+
+    if !status.success() {
+        warn!("The command widget `{cmd}` failed with {status}");
+        return None;
+    }
+
+    Some(
+        stdout
+            .lines()
+            .take(MAX_LINES)
+            .map(strip_escape_sequences)
+            .collect(),
+    )
+}
+
+impl CommandWidget {
+    fn style(&self) -> Style {
+        let mut style = Style::default().fg(get_color(&self.color));
+
+        for modifier in get_modifiers(&self.modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        style
+    }
+}
+
+/// The configured command widgets with their latest output. The output is shared between the
+/// clones.
+#[derive(Clone)]
+pub struct CommandWidgets {
+    widgets: Vec<CommandWidget>,
+    outputs: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl CommandWidgets {
+    pub fn new(widgets: Vec<CommandWidget>) -> Self {
+        let outputs = Arc::new(Mutex::new(vec![Vec::new(); widgets.len()]));
+        Self { widgets, outputs }
+    }
+
+    fn get_guard(&self) -> MutexGuard<Vec<Vec<String>>> {
+        match self.outputs.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Run each command periodically with `shell` in the background. `on_update` is called when
+    /// an output changes, and the command is no longer run once it returns `false`.
+    pub fn spawn(&self, shell: &str, on_update: impl Fn() -> bool + Clone + Send + 'static) {
+        for (index, widget) in self.widgets.iter().enumerate() {
+            let widgets = self.clone();
+            let shell = shell.to_string();
+            let cmd = widget.cmd.clone();
+            let interval = Duration::from_secs(widget.interval_secs.max(1).into());
+            let timeout = Duration::from_secs(widget.timeout_secs.max(1).into());
+            let on_update = on_update.clone();
+
+            info!(
+                "Running the command widget `{cmd}` every {} seconds",
+                interval.as_secs()
+            );
+
+            std::thread::spawn(move || loop {
+                let output = run_command(&shell, &cmd, timeout).unwrap_or_default();
+
+                let changed = {
+                    let mut outputs = widgets.get_guard();
+                    let changed = outputs[index] != output;
+                    outputs[index] = output;
+                    changed
+                };
+
+                if changed && !on_update() {
+                    break;
+                }
+
+                std::thread::sleep(interval);
+            });
+        }
+    }
+
+    /// Draw the outputs in the corners of the screen. They are left out when the screen is too
+    /// small for the full layout.
+    pub fn render<B: Backend>(&self, frame: &mut Frame<B>, chunks: &Chunks) {
+        if chunks.compact || self.widgets.is_empty() {
+            return;
+        }
+
+        let size = frame.size();
+        let x = size.x + HORIZONTAL_MARGIN;
+        let width = size.width.saturating_sub(2 * HORIZONTAL_MARGIN);
+        let top = chunks
+            .key_menu
+            .map_or(size.y + VERTICAL_MARGIN, |area| area.bottom());
+        let bottom = size.bottom().saturating_sub(VERTICAL_MARGIN).max(top);
+
+        let outputs = self.get_guard();
+
+        for position in [
+            CommandWidgetPosition::TopLeft,
+            CommandWidgetPosition::TopRight,
+            CommandWidgetPosition::BottomLeft,
+            CommandWidgetPosition::BottomRight,
+        ] {
+            let lines = self
+                .widgets
+                .iter()
+                .zip(outputs.iter())
+                .filter(|(widget, _)| widget.position == position)
+                .flat_map(|(widget, output)| {
+                    let style = widget.style();
+                    output
+                        .iter()
+                        .map(move |line| Line::from(Span::styled(line.clone(), style)))
+                })
+                .collect::<Vec<_>>();
+
+            if lines.is_empty() {
+                continue;
+            }
+
+            let height = u16::try_from(lines.len())
+                .unwrap_or(u16::MAX)
+                .min(bottom - top);
+            let (y, alignment) = match position {
+                CommandWidgetPosition::TopLeft => (top, Alignment::Left),
+                CommandWidgetPosition::TopRight => (top, Alignment::Right),
+                CommandWidgetPosition::BottomLeft => (bottom - height, Alignment::Left),
+                CommandWidgetPosition::BottomRight => (bottom - height, Alignment::Right),
+            };
+
+            frame.render_widget(
+                Paragraph::new(Text::from(lines)).alignment(alignment),
+                Rect::new(x, y, width, height),
+            );
+        }
+    }
+}
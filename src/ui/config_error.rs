@@ -0,0 +1,77 @@
+//! The screen that is shown when the configuration cannot be loaded. It explains why, and lets the
+//! user retry after fixing the file or continue with the default configuration.
+
+use std::io;
+
+use crossterm::event::{self, Event, KeyCode};
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Alignment, Rect},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    Frame, Terminal,
+};
+
+const MARGIN: u16 = 2;
+
+/// What to do after the configuration failed to load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigErrorChoice {
+    /// Load the configuration again, e.g. after the file was fixed
+    Retry,
+    /// Continue with the default configuration
+    Defaults,
+}
+
+/// A screen that explains why the configuration could not be loaded
+struct ConfigErrorWidget<'a> {
+    message: &'a str,
+}
+
+impl ConfigErrorWidget<'_> {
+    fn render(&self, frame: &mut Frame<impl Backend>) {
+        let bounding_box = frame.size();
+
+        let area = Rect {
+            x: bounding_box.x + MARGIN.min(bounding_box.width / 2),
+            y: bounding_box.y + MARGIN.min(bounding_box.height / 2),
+            width: bounding_box.width.saturating_sub(2 * MARGIN),
+            height: bounding_box.height.saturating_sub(2 * MARGIN),
+        };
+
+        let text = format!(
+            "{}\n\n[r] Retry after fixing the file    [d] Continue with the default configuration",
+            self.message
+        );
+        let paragraph = Paragraph::new(text)
+            .alignment(Alignment::Left)
+            .wrap(Wrap { trim: false })
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Configuration error"),
+            );
+
+        frame.render_widget(Clear, bounding_box);
+        frame.render_widget(paragraph, area);
+    }
+}
+
+/// Show `message` until the user decides how to continue
+pub fn show_config_error(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    message: &str,
+) -> io::Result<ConfigErrorChoice> {
+    let widget = ConfigErrorWidget { message };
+
+    loop {
+        terminal.draw(|f| widget.render(f))?;
+
+        if let Event::Key(key) = event::read()? {
+            match key.code {
+                KeyCode::Char('r') => return Ok(ConfigErrorChoice::Retry),
+                KeyCode::Char('d') => return Ok(ConfigErrorChoice::Defaults),
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! A secret key sequence that runs a command chosen by the administrator, e.g. to lock the
+//! encrypted disks or to shut down, without showing anything on the screen.
+
+use std::collections::VecDeque;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::{info, warn};
+
+use crate::config::{get_function_key, DuressConfig};
+
+/// A key of the sequence in the form "F1" to "F12" or "ctrl+LETTER"
+pub fn parse_key(key: &str) -> Option<(KeyCode, KeyModifiers)> {
+    if let Some(code) = get_function_key(key) {
+        return Some((code, KeyModifiers::NONE));
+    }
+
+    let letter = key.trim().strip_prefix("ctrl+")?;
+    let mut chars = letter.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_lowercase() => {
+            Some((KeyCode::Char(c), KeyModifiers::CONTROL))
+        }
+        _ => None,
+    }
+}
+
+pub struct DuressSequence {
+    sequence: Vec<(KeyCode, KeyModifiers)>,
+    command: String,
+    /// The latest key presses, at most as many as in the sequence
+    recent: VecDeque<(KeyCode, KeyModifiers)>,
+}
+
+impl DuressSequence {
+    pub fn new(config: &DuressConfig) -> Self {
+        let sequence = if config.command.is_empty() {
+            Vec::new()
+        } else {
+            // An invalid key disables the sequence, as it could otherwise be entered by accident
+            config
+                .sequence
+                .iter()
+                .map(|key| parse_key(key))
+                .collect::<Option<Vec<_>>>()
+                .unwrap_or_else(|| {
+                    warn!("The duress sequence has an invalid key. Disabling it");
+                    Vec::new()
+                })
+        };
+
+        Self {
+            recent: VecDeque::with_capacity(sequence.len()),
+            sequence,
+            command: config.command.clone(),
+        }
+    }
+
+    /// Register a key press. Returns whether it completed the sequence.
+    pub fn key_press(&mut self, key: &KeyEvent) -> bool {
+        if self.sequence.is_empty() {
+            return false;
+        }
+
+        if self.recent.len() == self.sequence.len() {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((key.code, key.modifiers));
+
+        if !self.recent.iter().eq(self.sequence.iter()) {
+            return false;
+        }
+
+        self.recent.clear();
+        true
+    }
+
+    /// Run the command in the background with `shell`. Nothing is logged when it starts, so that
+    /// the log does not tell that the sequence was entered.
+    pub fn trigger(&self, shell: &str, preview: bool) {
+        if preview {
+            info!("The duress sequence was entered. Not running the command in preview mode");
+            return;
+        }
+
+        // The command is placed in its own process group, so that it outlives lemurs
+        match Command::new(shell)
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn()
+        {
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(err) => warn!("Failed to run a command. Reason: {err}"),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Scripts that react to what happens on the login screen. They are Rhai scripts that run in a
+//! sandbox: they can only show a status message and cannot run commands or access files.
+
+use std::cell::RefCell;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope};
+
+use super::command_output::strip_escape_sequences;
+use crate::config::EventScriptsConfig;
+
+/// The extension of the scripts
+const SCRIPT_EXTENSION: &str = "rhai";
+
+/// The longest status message that a script can show
+const MAX_STRING_LEN: usize = 4096;
+
+/// Something that happened on the login screen
+pub enum ScriptEvent {
+    AuthFailure { username: String },
+    SessionSelect { environment: String },
+}
+
+impl ScriptEvent {
+    /// The function of a script that handles the event
+    fn handler(&self) -> &'static str {
+        match self {
+            Self::AuthFailure { .. } => "on_auth_failure",
+            Self::SessionSelect { .. } => "on_session_select",
+        }
+    }
+
+    fn argument(&self) -> &str {
+        match self {
+            Self::AuthFailure { username } => username,
+            Self::SessionSelect { environment } => environment,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventScripts {
+    config: EventScriptsConfig,
+}
+
+impl EventScripts {
+    pub fn new(config: EventScriptsConfig) -> Self {
+        Self { config }
+    }
+
+    /// The scripts in alphabetical order. The directory and the scripts have to be owned by root
+    /// and not be writable by other users, so that other users cannot show messages on the login
+    /// screen. Scripts that other users could have changed are skipped.
+    fn scripts(&self) -> Vec<PathBuf> {
+        if self.config.path.is_empty() {
+            return Vec::new();
+        }
+
+        let Ok(metadata) = fs::metadata(&self.config.path) else {
+            return Vec::new();
+        };
+        if !only_root_can_change(&metadata) {
+            warn!(
+                "Skipping the event scripts in '{}', because it can be changed by other users than root",
+                self.config.path
+            );
+            return Vec::new();
+        }
+
+        let Ok(entries) = fs::read_dir(&self.config.path) else {
+            return Vec::new();
+        };
+
+        let mut scripts = entries
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                if entry.path().extension() != Some(SCRIPT_EXTENSION.as_ref()) {
+                    return false;
+                }
+
+                let Ok(metadata) = entry.metadata() else {
+                    return false;
+                };
+
+                if !metadata.is_file() {
+                    return false;
+                }
+                if !only_root_can_change(&metadata) {
+                    warn!(
+                        "Skipping the event script '{}', because it can be changed by other users than root",
+                        entry.path().display()
+                    );
+                    return false;
+                }
+
+                true
+            })
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        scripts.sort();
+
+        scripts
+    }
+
+    /// Run the scripts for `event` in the background. `on_message` is called with the status
+    /// message that a script shows.
+    pub fn dispatch(&self, event: ScriptEvent, on_message: impl Fn(String) + Send + 'static) {
+        let scripts = self.scripts();
+        if scripts.is_empty() {
+            return;
+        }
+
+        let timeout = Duration::from_secs(self.config.timeout_secs.into());
+
+        std::thread::spawn(move || {
+            for script in scripts {
+                if let Some(message) = run_script(&script, &event, timeout) {
+                    on_message(message);
+                }
+            }
+        });
+    }
+}
+
+/// Whether only root can change the file. Files of the user of lemurs are also accepted, so that
+/// the scripts can be tried out without root.
+fn only_root_can_change(metadata: &fs::Metadata) -> bool {
+    let is_trusted_owner = metadata.uid() == 0 || metadata.uid() == unsafe { libc::geteuid() };
+    is_trusted_owner && metadata.mode() & 0o022 == 0
+}
+
+/// The engine that runs a script. Next to the functions of the language itself, it only has
+/// `status` to show a message. The script is stopped after `timeout`.
+fn sandboxed_engine(timeout: Duration, message: Rc<RefCell<Option<String>>>) -> Engine {
+    let mut engine = Engine::new();
+
+    engine
+        .set_max_string_size(MAX_STRING_LEN)
+        .set_max_array_size(1024)
+        .set_max_map_size(1024)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .disable_symbol("eval");
+
+    // Printing would write over the login screen
+    engine
+        .on_print(|text| info!("Event script: {text}"))
+        .on_debug(|text, _, position| info!("Event script at {position}: {text}"));
+
+    let deadline = Instant::now() + timeout;
+    engine.on_progress(move |_| (Instant::now() > deadline).then_some(Dynamic::UNIT));
+
+    engine.register_fn("status", move |text: &str| {
+        *message.borrow_mut() = Some(strip_escape_sequences(text));
+    });
+
+    engine
+}
+
+/// Run the handler of `event` in `script` and return the last status message that it showed
+fn run_script(script: &Path, event: &ScriptEvent, timeout: Duration) -> Option<String> {
+    let source = match fs::read_to_string(script) {
+        Ok(source) => source,
+        Err(err) => {
+            warn!(
+                "Failed to read the event script '{}'. Reason: {err}",
+                script.display()
+            );
+            return None;
+        }
+    };
+
+    let message = Rc::new(RefCell::new(None));
+    let engine = sandboxed_engine(timeout, message.clone());
+
+    let ast = match engine.compile(source) {
+        Ok(ast) => ast,
+        Err(err) => {
+            warn!(
+                "Failed to parse the event script '{}'. Reason: {err}",
+                script.display()
+            );
+            return None;
+        }
+    };
+
+    let handler = event.handler();
+    if !ast
+        .iter_functions()
+        .any(|function| function.name == handler)
+    {
+        return None;
+    }
+
+    info!(
+        "Running '{handler}' of the event script '{}'",
+        script.display()
+    );
+
+    let result = engine.call_fn::<Dynamic>(
+        &mut Scope::new(),
+        &ast,
+        handler,
+        (event.argument().to_string(),),
+    );
+    match result.map_err(|err| *err) {
+        Ok(_) => {}
+        Err(EvalAltResult::ErrorTerminated(..)) => {
+            warn!(
+                "Stopped the event script '{}' after {} seconds",
+                script.display(),
+                timeout.as_secs()
+            );
+            return None;
+        }
+        Err(err) => {
+            warn!(
+                "The event script '{}' failed. Reason: {err}",
+                script.display()
+            );
+            return None;
+        }
+    }
+
+    let message = message.take();
+    message.filter(|message| !message.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str, event: &ScriptEvent, timeout: Duration) -> Option<String> {
+        let path = std::env::temp_dir().join(format!(
+            "lemurs-event-script-{}-{}.rhai",
+            std::process::id(),
+            event.handler()
+        ));
+        fs::write(&path, source).unwrap();
+        let message = run_script(&path, event, timeout);
+        let _ = fs::remove_file(&path);
+        message
+    }
+
+    #[test]
+    fn handler_shows_status() {
+        let source = r#"
+            fn on_auth_failure(username) {
+                status(`Wrong password for ${username}` + "\x1b[31m");
+            }
+        "#;
+        let event = ScriptEvent::AuthFailure {
+            username: "lemur".to_string(),
+        };
+        assert_eq!(
+            run(source, &event, Duration::from_secs(5)).as_deref(),
+            Some("Wrong password for lemur")
+        );
+
+        let event = ScriptEvent::SessionSelect {
+            environment: "Sway".to_string(),
+        };
+        assert_eq!(run(source, &event, Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn eval_not_available() {
+        let source = r#"
+            fn on_auth_failure(username) {
+                eval(`status("${username}")`);
+            }
+        "#;
+        let event = ScriptEvent::AuthFailure {
+            username: "lemur".to_string(),
+        };
+        assert_eq!(run(source, &event, Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn script_stopped_after_timeout() {
+        let source = r#"
+            fn on_session_select(environment) {
+                loop { status(environment); }
+            }
+        "#;
+        let event = ScriptEvent::SessionSelect {
+            environment: "Sway".to_string(),
+        };
+
+        let start = Instant::now();
+        assert_eq!(run(source, &event, Duration::from_millis(200)), None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,121 @@
+//! Rendering of the login form without a terminal, e.g. for a gallery of themes or to check a
+//! configuration in CI.
+//!
+//! The form is shown as it is right after startup without a cache, with the environments of the
+//! machine it runs on.
+
+use ratatui::backend::TestBackend;
+use ratatui::buffer::Buffer;
+use ratatui::Terminal;
+
+use super::{
+    field_order, login_form_render, Chunks, ChunksLayout, InputMode, LoginForm, PinPadWidget,
+    SwitcherItem, ThemeVariant,
+};
+use crate::config::{Config, FocusBehaviour, SwitcherVisibility};
+
+/// The field that is focused at startup when nothing was cached
+fn initial_input_mode(config: &Config, fields: &[InputMode]) -> InputMode {
+    let mode = match config.focus_behaviour {
+        FocusBehaviour::FirstNonCached
+            if config.environment_switcher.switcher_visibility == SwitcherVisibility::Visible =>
+        {
+            InputMode::Switcher
+        }
+        FocusBehaviour::FirstNonCached | FocusBehaviour::Username => InputMode::Username,
+        FocusBehaviour::NoFocus => InputMode::Normal,
+        FocusBehaviour::Environment => InputMode::Switcher,
+        FocusBehaviour::Password => InputMode::Password,
+    };
+
+    // Hidden fields cannot be focused
+    match mode {
+        InputMode::Username | InputMode::Password | InputMode::Domain
+            if !fields.contains(&mode) =>
+        {
+            fields.first().copied().unwrap_or(InputMode::Normal)
+        }
+        mode => mode,
+    }
+}
+
+/// Render the login form of `config` to a buffer of `width` by `height` cells
+pub fn render(config: Config, width: u16, height: u16) -> std::io::Result<Buffer> {
+    let mut form = LoginForm::new(config, true);
+    if let Some(variant) = ThemeVariant::current(&form.config.theme) {
+        form.widgets.set_theme(&form.config, variant);
+    }
+
+    let envs = crate::post_login::get_envs(&form.config)
+        .into_iter()
+        .map(|entry| {
+            SwitcherItem::new(entry.name, entry.post_login_env)
+                .with_subtitle(entry.comment)
+                .with_hidden(entry.hidden)
+        })
+        .collect();
+    form.widgets.environment_guard().set_items(envs);
+
+    let switcher_config = &form.config.environment_switcher;
+    for env in [&switcher_config.default, &switcher_config.fallback] {
+        if !env.is_empty() && form.widgets.environment_try_select(env) {
+            break;
+        }
+    }
+
+    let fields = field_order(&form.config);
+    let input_mode = initial_input_mode(&form.config, &fields);
+    let field_widgets = fields
+        .iter()
+        .filter_map(|&field| Some((field, form.widgets.field(field)?)))
+        .collect::<Vec<_>>();
+    let chunks_layout = ChunksLayout {
+        key_menu: form.config.power_controls.visible,
+        switcher: !form.widgets.environment_guard().hidden(),
+        switcher_comment: form.config.environment_switcher.show_comments,
+        field_count: field_widgets.len(),
+        status_message: form.config.status_message.visible,
+        pin_pad: form.config.touch.enabled && form.config.touch.pin_pad,
+    };
+    let pin_pad = PinPadWidget::new(&form.config.touch);
+
+    let mut terminal = Terminal::new(TestBackend::new(width, height))?;
+    terminal.draw(|f| {
+        let layout = Chunks::new(f, &chunks_layout, &form.config.layout);
+        login_form_render(
+            f,
+            layout,
+            form.widgets.background.clone(),
+            form.widgets.key_menu.clone(),
+            &form.widgets.command_widgets,
+            &form.widgets.network,
+            form.widgets.environment.clone(),
+            &field_widgets,
+            input_mode,
+            None,
+            &form.config.status_message,
+            &pin_pad,
+        );
+    })?;
+
+    Ok(terminal.backend().buffer().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_default_config() {
+        let buffer = render(Config::default(), 80, 24).unwrap();
+        assert_eq!(buffer.area.width, 80);
+        assert_eq!(buffer.area.height, 24);
+
+        let text = buffer
+            .content
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect::<String>();
+        assert!(text.contains("Login"));
+    }
+}
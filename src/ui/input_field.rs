@@ -1,14 +1,14 @@
 use crossterm::event::{KeyCode, KeyModifiers};
 use ratatui::{
     layout::Rect,
-    style::Style,
+    style::{Modifier, Style},
     terminal::Frame,
-    text::Span,
+    text::{Line, Span},
     widgets::{Block, Borders, Paragraph},
 };
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
-use crate::config::{get_color, InputFieldStyle};
+use crate::config::{get_bg_color, get_color, get_modifiers, InputFieldStyle};
 
 /// The type of the input field display. How are the characters which are typed displayed?
 #[derive(Clone)]
@@ -31,6 +31,8 @@ pub struct InputFieldWidget {
     width: u16,
     display_type: InputFieldDisplayType,
     style: InputFieldStyle,
+    /// Show the terminal cursor whilst the field is focused
+    show_cursor: bool,
 }
 
 fn get_byte_offset_of_char_offset(s: &str, offset: usize) -> usize {
@@ -44,20 +46,24 @@ impl InputFieldWidget {
         style: InputFieldStyle,
         preset_content: String,
     ) -> Self {
-        // Calculate the initial cursor position from the preset_content
-        let initial_cursor_position = preset_content
-            .len()
-            .try_into() // Convert from usize to u16
-            .unwrap_or(0u16);
-
-        Self {
-            content: preset_content,
-            cursor: initial_cursor_position,
+        let mut input_field = Self {
+            content: String::new(),
+            cursor: 0,
             scroll: 0,
             width: 8, // Give it some initial width
             display_type,
             style,
-        }
+            show_cursor: true,
+        };
+
+        input_field.set_content(&preset_content);
+        input_field
+    }
+
+    /// Set whether the cursor is shown whilst the field is focused
+    pub fn with_cursor(mut self, show_cursor: bool) -> Self {
+        self.show_cursor = show_cursor;
+        self
     }
 
     #[inline]
@@ -65,6 +71,41 @@ impl InputFieldWidget {
         self.content.len()
     }
 
+    /// The amount of cells that a character takes up when it is shown
+    fn char_width(&self, c: char) -> usize {
+        match self.display_type {
+            InputFieldDisplayType::Echo => c.width().unwrap_or(0),
+            InputFieldDisplayType::Replace(_) => 1,
+        }
+    }
+
+    /// Place the cursor in front of the character at `position` (in characters from the start of
+    /// the content). The scroll is only increased as far as is needed to keep the cursor within
+    /// the field.
+    fn set_position(&mut self, position: usize) {
+        let char_count = self.content.chars().count();
+        let position = usize::min(position, char_count);
+
+        // Leave one cell for the cursor itself
+        let max_width = usize::from(self.width.saturating_sub(1));
+
+        // Find the smallest scroll for which the cursor is still within the field
+        let mut min_scroll = position;
+        let mut cell_width = 0;
+        for c in self.content.chars().rev().skip(char_count - position) {
+            cell_width += self.char_width(c);
+            if cell_width > max_width {
+                break;
+            }
+            min_scroll -= 1;
+        }
+
+        let scroll = usize::from(self.scroll).clamp(min_scroll, position);
+
+        self.scroll = u16::try_from(scroll).unwrap_or(u16::MAX);
+        self.cursor = u16::try_from(position - scroll).unwrap_or(u16::MAX);
+    }
+
     /// Return what string is currently shown to the user for an Echo type field
     fn show_echo(&self) -> String {
         let scroll = usize::from(self.scroll);
@@ -201,6 +242,10 @@ impl InputFieldWidget {
         }
     }
 
+    pub fn set_style(&mut self, style: InputFieldStyle) {
+        self.style = style;
+    }
+
     pub fn clear(&mut self) {
         self.cursor = 0;
         self.scroll = 0;
@@ -228,35 +273,52 @@ impl InputFieldWidget {
     }
 
     pub fn move_to_end(&mut self) {
-        self.cursor = (self.content.len() % (self.width as usize)) as u16;
-        self.scroll = if self.content.len() > (self.width as usize) {
-            (self.content.len() - (self.width as usize)) as u16
-        } else {
-            0
-        }
+        self.set_position(self.content.chars().count());
     }
 
     fn get_text_style(&self, is_focused: bool) -> Style {
-        if is_focused {
-            Style::default().fg(get_color(&self.style.content_color_focused))
-        } else {
-            Style::default().fg(get_color(&self.style.content_color))
-        }
-    }
-
-    fn get_block(&self, is_focused: bool) -> Block {
-        let (title_style, border_style) = if is_focused {
+        let (color, modifiers, bg_color) = if is_focused {
             (
-                Style::default().fg(get_color(&self.style.title_color_focused)),
-                Style::default().fg(get_color(&self.style.border_color_focused)),
+                &self.style.content_color_focused,
+                &self.style.content_modifiers_focused,
+                &self.style.bg_color_focused,
             )
         } else {
             (
-                Style::default().fg(get_color(&self.style.title_color)),
-                Style::default().fg(get_color(&self.style.border_color)),
+                &self.style.content_color,
+                &self.style.content_modifiers,
+                &self.style.bg_color,
             )
         };
 
+        let mut style = Style::default().fg(get_color(color));
+        for modifier in get_modifiers(modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        // The background of the text style fills the whole field, including the borders
+        match get_bg_color(bg_color) {
+            Some(bg_color) => style.bg(bg_color),
+            None => style,
+        }
+    }
+
+    fn get_title_style(&self, is_focused: bool) -> Style {
+        Style::default().fg(get_color(if is_focused {
+            &self.style.title_color_focused
+        } else {
+            &self.style.title_color
+        }))
+    }
+
+    fn get_block(&self, is_focused: bool) -> Block {
+        let title_style = self.get_title_style(is_focused);
+        let border_style = Style::default().fg(get_color(if is_focused {
+            &self.style.border_color_focused
+        } else {
+            &self.style.border_color
+        }));
+
         let block = Block::default();
 
         let block = if self.style.show_title {
@@ -288,30 +350,73 @@ impl InputFieldWidget {
         area
     }
 
+    /// Render the field in `area`. A `compact` field is a single line without borders, where the
+    /// title is put in front of the content.
     pub fn render(
         &mut self,
         frame: &mut Frame<impl ratatui::backend::Backend>,
         area: Rect,
         is_focused: bool,
+        compact: bool,
     ) {
-        let area = self.constraint_area(area);
-        let block = self.get_block(is_focused);
-        let inner = block.inner(area);
+        let (area, block, prefix) = if compact {
+            let prefix = if self.style.show_title && !self.style.title.trim().is_empty() {
+                format!("{}: ", self.style.title.trim())
+            } else {
+                String::new()
+            };
+            (area, Block::default(), prefix)
+        } else {
+            let area = self.constraint_area(area);
+            (area, self.get_block(is_focused), String::new())
+        };
+
+        let mut inner = block.inner(area);
+        let prefix_width = u16::try_from(prefix.width())
+            .unwrap_or(u16::MAX)
+            .min(inner.width);
+        inner.x += prefix_width;
+        inner.width -= prefix_width;
+
+        // The field does not fit on the screen. The width is kept, so that typing still works.
+        if inner.width == 0 || inner.height == 0 {
+            frame.render_widget(Paragraph::new(prefix).block(block), area);
+            return;
+        }
 
         // Get width of text field minus borders (2)
-        self.width = inner.width;
+        if self.width != inner.width {
+            self.width = inner.width;
+
+            // Keep the cursor within the field if it is resized. This also happens on the first
+            // render, as the content can be preset before the width is known.
+            self.set_position(usize::from(self.cursor + self.scroll));
+        }
 
         let show_string = self.show_string();
 
-        if is_focused {
+        if is_focused && self.show_cursor {
             let Rect { x, y, .. } = inner;
             let cursor_offset = get_byte_offset_of_char_offset(&show_string, self.cursor.into());
             frame.set_cursor(x + show_string[..cursor_offset].width() as u16, y);
         }
 
-        let widget = Paragraph::new(show_string)
+        let text = if !is_focused && self.content.is_empty() && !self.style.placeholder.is_empty() {
+            Span::styled(
+                self.style.placeholder.clone(),
+                Style::default().add_modifier(Modifier::DIM),
+            )
+        } else {
+            Span::raw(show_string)
+        };
+
+        let text = Line::from(vec![
+            Span::styled(prefix, self.get_title_style(is_focused)),
+            text,
+        ]);
+        let widget = Paragraph::new(text)
             .style(self.get_text_style(is_focused))
-            .block(self.get_block(is_focused));
+            .block(block);
 
         frame.render_widget(widget, area);
     }
@@ -343,14 +448,23 @@ impl InputFieldWidget {
         None
     }
 
+    /// Insert `text` at the cursor. Control characters, such as newlines, are left out.
+    pub fn paste(&mut self, text: &str) {
+        for character in text.chars().filter(|c| !c.is_control()) {
+            self.insert(character);
+        }
+    }
+
     /// Get the real content of the input field
     pub fn get_content(&self) -> String {
         self.content.clone()
     }
 
     pub fn set_content(&mut self, content: &str) {
-        self.cursor = content.len() as u16;
         self.content = content.to_string();
+        self.cursor = 0;
+        self.scroll = 0;
+        self.move_to_end();
     }
 }
 
@@ -439,4 +553,57 @@ mod tests {
         input_field.backspace();
         assert_eq!(&input_field.show_string(), "");
     }
+
+    #[test]
+    fn paste() {
+        let mut input_field = InputFieldWidget::new(
+            Echo,
+            Config::default().username_field.style,
+            String::from("ab"),
+        );
+        input_field.left();
+        input_field.paste("x\ty🐵\n");
+        assert_eq!(&input_field.show_string(), "axy🐵b");
+        assert_eq!(input_field.cursor, 4);
+    }
+
+    #[test]
+    fn preset_content() {
+        let mut input_field = InputFieldWidget::new(
+            Echo,
+            Config::default().username_field.style,
+            String::from("abc"),
+        );
+        assert_eq!(input_field.cursor, 3);
+        assert_eq!(input_field.scroll, 0);
+
+        // Longer than the field, so the end should be visible with the cursor behind it
+        input_field.set_content("averylongusername");
+        assert_eq!(input_field.cursor, 7);
+        assert_eq!(input_field.scroll, 10);
+        assert_eq!(&input_field.show_string(), "sername");
+
+        // Characters are counted, not bytes
+        input_field.set_content("🐵🐵🐵");
+        assert_eq!(input_field.cursor, 3);
+        assert_eq!(input_field.scroll, 0);
+
+        // Wide characters take up two cells
+        input_field.set_content("🐵🐵🐵🐵🐵");
+        assert_eq!(input_field.cursor, 3);
+        assert_eq!(input_field.scroll, 2);
+
+        // The cursor stays at the end when the width becomes known
+        input_field.set_content("averylongusername");
+        input_field.width = 4;
+        input_field.set_position(usize::from(input_field.cursor + input_field.scroll));
+        assert_eq!(input_field.cursor, 3);
+        assert_eq!(input_field.scroll, 14);
+        assert_eq!(&input_field.show_string(), "ame");
+
+        input_field.move_to_begin();
+        assert_eq!(input_field.cursor, 0);
+        input_field.move_to_end();
+        assert_eq!(input_field.cursor + input_field.scroll, 17);
+    }
 }
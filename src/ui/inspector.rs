@@ -0,0 +1,331 @@
+//! An overlay for preview mode that shows the areas of the login form and the options that style
+//! the part under a movable cursor.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crossterm::event::KeyCode;
+use log::error;
+use ratatui::{
+    backend::Backend,
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    widgets::{Paragraph, Widget},
+    Frame,
+};
+
+use super::chunks::Chunks;
+use super::InputMode;
+use crate::config::{Config, InputFieldStyle};
+
+/// The key that shows and hides the inspector
+pub const INSPECTOR_KEY: KeyCode = KeyCode::F(12);
+
+/// The key that copies the options of the inspected part to the clipboard
+pub const COPY_KEY: KeyCode = KeyCode::Char('y');
+
+/// A part of the login form with the section and the color options that style it
+#[derive(Clone)]
+pub struct InspectedPart {
+    name: &'static str,
+    area: Rect,
+    section: &'static str,
+    colors: Vec<(&'static str, String)>,
+}
+
+impl InspectedPart {
+    fn new(
+        name: &'static str,
+        area: Rect,
+        section: &'static str,
+        colors: &[(&'static str, &String)],
+    ) -> Self {
+        Self {
+            name,
+            area,
+            section,
+            colors: colors
+                .iter()
+                .map(|(key, value)| (*key, value.to_string()))
+                .collect(),
+        }
+    }
+
+    /// The options as they would be written in the configuration file
+    pub fn to_toml(&self) -> String {
+        let mut toml = format!("[{}]\n", self.section);
+        for (key, value) in &self.colors {
+            toml.push_str(&format!("{key} = \"{value}\"\n"));
+        }
+        toml
+    }
+
+    fn contains(&self, (x, y): (u16, u16)) -> bool {
+        let area = self.area;
+        x >= area.x && x < area.right() && y >= area.y && y < area.bottom()
+    }
+}
+
+fn field_part(
+    name: &'static str,
+    area: Rect,
+    section: &'static str,
+    style: &InputFieldStyle,
+) -> InspectedPart {
+    InspectedPart::new(
+        name,
+        area,
+        section,
+        &[
+            ("title_color", &style.title_color),
+            ("title_color_focused", &style.title_color_focused),
+            ("content_color", &style.content_color),
+            ("content_color_focused", &style.content_color_focused),
+            ("border_color", &style.border_color),
+            ("border_color_focused", &style.border_color_focused),
+            ("bg_color", &style.bg_color),
+            ("bg_color_focused", &style.bg_color_focused),
+        ],
+    )
+}
+
+/// The parts of the login form from back to front. `fields` are the input fields in the order of
+/// `chunks.fields`.
+pub fn inspected_parts(
+    chunks: &Chunks,
+    fields: &[InputMode],
+    size: Rect,
+    config: &Config,
+) -> Vec<InspectedPart> {
+    let mut parts = Vec::new();
+
+    let background = &config.background.style;
+    parts.push(InspectedPart::new(
+        "background",
+        size,
+        "background.style",
+        &[
+            ("color", &background.color),
+            ("border_color", &background.border_color),
+        ],
+    ));
+
+    if let Some(area) = chunks.key_menu {
+        parts.push(InspectedPart::new(
+            "power controls",
+            area,
+            "power_controls",
+            &[("bg_color", &config.power_controls.bg_color)],
+        ));
+    }
+
+    if let Some(area) = chunks.switcher {
+        let switcher = &config.environment_switcher;
+        parts.push(InspectedPart::new(
+            "environment switcher",
+            area,
+            "environment_switcher",
+            &[
+                ("toggle_hint_color", &switcher.toggle_hint_color),
+                ("mover_color", &switcher.mover_color),
+                ("mover_color_focused", &switcher.mover_color_focused),
+                ("selected_color", &switcher.selected_color),
+                ("selected_color_focused", &switcher.selected_color_focused),
+                ("selected_bg_color", &switcher.selected_bg_color),
+                (
+                    "selected_bg_color_focused",
+                    &switcher.selected_bg_color_focused,
+                ),
+                ("neighbour_color", &switcher.neighbour_color),
+                ("neighbour_color_focused", &switcher.neighbour_color_focused),
+            ],
+        ));
+    }
+
+    for (mode, area) in fields.iter().zip(chunks.fields.iter()) {
+        parts.push(match mode {
+            InputMode::Username => field_part(
+                "username field",
+                *area,
+                "username_field.style",
+                &config.username_field.style,
+            ),
+            InputMode::Password => field_part(
+                "password field",
+                *area,
+                "password_field.style",
+                &config.password_field.style,
+            ),
+            _ => field_part(
+                "domain field",
+                *area,
+                "domain_field.style",
+                &config.domain_field.style,
+            ),
+        });
+    }
+
+    if let Some(area) = chunks.status_message {
+        let status_message = &config.status_message;
+        parts.push(InspectedPart::new(
+            "status message",
+            area,
+            "status_message",
+            &[
+                ("bg_color", &status_message.bg_color),
+                ("info_color", &status_message.info_color),
+                ("error_color", &status_message.error_color),
+            ],
+        ));
+    }
+
+    parts
+}
+
+struct InspectorState {
+    shown: bool,
+    cursor: (u16, u16),
+    size: Rect,
+    parts: Vec<InspectedPart>,
+}
+
+impl InspectorState {
+    /// The frontmost part under the cursor
+    fn inspected(&self) -> Option<&InspectedPart> {
+        self.parts
+            .iter()
+            .rev()
+            .find(|part| part.contains(self.cursor))
+    }
+}
+
+#[derive(Clone)]
+pub struct Inspector(Arc<Mutex<InspectorState>>);
+
+impl Inspector {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(InspectorState {
+            shown: false,
+            cursor: (0, 0),
+            size: Rect::default(),
+            parts: Vec::new(),
+        })))
+    }
+
+    fn get_guard(&self) -> MutexGuard<InspectorState> {
+        let Self(mutex) = self;
+
+        match mutex.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.get_guard().shown
+    }
+
+    pub fn toggle(&self) {
+        let mut state = self.get_guard();
+        state.shown = !state.shown;
+    }
+
+    /// Move the cursor with the arrow keys. Returns `false` for other keys.
+    pub fn move_cursor(&self, key_code: KeyCode) -> bool {
+        let mut state = self.get_guard();
+        let (x, y) = state.cursor;
+        let size = state.size;
+
+        state.cursor = match key_code {
+            KeyCode::Left => (x.saturating_sub(1), y),
+            KeyCode::Right => (x.saturating_add(1).min(size.right().saturating_sub(1)), y),
+            KeyCode::Up => (x, y.saturating_sub(1)),
+            KeyCode::Down => (x, y.saturating_add(1).min(size.bottom().saturating_sub(1))),
+            _ => return false,
+        };
+
+        true
+    }
+
+    /// The options of the part under the cursor, as they would be written in the configuration
+    /// file
+    pub fn inspected_toml(&self) -> Option<String> {
+        self.get_guard().inspected().map(InspectedPart::to_toml)
+    }
+
+    /// Draw the overlay on top of the login form, which consists of `parts`
+    pub fn render<B: Backend>(&self, frame: &mut Frame<B>, parts: Vec<InspectedPart>) {
+        let mut state = self.get_guard();
+        state.size = frame.size();
+        state.parts = parts;
+
+        frame.render_widget(InspectorOverlay(&state), frame.size());
+    }
+}
+
+struct InspectorOverlay<'a>(&'a InspectorState);
+
+/// Mark the edges of `area` with `style`
+fn outline(buf: &mut Buffer, area: Rect, style: Style) {
+    for x in area.left()..area.right() {
+        buf.get_mut(x, area.top()).set_style(style);
+        buf.get_mut(x, area.bottom() - 1).set_style(style);
+    }
+    for y in area.top()..area.bottom() {
+        buf.get_mut(area.left(), y).set_style(style);
+        buf.get_mut(area.right() - 1, y).set_style(style);
+    }
+}
+
+impl Widget for InspectorOverlay<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let Self(state) = self;
+        if area.area() == 0 {
+            return;
+        }
+
+        let inspected = state.inspected();
+
+        // The background covers the whole screen, so it is not outlined
+        for part in state.parts.iter().skip(1) {
+            let area = part.area.intersection(area);
+            if area.area() > 0 {
+                outline(buf, area, Style::default().bg(Color::DarkGray));
+            }
+        }
+        if let Some(part) = inspected.filter(|part| part.area != area) {
+            let area = part.area.intersection(area);
+            if area.area() > 0 {
+                outline(buf, area, Style::default().bg(Color::Magenta));
+            }
+        }
+
+        let (x, y) = state.cursor;
+        if x < area.right() && y < area.bottom() {
+            buf.get_mut(x, y)
+                .set_style(Style::default().add_modifier(Modifier::REVERSED));
+        }
+
+        let description = match inspected {
+            Some(part) => {
+                let colors = part
+                    .colors
+                    .iter()
+                    .map(|(key, value)| format!("{key} = \"{value}\""))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{} [{}] {colors}", part.name, part.section)
+            }
+            None => String::from("nothing"),
+        };
+        let info = format!("{x},{y}: {description} | arrows: move, y: copy, F12: close");
+
+        let info_area = Rect::new(area.x, area.bottom() - 1, area.width, 1);
+        Paragraph::new(info)
+            .style(Style::default().fg(Color::Black).bg(Color::White))
+            .render(info_area, buf);
+    }
+}
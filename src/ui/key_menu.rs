@@ -1,4 +1,5 @@
 use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
 
 use crossterm::event::KeyCode;
 use ratatui::layout::{Alignment, Rect};
@@ -8,14 +9,22 @@ use ratatui::widgets::Paragraph;
 use ratatui::Frame;
 
 use crate::config::{
-    get_color, get_key, get_modifiers, PowerControl, PowerControlConfig, SwitcherConfig,
-    SwitcherVisibility,
+    get_bg_color, get_color, get_key, get_modifiers, DisplayLayoutConfig, HiDpiConfig,
+    PowerControl, PowerControlConfig, RecoveryShellConfig, SwitcherConfig, SwitcherVisibility,
 };
 
 #[derive(Clone)]
 pub struct KeyMenuWidget {
     power_config: PowerControlConfig,
     switcher_config: SwitcherConfig,
+    hidpi_config: HiDpiConfig,
+    recovery_config: RecoveryShellConfig,
+    display_layout_config: DisplayLayoutConfig,
+    /// Whether HiDPI scaling is toggled on. This is shared between the clones of the widget.
+    hidpi: Arc<Mutex<bool>>,
+    /// The name of the chosen display layout, which is empty to leave the layout unchanged. This
+    /// is shared between the clones of the widget.
+    display_layout: Arc<Mutex<String>>,
 }
 
 impl PowerControl {
@@ -31,13 +40,116 @@ impl PowerControl {
 }
 
 impl KeyMenuWidget {
-    pub fn new(power_config: PowerControlConfig, switcher_config: SwitcherConfig) -> Self {
+    pub fn new(
+        power_config: PowerControlConfig,
+        switcher_config: SwitcherConfig,
+        hidpi_config: HiDpiConfig,
+        recovery_config: RecoveryShellConfig,
+        display_layout_config: DisplayLayoutConfig,
+    ) -> Self {
+        let hidpi = Arc::new(Mutex::new(hidpi_config.enabled));
+        let display_layout = Arc::new(Mutex::new(display_layout_config.default.clone()));
         Self {
             power_config,
             switcher_config,
+            hidpi_config,
+            recovery_config,
+            display_layout_config,
+            hidpi,
+            display_layout,
         }
     }
 
+    /// Whether HiDPI scaling can be toggled with a key
+    pub fn can_toggle_hidpi(&self) -> bool {
+        !self.hidpi_config.toggle_key.is_empty()
+    }
+
+    pub fn hidpi_enabled(&self) -> bool {
+        self.hidpi
+            .lock()
+            .map_or(self.hidpi_config.enabled, |hidpi| *hidpi)
+    }
+
+    pub fn set_hidpi(&self, enabled: bool) {
+        if let Ok(mut hidpi) = self.hidpi.lock() {
+            *hidpi = enabled;
+        }
+    }
+
+    /// Whether the display layout can be chosen with a key
+    pub fn can_choose_display_layout(&self) -> bool {
+        !self.display_layout_config.toggle_key.is_empty()
+            && !self.display_layout_config.layouts.0.is_empty()
+    }
+
+    pub fn display_layout(&self) -> String {
+        self.display_layout.lock().map_or_else(
+            |_| self.display_layout_config.default.clone(),
+            |layout| layout.clone(),
+        )
+    }
+
+    /// Choose the layout with the name `name`. Unknown layouts are ignored, as they may have been
+    /// removed from the configuration.
+    pub fn set_display_layout(&self, name: &str) {
+        if !name.is_empty() && self.display_layout_config.layout(name).is_none() {
+            return;
+        }
+
+        if let Ok(mut layout) = self.display_layout.lock() {
+            *layout = name.to_string();
+        }
+    }
+
+    /// The layout after the current one, where the last layout is followed by no layout
+    fn next_display_layout(&self) -> String {
+        let layouts = &self.display_layout_config.layouts.0;
+        let current = self.display_layout();
+
+        let next = match layouts.iter().position(|layout| layout.name == current) {
+            Some(index) => layouts.get(index + 1),
+            None => layouts.first(),
+        };
+        next.map(|layout| layout.name.clone()).unwrap_or_default()
+    }
+
+    fn display_layout_toggle_style(&self) -> Style {
+        let mut style =
+            Style::default().fg(get_color(&self.display_layout_config.toggle_hint_color));
+
+        for modifier in get_modifiers(&self.display_layout_config.toggle_hint_modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        style
+    }
+
+    fn hidpi_toggle_style(&self) -> Style {
+        let mut style = Style::default().fg(get_color(&self.hidpi_config.toggle_hint_color));
+
+        for modifier in get_modifiers(&self.hidpi_config.toggle_hint_modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        style
+    }
+
+    /// Whether `key_code` opens the recovery shell
+    pub fn is_recovery_key(&self, key_code: KeyCode) -> bool {
+        !self.recovery_config.key.is_empty() && key_code == get_key(&self.recovery_config.key)
+    }
+
+    fn recovery_style(&self) -> Style {
+        let mut style = Style::default().fg(get_color(&self.recovery_config.hint_color));
+
+        for modifier in get_modifiers(&self.recovery_config.hint_modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        style
+    }
+
     fn switcher_toggle_style(&self) -> Style {
         let mut style = Style::default().fg(get_color(&self.switcher_config.toggle_hint_color));
 
@@ -72,7 +184,60 @@ impl KeyMenuWidget {
             items.push(Span::raw(" ".repeat(self.power_config.hint_margin.into())));
         }
 
-        let left_widget = Paragraph::new(Line::from(items));
+        if !self.recovery_config.key.is_empty() {
+            items.push(Span::styled(
+                self.recovery_config.key.as_str(),
+                self.recovery_style().add_modifier(Modifier::UNDERLINED),
+            ));
+            items.push(Span::raw(" "));
+            items.push(Span::styled(
+                self.recovery_config.hint.as_str(),
+                self.recovery_style(),
+            ));
+            items.push(Span::raw(" ".repeat(self.power_config.hint_margin.into())));
+        }
+
+        if self.can_toggle_hidpi() {
+            let state = if self.hidpi_enabled() { "on" } else { "off" };
+
+            items.push(Span::styled(
+                self.hidpi_config.toggle_key.as_str(),
+                self.hidpi_toggle_style().add_modifier(Modifier::UNDERLINED),
+            ));
+            items.push(Span::raw(" "));
+            items.push(Span::styled(
+                self.hidpi_config.toggle_hint.replace("%state%", state),
+                self.hidpi_toggle_style(),
+            ));
+            items.push(Span::raw(" ".repeat(self.power_config.hint_margin.into())));
+        }
+
+        if self.can_choose_display_layout() {
+            let layout = match self.display_layout() {
+                layout if layout.is_empty() => String::from("unchanged"),
+                layout => layout,
+            };
+
+            items.push(Span::styled(
+                self.display_layout_config.toggle_key.as_str(),
+                self.display_layout_toggle_style()
+                    .add_modifier(Modifier::UNDERLINED),
+            ));
+            items.push(Span::raw(" "));
+            items.push(Span::styled(
+                self.display_layout_config
+                    .toggle_hint
+                    .replace("%layout%", &layout),
+                self.display_layout_toggle_style(),
+            ));
+        }
+
+        let menu_style = match get_bg_color(&self.power_config.bg_color) {
+            Some(bg_color) => Style::default().bg(bg_color),
+            None => Style::default(),
+        };
+
+        let left_widget = Paragraph::new(Line::from(items)).style(menu_style);
         frame.render_widget(left_widget, area);
 
         // Since we only allow Fn keys, this should always match if it's set... because of this, an
@@ -91,6 +256,32 @@ impl KeyMenuWidget {
     }
 
     pub(crate) fn key_press(&self, key_code: KeyCode) -> Option<super::ErrorStatusMessage> {
+        if self.can_toggle_hidpi() && key_code == get_key(&self.hidpi_config.toggle_key) {
+            let enabled = !self.hidpi_enabled();
+            log::info!(
+                "Toggled HiDPI scaling {}",
+                if enabled { "on" } else { "off" }
+            );
+            self.set_hidpi(enabled);
+            return None;
+        }
+
+        if self.can_choose_display_layout()
+            && key_code == get_key(&self.display_layout_config.toggle_key)
+        {
+            let layout = self.next_display_layout();
+            log::info!(
+                "Chose the display layout '{}'",
+                if layout.is_empty() {
+                    "unchanged"
+                } else {
+                    &layout
+                }
+            );
+            self.set_display_layout(&layout);
+            return None;
+        }
+
         // TODO: Properly handle StdIn
         for power_control in self
             .power_config
@@ -1,18 +1,35 @@
 use log::{error, info, warn};
 
+use std::cell::Cell;
 use std::io;
+use std::path::Path;
 use std::sync::mpsc::channel;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
-use crate::config::{Config, FocusBehaviour, SwitcherVisibility};
-use crate::info_caching::{get_cached_information, set_cache};
-use crate::post_login::PostLoginEnvironment;
+use crate::auth::AuthenticationError;
+use crate::config::{
+    Config, CursorConfig, DomainFieldConfig, FailedAttemptsConfig, FocusBehaviour,
+    StatusMessageConfig, SwitcherVisibility,
+};
+use crate::error::ErrorCode;
+use crate::info_caching::{
+    get_cached_information, now_as_secs, set_cache, set_failed_attempts, CachedInfo,
+};
+use crate::network::{is_local_user, NetworkStatus};
+use crate::notifications;
+use crate::post_login::{EnvironmentStartError, ExecHooks, PostLoginEnvironment};
+use crate::provisioning::Provisioned;
+use crate::users::{complete_username, get_users};
+use crate::vt_control::VtControl;
 use crate::{start_session, Hooks, StartSessionError};
 use status_message::StatusMessage;
 
-use crossterm::cursor::MoveTo;
-use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::cursor::{MoveTo, SetCursorStyle};
+use crossterm::event::{
+    DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event,
+    KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind,
+};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
@@ -22,16 +39,37 @@ use ratatui::{backend::Backend, Frame, Terminal};
 
 mod background;
 mod chunks;
+mod clipboard;
+mod command_output;
+mod command_widget;
+pub mod config_error;
+pub mod duress;
+mod event_scripts;
+pub mod headless;
 mod input_field;
+mod inspector;
 mod key_menu;
+mod network_status;
+pub mod screenshot;
+mod session_summary;
 mod status_message;
 mod switcher;
+mod theme;
+mod touch;
 
-use chunks::Chunks;
+use chunks::{Chunks, ChunksLayout};
+use command_widget::CommandWidgets;
+use duress::DuressSequence;
+use event_scripts::{EventScripts, ScriptEvent};
 use input_field::{InputFieldDisplayType, InputFieldWidget};
+use inspector::{inspected_parts, Inspector, COPY_KEY, INSPECTOR_KEY};
 use key_menu::KeyMenuWidget;
+use network_status::NetworkIndicator;
+use session_summary::SessionSummaryWidget;
 use status_message::{ErrorStatusMessage, InfoStatusMessage};
 use switcher::{SwitcherItem, SwitcherWidget};
+use theme::{apply_theme, ThemeVariant, THEME_CHECK_INTERVAL};
+use touch::{InputEvents, PinPadWidget, TouchTarget, TouchTargets};
 
 use self::background::BackgroundWidget;
 
@@ -59,11 +97,11 @@ impl LoginFormInputMode {
         *self.get_guard()
     }
 
-    fn prev(&self, skip_switcher: bool) {
-        self.get_guard().prev(skip_switcher)
+    fn prev(&self, skip_switcher: bool, fields: &[InputMode]) {
+        self.get_guard().prev(skip_switcher, fields)
     }
-    fn next(&self, skip_switcher: bool) {
-        self.get_guard().next(skip_switcher)
+    fn next(&self, skip_switcher: bool, fields: &[InputMode]) {
+        self.get_guard().next(skip_switcher, fields)
     }
     fn set(&self, mode: InputMode) {
         *self.get_guard() = mode;
@@ -100,10 +138,68 @@ impl LoginFormStatusMessage {
     fn set(&self, msg: impl Into<StatusMessage>) {
         *self.get_guard() = Some(msg.into());
     }
+    /// Clear the message if it is still the notification with `id`
+    fn clear_notification(&self, id: u64) {
+        let mut guard = self.get_guard();
+        if matches!(&*guard, Some(StatusMessage::Notification(notification)) if notification.id == id)
+        {
+            *guard = None;
+        }
+    }
+}
+
+/// The state of the submission of the login form
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SubmissionState {
+    /// Waiting for the user to submit the form
+    Idle,
+
+    /// A login attempt is being handled. The fields are disabled until it is resolved.
+    InFlight,
+}
+
+#[derive(Clone)]
+struct LoginFormSubmission(Arc<Mutex<SubmissionState>>);
+
+impl LoginFormSubmission {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(SubmissionState::Idle)))
+    }
+
+    fn get_guard(&self) -> MutexGuard<SubmissionState> {
+        let Self(mutex) = self;
+
+        match mutex.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn in_flight(&self) -> bool {
+        *self.get_guard() == SubmissionState::InFlight
+    }
+
+    /// Start a login attempt, which is resolved when the returned guard is dropped. The attempts
+    /// are handled one by one on the event thread, so there is never another one in flight.
+    fn begin(&self) -> SubmissionGuard {
+        *self.get_guard() = SubmissionState::InFlight;
+        SubmissionGuard(self.clone())
+    }
+}
+
+struct SubmissionGuard(LoginFormSubmission);
+
+impl Drop for SubmissionGuard {
+    fn drop(&mut self) {
+        *self.0.get_guard() = SubmissionState::Idle;
+    }
 }
 
 /// All the different modes for input
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum InputMode {
     /// Using the env switcher widget
     Switcher,
@@ -114,62 +210,178 @@ enum InputMode {
     /// Typing within the Password input field
     Password,
 
+    /// Typing within the Domain input field
+    Domain,
+
     /// Nothing selected
     Normal,
 }
 
 impl InputMode {
-    /// Move to the next mode
-    fn next(&mut self, skip_switcher: bool) {
-        use InputMode::*;
-
-        *self = match self {
-            Normal => {
-                if skip_switcher {
-                    Username
-                } else {
-                    Switcher
-                }
-            }
-            Switcher => Username,
-            Username => Password,
-            Password => Password,
+    /// All modes in the order in which they are cycled through
+    fn cycle(skip_switcher: bool, fields: &[InputMode]) -> Vec<InputMode> {
+        let mut modes = vec![InputMode::Normal];
+        if !skip_switcher {
+            modes.push(InputMode::Switcher);
         }
+        modes.extend_from_slice(fields);
+        modes
+    }
+
+    /// Move to the next mode
+    fn next(&mut self, skip_switcher: bool, fields: &[InputMode]) {
+        let modes = Self::cycle(skip_switcher, fields);
+
+        // A mode that is not cycled through (e.g. a hidden switcher) acts as `Normal`
+        let position = modes.iter().position(|mode| mode == self).unwrap_or(0);
+        *self = modes[(position + 1).min(modes.len() - 1)];
     }
 
     /// Move to the previous mode
-    fn prev(&mut self, skip_switcher: bool) {
-        use InputMode::*;
-
-        *self = match self {
-            Normal => Normal,
-            Switcher => Normal,
-            Username => {
-                if skip_switcher {
-                    Normal
-                } else {
-                    Switcher
-                }
+    fn prev(&mut self, skip_switcher: bool, fields: &[InputMode]) {
+        let modes = Self::cycle(skip_switcher, fields);
+
+        let position = modes.iter().position(|mode| mode == self).unwrap_or(0);
+        *self = modes[position.saturating_sub(1)];
+    }
+}
+
+/// The input fields in the order in which they are shown
+fn field_order(config: &Config) -> Vec<InputMode> {
+    let mut fields = Vec::new();
+
+    for name in &config.field_order {
+        let field = match name.trim() {
+            "username" => InputMode::Username,
+            "password" => InputMode::Password,
+            "domain" => InputMode::Domain,
+            name => {
+                warn!("Ignoring unknown field '{name}' in the field order");
+                continue;
             }
-            Password => Username,
+        };
+
+        if !fields.contains(&field) {
+            fields.push(field);
+        }
+    }
+
+    // Fields that were left out are shown at the end
+    for field in [InputMode::Domain, InputMode::Username, InputMode::Password] {
+        if !fields.contains(&field) {
+            fields.push(field);
         }
     }
+
+    if !config.domain_field.enabled {
+        fields.retain(|&field| field != InputMode::Domain);
+    }
+    if !config.username_field.visible {
+        fields.retain(|&field| field != InputMode::Username);
+    }
+
+    fields
+}
+
+/// The failed login attempts since the last successful login
+#[derive(Clone, Copy, Default)]
+struct FailedAttempts {
+    count: u32,
+    /// Seconds since the UNIX epoch of the last failed attempt
+    last_failed: Option<u64>,
+}
+
+impl FailedAttempts {
+    fn from_cache(cached: &CachedInfo) -> Self {
+        Self {
+            count: cached.failed_attempts(),
+            last_failed: cached.last_failed_attempt(),
+        }
+    }
+
+    /// Register a new failed attempt
+    fn increment(self) -> Self {
+        Self {
+            count: self.count.saturating_add(1),
+            last_failed: now_as_secs(),
+        }
+    }
+
+    /// Returns the amount of seconds remaining if logging in is currently locked out
+    fn lockout_remaining(&self, config: &FailedAttemptsConfig) -> Option<u64> {
+        if config.lockout_threshold == 0 || self.count < u32::from(config.lockout_threshold) {
+            return None;
+        }
+
+        let lockout_end = self.last_failed? + u64::from(config.lockout_secs);
+        let now = now_as_secs()?;
+
+        lockout_end
+            .checked_sub(now)
+            .filter(|&remaining| remaining > 0)
+    }
 }
 
 enum UIThreadRequest {
     Redraw,
+    ShowSessionSummary(SessionSummaryWidget),
+    ApplyTheme(ThemeVariant),
     DisableTui,
     EnableTui,
+    Resumed,
+    VtShown,
+    CopyToClipboard(String),
+    /// Redraw and save the drawn screen to files
+    Screenshot,
+    /// Redraw, unless a session is running
+    Refresh,
     StopDrawing,
 }
 
+/// How often is checked whether the system was suspended
+const RESUME_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The time that the system spent suspended since it booted. The boot time clock keeps counting
+/// while the system is suspended, but the monotonic clock does not.
+fn suspended_time() -> Option<Duration> {
+    let clock = |clock_id| {
+        let mut time = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+
+        (unsafe { libc::clock_gettime(clock_id, &mut time) } == 0)
+            .then(|| Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+    };
+
+    clock(libc::CLOCK_BOOTTIME)?.checked_sub(clock(libc::CLOCK_MONOTONIC)?)
+}
+
+/// Put the terminal in the state for drawing the login form. This resets all of it, so it also
+/// repairs a terminal that was garbled in the meantime.
+fn enable_tui(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    cursor_config: &CursorConfig,
+) -> io::Result<()> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    if let Some(cursor_style) = cursor_config.cursor_style() {
+        execute!(stdout, cursor_style)?;
+    }
+    terminal.clear()
+}
+
 #[derive(Clone)]
 struct Widgets {
     background: BackgroundWidget,
     key_menu: KeyMenuWidget,
+    command_widgets: CommandWidgets,
+    network: NetworkIndicator,
     environment: Arc<Mutex<SwitcherWidget<PostLoginEnvironment>>>,
     username: Arc<Mutex<InputFieldWidget>>,
     password: Arc<Mutex<InputFieldWidget>>,
+    domain: Arc<Mutex<InputFieldWidget>>,
 }
 
 impl Widgets {
@@ -200,14 +412,33 @@ impl Widgets {
             }
         }
     }
+    fn domain_guard(&self) -> MutexGuard<InputFieldWidget> {
+        match self.domain.lock() {
+            Ok(guard) => guard,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// The input field widget that belongs to an input mode
+    fn field(&self, mode: InputMode) -> Option<Arc<Mutex<InputFieldWidget>>> {
+        match mode {
+            InputMode::Username => Some(self.username.clone()),
+            InputMode::Password => Some(self.password.clone()),
+            InputMode::Domain => Some(self.domain.clone()),
+            InputMode::Switcher | InputMode::Normal => None,
+        }
+    }
 
     fn get_environment(&self) -> Option<(String, PostLoginEnvironment)> {
         self.environment_guard()
             .selected()
             .map(|s| (s.title.clone(), s.content.clone()))
     }
-    fn environment_try_select(&self, title: &str) {
-        self.environment_guard().try_select(title);
+    fn environment_try_select(&self, title: &str) -> bool {
+        self.environment_guard().try_select(title)
     }
     fn get_username(&self) -> String {
         self.username_guard().get_content()
@@ -215,12 +446,52 @@ impl Widgets {
     fn set_username(&self, content: &str) {
         self.username_guard().set_content(content)
     }
+    /// Complete the username to one of `users`. Returns whether it was changed.
+    fn complete_username(&self, users: &[String]) -> bool {
+        let Some(completion) = complete_username(&self.get_username(), users) else {
+            return false;
+        };
+
+        self.set_username(&completion);
+        true
+    }
     fn get_password(&self) -> String {
         self.password_guard().get_content()
     }
     fn clear_password(&self) {
         self.password_guard().clear()
     }
+
+    /// The username that is given to PAM. This includes the domain if the domain field is used.
+    fn get_login_username(&self, config: &DomainFieldConfig) -> String {
+        let username = self.get_username();
+        if !config.enabled {
+            return username;
+        }
+
+        let domain = self.domain_guard().get_content();
+        if domain.is_empty() {
+            return username;
+        }
+
+        config
+            .username_format
+            .replace("{username}", &username)
+            .replace("{domain}", &domain)
+    }
+
+    /// Restyle the widgets with the palette of `variant` on top of the colors of `config`
+    fn set_theme(&mut self, config: &Config, variant: ThemeVariant) {
+        let mut config = config.clone();
+        apply_theme(&mut config, variant);
+
+        self.background = BackgroundWidget::new(config.background);
+        self.username_guard().set_style(config.username_field.style);
+        self.password_guard().set_style(config.password_field.style);
+        self.domain_guard().set_style(config.domain_field.style);
+        self.environment_guard()
+            .set_config(config.environment_switcher);
+    }
 }
 
 /// App holds the state of the application
@@ -228,6 +499,8 @@ impl Widgets {
 pub struct LoginForm {
     /// Whether the application is running in preview mode
     preview: bool,
+    /// Whether the application is running inside of a compositor instead of on a TTY
+    windowed: bool,
 
     widgets: Widgets,
 
@@ -256,77 +529,150 @@ impl LoginForm {
             .remember
             .then_some(self.widgets.get_username());
 
+        // Only remember the scaling when the user could have chosen it
+        let hidpi = self
+            .widgets
+            .key_menu
+            .can_toggle_hidpi()
+            .then(|| self.widgets.key_menu.hidpi_enabled());
+        let display_layout = self
+            .widgets
+            .key_menu
+            .can_choose_display_layout()
+            .then(|| self.widgets.key_menu.display_layout());
+
         info!("Setting cached information");
-        set_cache(selected_env.as_deref(), username.as_deref(), &self.config);
+        set_cache(
+            selected_env.as_deref(),
+            username.as_deref(),
+            hidpi,
+            display_layout.as_deref(),
+            &self.config,
+        );
     }
 
-    fn load_cache(&self) {
-        let env_remember = self.config.environment_switcher.remember;
+    /// Load the cache and prefill the username. A provisioned username takes precedence over the
+    /// cached username.
+    fn load_cache(&self, provisioned: &Provisioned) -> CachedInfo {
         let username_remember = self.config.username_field.remember;
 
         let cached = get_cached_information(&self.config);
 
-        if username_remember {
-            if let Some(username) = cached.username() {
+        let username = if let Some(username) = provisioned.username.as_deref() {
+            info!("Loading provisioned username '{}'", username);
+            Some(username)
+        } else if username_remember {
+            let username = cached.username();
+            if let Some(username) = username {
                 info!("Loading username '{}' from cache", username);
-                self.widgets.set_username(username);
             }
-        }
-        if env_remember {
-            if let Some(env) = cached.environment() {
-                info!("Loading environment '{}' from cache", env);
-                self.widgets.environment_try_select(env);
+            username
+        } else {
+            None
+        };
+
+        if let Some(username) = username {
+            self.widgets.set_username(username);
+
+            if let Some(hidpi) = cached.user(username).and_then(|user| user.hidpi()) {
+                info!("Loading HiDPI scaling from cache");
+                self.widgets.key_menu.set_hidpi(hidpi);
+            }
+            if let Some(layout) = cached.user(username).and_then(|user| user.display_layout()) {
+                info!("Loading display layout from cache");
+                self.widgets.key_menu.set_display_layout(layout);
             }
         }
+
+        cached
+    }
+
+    /// The environment that should be selected once the environments are loaded
+    fn cached_environment(&self, cached: &CachedInfo) -> Option<String> {
+        if !self.config.environment_switcher.remember {
+            return None;
+        }
+
+        // Prefer the environment that was last used by the cached user
+        cached
+            .username()
+            .and_then(|username| cached.user(username))
+            .and_then(|user_info| user_info.environment())
+            .or(cached.environment())
+            .map(str::to_string)
     }
 
     pub fn new(config: Config, preview: bool) -> LoginForm {
         LoginForm {
             preview,
+            windowed: false,
             widgets: Widgets {
                 background: BackgroundWidget::new(config.background.clone()),
                 key_menu: KeyMenuWidget::new(
                     config.power_controls.clone(),
                     config.environment_switcher.clone(),
+                    config.hidpi.clone(),
+                    config.recovery_shell.clone(),
+                    config.display_layout.clone(),
                 ),
-                environment: Arc::new(Mutex::new(SwitcherWidget::new(
-                    crate::post_login::get_envs(&config)
-                        .into_iter()
-                        .map(|(title, content)| SwitcherItem::new(title, content))
-                        .collect(),
+                command_widgets: CommandWidgets::new(config.command_widgets.0.clone()),
+                network: NetworkIndicator::new(config.network.clone()),
+                environment: Arc::new(Mutex::new(SwitcherWidget::loading(
                     config.environment_switcher.clone(),
                 ))),
-                username: Arc::new(Mutex::new(InputFieldWidget::new(
-                    InputFieldDisplayType::Echo,
-                    config.username_field.style.clone(),
-                    String::default(),
-                ))),
-                password: Arc::new(Mutex::new(InputFieldWidget::new(
-                    InputFieldDisplayType::Replace(
-                        config
-                            .password_field
-                            .content_replacement_character
-                            .to_string(),
-                    ),
-                    config.password_field.style.clone(),
-                    String::default(),
-                ))),
+                username: Arc::new(Mutex::new(
+                    InputFieldWidget::new(
+                        InputFieldDisplayType::Echo,
+                        config.username_field.style.clone(),
+                        String::default(),
+                    )
+                    .with_cursor(config.cursor.is_visible()),
+                )),
+                password: Arc::new(Mutex::new(
+                    InputFieldWidget::new(
+                        InputFieldDisplayType::Replace(
+                            config
+                                .password_field
+                                .content_replacement_character
+                                .to_string(),
+                        ),
+                        config.password_field.style.clone(),
+                        String::default(),
+                    )
+                    .with_cursor(config.cursor.is_visible()),
+                )),
+                domain: Arc::new(Mutex::new(
+                    InputFieldWidget::new(
+                        InputFieldDisplayType::Echo,
+                        config.domain_field.style.clone(),
+                        String::default(),
+                    )
+                    .with_cursor(config.cursor.is_visible()),
+                )),
             },
             config,
         }
     }
 
-    pub fn run(self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
-        self.load_cache();
+    /// Run inside of a compositor, where taps arrive as mouse clicks of the terminal emulator
+    pub fn windowed(mut self, windowed: bool) -> Self {
+        self.windowed = windowed;
+        self
+    }
+
+    pub fn run(mut self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
+        let provisioned = Provisioned::read();
+        let cached = self.load_cache(&provisioned);
+        let cached_environment = self.cached_environment(&cached);
+        let initial_failed_attempts = FailedAttempts::from_cache(&cached);
         let input_mode = LoginFormInputMode::new(match self.config.focus_behaviour {
             FocusBehaviour::FirstNonCached => match (
-                self.config.username_field.remember && !self.widgets.get_username().is_empty(),
-                self.config.environment_switcher.remember
-                    && self
-                        .widgets
-                        .get_environment()
-                        .map(|(title, _)| !title.is_empty())
-                        .unwrap_or(false),
+                !self.widgets.get_username().is_empty(),
+                cached_environment
+                    .as_ref()
+                    .is_some_and(|title| !title.is_empty())
+                    || !self.config.environment_switcher.default.is_empty()
+                    || provisioned.session.is_some(),
             ) {
                 (true, true) => InputMode::Password,
                 (true, _) => InputMode::Username,
@@ -346,24 +692,78 @@ impl LoginForm {
             FocusBehaviour::Password => InputMode::Password,
         });
         let status_message = LoginFormStatusMessage::new();
-        let background = self.widgets.background.clone();
+        let submission = LoginFormSubmission::new();
+
+        let theme_variant = ThemeVariant::current(&self.config.theme);
+        if let Some(variant) = theme_variant {
+            info!("Using the {variant:?} theme");
+            self.widgets.set_theme(&self.config, variant);
+        }
+        let mut theme_widgets = self.widgets.clone();
+        let theme_config = self.config.clone();
+
+        // The inspector shows the colors with the theme applied
+        let inspector = Inspector::new();
+        let mut inspected_config = self.config.clone();
+        if let Some(variant) = theme_variant {
+            apply_theme(&mut inspected_config, variant);
+        }
+
+        let mut background = self.widgets.background.clone();
         let key_menu = self.widgets.key_menu.clone();
+        let command_widgets = self.widgets.command_widgets.clone();
+        let network = self.widgets.network.clone();
         let environment = self.widgets.environment.clone();
-        let username = self.widgets.username.clone();
-        let password = self.widgets.password.clone();
+        let fields = field_order(&self.config);
+
+        // Hidden fields cannot be focused
+        let initial_mode = input_mode.get();
+        if self.widgets.field(initial_mode).is_some() && !fields.contains(&initial_mode) {
+            input_mode.set(fields.first().copied().unwrap_or(InputMode::Normal));
+        }
+        let field_widgets = fields
+            .iter()
+            .filter_map(|&field| Some((field, self.widgets.field(field)?)))
+            .collect::<Vec<_>>();
+
+        let show_key_menu = self.config.power_controls.visible;
+        let show_switcher_comment = self.config.environment_switcher.show_comments;
+        let show_status_message = self.config.status_message.visible;
+        let layout_config = self.config.layout.clone();
+        let status_message_config = self.config.status_message.clone();
+        let cursor_config = self.config.cursor.clone();
+        let show_pin_pad = self.config.touch.enabled && self.config.touch.pin_pad;
+        let pin_pad = PinPadWidget::new(&self.config.touch);
+        let touch_targets = TouchTargets::default();
+        let field_modes = field_widgets
+            .iter()
+            .map(|(mode, _)| *mode)
+            .collect::<Vec<_>>();
+        let chunks_layout = || ChunksLayout {
+            key_menu: show_key_menu,
+            switcher: environment.lock().is_ok_and(|switcher| !switcher.hidden()),
+            switcher_comment: show_switcher_comment,
+            field_count: field_widgets.len(),
+            status_message: show_status_message,
+            pin_pad: show_pin_pad,
+        };
 
         let draw_action = terminal.draw(|f| {
-            let layout = Chunks::new(f);
+            let layout = Chunks::new(f, &chunks_layout(), &layout_config);
+            touch_targets.update(&layout, &field_modes);
             login_form_render(
                 f,
                 layout,
                 background.clone(),
                 key_menu.clone(),
+                &command_widgets,
+                &network,
                 environment.clone(),
-                username.clone(),
-                password.clone(),
-                input_mode.get(),
+                &field_widgets,
+                login_input_mode(&input_mode, &submission),
                 status_message.get(),
+                &status_message_config,
+                &pin_pad,
             );
         });
 
@@ -373,9 +773,193 @@ impl LoginForm {
         }
 
         let event_input_mode = input_mode.clone();
+        let event_fields = fields.clone();
         let event_status_message = status_message.clone();
+        let event_submission = submission.clone();
+        let event_inspector = inspector.clone();
+        let event_scripts = EventScripts::new(self.config.event_scripts.clone());
 
         let (req_send_channel, req_recv_channel) = channel();
+
+        // Load the environments in the background, so the form can be shown immediately.
+        let loader_widgets = self.widgets.clone();
+        let loader_config = self.config.clone();
+        let loader_send_channel = req_send_channel.clone();
+        std::thread::spawn(move || {
+            let envs = crate::post_login::get_envs(&loader_config)
+                .into_iter()
+                .map(|entry| {
+                    SwitcherItem::new(entry.name, entry.post_login_env)
+                        .with_subtitle(entry.comment)
+                        .with_hidden(entry.hidden)
+                })
+                .collect();
+
+            loader_widgets.environment_guard().set_items(envs);
+
+            // A provisioned session and the configured default take precedence over the cache.
+            // The fallback is used when the cached environment no longer exists.
+            let switcher_config = &loader_config.environment_switcher;
+            let selections = [
+                provisioned.session.map(|env| ("provisioning", env)),
+                Some(("the default", switcher_config.default.clone())),
+                cached_environment.map(|env| ("cache", env)),
+                Some(("the fallback", switcher_config.fallback.clone())),
+            ];
+            for (source, env) in selections.into_iter().flatten() {
+                if env.is_empty() {
+                    continue;
+                }
+
+                info!("Loading environment '{}' from {}", env, source);
+                if loader_widgets.environment_try_select(&env) {
+                    break;
+                }
+            }
+
+            if let Err(err) = loader_send_channel.send(UIThreadRequest::Redraw) {
+                warn!("Failed to send UI request. Reason: {}", err);
+            }
+        });
+        // Swap the palette when one of the switch times passes
+        if let Some(mut variant) = theme_variant {
+            let theme_send_channel = req_send_channel.clone();
+            let theme = self.config.theme.clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(THEME_CHECK_INTERVAL);
+
+                let Some(current) = ThemeVariant::current(&theme) else {
+                    continue;
+                };
+
+                if current != variant {
+                    variant = current;
+
+                    if let Err(err) = theme_send_channel.send(UIThreadRequest::ApplyTheme(variant))
+                    {
+                        warn!("Failed to send UI request. Reason: {}", err);
+                        break;
+                    }
+                }
+            });
+        }
+        // The console can be garbled after the system was suspended
+        let resume_send_channel = req_send_channel.clone();
+        std::thread::spawn(move || {
+            let mut suspended = suspended_time();
+            loop {
+                std::thread::sleep(RESUME_CHECK_INTERVAL);
+
+                let previously_suspended = suspended;
+                suspended = suspended_time();
+
+                let (Some(before), Some(after)) = (previously_suspended, suspended) else {
+                    continue;
+                };
+
+                if after.saturating_sub(before) > RESUME_CHECK_INTERVAL {
+                    info!(
+                        "Resumed after a suspend of {} seconds",
+                        (after - before).as_secs()
+                    );
+
+                    if let Err(err) = resume_send_channel.send(UIThreadRequest::Resumed) {
+                        warn!("Failed to send UI request. Reason: {}", err);
+                        break;
+                    }
+                }
+            }
+        });
+        // Draw the output of the command widgets once it changes
+        let widget_send_channel = req_send_channel.clone();
+        command_widgets.spawn(&self.config.system_shell, move || match widget_send_channel
+            .send(UIThreadRequest::Refresh)
+        {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Failed to send UI request. Reason: {}", err);
+                false
+            }
+        });
+        // Draw the network status once it changes
+        let network_send_channel = req_send_channel.clone();
+        network.spawn(
+            move || match network_send_channel.send(UIThreadRequest::Refresh) {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!("Failed to send UI request. Reason: {}", err);
+                    false
+                }
+            },
+        );
+        // Show the messages that other programs send to the notification socket
+        if !self.config.notifications.socket_path.is_empty() {
+            let socket_path = self
+                .config
+                .notifications
+                .socket_path
+                .replace("%tty%", &self.config.tty.to_string());
+            let default_timeout =
+                Duration::from_secs(self.config.notifications.default_timeout_secs.into());
+            let notify_status_message = status_message.clone();
+            let notify_send_channel = req_send_channel.clone();
+
+            let listening = notifications::listen(
+                Path::new(&socket_path),
+                default_timeout,
+                move |notification| {
+                    let id = notification.id;
+                    let timeout = notification.timeout;
+
+                    notify_status_message.set(StatusMessage::Notification(notification));
+                    if let Err(err) = notify_send_channel.send(UIThreadRequest::Refresh) {
+                        warn!("Failed to send UI request. Reason: {}", err);
+                    }
+
+                    if let Some(timeout) = timeout {
+                        let status_message = notify_status_message.clone();
+                        let send_channel = notify_send_channel.clone();
+                        std::thread::spawn(move || {
+                            std::thread::sleep(timeout);
+                            status_message.clear_notification(id);
+                            let _ = send_channel.send(UIThreadRequest::Refresh);
+                        });
+                    }
+                },
+            );
+            if let Err(err) = listening {
+                warn!("Not listening for notifications on '{socket_path}'. Reason: {err}");
+            }
+        }
+        // Pasting into the fields is meant for trying out the login form in a terminal emulator
+        if self.preview {
+            execute!(io::stdout(), EnableBracketedPaste)?;
+        }
+        // Terminal emulators report taps as mouse clicks. On the console, they are read from the
+        // touchscreen itself.
+        let mouse_capture = self.config.touch.enabled && (self.preview || self.windowed);
+        if mouse_capture {
+            execute!(io::stdout(), EnableMouseCapture)?;
+        }
+        let input_events = InputEvents::new(&self.config.touch, !self.preview && !self.windowed);
+        let event_touch_targets = touch_targets.clone();
+
+        // Stop drawing while another VT is shown, and redraw everything once lemurs is shown again.
+        // In windowed mode, the compositor owns the VT.
+        let preview = self.preview;
+        let vt_send_channel = req_send_channel.clone();
+        let vt_control = if preview || self.windowed {
+            None
+        } else {
+            VtControl::take(move || {
+                if let Err(err) = vt_send_channel.send(UIThreadRequest::VtShown) {
+                    warn!("Failed to send UI request. Reason: {}", err);
+                }
+            })
+            .map(Arc::new)
+        };
+        let event_vt_control = vt_control.clone();
+        let ui_send_channel = req_send_channel.clone();
         std::thread::spawn(move || {
             let mut switcher_hidden = self
                 .widgets
@@ -384,7 +968,16 @@ impl LoginForm {
                 .expect("Failed to grab environment lock")
                 .hidden();
             let input_mode = event_input_mode;
+            let fields = event_fields;
             let status_message = event_status_message;
+            let submission = event_submission;
+            let inspector = event_inspector;
+            let failed_attempts = Cell::new(initial_failed_attempts);
+            // The user that was warned about logging in without a network connection
+            let mut warned_offline = None;
+            let mut duress = DuressSequence::new(&self.config.duress);
+            // The users that usernames are completed to
+            let users = get_users(&self.config.users);
 
             let send_ui_request = |request: UIThreadRequest| match req_send_channel.send(request) {
                 Ok(_) => {}
@@ -397,15 +990,29 @@ impl LoginForm {
                 status_message.set(InfoStatusMessage::Authenticating);
                 send_ui_request(UIThreadRequest::Redraw);
             };
-            let pre_environment = || {
-                // Remember username and environment for next time
-                self.set_cache();
-
+            let leave_tui = || {
                 status_message.set(InfoStatusMessage::LoggingIn);
                 send_ui_request(UIThreadRequest::Redraw);
 
                 // Disable the rendering of the login manager
                 send_ui_request(UIThreadRequest::DisableTui);
+
+                // The session switches VTs without lemurs
+                if let Some(vt_control) = &event_vt_control {
+                    vt_control.pause();
+                }
+            };
+            let pre_environment = || {
+                // Remember username and environment for next time
+                self.set_cache();
+
+                // A successful login resets the failed attempts
+                if failed_attempts.get().count > 0 {
+                    failed_attempts.set(FailedAttempts::default());
+                    set_failed_attempts(0, &self.config);
+                }
+
+                leave_tui();
             };
             let pre_return = || {
                 // Enable the rendering of the login manager
@@ -423,11 +1030,160 @@ impl LoginForm {
                 pre_return: Some(&pre_return),
             };
 
+            // The recovery shell is not remembered and does not reset the failed attempts
+            let recovery_hooks = Hooks {
+                pre_validate: None,
+                pre_auth: Some(&pre_auth),
+                pre_environment: Some(&leave_tui),
+                pre_wait: None,
+                pre_return: Some(&pre_return),
+            };
+
+            let is_locked_out = |config: &Config| {
+                let Some(remaining_secs) = failed_attempts
+                    .get()
+                    .lockout_remaining(&config.failed_attempts)
+                else {
+                    return false;
+                };
+
+                info!("Login attempt while locked out for another {remaining_secs} seconds");
+                self.widgets.clear_password();
+                status_message.set(ErrorStatusMessage::LockedOut(remaining_secs));
+                send_ui_request(UIThreadRequest::Redraw);
+                true
+            };
+            let run_event_scripts = |event: ScriptEvent| {
+                let status_message = status_message.clone();
+                let script_send_channel = req_send_channel.clone();
+                event_scripts.dispatch(event, move |message| {
+                    status_message.set(StatusMessage::Script(message));
+                    if let Err(err) = script_send_channel.send(UIThreadRequest::Redraw) {
+                        warn!("Failed to send UI request. Reason: {}", err);
+                    }
+                });
+            };
+            let report_session_error =
+                |err: StartSessionError, username: &str, config: &Config| match err {
+                    StartSessionError::AuthenticationError(err) => {
+                        run_event_scripts(ScriptEvent::AuthFailure {
+                            username: username.to_string(),
+                        });
+
+                        let is_invalid_credentials =
+                            matches!(err, AuthenticationError::AccountValidation);
+
+                        if is_invalid_credentials {
+                            let new_failed_attempts = failed_attempts.get().increment();
+                            failed_attempts.set(new_failed_attempts);
+                            set_failed_attempts(new_failed_attempts.count, config);
+                        }
+
+                        let count = failed_attempts.get().count;
+                        if is_invalid_credentials
+                            && config.failed_attempts.show_counter
+                            && count > 0
+                        {
+                            status_message.set(ErrorStatusMessage::FailedAttempts(count));
+                        } else {
+                            status_message.set(ErrorStatusMessage::from(err));
+                        }
+                        send_ui_request(UIThreadRequest::Redraw);
+                    }
+                    StartSessionError::EnvironmentStartError(err) => {
+                        error!(
+                            "Starting post-login environment failed. Reason: '{}'",
+                            err.with_code()
+                        );
+                        send_ui_request(UIThreadRequest::EnableTui);
+
+                        status_message.set(match err {
+                            EnvironmentStartError::NoInteractiveShell => {
+                                ErrorStatusMessage::NoInteractiveShell
+                            }
+                            EnvironmentStartError::MissingExecutable(program) => {
+                                ErrorStatusMessage::MissingExecutable(program)
+                            }
+                            err => ErrorStatusMessage::FailedGraphicalEnvironment(err),
+                        });
+                        send_ui_request(UIThreadRequest::Redraw);
+                    }
+                };
+
+            let paste = |text: &str| {
+                if let Some(field) = self.widgets.field(input_mode.get()) {
+                    field
+                        .lock()
+                        .unwrap_or_else(|err| {
+                            error!("Failed to lock input field. Reason: {}", err);
+                            std::process::exit(1);
+                        })
+                        .paste(text);
+                }
+            };
+
             loop {
-                if let Ok(Event::Key(key)) = event::read() {
+                let mut event = input_events.read();
+
+                // A tap on a field focuses it, and a tap on the PIN pad is handled as its key
+                if let Ok(Event::Mouse(mouse)) = &event {
+                    if mouse.kind != MouseEventKind::Down(MouseButton::Left) {
+                        continue;
+                    }
+
+                    match event_touch_targets.at(mouse.column, mouse.row) {
+                        Some(TouchTarget::Field(mode)) => {
+                            input_mode.set(mode);
+                            send_ui_request(UIThreadRequest::Redraw);
+                            continue;
+                        }
+                        Some(TouchTarget::Key(code)) => {
+                            let unfocused =
+                                matches!(input_mode.get(), InputMode::Normal | InputMode::Switcher);
+                            if unfocused && fields.contains(&InputMode::Password) {
+                                input_mode.set(InputMode::Password);
+                            }
+                            event = Ok(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+                        }
+                        None => continue,
+                    }
+                }
+
+                // Only terminal emulators send pasted text, so this only happens in preview mode
+                if let Ok(Event::Paste(text)) = &event {
+                    paste(text);
+                }
+
+                if let Ok(Event::Key(key)) = event {
+                    // The key that completes the sequence is not handled otherwise, and the
+                    // screen stays as it is
+                    if duress.key_press(&key) {
+                        duress.trigger(&self.config.system_shell, self.preview);
+                        continue;
+                    }
+
+                    let selected = self.widgets.get_environment().map(|(title, _)| title);
+
                     match (key.code, input_mode.get(), key.modifiers) {
-                        (KeyCode::Enter, InputMode::Password, _) => {
+                        (INSPECTOR_KEY, _, _) if self.preview => inspector.toggle(),
+                        (COPY_KEY, _, _) if inspector.is_shown() => {
+                            if let Some(toml) = inspector.inspected_toml() {
+                                send_ui_request(UIThreadRequest::CopyToClipboard(toml));
+                            }
+                        }
+                        (k, _, _) if inspector.is_shown() && inspector.move_cursor(k) => {}
+                        (KeyCode::Char('s'), _, KeyModifiers::CONTROL) if self.preview => {
+                            send_ui_request(UIThreadRequest::Screenshot);
+                            continue;
+                        }
+
+                        (KeyCode::Enter, mode @ (InputMode::Password | InputMode::Username), _)
+                            if matches!(mode, InputMode::Password)
+                                || self.config.password_field.allow_empty =>
+                        {
                             if self.preview {
+                                let _submission = submission.begin();
+
                                 // This is only for demonstration purposes
                                 status_message.set(InfoStatusMessage::Authenticating);
                                 send_ui_request(UIThreadRequest::Redraw);
@@ -438,13 +1194,17 @@ impl LoginForm {
                                 std::thread::sleep(Duration::from_secs(2));
 
                                 status_message.clear();
-                                send_ui_request(UIThreadRequest::Redraw);
+                                input_events.discard_pending();
                             } else {
                                 let environment =
                                     self.widgets.get_environment().map(|(_, content)| content);
-                                let username = self.widgets.get_username();
+                                let username =
+                                    self.widgets.get_login_username(&self.config.domain_field);
                                 let password = self.widgets.get_password();
-                                let config = self.config.clone();
+                                let mut config = self.config.clone();
+                                config.hidpi.enabled = self.widgets.key_menu.hidpi_enabled();
+                                config.display_layout.default =
+                                    self.widgets.key_menu.display_layout();
 
                                 let Some(post_login_env) = environment else {
                                     status_message.set(ErrorStatusMessage::NoGraphicalEnvironment);
@@ -452,45 +1212,98 @@ impl LoginForm {
                                     continue;
                                 };
 
-                                match start_session(
+                                if is_locked_out(&config) {
+                                    continue;
+                                }
+
+                                // Network accounts are warned once, as they can have cached
+                                // credentials
+                                if config.network.warn_offline
+                                    && self.widgets.network.status() != NetworkStatus::Connected
+                                    && !is_local_user(&username)
+                                    && warned_offline.as_ref() != Some(&username)
+                                {
+                                    info!("Warning '{username}' that the network is not connected");
+                                    warned_offline = Some(username);
+                                    status_message.set(ErrorStatusMessage::NetworkOffline);
+                                    send_ui_request(UIThreadRequest::Redraw);
+                                    continue;
+                                }
+
+                                let _submission = submission.begin();
+
+                                let result = start_session(
                                     &username,
                                     &password,
                                     &post_login_env,
                                     &hooks,
                                     &config,
-                                ) {
-                                    Ok(()) => {}
-                                    Err(StartSessionError::AuthenticationError(err)) => {
-                                        status_message
-                                            .set(ErrorStatusMessage::AuthenticationError(err));
-                                        send_ui_request(UIThreadRequest::Redraw);
-                                    }
-                                    Err(StartSessionError::EnvironmentStartError(err)) => {
-                                        error!(
-                                            "Starting post-login environment failed. Reason: '{}'",
-                                            err
-                                        );
-                                        send_ui_request(UIThreadRequest::EnableTui);
-
-                                        status_message
-                                            .set(ErrorStatusMessage::FailedGraphicalEnvironment);
-                                        send_ui_request(UIThreadRequest::Redraw);
+                                );
+                                input_events.discard_pending();
+
+                                match result {
+                                    Ok(summary) => {
+                                        if config.session.show_summary {
+                                            send_ui_request(UIThreadRequest::ShowSessionSummary(
+                                                SessionSummaryWidget::new(&summary),
+                                            ));
+
+                                            // Dismiss the summary after a timeout, a key press
+                                            // or a tap
+                                            let timeout = Duration::from_secs(
+                                                config.session.summary_timeout_secs.into(),
+                                            );
+                                            let _ = input_events.read_timeout(timeout);
+                                        }
                                     }
+                                    Err(err) => report_session_error(err, &username, &config),
                                 }
                             }
                         }
+                        (
+                            KeyCode::Char(c),
+                            InputMode::Normal,
+                            KeyModifiers::NONE | KeyModifiers::SHIFT,
+                        ) if self.config.focus_on_typing
+                            && c.is_alphanumeric()
+                            && fields.contains(&InputMode::Username) =>
+                        {
+                            input_mode.set(InputMode::Username);
+                            if let Some(status_msg) = self
+                                .widgets
+                                .username_guard()
+                                .key_press(key.code, key.modifiers)
+                            {
+                                status_message.set(status_msg);
+                            }
+                        }
                         (KeyCode::Char('s'), InputMode::Normal, _) => self.set_cache(),
 
+                        // Enter accepts the filter of the switcher and Esc cancels it
+                        (KeyCode::Enter | KeyCode::Esc, InputMode::Switcher, _)
+                            if self.widgets.environment_guard().is_filtering() =>
+                        {
+                            self.widgets
+                                .environment_guard()
+                                .stop_filter(key.code == KeyCode::Enter);
+                        }
+
+                        // Tab completes the username before it moves to the next field
+                        (KeyCode::Tab, InputMode::Username, KeyModifiers::NONE)
+                            if self.widgets.complete_username(&users) => {}
+
                         // On the TTY, it triggers the ALT key for some reason.
                         (KeyCode::Up | KeyCode::BackTab, _, _)
                         | (KeyCode::Tab, _, KeyModifiers::ALT | KeyModifiers::SHIFT)
                         | (KeyCode::Char('p'), _, KeyModifiers::CONTROL) => {
-                            input_mode.prev(switcher_hidden);
+                            self.widgets.environment_guard().stop_filter(true);
+                            input_mode.prev(switcher_hidden, &fields);
                         }
 
                         (KeyCode::Enter | KeyCode::Down | KeyCode::Tab, _, _)
                         | (KeyCode::Char('n'), _, KeyModifiers::CONTROL) => {
-                            input_mode.next(switcher_hidden);
+                            self.widgets.environment_guard().stop_filter(true);
+                            input_mode.next(switcher_hidden, &fields);
                         }
 
                         // Esc is the overal key to get out of your input mode
@@ -505,6 +1318,62 @@ impl LoginForm {
                             input_mode.set(InputMode::Normal);
                         }
 
+                        (k, _, _) if self.widgets.key_menu.is_recovery_key(k) => {
+                            if self.preview {
+                                info!("The recovery shell is not available in preview mode");
+                                continue;
+                            }
+
+                            let password = self.widgets.get_password();
+                            if password.is_empty() {
+                                status_message.set(ErrorStatusMessage::RecoveryNeedsPassword);
+                                send_ui_request(UIThreadRequest::Redraw);
+                                continue;
+                            }
+
+                            // Root is authenticated regardless of the policies for regular logins
+                            let mut config = self.config.clone();
+                            config.auth.allow_root = true;
+                            config.auth.allow_groups.clear();
+                            config.auth.deny_groups.clear();
+                            config.access.rules.0.clear();
+
+                            if is_locked_out(&config) {
+                                continue;
+                            }
+
+                            let _submission = submission.begin();
+
+                            warn!("Recovery shell requested on tty {}", config.tty);
+
+                            let shell = PostLoginEnvironment::Shell {
+                                command: None,
+                                hooks: ExecHooks::default(),
+                            };
+                            let result =
+                                start_session("root", &password, &shell, &recovery_hooks, &config);
+                            input_events.discard_pending();
+
+                            match result {
+                                Ok(summary) => match summary.exit_status {
+                                    Some(status) => {
+                                        warn!(
+                                            "Recovery shell on tty {} exited with {status}",
+                                            config.tty
+                                        )
+                                    }
+                                    None => warn!("Recovery shell on tty {} exited", config.tty),
+                                },
+                                Err(err) => {
+                                    warn!(
+                                        "Failed to open the recovery shell on tty {}",
+                                        config.tty
+                                    );
+                                    report_session_error(err, "root", &config);
+                                }
+                            }
+                        }
+
                         (KeyCode::F(_), _, _) => {
                             self.widgets.key_menu.key_press(key.code);
                             self.widgets.environment_guard().key_press(key.code);
@@ -517,7 +1386,13 @@ impl LoginForm {
                                 .hidden();
 
                             if matches!(input_mode.get(), InputMode::Switcher) && switcher_hidden {
-                                input_mode.next(true);
+                                input_mode.next(true, &fields);
+                            }
+                        }
+
+                        (KeyCode::Char('v'), _, KeyModifiers::CONTROL) if self.preview => {
+                            if let Some(text) = clipboard::paste() {
+                                paste(&text);
                             }
                         }
 
@@ -534,6 +1409,9 @@ impl LoginForm {
                                 InputMode::Password => {
                                     self.widgets.password_guard().key_press(k, modifiers)
                                 }
+                                InputMode::Domain => {
+                                    self.widgets.domain_guard().key_press(k, modifiers)
+                                }
                                 _ => None,
                             };
 
@@ -543,6 +1421,15 @@ impl LoginForm {
                             }
                         }
                     };
+
+                    if let Some(environment) = self
+                        .widgets
+                        .get_environment()
+                        .map(|(title, _)| title)
+                        .filter(|title| selected.as_ref() != Some(title))
+                    {
+                        run_event_scripts(ScriptEvent::SessionSelect { environment });
+                    }
                 }
 
                 send_ui_request(UIThreadRequest::Redraw);
@@ -552,100 +1439,190 @@ impl LoginForm {
         // Start the UI thread. This actually draws to the screen.
         //
         // This blocks until we actually call StopDrawing
+        let mut tui_enabled = true;
         while let Ok(request) = req_recv_channel.recv() {
             match request {
-                UIThreadRequest::Redraw => {
+                request @ (UIThreadRequest::Redraw | UIThreadRequest::Screenshot) => {
+                    if vt_control.as_ref().is_some_and(|vt| !vt.is_shown()) {
+                        continue;
+                    }
+
                     let draw_action = terminal.draw(|f| {
-                        let layout = Chunks::new(f);
+                        let layout = Chunks::new(f, &chunks_layout(), &layout_config);
+                        touch_targets.update(&layout, &field_modes);
+                        let inspected = inspector.is_shown().then(|| {
+                            inspected_parts(&layout, &field_modes, f.size(), &inspected_config)
+                        });
+
                         login_form_render(
                             f,
                             layout,
                             background.clone(),
                             key_menu.clone(),
+                            &command_widgets,
+                            &network,
                             environment.clone(),
-                            username.clone(),
-                            password.clone(),
-                            input_mode.get(),
+                            &field_widgets,
+                            login_input_mode(&input_mode, &submission),
                             status_message.get(),
+                            &status_message_config,
+                            &pin_pad,
                         );
+
+                        if let Some(parts) = inspected {
+                            inspector.render(f, parts);
+                        }
                     });
 
-                    if let Err(err) = draw_action {
+                    match draw_action {
+                        Ok(frame) if matches!(request, UIThreadRequest::Screenshot) => {
+                            match screenshot::save(frame.buffer) {
+                                Ok(paths) => info!("Saved a screenshot to {paths:?}"),
+                                Err(err) => warn!("Failed to save a screenshot. Reason: {err}"),
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(err) => warn!("Failed to draw to screen. Reason: {err}"),
+                    }
+                }
+                UIThreadRequest::CopyToClipboard(text) => match clipboard::copy(&text) {
+                    Ok(()) => info!("Copied the inspected options to the clipboard"),
+                    Err(err) => warn!("Failed to copy to the clipboard. Reason: {err}"),
+                },
+                UIThreadRequest::ShowSessionSummary(summary) => {
+                    if let Err(err) = terminal.draw(|f| summary.render(f)) {
                         warn!("Failed to draw to screen. Reason: {err}");
                     }
                 }
+                UIThreadRequest::ApplyTheme(variant) => {
+                    info!("Switching to the {variant:?} theme");
+                    theme_widgets.set_theme(&theme_config, variant);
+                    inspected_config = theme_config.clone();
+                    apply_theme(&mut inspected_config, variant);
+                    background = theme_widgets.background.clone();
+
+                    // Do not draw over a running session
+                    if tui_enabled {
+                        let _ = ui_send_channel.send(UIThreadRequest::Redraw);
+                    }
+                }
+                UIThreadRequest::Refresh => {
+                    // Do not draw over a running session
+                    if tui_enabled {
+                        let _ = ui_send_channel.send(UIThreadRequest::Redraw);
+                    }
+                }
                 UIThreadRequest::DisableTui => {
+                    tui_enabled = false;
+                    if mouse_capture {
+                        execute!(terminal.backend_mut(), DisableMouseCapture)?;
+                    }
                     disable_raw_mode()?;
                     execute!(
                         terminal.backend_mut(),
                         LeaveAlternateScreen,
                         Clear(ClearType::All),
-                        MoveTo(0, 0)
+                        MoveTo(0, 0),
+                        SetCursorStyle::DefaultUserShape
                     )?;
                     terminal.show_cursor()?;
                 }
                 UIThreadRequest::EnableTui => {
-                    enable_raw_mode()?;
-                    let mut stdout = io::stdout();
-                    execute!(stdout, EnterAlternateScreen)?;
-                    terminal.clear()?;
+                    tui_enabled = true;
+                    enable_tui(terminal, &cursor_config)?;
+                    if mouse_capture {
+                        execute!(terminal.backend_mut(), EnableMouseCapture)?;
+                    }
+
+                    if let Some(vt_control) = &vt_control {
+                        vt_control.resume();
+                    }
+                }
+                UIThreadRequest::Resumed | UIThreadRequest::VtShown => {
+                    // Do not draw over a running session
+                    if tui_enabled {
+                        info!("Reinitializing the terminal");
+                        enable_tui(terminal, &cursor_config)?;
+                        let _ = ui_send_channel.send(UIThreadRequest::Redraw);
+                    }
                 }
                 _ => break,
             }
         }
 
+        if let Some(vt_control) = &vt_control {
+            vt_control.pause();
+        }
+
+        if preview {
+            execute!(io::stdout(), DisableBracketedPaste)?;
+        }
+        if mouse_capture {
+            execute!(io::stdout(), DisableMouseCapture)?;
+        }
+
         Ok(())
     }
 }
 
+/// The input mode to render with. No field is focused whilst a login attempt is in flight.
+fn login_input_mode(
+    input_mode: &LoginFormInputMode,
+    submission: &LoginFormSubmission,
+) -> InputMode {
+    if submission.in_flight() {
+        InputMode::Normal
+    } else {
+        input_mode.get()
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn login_form_render<B: Backend>(
     frame: &mut Frame<B>,
     chunks: Chunks,
     background: BackgroundWidget,
     key_menu: KeyMenuWidget,
+    command_widgets: &CommandWidgets,
+    network: &NetworkIndicator,
     environment: Arc<Mutex<SwitcherWidget<PostLoginEnvironment>>>,
-    username: Arc<Mutex<InputFieldWidget>>,
-    password: Arc<Mutex<InputFieldWidget>>,
+    fields: &[(InputMode, Arc<Mutex<InputFieldWidget>>)],
     input_mode: InputMode,
     status_message: Option<StatusMessage>,
+    status_message_config: &StatusMessageConfig,
+    pin_pad: &PinPadWidget,
 ) {
     background.render(frame);
-    key_menu.render(frame, chunks.key_menu);
-    environment
-        .lock()
-        .unwrap_or_else(|err| {
-            error!("Failed to lock post-login environment. Reason: {}", err);
-            std::process::exit(1);
-        })
-        .render(
-            frame,
-            chunks.switcher,
-            matches!(input_mode, InputMode::Switcher),
-        );
-    username
-        .lock()
-        .unwrap_or_else(|err| {
-            error!("Failed to lock username. Reason: {}", err);
-            std::process::exit(1);
-        })
-        .render(
-            frame,
-            chunks.username_field,
-            matches!(input_mode, InputMode::Username),
-        );
-    password
-        .lock()
-        .unwrap_or_else(|err| {
-            error!("Failed to lock password. Reason: {}", err);
-            std::process::exit(1);
-        })
-        .render(
-            frame,
-            chunks.password_field,
-            matches!(input_mode, InputMode::Password),
-        );
+    if let Some(area) = chunks.key_menu {
+        key_menu.render(frame, area);
+        network.render(frame, area);
+    }
+    command_widgets.render(frame, &chunks);
+    if let Some(area) = chunks.switcher {
+        environment
+            .lock()
+            .unwrap_or_else(|err| {
+                error!("Failed to lock post-login environment. Reason: {}", err);
+                std::process::exit(1);
+            })
+            .render(frame, area, matches!(input_mode, InputMode::Switcher));
+    }
+    for ((mode, field), area) in fields.iter().zip(chunks.fields.iter()) {
+        field
+            .lock()
+            .unwrap_or_else(|err| {
+                error!("Failed to lock input field. Reason: {}", err);
+                std::process::exit(1);
+            })
+            .render(frame, *area, input_mode == *mode, chunks.compact);
+    }
 
     // Display Status Message
-    StatusMessage::render(status_message, frame, chunks.status_message);
+    if let Some(area) = chunks.status_message {
+        StatusMessage::render(status_message, frame, area, status_message_config);
+    }
+
+    if let Some(area) = chunks.pin_pad {
+        pin_pad.render(frame, area);
+    }
 }
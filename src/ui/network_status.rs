@@ -0,0 +1,115 @@
+//! An indicator of whether the machine is connected to a network
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{error, info};
+use ratatui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    style::Style,
+    text::Span,
+    widgets::Paragraph,
+    Frame,
+};
+
+use crate::config::{get_color, get_modifiers, NetworkConfig};
+use crate::network::NetworkStatus;
+
+/// The latest network status, which is shared between the clones
+#[derive(Clone)]
+pub struct NetworkIndicator {
+    config: NetworkConfig,
+    status: Arc<Mutex<NetworkStatus>>,
+}
+
+impl NetworkIndicator {
+    pub fn new(config: NetworkConfig) -> Self {
+        let status = Arc::new(Mutex::new(NetworkStatus::current()));
+        Self { config, status }
+    }
+
+    pub fn status(&self) -> NetworkStatus {
+        match self.status.lock() {
+            Ok(status) => *status,
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn set_status(&self, new_status: NetworkStatus) -> bool {
+        match self.status.lock() {
+            Ok(mut status) => {
+                let changed = *status != new_status;
+                *status = new_status;
+                changed
+            }
+            Err(err) => {
+                error!("Lock failed. Reason: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Check the network periodically in the background, if the status is used. `on_update` is
+    /// called when the status changes, and the network is no longer checked once it returns
+    /// `false`.
+    pub fn spawn(&self, on_update: impl Fn() -> bool + Send + 'static) {
+        if !self.config.show_indicator && !self.config.warn_offline {
+            return;
+        }
+
+        let indicator = self.clone();
+        let interval = Duration::from_secs(self.config.check_interval_secs.max(1).into());
+
+        info!("Network status is {:?}", self.status());
+
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+
+            let status = NetworkStatus::current();
+            if indicator.set_status(status) {
+                info!("Network status changed to {status:?}");
+                if !on_update() {
+                    break;
+                }
+            }
+        });
+    }
+
+    fn style(&self, status: NetworkStatus) -> Style {
+        let color = match status {
+            NetworkStatus::Connected => &self.config.hint_color,
+            NetworkStatus::NoAddress | NetworkStatus::Offline => &self.config.offline_hint_color,
+        };
+        let mut style = Style::default().fg(get_color(color));
+
+        for modifier in get_modifiers(&self.config.hint_modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        style
+    }
+
+    /// Draw the status at the right of `area`
+    pub fn render<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        if !self.config.show_indicator {
+            return;
+        }
+
+        let status = self.status();
+        let hint = match status {
+            NetworkStatus::Connected => &self.config.connected_hint,
+            NetworkStatus::NoAddress => &self.config.no_address_hint,
+            NetworkStatus::Offline => &self.config.offline_hint,
+        };
+
+        frame.render_widget(
+            Paragraph::new(Span::styled(hint.as_str(), self.style(status)))
+                .alignment(Alignment::Right),
+            area,
+        );
+    }
+}
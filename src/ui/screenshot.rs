@@ -0,0 +1,375 @@
+//! Screenshots of the preview as ANSI, HTML and SVG files, so that the look of a configuration can
+//! be shared without photographing a monitor.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ratatui::buffer::Buffer;
+use ratatui::style::{Color, Modifier};
+use unicode_width::UnicodeWidthStr;
+
+/// The colors of the Linux console for the default colors
+const DEFAULT_FG: (u8, u8, u8) = (0xaa, 0xaa, 0xaa);
+const DEFAULT_BG: (u8, u8, u8) = (0x00, 0x00, 0x00);
+
+/// The size of a cell in the SVG in pixels
+const CELL_WIDTH: u32 = 9;
+const CELL_HEIGHT: u32 = 18;
+const FONT_SIZE: u32 = 15;
+
+/// The 16 colors of the console in the order of their indices
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xaa, 0x00, 0x00),
+    (0x00, 0xaa, 0x00),
+    (0xaa, 0x55, 0x00),
+    (0x00, 0x00, 0xaa),
+    (0xaa, 0x00, 0xaa),
+    (0x00, 0xaa, 0xaa),
+    (0xaa, 0xaa, 0xaa),
+    (0x55, 0x55, 0x55),
+    (0xff, 0x55, 0x55),
+    (0x55, 0xff, 0x55),
+    (0xff, 0xff, 0x55),
+    (0x55, 0x55, 0xff),
+    (0xff, 0x55, 0xff),
+    (0x55, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// Text of a line with the same style, starting at column `x`
+struct Run {
+    x: u16,
+    width: u16,
+    fg: Color,
+    bg: Color,
+    modifier: Modifier,
+    text: String,
+}
+
+/// The runs of each line of `buffer`
+fn runs(buffer: &Buffer) -> Vec<Vec<Run>> {
+    let area = buffer.area;
+
+    (area.top()..area.bottom())
+        .map(|y| {
+            let mut runs: Vec<Run> = Vec::new();
+            let mut x = area.left();
+
+            while x < area.right() {
+                let cell = buffer.get(x, y);
+                // The cells that a wide character covers are not drawn
+                let width = (cell.symbol.width() as u16).max(1);
+
+                match runs.last_mut() {
+                    Some(run)
+                        if run.fg == cell.fg
+                            && run.bg == cell.bg
+                            && run.modifier == cell.modifier =>
+                    {
+                        run.width += width;
+                        run.text.push_str(&cell.symbol);
+                    }
+                    _ => runs.push(Run {
+                        x: x - area.left(),
+                        width,
+                        fg: cell.fg,
+                        bg: cell.bg,
+                        modifier: cell.modifier,
+                        text: cell.symbol.clone(),
+                    }),
+                }
+
+                x = x.saturating_add(width);
+            }
+
+            runs
+        })
+        .collect()
+}
+
+fn rgb(color: Color, default: (u8, u8, u8)) -> (u8, u8, u8) {
+    match color {
+        Color::Reset => default,
+        Color::Black => PALETTE[0],
+        Color::Red => PALETTE[1],
+        Color::Green => PALETTE[2],
+        Color::Yellow => PALETTE[3],
+        Color::Blue => PALETTE[4],
+        Color::Magenta => PALETTE[5],
+        Color::Cyan => PALETTE[6],
+        Color::Gray => PALETTE[7],
+        Color::DarkGray => PALETTE[8],
+        Color::LightRed => PALETTE[9],
+        Color::LightGreen => PALETTE[10],
+        Color::LightYellow => PALETTE[11],
+        Color::LightBlue => PALETTE[12],
+        Color::LightMagenta => PALETTE[13],
+        Color::LightCyan => PALETTE[14],
+        Color::White => PALETTE[15],
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(index @ 0..=15) => PALETTE[usize::from(index)],
+        Color::Indexed(index @ 16..=231) => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        Color::Indexed(index) => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// The foreground and the background of `run` in RGB, with reversed colors applied
+fn run_colors(run: &Run) -> ((u8, u8, u8), (u8, u8, u8)) {
+    let fg = rgb(run.fg, DEFAULT_FG);
+    let bg = rgb(run.bg, DEFAULT_BG);
+
+    if run.modifier.contains(Modifier::REVERSED) {
+        (bg, fg)
+    } else {
+        (fg, bg)
+    }
+}
+
+fn hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The buffer with the escape sequences of a terminal, e.g. for `cat`
+fn to_ansi(buffer: &Buffer) -> String {
+    let mut ansi = String::new();
+
+    for line in runs(buffer) {
+        for run in line {
+            let (fg, bg) = run_colors(&run);
+            let mut codes = format!(
+                "0;38;2;{};{};{};48;2;{};{};{}",
+                fg.0, fg.1, fg.2, bg.0, bg.1, bg.2
+            );
+            for (modifier, code) in [
+                (Modifier::BOLD, "1"),
+                (Modifier::DIM, "2"),
+                (Modifier::ITALIC, "3"),
+                (Modifier::UNDERLINED, "4"),
+                (Modifier::CROSSED_OUT, "9"),
+            ] {
+                if run.modifier.contains(modifier) {
+                    codes.push(';');
+                    codes.push_str(code);
+                }
+            }
+
+            let _ = write!(ansi, "\x1b[{codes}m{}", run.text);
+        }
+        ansi.push_str("\x1b[0m\n");
+    }
+
+    ansi
+}
+
+/// The CSS that styles a run
+fn css(run: &Run) -> String {
+    let (fg, bg) = run_colors(run);
+    let mut css = format!("color:{};background:{}", hex(fg), hex(bg));
+
+    if run.modifier.contains(Modifier::BOLD) {
+        css.push_str(";font-weight:bold");
+    }
+    if run.modifier.contains(Modifier::DIM) {
+        css.push_str(";opacity:0.6");
+    }
+    if run.modifier.contains(Modifier::ITALIC) {
+        css.push_str(";font-style:italic");
+    }
+    match (
+        run.modifier.contains(Modifier::UNDERLINED),
+        run.modifier.contains(Modifier::CROSSED_OUT),
+    ) {
+        (true, true) => css.push_str(";text-decoration:underline line-through"),
+        (true, false) => css.push_str(";text-decoration:underline"),
+        (false, true) => css.push_str(";text-decoration:line-through"),
+        (false, false) => {}
+    }
+
+    css
+}
+
+fn to_html(buffer: &Buffer) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>lemurs</title>\n</head>\n\
+         <body style=\"margin:0;background:{}\">\n<pre style=\"margin:0;font-family:monospace\">",
+        hex(DEFAULT_BG)
+    );
+
+    for line in runs(buffer) {
+        for run in line {
+            let _ = write!(
+                html,
+                "<span style=\"{}\">{}</span>",
+                css(&run),
+                escape_xml(&run.text)
+            );
+        }
+        html.push('\n');
+    }
+
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+fn to_svg(buffer: &Buffer) -> String {
+    let width = u32::from(buffer.area.width) * CELL_WIDTH;
+    let height = u32::from(buffer.area.height) * CELL_HEIGHT;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\" font-family=\"monospace\" font-size=\"{FONT_SIZE}\">\n\
+         <rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+        hex(DEFAULT_BG)
+    );
+
+    for (y, line) in runs(buffer).into_iter().enumerate() {
+        let top = y as u32 * CELL_HEIGHT;
+
+        for run in line {
+            let (fg, bg) = run_colors(&run);
+            let left = u32::from(run.x) * CELL_WIDTH;
+            let run_width = u32::from(run.width) * CELL_WIDTH;
+
+            if bg != DEFAULT_BG {
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{left}\" y=\"{top}\" width=\"{run_width}\" height=\"{CELL_HEIGHT}\" \
+                     fill=\"{}\"/>",
+                    hex(bg)
+                );
+            }
+
+            if run.text.trim().is_empty() {
+                continue;
+            }
+
+            let mut attributes = String::new();
+            if run.modifier.contains(Modifier::BOLD) {
+                attributes.push_str(" font-weight=\"bold\"");
+            }
+            if run.modifier.contains(Modifier::DIM) {
+                attributes.push_str(" opacity=\"0.6\"");
+            }
+            if run.modifier.contains(Modifier::ITALIC) {
+                attributes.push_str(" font-style=\"italic\"");
+            }
+            if run.modifier.contains(Modifier::UNDERLINED) {
+                attributes.push_str(" text-decoration=\"underline\"");
+            }
+
+            // The text is stretched to the cells, so that it lines up with any font
+            let _ = writeln!(
+                svg,
+                "<text x=\"{left}\" y=\"{}\" textLength=\"{run_width}\" fill=\"{}\" \
+                 xml:space=\"preserve\"{attributes}>{}</text>",
+                top + CELL_HEIGHT * 3 / 4,
+                hex(fg),
+                escape_xml(&run.text)
+            );
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A file format that a buffer can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ansi,
+    Html,
+    Svg,
+}
+
+impl Format {
+    pub const ALL: [Self; 3] = [Self::Ansi, Self::Html, Self::Svg];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension() == name.trim())
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Ansi => "ansi",
+            Self::Html => "html",
+            Self::Svg => "svg",
+        }
+    }
+
+    pub fn render(self, buffer: &Buffer) -> String {
+        match self {
+            Self::Ansi => to_ansi(buffer),
+            Self::Html => to_html(buffer),
+            Self::Svg => to_svg(buffer),
+        }
+    }
+}
+
+/// Write the buffer to `lemurs-screenshot-<TIMESTAMP>.{ansi,html,svg}` in the working directory
+pub fn save(buffer: &Buffer) -> io::Result<Vec<PathBuf>> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+
+    Format::ALL
+        .into_iter()
+        .map(|format| {
+            let path = PathBuf::from(format!(
+                "lemurs-screenshot-{timestamp}.{}",
+                format.extension()
+            ));
+            fs::write(&path, format.render(buffer))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ratatui::layout::Rect;
+    use ratatui::style::Style;
+
+    #[test]
+    fn screenshot_formats() {
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 6, 1));
+        buffer.set_string(0, 0, "a<b", Style::default().fg(Color::Red));
+
+        let lines = runs(&buffer);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].len(), 2);
+        assert_eq!(lines[0][0].text, "a<b");
+        assert_eq!(lines[0][1].x, 3);
+
+        assert!(to_ansi(&buffer).starts_with("\x1b[0;38;2;170;0;0;48;2;0;0;0ma<b"));
+        assert!(to_html(&buffer)
+            .contains("<span style=\"color:#aa0000;background:#000000\">a&lt;b</span>"));
+        assert!(to_svg(&buffer).contains(">a&lt;b</text>"));
+        assert_eq!(Format::from_name("svg"), Some(Format::Svg));
+        assert_eq!(Format::from_name("png"), None);
+
+        assert_eq!(rgb(Color::Indexed(196), DEFAULT_FG), (255, 0, 0));
+        assert_eq!(rgb(Color::Indexed(232), DEFAULT_FG), (8, 8, 8));
+    }
+}
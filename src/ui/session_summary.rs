@@ -0,0 +1,101 @@
+use std::os::unix::process::ExitStatusExt;
+use std::time::Duration;
+
+use nix::sys::signal::Signal;
+use ratatui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crate::post_login::SessionSummary;
+
+/// A screen that summarizes how the last session ended
+pub struct SessionSummaryWidget {
+    title: &'static str,
+    lines: Vec<String>,
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s:02}s"),
+        (h, m, s) => format!("{h}h {m:02}m {s:02}s"),
+    }
+}
+
+impl SessionSummaryWidget {
+    pub fn new(summary: &SessionSummary) -> Self {
+        let mut lines = Vec::new();
+
+        let exit_status = summary.exit_status;
+        let is_clean = exit_status.is_some_and(|status| status.success());
+
+        match exit_status {
+            Some(status) => {
+                if let Some(code) = status.code() {
+                    lines.push(format!("Exit code: {code}"));
+                } else if let Some(signal) = status.signal() {
+                    let name = Signal::try_from(signal).map_or("unknown signal", Signal::as_str);
+                    lines.push(format!("Terminated by signal {signal} ({name})"));
+                }
+            }
+            None => lines.push("Exit status is unknown".to_string()),
+        }
+
+        lines.push(format!("Duration: {}", format_duration(summary.duration)));
+
+        if let Some(log_path) = &summary.log_path {
+            lines.push(format!("Log file: {log_path}"));
+        }
+
+        lines.push(String::new());
+        lines.push("Press any key to continue".to_string());
+
+        Self {
+            title: if is_clean {
+                "Session ended"
+            } else {
+                "Session crashed"
+            },
+            lines,
+        }
+    }
+
+    pub fn render(&self, frame: &mut Frame<impl Backend>) {
+        let bounding_box = frame.size();
+
+        let content_width = self
+            .lines
+            .iter()
+            .map(|line| line.chars().count())
+            .chain(std::iter::once(self.title.len()))
+            .max()
+            .unwrap_or_default();
+
+        // Add space for the borders and some padding
+        let width = u16::try_from(content_width + 4)
+            .unwrap_or(u16::MAX)
+            .min(bounding_box.width);
+        let height = u16::try_from(self.lines.len() + 2)
+            .unwrap_or(u16::MAX)
+            .min(bounding_box.height);
+
+        let area = Rect {
+            x: bounding_box.x + (bounding_box.width - width) / 2,
+            y: bounding_box.y + (bounding_box.height - height) / 2,
+            width,
+            height,
+        };
+
+        let paragraph = Paragraph::new(self.lines.join("\n"))
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).title(self.title));
+
+        frame.render_widget(Clear, bounding_box);
+        frame.render_widget(paragraph, area);
+    }
+}
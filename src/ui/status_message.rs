@@ -1,18 +1,36 @@
 use ratatui::backend::Backend;
 use ratatui::layout::Rect;
-use ratatui::style::{Color, Style};
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
 use ratatui::widgets::Paragraph;
 use ratatui::Frame;
 
 use crate::auth::AuthenticationError;
+use crate::config::{get_bg_color, get_color, get_modifiers, StatusMessageConfig};
+use crate::error::ErrorCode;
+use crate::notifications::{Notification, Severity};
+use crate::post_login::EnvironmentStartError;
 
 #[derive(Clone)]
 pub enum ErrorStatusMessage {
     AuthenticationError(AuthenticationError),
+    AccountExpired,
+    PasswordExpired,
+    AccessDenied,
+    AccountLocked,
+    GroupDenied,
+    RootDenied,
+    OutsideAccessHours(Option<String>),
+    FailedAttempts(u32),
+    LockedOut(u64),
     NoGraphicalEnvironment,
-    FailedGraphicalEnvironment,
+    FailedGraphicalEnvironment(EnvironmentStartError),
+    NoInteractiveShell,
+    MissingExecutable(String),
     FailedDesktop,
     FailedPowerControl(String),
+    RecoveryNeedsPassword,
+    NetworkOffline,
 }
 
 impl From<ErrorStatusMessage> for Box<str> {
@@ -20,13 +38,87 @@ impl From<ErrorStatusMessage> for Box<str> {
         use ErrorStatusMessage::*;
 
         match err {
-            AuthenticationError(_) => "Authentication failed".into(),
+            AuthenticationError(err) => format!("Authentication failed [{}]", err.code()).into(),
+            AccountExpired => "Account has expired. Contact your administrator".into(),
+            PasswordExpired => "Password has expired. Change it before logging in".into(),
+            AccessDenied => "Access denied. Logging in here or now is not allowed".into(),
+            AccountLocked => "Account is locked because of too many failed attempts".into(),
+            GroupDenied => "Login is not permitted for members of your groups".into(),
+            RootDenied => "Logging in as root is not allowed".into(),
+            OutsideAccessHours(Some(resume)) => {
+                format!("Logins are not allowed at this time. They resume {resume}").into()
+            }
+            OutsideAccessHours(None) => "Logins are not allowed at this time".into(),
+            FailedAttempts(1) => "Authentication failed. 1 failed attempt".into(),
+            FailedAttempts(n) => format!("Authentication failed. {n} failed attempts").into(),
+            LockedOut(secs) => {
+                format!("Too many failed attempts. Try again in {secs} seconds").into()
+            }
             NoGraphicalEnvironment => "No graphical environment specified".into(),
-            FailedGraphicalEnvironment => "Failed booting into the graphical environment".into(),
+            FailedGraphicalEnvironment(err) => {
+                let hint = match &err {
+                    EnvironmentStartError::XSetup(err) => err.hint(),
+                    _ => None,
+                };
+                let message = hint
+                    .as_deref()
+                    .unwrap_or("Failed booting into the graphical environment");
+
+                format!("{message} [{}]", err.code()).into()
+            }
+            NoInteractiveShell => "Account has no interactive shell".into(),
+            MissingExecutable(program) => format!("`{program}` not found in PATH").into(),
             FailedDesktop => "Failed booting into desktop environment".into(),
             FailedPowerControl(name) => {
                 format!("Failed to {name}... Check the logs for more information").into()
             }
+            RecoveryNeedsPassword => "Type the root password to open the recovery shell".into(),
+            NetworkOffline => {
+                "No network. Network accounts may fail to log in. Press enter to try anyway".into()
+            }
+        }
+    }
+}
+
+impl ErrorStatusMessage {
+    /// The name used to override the style of the message in the configuration
+    fn name(&self) -> &'static str {
+        use ErrorStatusMessage::*;
+
+        match self {
+            AuthenticationError(_) => "authentication_error",
+            AccountExpired => "account_expired",
+            PasswordExpired => "password_expired",
+            AccessDenied => "access_denied",
+            AccountLocked => "account_locked",
+            GroupDenied => "group_denied",
+            RootDenied => "root_denied",
+            OutsideAccessHours(_) => "outside_access_hours",
+            FailedAttempts(_) => "failed_attempts",
+            LockedOut(_) => "locked_out",
+            NoGraphicalEnvironment => "no_graphical_environment",
+            FailedGraphicalEnvironment(_) => "failed_graphical_environment",
+            NoInteractiveShell => "no_interactive_shell",
+            MissingExecutable(_) => "missing_executable",
+            FailedDesktop => "failed_desktop",
+            FailedPowerControl(_) => "failed_power_control",
+            RecoveryNeedsPassword => "recovery_needs_password",
+            NetworkOffline => "network_offline",
+        }
+    }
+}
+
+impl From<AuthenticationError> for ErrorStatusMessage {
+    fn from(err: AuthenticationError) -> Self {
+        match err {
+            AuthenticationError::AccountExpired => Self::AccountExpired,
+            AuthenticationError::PasswordExpired => Self::PasswordExpired,
+            AuthenticationError::AccessDenied => Self::AccessDenied,
+            AuthenticationError::AccountLocked => Self::AccountLocked,
+            AuthenticationError::GroupDenied => Self::GroupDenied,
+            AuthenticationError::RootDenied => Self::RootDenied,
+            AuthenticationError::OutsideAccessHours(resume) => Self::OutsideAccessHours(resume),
+            err => Self::AuthenticationError(err),
         }
     }
 }
@@ -54,6 +146,18 @@ impl From<InfoStatusMessage> for Box<str> {
     }
 }
 
+impl InfoStatusMessage {
+    /// The name used to override the style of the message in the configuration
+    fn name(&self) -> &'static str {
+        use InfoStatusMessage::*;
+
+        match self {
+            LoggingIn => "logging_in",
+            Authenticating => "authenticating",
+        }
+    }
+}
+
 impl From<InfoStatusMessage> for StatusMessage {
     fn from(info: InfoStatusMessage) -> Self {
         Self::Info(info)
@@ -64,6 +168,10 @@ impl From<InfoStatusMessage> for StatusMessage {
 pub enum StatusMessage {
     Error(ErrorStatusMessage),
     Info(InfoStatusMessage),
+    /// A line printed by an event script
+    Script(String),
+    /// A message that was sent to the notification socket
+    Notification(Notification),
 }
 
 impl From<StatusMessage> for Box<str> {
@@ -73,6 +181,8 @@ impl From<StatusMessage> for Box<str> {
         match msg {
             Error(sm) => sm.into(),
             Info(sm) => sm.into(),
+            Script(line) => line.into(),
+            Notification(notification) => notification.message.into(),
         }
     }
 }
@@ -80,19 +190,69 @@ impl From<StatusMessage> for Box<str> {
 impl StatusMessage {
     /// Fetch whether status is an error
     pub fn is_error(&self) -> bool {
-        matches!(self, Self::Error(_))
+        match self {
+            Self::Error(_) => true,
+            Self::Notification(notification) => notification.severity != Severity::Info,
+            Self::Info(_) | Self::Script(_) => false,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Error(sm) => sm.name(),
+            Self::Info(sm) => sm.name(),
+            Self::Script(_) => "script",
+            Self::Notification(notification) => match notification.severity {
+                Severity::Info => "notification_info",
+                Severity::Warning => "notification_warning",
+                Severity::Error => "notification_error",
+            },
+        }
+    }
+
+    fn style(&self, config: &StatusMessageConfig) -> Style {
+        let (color, modifiers) = if self.is_error() {
+            (&config.error_color, &config.error_modifiers)
+        } else {
+            (&config.info_color, &config.info_modifiers)
+        };
+
+        // A message can be given its own color
+        let color = config.colors.0.get(self.name()).unwrap_or(color);
+
+        let mut style = Style::default().fg(get_color(color));
+        for modifier in get_modifiers(modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        match get_bg_color(&config.bg_color) {
+            Some(bg_color) => style.bg(bg_color),
+            None => style,
+        }
     }
 
-    pub fn render<B: Backend>(status: Option<Self>, frame: &mut Frame<B>, area: Rect) {
+    pub fn render<B: Backend>(
+        status: Option<Self>,
+        frame: &mut Frame<B>,
+        area: Rect,
+        config: &StatusMessageConfig,
+    ) {
         if let Some(status_message) = status {
-            let text: Box<str> = status_message.clone().into();
-            let widget = Paragraph::new(text.as_ref()).style(Style::default().fg(
-                if status_message.is_error() {
-                    Color::Red
-                } else {
-                    Color::Yellow
-                },
-            ));
+            let style = status_message.style(config);
+            let prefix = if status_message.is_error() {
+                &config.error_prefix
+            } else {
+                &config.info_prefix
+            };
+            let text: Box<str> = status_message.into();
+
+            let mut spans = Vec::new();
+            if !prefix.is_empty() {
+                spans.push(Span::raw(format!("{prefix} ")));
+            }
+            spans.push(Span::raw(text.into_string()));
+
+            let widget = Paragraph::new(Line::from(spans)).style(style);
 
             frame.render_widget(widget, area);
         } else {
@@ -8,11 +8,15 @@ use ratatui::{
     widgets::{Block, Paragraph},
 };
 
-use crate::config::{get_color, get_modifiers, SwitcherConfig, SwitcherVisibility};
+use crate::config::{get_bg_color, get_color, get_modifiers, SwitcherConfig, SwitcherVisibility};
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct SwitcherItem<T> {
     pub title: String,
+    /// A description that is shown below the title when the item is selected
+    pub subtitle: Option<String>,
+    /// Hidden items are only shown when their exact title is typed as the filter
+    pub hidden: bool,
     pub content: T,
 }
 
@@ -20,6 +24,12 @@ pub struct SwitcherItem<T> {
 struct Switcher<T> {
     selected: Option<usize>,
     items: Vec<SwitcherItem<T>>,
+    /// The text that is typed after `/`. Only items that contain it are shown.
+    filter: Option<String>,
+    /// The selection to return to when filtering is cancelled
+    selected_before_filter: Option<usize>,
+    /// The hidden item that was selected with the filter and is shown from then on
+    revealed: Option<usize>,
 }
 
 /// A widget used to select a specific window manager
@@ -29,19 +39,40 @@ pub struct SwitcherWidget<T> {
     config: SwitcherConfig,
     /// Indicates whether the widget has been hidden by the config or keybind
     hidden: bool,
+    /// Indicates whether the items are still being loaded
+    loading: bool,
 }
 
 impl<T> SwitcherItem<T> {
     pub fn new(title: impl ToString, content: T) -> Self {
         let title = title.to_string();
-        Self { title, content }
+        Self {
+            title,
+            subtitle: None,
+            hidden: false,
+            content,
+        }
+    }
+
+    pub fn with_subtitle(self, subtitle: Option<String>) -> Self {
+        Self { subtitle, ..self }
+    }
+
+    pub fn with_hidden(self, hidden: bool) -> Self {
+        Self { hidden, ..self }
     }
 }
 
 impl<T> Switcher<T> {
     fn new(items: Vec<SwitcherItem<T>>) -> Self {
-        let selected = if items.is_empty() { None } else { Some(0) };
-        Self { selected, items }
+        let selected = items.iter().position(|item| !item.hidden);
+        Self {
+            selected,
+            items,
+            filter: None,
+            selected_before_filter: None,
+            revealed: None,
+        }
     }
 
     #[inline]
@@ -49,37 +80,103 @@ impl<T> Switcher<T> {
         self.items.len()
     }
 
-    pub fn try_select(&mut self, title: &str) {
+    /// Select the item with `title`. Returns whether such an item exists.
+    pub fn try_select(&mut self, title: &str) -> bool {
         // Only set the selected if we find a matching title
         if let Some(selected) = self
             .items
             .iter()
             .enumerate()
-            .find(|(_, item)| item.title == title)
+            .find(|(_, item)| item.title == title && !item.hidden)
             .map(|(index, _)| index)
         {
             self.selected = Some(selected);
+            true
         } else {
             warn!("Failed to find selection with title: '{}'", title);
+            false
         }
     }
 
-    fn next_index(&self, index: usize) -> Option<usize> {
-        let next_index = index + 1;
+    /// Whether the item at `index` matches the filter
+    fn is_visible(&self, index: usize) -> bool {
+        let item = &self.items[index];
 
-        if next_index == self.len() {
-            None
-        } else {
-            Some(next_index)
+        if item.hidden && self.revealed != Some(index) {
+            return self.filter.as_ref() == Some(&item.title);
+        }
+
+        match &self.filter {
+            None => true,
+            Some(filter) => item.title.to_lowercase().contains(&filter.to_lowercase()),
         }
     }
 
+    fn next_index(&self, index: usize) -> Option<usize> {
+        (index + 1..self.len()).find(|&index| self.is_visible(index))
+    }
+
     fn prev_index(&self, index: usize) -> Option<usize> {
-        if index == 0 {
-            return None;
+        (0..index).rev().find(|&index| self.is_visible(index))
+    }
+
+    fn start_filter(&mut self) {
+        self.filter = Some(String::new());
+        self.selected_before_filter = self.selected;
+    }
+
+    /// Stop filtering. If `keep_selection` is false, the selection from before filtering is
+    /// restored.
+    fn stop_filter(&mut self, keep_selection: bool) {
+        if keep_selection
+            && self
+                .selected
+                .is_some_and(|index| self.items[index].hidden && self.is_visible(index))
+        {
+            self.revealed = self.selected;
+        }
+
+        if self.filter.take().is_some() && !keep_selection {
+            self.selected = self.selected_before_filter;
+        }
+    }
+
+    /// Change the filter and select the first item that matches it. A hidden item whose title is
+    /// typed exactly is always selected.
+    fn set_filter(&mut self, filter: String) {
+        self.filter = Some(filter);
+
+        if let Some(index) =
+            (0..self.len()).find(|&index| self.items[index].hidden && self.is_visible(index))
+        {
+            self.selected = Some(index);
+        } else if self.selected.is_some_and(|index| !self.is_visible(index)) {
+            if let Some(index) = (0..self.len()).find(|&index| self.is_visible(index)) {
+                self.selected = Some(index);
+            }
         }
+    }
 
-        Some(index - 1)
+    /// Select the next item whose title starts with `letter`, wrapping around at the end
+    fn jump_to_letter(&mut self, letter: char) {
+        let Some(selected) = self.selected else {
+            return;
+        };
+
+        let starts_with_letter = |index: usize| {
+            self.items[index]
+                .title
+                .chars()
+                .next()
+                .is_some_and(|first| first.to_lowercase().eq(letter.to_lowercase()))
+        };
+
+        if let Some(index) = (selected + 1..self.len())
+            .chain(0..selected)
+            .find(|&index| self.is_visible(index) && starts_with_letter(index))
+        {
+            self.selected = Some(index);
+        }
     }
 
     fn go_next(&mut self) {
@@ -132,10 +229,31 @@ impl<T> SwitcherWidget<T> {
             selector: Switcher::new(items),
             config,
             hidden,
+            loading: false,
+        }
+    }
+
+    /// Creates a widget without items that shows a placeholder until [`Self::set_items`] is called
+    pub fn loading(config: SwitcherConfig) -> Self {
+        Self {
+            loading: true,
+            ..Self::new(Vec::new(), config)
         }
     }
 
-    pub fn try_select(&mut self, title: &str) {
+    /// Replace the configuration of the widget, e.g. to change its colors
+    ///
+    /// This does not change whether the widget is hidden.
+    pub fn set_config(&mut self, config: SwitcherConfig) {
+        self.config = config;
+    }
+
+    pub fn set_items(&mut self, items: Vec<SwitcherItem<T>>) {
+        self.selector = Switcher::new(items);
+        self.loading = false;
+    }
+
+    pub fn try_select(&mut self, title: &str) -> bool {
         self.selector.try_select(title)
     }
 
@@ -251,6 +369,14 @@ impl<T> SwitcherWidget<T> {
             style = style.add_modifier(modifier);
         }
 
+        if let Some(bg_color) = get_bg_color(if is_focused {
+            &self.config.selected_bg_color_focused
+        } else {
+            &self.config.selected_bg_color
+        }) {
+            style = style.bg(bg_color);
+        }
+
         style
     }
 
@@ -289,6 +415,7 @@ impl<T> SwitcherWidget<T> {
             selector,
             config,
             hidden,
+            loading,
         } = &self;
 
         if *hidden {
@@ -309,7 +436,23 @@ impl<T> SwitcherWidget<T> {
             // MiddleWM(3) = 15
             15,
         );
-        if let Some(current) = selector.current() {
+        if let Some(filter) = &selector.filter {
+            spans.push(Span::styled(
+                format!("/{filter} "),
+                self.arrow_style(is_focused),
+            ));
+        }
+
+        if *loading {
+            spans.push(Span::styled(
+                &config.loading_text,
+                self.empty_style(is_focused),
+            ));
+        } else if let Some(current) = selector.current().filter(|_| {
+            selector
+                .selected
+                .is_some_and(|index| selector.is_visible(index))
+        }) {
             let do_show_neighbours = self.do_show_neighbours(area.width.into());
 
             // Showing left item
@@ -371,14 +514,27 @@ impl<T> SwitcherWidget<T> {
                     },
                 )));
             }
-        } else {
+        } else if selector.filter.is_none() {
             spans.push(Span::styled(
                 &config.no_envs_text,
                 self.empty_style(is_focused),
             ));
         }
 
-        let text = Text::from(Line::from(spans));
+        let mut lines = vec![Line::from(spans)];
+
+        let subtitle = selector
+            .selected
+            .filter(|&index| selector.is_visible(index))
+            .and_then(|_| selector.current()?.subtitle.as_ref());
+        if let Some(subtitle) = subtitle.filter(|_| config.show_comments && !*loading) {
+            lines.push(Line::from(Span::styled(
+                subtitle.as_str(),
+                self.neighbour_wm_style(is_focused),
+            )));
+        }
+
+        let text = Text::from(lines);
         let widget = Paragraph::new(text)
             .block(Block::default())
             .alignment(Alignment::Center);
@@ -386,8 +542,43 @@ impl<T> SwitcherWidget<T> {
         frame.render_widget(widget, area);
     }
 
+    /// Whether a filter is being typed
+    pub fn is_filtering(&self) -> bool {
+        self.selector.filter.is_some()
+    }
+
+    /// Stop filtering. If `keep_selection` is false, the selection from before filtering is
+    /// restored.
+    pub fn stop_filter(&mut self, keep_selection: bool) {
+        self.selector.stop_filter(keep_selection);
+    }
+
     pub(crate) fn key_press(&mut self, key_code: KeyCode) -> Option<super::ErrorStatusMessage> {
+        if let Some(filter) = &self.selector.filter {
+            let mut filter = filter.clone();
+
+            match key_code {
+                KeyCode::Left => self.left(),
+                KeyCode::Right => self.right(),
+                KeyCode::Backspace if filter.is_empty() => self.selector.stop_filter(false),
+                KeyCode::Backspace => {
+                    filter.pop();
+                    self.selector.set_filter(filter);
+                }
+                KeyCode::Char(c) => {
+                    filter.push(c);
+                    self.selector.set_filter(filter);
+                }
+                _ => {}
+            }
+
+            return None;
+        }
+
         match key_code {
+            KeyCode::Char('/') => {
+                self.selector.start_filter();
+            }
             KeyCode::Left | KeyCode::Char('h') => {
                 self.left();
             }
@@ -397,6 +588,10 @@ impl<T> SwitcherWidget<T> {
             kc if self.config.switcher_visibility == SwitcherVisibility::Keybind(kc) => {
                 self.hidden ^= true;
             }
+            // `h` and `l` are handled above, so they cannot be jumped to
+            KeyCode::Char(c) if c.is_alphanumeric() => {
+                self.selector.jump_to_letter(c);
+            }
             _ => {}
         }
 
@@ -519,5 +714,80 @@ mod tests {
             selector.go_next();
             assert_eq!(selector.current(), Some(&wm4));
         }
+
+        #[test]
+        fn filter() {
+            let sway: SwitcherItem<()> = SwitcherItem::new("Sway", ());
+            let bspwm = SwitcherItem::new("bspwm", ());
+            let swayfx = SwitcherItem::new("SwayFX", ());
+
+            let mut selector = Switcher::new(vec![sway.clone(), bspwm.clone(), swayfx.clone()]);
+            selector.go_next();
+            assert_eq!(selector.current(), Some(&bspwm));
+
+            selector.start_filter();
+            selector.set_filter("sw".to_string());
+            assert_eq!(selector.current(), Some(&sway));
+            selector.go_next();
+            assert_eq!(selector.current(), Some(&swayfx));
+            selector.go_prev();
+            assert_eq!(selector.current(), Some(&sway));
+
+            // Cancelling restores the selection from before filtering
+            selector.stop_filter(false);
+            assert_eq!(selector.current(), Some(&bspwm));
+
+            selector.start_filter();
+            selector.set_filter("fx".to_string());
+            selector.stop_filter(true);
+            assert_eq!(selector.current(), Some(&swayfx));
+        }
+
+        #[test]
+        fn jump_to_letter() {
+            let sway: SwitcherItem<()> = SwitcherItem::new("Sway", ());
+            let bspwm = SwitcherItem::new("bspwm", ());
+            let swayfx = SwitcherItem::new("SwayFX", ());
+
+            let mut selector = Switcher::new(vec![sway.clone(), bspwm.clone(), swayfx.clone()]);
+            selector.jump_to_letter('s');
+            assert_eq!(selector.current(), Some(&swayfx));
+            selector.jump_to_letter('S');
+            assert_eq!(selector.current(), Some(&sway));
+            selector.jump_to_letter('B');
+            assert_eq!(selector.current(), Some(&bspwm));
+            selector.jump_to_letter('x');
+            assert_eq!(selector.current(), Some(&bspwm));
+        }
+
+        #[test]
+        fn hidden_items() {
+            let sway: SwitcherItem<()> = SwitcherItem::new("Sway", ());
+            let rescue = SwitcherItem::new("Rescue", ()).with_hidden(true);
+            let bspwm = SwitcherItem::new("bspwm", ());
+
+            let mut selector = Switcher::new(vec![rescue.clone(), sway.clone(), bspwm.clone()]);
+            assert_eq!(selector.current(), Some(&sway));
+            selector.go_prev();
+            assert_eq!(selector.current(), Some(&sway));
+
+            // Hidden items cannot be selected from the cache
+            selector.try_select("Rescue");
+            assert_eq!(selector.current(), Some(&sway));
+
+            selector.start_filter();
+            selector.set_filter("Resc".to_string());
+            assert_eq!(selector.current(), Some(&sway));
+            selector.set_filter("Rescue".to_string());
+            assert_eq!(selector.current(), Some(&rescue));
+
+            // The hidden item stays available once it has been selected
+            selector.stop_filter(true);
+            assert_eq!(selector.current(), Some(&rescue));
+            selector.go_next();
+            assert_eq!(selector.current(), Some(&sway));
+            selector.go_prev();
+            assert_eq!(selector.current(), Some(&rescue));
+        }
     }
 }
@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use log::warn;
+
+use crate::config::{Config, ThemeConfig};
+
+/// How often to check whether the palette should be swapped
+pub const THEME_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The palettes that can be selected by the time of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThemeVariant {
+    Day,
+    Night,
+}
+
+/// Parse a time of day in the `HH:MM` format to the minutes since midnight
+fn parse_time_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// The current local time in minutes since midnight
+fn local_minutes_of_day() -> Option<u32> {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm = std::mem::MaybeUninit::<libc::tm>::uninit();
+
+    if unsafe { libc::localtime_r(&now, tm.as_mut_ptr()) }.is_null() {
+        return None;
+    }
+
+    let tm = unsafe { tm.assume_init() };
+    Some((tm.tm_hour * 60 + tm.tm_min) as u32)
+}
+
+impl ThemeVariant {
+    fn at(minutes: u32, day_start: u32, night_start: u32) -> Self {
+        let is_day = if day_start <= night_start {
+            (day_start..night_start).contains(&minutes)
+        } else {
+            // The day wraps around midnight
+            !(night_start..day_start).contains(&minutes)
+        };
+
+        if is_day {
+            Self::Day
+        } else {
+            Self::Night
+        }
+    }
+
+    /// The variant that should be shown at this moment. Returns `None` if themes are disabled.
+    pub fn current(config: &ThemeConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+
+        let (Some(day_start), Some(night_start)) = (
+            parse_time_of_day(&config.day_start),
+            parse_time_of_day(&config.night_start),
+        ) else {
+            warn!(
+                "Invalid theme switch times '{}' and '{}'. Expected the HH:MM format",
+                config.day_start, config.night_start
+            );
+            return None;
+        };
+
+        Some(Self::at(local_minutes_of_day()?, day_start, night_start))
+    }
+}
+
+/// Replace the colors in `config` with the colors of the palette of `variant`
+pub fn apply_theme(config: &mut Config, variant: ThemeVariant) {
+    fn set(color: &mut String, palette_color: &str) {
+        if !palette_color.is_empty() {
+            *color = palette_color.to_string();
+        }
+    }
+
+    let palette = match variant {
+        ThemeVariant::Day => config.theme.day.clone(),
+        ThemeVariant::Night => config.theme.night.clone(),
+    };
+
+    let background = &mut config.background.style;
+    set(&mut background.color, &palette.background);
+    set(&mut background.border_color, &palette.foreground);
+
+    for style in [
+        &mut config.username_field.style,
+        &mut config.password_field.style,
+        &mut config.domain_field.style,
+    ] {
+        set(&mut style.title_color, &palette.foreground);
+        set(&mut style.content_color, &palette.foreground);
+        set(&mut style.border_color, &palette.foreground);
+
+        set(&mut style.title_color_focused, &palette.accent);
+        set(&mut style.content_color_focused, &palette.accent);
+        set(&mut style.border_color_focused, &palette.accent);
+    }
+
+    let switcher = &mut config.environment_switcher;
+    set(&mut switcher.toggle_hint_color, &palette.muted);
+    set(&mut switcher.mover_color, &palette.muted);
+    set(&mut switcher.mover_color_focused, &palette.accent);
+    set(&mut switcher.neighbour_color, &palette.muted);
+    set(&mut switcher.neighbour_color_focused, &palette.muted);
+    set(&mut switcher.selected_color, &palette.muted);
+    set(&mut switcher.selected_color_focused, &palette.foreground);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_of_day() {
+        assert_eq!(parse_time_of_day("07:00"), Some(7 * 60));
+        assert_eq!(parse_time_of_day(" 19:30 "), Some(19 * 60 + 30));
+        assert_eq!(parse_time_of_day("24:00"), None);
+        assert_eq!(parse_time_of_day("7"), None);
+    }
+
+    #[test]
+    fn variant_at() {
+        use ThemeVariant::*;
+
+        assert_eq!(ThemeVariant::at(12 * 60, 7 * 60, 19 * 60), Day);
+        assert_eq!(ThemeVariant::at(19 * 60, 7 * 60, 19 * 60), Night);
+        assert_eq!(ThemeVariant::at(3 * 60, 7 * 60, 19 * 60), Night);
+
+        // Night during the day
+        assert_eq!(ThemeVariant::at(12 * 60, 20 * 60, 8 * 60), Night);
+        assert_eq!(ThemeVariant::at(23 * 60, 20 * 60, 8 * 60), Day);
+        assert_eq!(ThemeVariant::at(3 * 60, 20 * 60, 8 * 60), Day);
+    }
+}
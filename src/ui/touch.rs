@@ -0,0 +1,397 @@
+//! Touch input for tablets and kiosks without a keyboard. A tap focuses an input field or presses a
+//! button of the on-screen PIN pad.
+//!
+//! On the Linux console, the taps are read from the touchscreen through evdev. In a terminal
+//! emulator (e.g. with `--windowed`), the terminal reports them as mouse clicks.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    self, Event, KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use log::{info, warn};
+use ratatui::{
+    backend::Backend,
+    layout::{Alignment, Rect},
+    style::Style,
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use super::chunks::Chunks;
+use super::InputMode;
+use crate::config::{get_color, get_modifiers, TouchConfig};
+
+/// The height of the PIN pad, which has 4 rows of buttons
+pub const PIN_PAD_HEIGHT: u16 = 4 * BUTTON_HEIGHT;
+const BUTTON_HEIGHT: u16 = 3;
+
+/// The labels and keys of the PIN pad from the top left to the bottom right
+const PIN_PAD_KEYS: [(&str, KeyCode); 12] = [
+    ("1", KeyCode::Char('1')),
+    ("2", KeyCode::Char('2')),
+    ("3", KeyCode::Char('3')),
+    ("4", KeyCode::Char('4')),
+    ("5", KeyCode::Char('5')),
+    ("6", KeyCode::Char('6')),
+    ("7", KeyCode::Char('7')),
+    ("8", KeyCode::Char('8')),
+    ("9", KeyCode::Char('9')),
+    ("DEL", KeyCode::Backspace),
+    ("0", KeyCode::Char('0')),
+    ("OK", KeyCode::Enter),
+];
+
+/// How often the terminal is checked for events while waiting for taps
+const TAP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What is under a tap
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TouchTarget {
+    Field(InputMode),
+    Key(KeyCode),
+}
+
+/// The buttons of the PIN pad in `area`
+fn pin_pad_buttons(area: Rect) -> impl Iterator<Item = (Rect, &'static str, KeyCode)> {
+    let width = area.width / 3;
+
+    PIN_PAD_KEYS
+        .into_iter()
+        .enumerate()
+        .map(move |(index, (label, key))| {
+            let column = (index % 3) as u16;
+            let row = (index / 3) as u16;
+            let button = Rect::new(
+                area.x + column * width,
+                area.y + row * BUTTON_HEIGHT,
+                width,
+                BUTTON_HEIGHT,
+            );
+
+            (button.intersection(area), label, key)
+        })
+}
+
+/// Where the fields and the buttons were last drawn, which is shared between the UI thread and
+/// the event thread
+#[derive(Clone, Default)]
+pub struct TouchTargets(Arc<Mutex<Vec<(Rect, TouchTarget)>>>);
+
+impl TouchTargets {
+    pub fn update(&self, chunks: &Chunks, fields: &[InputMode]) {
+        let mut targets = fields
+            .iter()
+            .zip(&chunks.fields)
+            .map(|(mode, area)| (*area, TouchTarget::Field(*mode)))
+            .collect::<Vec<_>>();
+
+        if let Some(area) = chunks.pin_pad {
+            targets.extend(
+                pin_pad_buttons(area).map(|(button, _, key)| (button, TouchTarget::Key(key))),
+            );
+        }
+
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = targets;
+    }
+
+    pub fn at(&self, column: u16, row: u16) -> Option<TouchTarget> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find(|(area, _)| {
+                (area.left()..area.right()).contains(&column)
+                    && (area.top()..area.bottom()).contains(&row)
+            })
+            .map(|(_, target)| *target)
+    }
+}
+
+pub struct PinPadWidget {
+    style: Style,
+}
+
+impl PinPadWidget {
+    pub fn new(config: &TouchConfig) -> Self {
+        let mut style = Style::default().fg(get_color(&config.pin_pad_color));
+        for modifier in get_modifiers(&config.pin_pad_modifiers) {
+            style = style.add_modifier(modifier);
+        }
+
+        Self { style }
+    }
+
+    pub fn render<B: Backend>(&self, frame: &mut Frame<B>, area: Rect) {
+        for (button, label, _) in pin_pad_buttons(area) {
+            frame.render_widget(
+                Paragraph::new(label)
+                    .alignment(Alignment::Center)
+                    .style(self.style)
+                    .block(Block::default().borders(Borders::ALL)),
+                button,
+            );
+        }
+    }
+}
+
+/// A tap on the cell at `column` and `row`, as a mouse click
+fn tap_event(column: u16, row: u16) -> Event {
+    Event::Mouse(MouseEvent {
+        kind: MouseEventKind::Down(MouseButton::Left),
+        column,
+        row,
+        modifiers: KeyModifiers::NONE,
+    })
+}
+
+/// The events of the terminal and the taps on the touchscreen, which are given as mouse clicks.
+/// All input of the login screen is read through this, so that taps are handled like key presses.
+pub struct InputEvents {
+    taps: Option<Receiver<(u16, u16)>>,
+}
+
+impl InputEvents {
+    /// Read the taps from the touchscreen, if touch input is enabled and `evdev` is set
+    pub fn new(config: &TouchConfig, evdev: bool) -> Self {
+        if !config.enabled || !evdev {
+            return Self { taps: None };
+        }
+
+        let device = if config.device.is_empty() {
+            find_touchscreen()
+        } else {
+            Some(PathBuf::from(&config.device))
+        };
+        let Some(device) = device else {
+            warn!("No touchscreen was found. Taps are not read");
+            return Self { taps: None };
+        };
+
+        let (sender, receiver) = channel();
+        if let Err(err) = spawn_tap_reader(device, sender) {
+            warn!("Failed to read the touchscreen. Reason: {err}");
+            return Self { taps: None };
+        }
+
+        Self {
+            taps: Some(receiver),
+        }
+    }
+
+    pub fn read(&self) -> io::Result<Event> {
+        let Some(taps) = &self.taps else {
+            return event::read();
+        };
+
+        loop {
+            if let Ok((column, row)) = taps.try_recv() {
+                return Ok(tap_event(column, row));
+            }
+
+            if event::poll(TAP_POLL_INTERVAL)? {
+                return event::read();
+            }
+        }
+    }
+
+    /// The next event, or `None` when there is none within `timeout`
+    pub fn read_timeout(&self, timeout: Duration) -> io::Result<Option<Event>> {
+        let Some(taps) = &self.taps else {
+            return Ok(if event::poll(timeout)? {
+                Some(event::read()?)
+            } else {
+                None
+            });
+        };
+
+        let start = Instant::now();
+        loop {
+            if let Ok((column, row)) = taps.try_recv() {
+                return Ok(Some(tap_event(column, row)));
+            }
+
+            let remaining = timeout.saturating_sub(start.elapsed());
+            if event::poll(remaining.min(TAP_POLL_INTERVAL))? {
+                return event::read().map(Some);
+            }
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Throw away the key presses and taps that were made whilst a login attempt was in flight or
+    /// a session was running. Otherwise, a second press of Enter or a tap on OK would immediately
+    /// submit the form again.
+    pub fn discard_pending(&self) {
+        while let Ok(true) = event::poll(Duration::ZERO) {
+            if event::read().is_err() {
+                break;
+            }
+        }
+
+        if let Some(taps) = &self.taps {
+            let discarded = taps.try_iter().count();
+            if discarded > 0 {
+                info!("Discarded {discarded} taps that were made during the session");
+            }
+        }
+    }
+}
+
+/// The `INPUT_PROP_DIRECT` property, which is set for touchscreens
+const INPUT_PROP_DIRECT: u64 = 1 << 1;
+
+/// The first input device that is a touchscreen
+fn find_touchscreen() -> Option<PathBuf> {
+    let mut names = fs::read_dir("/sys/class/input")
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with("event"))
+        .collect::<Vec<_>>();
+    names.sort();
+
+    names.into_iter().find_map(|name| {
+        let properties =
+            fs::read_to_string(format!("/sys/class/input/{name}/device/properties")).ok()?;
+        // The bitmap is written as words from the highest to the lowest bits
+        let lowest = properties.split_whitespace().last()?;
+        let properties = u64::from_str_radix(lowest, 16).ok()?;
+
+        (properties & INPUT_PROP_DIRECT != 0).then(|| PathBuf::from(format!("/dev/input/{name}")))
+    })
+}
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0x00;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const BTN_TOUCH: u16 = 0x14a;
+
+/// The range of an absolute axis of the touchscreen
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+    min: i32,
+    max: i32,
+}
+
+impl Axis {
+    fn of(file: &File, code: u16) -> Option<Self> {
+        let mut info = unsafe { std::mem::zeroed::<libc::input_absinfo>() };
+        // EVIOCGABS
+        let request = nix::request_code_read!(
+            b'E',
+            0x40 + code,
+            std::mem::size_of::<libc::input_absinfo>()
+        );
+
+        let result = unsafe { libc::ioctl(file.as_raw_fd(), request as _, &mut info) };
+        (result == 0 && info.maximum > info.minimum).then_some(Self {
+            min: info.minimum,
+            max: info.maximum,
+        })
+    }
+
+    /// The cell of `value` when the axis is divided over `cells` cells
+    fn to_cell(self, value: i32, cells: u16) -> u16 {
+        let range = i64::from(self.max) - i64::from(self.min) + 1;
+        let offset = i64::from(value.clamp(self.min, self.max)) - i64::from(self.min);
+
+        (offset * i64::from(cells) / range) as u16
+    }
+}
+
+/// Send the cell of every tap on the touchscreen at `device` to `sender`
+fn spawn_tap_reader(device: PathBuf, sender: Sender<(u16, u16)>) -> io::Result<()> {
+    let mut file = File::open(&device)?;
+    let (Some(x_axis), Some(y_axis)) = (Axis::of(&file, ABS_X), Axis::of(&file, ABS_Y)) else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the device has no absolute axes",
+        ));
+    };
+
+    info!("Reading taps from '{}'", device.display());
+
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; std::mem::size_of::<libc::input_event>()];
+        let (mut x, mut y) = (x_axis.min, y_axis.min);
+        let mut touched = false;
+
+        while file.read_exact(&mut buffer).is_ok() {
+            let event =
+                unsafe { std::ptr::read_unaligned(buffer.as_ptr().cast::<libc::input_event>()) };
+
+            match (event.type_, event.code) {
+                (EV_ABS, ABS_X) => x = event.value,
+                (EV_ABS, ABS_Y) => y = event.value,
+                (EV_KEY, BTN_TOUCH) if event.value == 1 => touched = true,
+                // The position of the touch is complete at the end of the report
+                (EV_SYN, SYN_REPORT) if touched => {
+                    touched = false;
+
+                    let Ok((columns, rows)) = crossterm::terminal::size() else {
+                        continue;
+                    };
+                    let cell = (x_axis.to_cell(x, columns), y_axis.to_cell(y, rows));
+                    if sender.send(cell).is_err() {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        warn!("Stopped reading taps from the touchscreen");
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queued_taps_discarded() {
+        let (sender, receiver) = channel();
+        let input_events = InputEvents {
+            taps: Some(receiver),
+        };
+
+        // Taps on OK while a session is running
+        sender.send((35, 15)).unwrap();
+        sender.send((35, 15)).unwrap();
+        input_events.discard_pending();
+
+        sender.send((12, 6)).unwrap();
+        let event = input_events.read_timeout(Duration::ZERO).unwrap();
+        assert_eq!(event, Some(tap_event(12, 6)));
+    }
+
+    #[test]
+    fn pin_pad_layout() {
+        let buttons = pin_pad_buttons(Rect::new(10, 5, 30, PIN_PAD_HEIGHT)).collect::<Vec<_>>();
+
+        assert_eq!(buttons.len(), 12);
+        assert_eq!(buttons[0].0, Rect::new(10, 5, 10, 3));
+        assert_eq!(
+            buttons[11],
+            (Rect::new(30, 14, 10, 3), "OK", KeyCode::Enter)
+        );
+
+        let axis = Axis { min: 0, max: 4095 };
+        assert_eq!(axis.to_cell(0, 80), 0);
+        assert_eq!(axis.to_cell(4095, 80), 79);
+        assert_eq!(axis.to_cell(5000, 80), 79);
+    }
+}
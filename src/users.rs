@@ -0,0 +1,79 @@
+//! Enumeration of the users that can be shown in the UI, such as for the completion of usernames.
+
+use log::info;
+use uzers::os::unix::UserExt;
+use uzers::User;
+
+use crate::config::UsersConfig;
+use crate::post_login::is_non_interactive_shell;
+
+/// Whether a user should be shown in the UI according to the configuration
+fn is_shown(user: &User, config: &UsersConfig) -> bool {
+    let Some(username) = user.name().to_str() else {
+        return false;
+    };
+    let Some(shell) = user.shell().to_str() else {
+        return false;
+    };
+
+    (config.min_uid..=config.max_uid).contains(&user.uid())
+        && !config.hide.iter().any(|hidden| hidden == username)
+        && !is_non_interactive_shell(shell)
+        && (config.shells.is_empty() || config.shells.iter().any(|allowed| allowed == shell))
+}
+
+/// Get the names of all users that should be shown in the UI, sorted by user id
+pub fn get_users(config: &UsersConfig) -> Vec<String> {
+    // SAFETY: No other thread is enumerating the users at the same time.
+    let mut users = unsafe { uzers::all_users() }
+        .filter(|user| is_shown(user, config))
+        .collect::<Vec<User>>();
+
+    users.sort_by_key(User::uid);
+
+    info!("Found {} user(s) to show", users.len());
+
+    users
+        .into_iter()
+        .filter_map(|user| user.name().to_str().map(str::to_string))
+        .collect()
+}
+
+/// Complete `prefix` to the longest prefix that all usernames in `users` starting with it share.
+/// Returns `None` if that does not add anything to `prefix`.
+pub fn complete_username(prefix: &str, users: &[String]) -> Option<String> {
+    if prefix.is_empty() {
+        return None;
+    }
+
+    let mut candidates = users.iter().filter(|username| username.starts_with(prefix));
+    let mut completion = candidates.next()?.as_str();
+    for candidate in candidates {
+        let common = completion
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(completion.len().min(candidate.len()), |((i, _), _)| i);
+        completion = &completion[..common];
+    }
+
+    (completion.len() > prefix.len()).then(|| completion.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completes_common_prefix() {
+        let users = ["alice", "albert", "bob"].map(String::from);
+
+        assert_eq!(complete_username("b", &users).as_deref(), Some("bob"));
+        assert_eq!(complete_username("a", &users).as_deref(), Some("al"));
+        assert_eq!(complete_username("ali", &users).as_deref(), Some("alice"));
+        assert_eq!(complete_username("al", &users), None);
+        assert_eq!(complete_username("bob", &users), None);
+        assert_eq!(complete_username("carol", &users), None);
+        assert_eq!(complete_username("", &users), None);
+    }
+}
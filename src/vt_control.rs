@@ -0,0 +1,168 @@
+//! Process control of the switches away from and back to the VT of lemurs.
+//!
+//! By default, the kernel switches VTs without telling lemurs. Lemurs then keeps drawing to a VT
+//! that is not shown, and returns to a console that another process may have left garbled. With
+//! `VT_PROCESS`, the kernel signals lemurs before it switches away and once the VT is shown again,
+//! so drawing can be paused and the login form fully redrawn.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Arc;
+
+use libc::c_int;
+use log::{info, warn};
+
+use crate::console_state::{RequestType, VtMode, VT_SETMODE};
+
+const VT_RELDISP: RequestType = 0x5605;
+
+const VT_AUTO: libc::c_char = 0;
+const VT_PROCESS: libc::c_char = 1;
+
+/// Allow the switch away from the VT
+const RELEASE_DISPLAY: c_int = 1;
+/// Acknowledge the switch to the VT
+const VT_ACKACQ: c_int = 2;
+
+/// The kernel sends this signal both before switching away and after switching back. `SIGUSR1` is
+/// already used to find out when the X server is ready.
+const VT_SIGNAL: c_int = libc::SIGUSR2;
+
+/// The pipe that the signal handler wakes up the listener thread with
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handle_vt_signal(_: c_int) {
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst);
+    if fd < 0 {
+        return;
+    }
+
+    // Only async-signal-safe functions can be used here. `errno` is kept for the interrupted code.
+    unsafe {
+        let errno = *libc::__errno_location();
+        libc::write(fd, [0u8].as_ptr().cast(), 1);
+        *libc::__errno_location() = errno;
+    }
+}
+
+fn set_vt_mode(fd: c_int, mode: libc::c_char) -> io::Result<()> {
+    let signal = if mode == VT_PROCESS { VT_SIGNAL } else { 0 };
+    let vt_mode = VtMode {
+        mode,
+        waitv: 0,
+        relsig: signal as libc::c_short,
+        acqsig: signal as libc::c_short,
+        frsig: 0,
+    };
+
+    if unsafe { libc::ioctl(fd, VT_SETMODE, &vt_mode) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Control over the switches of the VT that lemurs is running on
+pub struct VtControl {
+    fd: c_int,
+    shown: Arc<AtomicBool>,
+}
+
+impl VtControl {
+    /// Take control over the switches of the VT. `on_return` is called from a separate thread
+    /// each time the VT is shown again. Returns `None` if lemurs is not running on a VT.
+    pub fn take(on_return: impl Fn() + Send + 'static) -> Option<Self> {
+        let fd = libc::STDIN_FILENO;
+
+        let mut pipe = [-1; 2];
+        if unsafe { libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            warn!(
+                "Failed to create a pipe for VT switches. Reason: {}",
+                io::Error::last_os_error()
+            );
+            return None;
+        }
+        let [read_fd, write_fd] = pipe;
+        SIGNAL_PIPE.store(write_fd, Ordering::SeqCst);
+
+        unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction = handle_vt_signal as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(VT_SIGNAL, &action, std::ptr::null_mut());
+        }
+
+        if let Err(err) = set_vt_mode(fd, VT_PROCESS) {
+            info!("Not controlling VT switches. Reason: {err}");
+
+            SIGNAL_PIPE.store(-1, Ordering::SeqCst);
+            unsafe {
+                libc::signal(VT_SIGNAL, libc::SIG_DFL);
+                libc::close(read_fd);
+                libc::close(write_fd);
+            }
+            return None;
+        }
+
+        info!("Controlling the VT switches");
+
+        let shown = Arc::new(AtomicBool::new(true));
+        let thread_shown = shown.clone();
+        std::thread::spawn(move || loop {
+            let mut byte = 0u8;
+            let read = unsafe { libc::read(read_fd, (&mut byte as *mut u8).cast(), 1) };
+            if read < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            if read <= 0 {
+                warn!("Stopped listening for VT switches");
+                break;
+            }
+
+            // The same signal is used for both switches, so the switch is known from the state
+            if thread_shown.swap(false, Ordering::SeqCst) {
+                info!("Switching away from the VT");
+                if unsafe { libc::ioctl(fd, VT_RELDISP, RELEASE_DISPLAY) } != 0 {
+                    warn!(
+                        "Failed to release the VT. Reason: {}",
+                        io::Error::last_os_error()
+                    );
+                }
+            } else {
+                info!("Switched back to the VT");
+                if unsafe { libc::ioctl(fd, VT_RELDISP, VT_ACKACQ) } != 0 {
+                    warn!(
+                        "Failed to acknowledge the VT. Reason: {}",
+                        io::Error::last_os_error()
+                    );
+                }
+                thread_shown.store(true, Ordering::SeqCst);
+                on_return();
+            }
+        });
+
+        Some(Self { fd, shown })
+    }
+
+    /// Whether the VT is currently shown. Nothing should be drawn while it is not.
+    pub fn is_shown(&self) -> bool {
+        self.shown.load(Ordering::SeqCst)
+    }
+
+    /// Let the kernel switch on its own, e.g. while a session is running. This has to happen
+    /// before the state of the console is saved for the session.
+    pub fn pause(&self) {
+        if let Err(err) = set_vt_mode(self.fd, VT_AUTO) {
+            warn!("Failed to give the VT switches back to the kernel. Reason: {err}");
+        }
+    }
+
+    /// Take back control after `pause`. The VT is assumed to be shown.
+    pub fn resume(&self) {
+        self.shown.store(true, Ordering::SeqCst);
+        if let Err(err) = set_vt_mode(self.fd, VT_PROCESS) {
+            warn!("Failed to take back control over the VT switches. Reason: {err}");
+        }
+    }
+}